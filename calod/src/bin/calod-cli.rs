@@ -0,0 +1,65 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::process;
+use std::time::Duration;
+
+use calod::cluster::rebalance::{rebalance, RebalanceOptions};
+
+const USAGE: &str = "Usage: calod-cli --cluster rebalance <host:port>... [--pipeline <keys>] [--timeout <ms>]";
+
+fn parse_args(args: &[String]) -> Result<(Vec<String>, RebalanceOptions), String> {
+	let [cluster, command, rest @ ..] = args else {
+		return Err(String::from(USAGE));
+	};
+	if cluster != "--cluster" || command != "rebalance" {
+		return Err(String::from(USAGE));
+	}
+
+	let mut seeds = Vec::new();
+	let mut options = RebalanceOptions::default();
+	let mut rest = rest.iter();
+	while let Some(arg) = rest.next() {
+		if !arg.starts_with("--") {
+			seeds.push(arg.clone());
+			continue;
+		}
+		let value = rest.next().ok_or_else(|| format!("{} needs a value", arg))?;
+		match arg.as_str() {
+			"--pipeline" => options.pipeline = value.parse().ok().filter(|keys| *keys > 0).ok_or_else(|| format!("invalid pipeline `{}`", value))?,
+			"--timeout" => options.timeout = value.parse().map(Duration::from_millis).map_err(|_| String::from("--timeout needs milliseconds"))?,
+			other => return Err(format!("unexpected argument `{}`\n{}", other, USAGE)),
+		}
+	}
+	if seeds.is_empty() {
+		return Err(String::from(USAGE));
+	}
+	Ok((seeds, options))
+}
+
+// Cluster administration, for now spreading the slots evenly over every node
+fn main() {
+	let args: Vec<String> = env::args().skip(1).collect();
+	let (seeds, options) = match parse_args(&args) {
+		Ok(parsed) => parsed,
+		Err(e) => {
+			eprintln!("{}", e);
+			process::exit(2);
+		}
+	};
+
+	let moves = match rebalance(&seeds, &options) {
+		Ok(moves) => moves,
+		Err(e) => {
+			eprintln!("Rebalance failed: {}", e);
+			process::exit(1);
+		}
+	};
+	let mut pairs: BTreeMap<(&str, &str), usize> = BTreeMap::new();
+	for slot_move in &moves {
+		*pairs.entry((&slot_move.from, &slot_move.to)).or_default() += 1;
+	}
+	for ((from, to), slots) in pairs {
+		println!("Moved {} slots from {} to {}", slots, from, to);
+	}
+	println!("Rebalanced the cluster with {} slot moves", moves.len());
+}
@@ -0,0 +1,58 @@
+use std::env;
+use std::net::TcpListener;
+use std::process;
+use std::thread;
+use std::time::Duration;
+
+use calod::sentinel::state::{Sentinel, SentinelConfig};
+
+const USAGE: &str = "Usage: calod-sentinel <port> <name> <leader host:port> <quorum> [--peer <host:port>]... [--down-after <ms>] [--failover-timeout <ms>] [--ping-interval <ms>]";
+
+fn parse_args(args: &[String]) -> Result<(u16, SentinelConfig), String> {
+	let [port, name, leader, quorum, options @ ..] = args else {
+		return Err(String::from(USAGE));
+	};
+	let port = port.parse::<u16>().map_err(|_| format!("invalid port `{}`", port))?;
+	let quorum = quorum.parse::<usize>().ok().filter(|quorum| *quorum > 0).ok_or_else(|| format!("invalid quorum `{}`", quorum))?;
+	let mut config = SentinelConfig::new(name, leader, quorum);
+
+	let mut options = options.iter();
+	while let Some(option) = options.next() {
+		let value = options.next().ok_or_else(|| format!("{} needs a value", option))?;
+		let millis = || value.parse::<u64>().map(Duration::from_millis).map_err(|_| format!("{} needs milliseconds", option));
+		match option.as_str() {
+			"--peer" => config.peers.push(value.clone()),
+			"--down-after" => config.down_after = millis()?,
+			"--failover-timeout" => config.failover_timeout = millis()?,
+			"--ping-interval" => config.ping_interval = millis()?,
+			other => return Err(format!("unexpected argument `{}`\n{}", other, USAGE)),
+		}
+	}
+	Ok((port, config))
+}
+
+// Watch one leader with its followers and fail over together with the other sentinels
+fn main() {
+	let args: Vec<String> = env::args().skip(1).collect();
+	let (port, config) = match parse_args(&args) {
+		Ok(parsed) => parsed,
+		Err(e) => {
+			eprintln!("{}", e);
+			process::exit(2);
+		}
+	};
+
+	let listener = match TcpListener::bind(("127.0.0.1", port)) {
+		Ok(listener) => listener,
+		Err(e) => {
+			eprintln!("Failed to listen on port {}: {}", port, e);
+			process::exit(1);
+		}
+	};
+	println!("Sentinel monitoring {} at {} with quorum {}", config.name, config.leader, config.quorum);
+	let sentinel: &'static Sentinel = Box::leak(Box::new(Sentinel::new(config)));
+	sentinel.start(listener);
+	loop {
+		thread::park();
+	}
+}
@@ -0,0 +1,35 @@
+use std::env;
+use std::process;
+
+use calod::store::calod_store::CalodStore;
+
+// Offline conversion of a Redis RDB file into a calod snapshot
+// Usage: rdb_import <dump.rdb> <calod.snapshot>
+fn main() {
+	let args: Vec<String> = env::args().collect();
+	if args.len() != 3 {
+		eprintln!("Usage: rdb_import <dump.rdb> <calod.snapshot>");
+		process::exit(2);
+	}
+
+	let store = CalodStore::new(usize::MAX);
+	let stats = match store.import_rdb(&args[1]) {
+		Ok(stats) => stats,
+		Err(e) => {
+			eprintln!("Failed to import {}: {}", args[1], e);
+			process::exit(1);
+		}
+	};
+	println!("Imported {} keys from {} (RDB version {}), {} already expired", stats.keys, args[1], stats.version, stats.expired);
+	for (kind, count) in &stats.skipped {
+		println!("Skipped {} keys: {}", count, kind);
+	}
+
+	match store.save_to_file(&args[2]) {
+		Ok(saved) => println!("Saved {} keys to {}", saved, args[2]),
+		Err(e) => {
+			eprintln!("Failed to save {}: {}", args[2], e);
+			process::exit(1);
+		}
+	}
+}
@@ -0,0 +1,175 @@
+use std::io;
+use std::time::Duration;
+
+use tokio::io::{AsyncWriteExt, BufReader};
+use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
+use tokio::net::TcpStream;
+use tokio::time::{sleep, timeout};
+
+use crate::client::commands::Cmd;
+use crate::client::reply::{ClientError, FromResp};
+use crate::client::resp3::read_value;
+use crate::parser::parser::RESPOutput;
+use crate::persistence::aof::command_record;
+
+#[derive(Debug, Clone)]
+pub struct ClientOptions {
+	// Bound on opening one connection
+	pub connect_timeout: Duration,
+	// Bound on sending a command or a pipeline and reading every reply
+	pub response_timeout: Duration,
+	// Connections a pool keeps open at most
+	pub pool_size: usize,
+	// Tries to open a connection before giving up, waiting `reconnect_delay` after the first failure and twice as long after each next one
+	pub reconnect_attempts: usize,
+	pub reconnect_delay: Duration,
+	// Switch connections to RESP3 with HELLO 3, for servers that speak it
+	pub resp3: bool,
+}
+
+impl Default for ClientOptions {
+	fn default() -> Self {
+		ClientOptions {
+			connect_timeout: Duration::from_secs(5),
+			response_timeout: Duration::from_secs(5),
+			pool_size: 8,
+			reconnect_attempts: 3,
+			reconnect_delay: Duration::from_millis(100),
+			resp3: false,
+		}
+	}
+}
+
+// Commands sent together and answered in order, without waiting for each reply
+#[derive(Debug, Default)]
+pub struct Pipeline {
+	commands: Vec<Vec<String>>,
+}
+
+impl Pipeline {
+	pub fn new() -> Self {
+		Pipeline::default()
+	}
+
+	pub fn add<T: FromResp>(&mut self, cmd: Cmd<T>) -> &mut Self {
+		self.commands.push(cmd.arguments().to_vec());
+		self
+	}
+
+	pub fn len(&self) -> usize {
+		self.commands.len()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.commands.is_empty()
+	}
+}
+
+// One connection to a server, opened on the first command and again on the next one after it broke
+// 1. A connection that failed or timed out is dropped, a late reply would otherwise answer the next command
+// 2. Error replies of the server keep the connection
+// 3. A command whose future was dropped before its replies were read leaves the connection to be dropped the same way
+pub struct AsyncConnection {
+	address: String,
+	options: ClientOptions,
+	stream: Option<(BufReader<OwnedReadHalf>, OwnedWriteHalf)>,
+	// Replies are owed on `stream`
+	pending: bool,
+}
+
+impl AsyncConnection {
+	pub fn new(address: &str, options: ClientOptions) -> Self {
+		AsyncConnection { address: address.to_string(), options, stream: None, pending: false }
+	}
+
+	pub fn address(&self) -> &str {
+		&self.address
+	}
+
+	pub fn is_connected(&self) -> bool {
+		self.stream.is_some() && !self.pending
+	}
+
+	pub async fn query<T: FromResp>(&mut self, cmd: &Cmd<T>) -> Result<T, ClientError> {
+		let mut replies = self.exchange(command_record(cmd.arguments().to_vec()), 1).await?;
+		T::from_resp(replies.remove(0))
+	}
+
+	// The reply of every command of `pipeline` in order, error replies included
+	pub async fn pipeline(&mut self, pipeline: &Pipeline) -> Result<Vec<RESPOutput>, ClientError> {
+		if pipeline.is_empty() {
+			return Ok(Vec::new());
+		}
+		let records = pipeline.commands.iter().flat_map(|args| command_record(args.clone())).collect();
+		self.exchange(records, pipeline.len()).await
+	}
+
+	async fn exchange(&mut self, records: Vec<u8>, count: usize) -> Result<Vec<RESPOutput>, ClientError> {
+		if !self.is_connected() {
+			self.stream = None;
+			self.stream = Some(self.connect().await?);
+		}
+		self.pending = true;
+		let (reader, writer) = self.stream.as_mut().unwrap();
+		let exchanged = timeout(self.options.response_timeout, async {
+			writer.write_all(&records).await?;
+			let mut replies = Vec::with_capacity(count);
+			for _ in 0..count {
+				replies.push(read_value(reader).await?);
+			}
+			Ok::<Vec<RESPOutput>, io::Error>(replies)
+		})
+		.await;
+		self.pending = false;
+		match exchanged {
+			Ok(Ok(replies)) => Ok(replies),
+			Ok(Err(e)) => {
+				self.stream = None;
+				Err(ClientError::Io(e))
+			}
+			Err(_) => {
+				self.stream = None;
+				Err(ClientError::Timeout(self.options.response_timeout))
+			}
+		}
+	}
+
+	async fn connect(&self) -> Result<(BufReader<OwnedReadHalf>, OwnedWriteHalf), ClientError> {
+		let mut delay = self.options.reconnect_delay;
+		let mut attempt = 1;
+		loop {
+			match self.open().await {
+				Ok(stream) => return Ok(stream),
+				Err(ClientError::Server(e)) => return Err(ClientError::Server(e)),
+				Err(e) if attempt >= self.options.reconnect_attempts => return Err(e),
+				Err(_) => {
+					sleep(delay).await;
+					delay *= 2;
+					attempt += 1;
+				}
+			}
+		}
+	}
+
+	async fn open(&self) -> Result<(BufReader<OwnedReadHalf>, OwnedWriteHalf), ClientError> {
+		let stream = match timeout(self.options.connect_timeout, TcpStream::connect(&self.address)).await {
+			Ok(stream) => stream?,
+			Err(_) => return Err(ClientError::Timeout(self.options.connect_timeout)),
+		};
+		stream.set_nodelay(true)?;
+		let (reader, mut writer) = stream.into_split();
+		let mut reader = BufReader::new(reader);
+		if self.options.resp3 {
+			let hello = timeout(self.options.response_timeout, async {
+				writer.write_all(&command_record(vec![String::from("HELLO"), String::from("3")])).await?;
+				read_value(&mut reader).await
+			});
+			match hello.await {
+				Ok(Ok(RESPOutput::Error(e))) => return Err(ClientError::Server(e)),
+				Ok(reply) => reply?,
+				Err(_) => return Err(ClientError::Timeout(self.options.response_timeout)),
+			};
+		}
+		Ok((reader, writer))
+	}
+}
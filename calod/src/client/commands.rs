@@ -0,0 +1,499 @@
+use std::marker::PhantomData;
+use std::time::Duration;
+
+use crate::client::reply::{FromResp, ScanPage};
+use crate::parser::parser::RESPOutput;
+
+// A command with the type its reply decodes into
+// PSYNC is left out, it turns the connection into a replication stream
+pub struct Cmd<T = RESPOutput> {
+	args: Vec<String>,
+	reply: PhantomData<fn() -> T>,
+}
+
+impl<T: FromResp> Cmd<T> {
+	pub fn new(name: &str) -> Self {
+		Cmd { args: vec![name.to_string()], reply: PhantomData }
+	}
+
+	pub fn arg<A: ToString>(mut self, arg: A) -> Self {
+		self.args.push(arg.to_string());
+		self
+	}
+
+	pub fn args<A: ToString>(mut self, args: impl IntoIterator<Item = A>) -> Self {
+		self.args.extend(args.into_iter().map(|arg| arg.to_string()));
+		self
+	}
+
+	// The same arguments with another reply type
+	pub fn returning<R: FromResp>(self) -> Cmd<R> {
+		Cmd { args: self.args, reply: PhantomData }
+	}
+
+	pub fn arguments(&self) -> &[String] {
+		&self.args
+	}
+}
+
+// Any command by name, its reply left undecoded
+pub fn cmd(name: &str) -> Cmd {
+	Cmd::new(name)
+}
+
+pub fn ping() -> Cmd<String> {
+	Cmd::new("PING")
+}
+
+pub fn echo(message: &str) -> Cmd<String> {
+	Cmd::new("ECHO").arg(message)
+}
+
+pub fn get(key: &str) -> Cmd<Option<String>> {
+	Cmd::new("GET").arg(key)
+}
+
+pub fn set(key: &str, value: &str) -> Cmd<()> {
+	Cmd::new("SET").arg(key).arg(value)
+}
+
+// SET with a time to live, whole milliseconds
+pub fn set_px(key: &str, value: &str, ttl: Duration) -> Cmd<()> {
+	set(key, value).arg("PX").arg(ttl.as_millis().max(1))
+}
+
+// Keys
+
+// The number of keys removed
+pub fn del(keys: &[&str]) -> Cmd<i64> {
+	Cmd::new("DEL").args(keys)
+}
+
+// DEL that frees large values in the background
+pub fn unlink(keys: &[&str]) -> Cmd<i64> {
+	Cmd::new("UNLINK").args(keys)
+}
+
+// The number of keys that exist, a key given twice counts twice
+pub fn exists(keys: &[&str]) -> Cmd<i64> {
+	Cmd::new("EXISTS").args(keys)
+}
+
+// `none` for a missing key
+pub fn key_type(key: &str) -> Cmd<String> {
+	Cmd::new("TYPE").arg(key)
+}
+
+pub fn rename(key: &str, new_key: &str) -> Cmd<()> {
+	Cmd::new("RENAME").arg(key).arg(new_key)
+}
+
+pub fn renamenx(key: &str, new_key: &str) -> Cmd<bool> {
+	Cmd::new("RENAMENX").arg(key).arg(new_key)
+}
+
+// Add DB and REPLACE with `arg`
+pub fn copy(source: &str, destination: &str) -> Cmd<bool> {
+	Cmd::new("COPY").arg(source).arg(destination)
+}
+
+pub fn touch(keys: &[&str]) -> Cmd<i64> {
+	Cmd::new("TOUCH").args(keys)
+}
+
+pub fn randomkey() -> Cmd<Option<String>> {
+	Cmd::new("RANDOMKEY")
+}
+
+// Scans, add MATCH, COUNT and TYPE with `arg`, `ScanCursor` walks every page
+
+pub fn scan(cursor: u64) -> Cmd<ScanPage<String>> {
+	Cmd::new("SCAN").arg(cursor)
+}
+
+// Fields with their values
+pub fn hscan(key: &str, cursor: u64) -> Cmd<ScanPage<(String, String)>> {
+	Cmd::new("HSCAN").arg(key).arg(cursor)
+}
+
+pub fn sscan(key: &str, cursor: u64) -> Cmd<ScanPage<String>> {
+	Cmd::new("SSCAN").arg(key).arg(cursor)
+}
+
+// Members with their scores
+pub fn zscan(key: &str, cursor: u64) -> Cmd<ScanPage<(String, f64)>> {
+	Cmd::new("ZSCAN").arg(key).arg(cursor)
+}
+
+// Every matching key in one reply, SCAN does not block the server for as long
+pub fn keys(pattern: &str) -> Cmd<Vec<String>> {
+	Cmd::new("KEYS").arg(pattern)
+}
+
+// Databases
+
+// Only switches the connection it is sent on, which goes back to the pool still on `index`
+// Send it over a `PooledConnection` together with the commands meant for that database
+pub fn select(index: usize) -> Cmd<()> {
+	Cmd::new("SELECT").arg(index)
+}
+
+// False when the key is missing or the target database has it already
+pub fn move_key(key: &str, index: usize) -> Cmd<bool> {
+	Cmd::new("MOVE").arg(key).arg(index)
+}
+
+pub fn swapdb(first: usize, second: usize) -> Cmd<()> {
+	Cmd::new("SWAPDB").arg(first).arg(second)
+}
+
+pub fn flushdb() -> Cmd<()> {
+	Cmd::new("FLUSHDB")
+}
+
+pub fn flushall() -> Cmd<()> {
+	Cmd::new("FLUSHALL")
+}
+
+pub fn dbsize() -> Cmd<u64> {
+	Cmd::new("DBSIZE")
+}
+
+// Bitmaps
+
+pub fn setbit(key: &str, offset: u64, bit: bool) -> Cmd<bool> {
+	Cmd::new("SETBIT").arg(key).arg(offset).arg(bit as u8)
+}
+
+pub fn getbit(key: &str, offset: u64) -> Cmd<bool> {
+	Cmd::new("GETBIT").arg(key).arg(offset)
+}
+
+pub fn bitcount(key: &str) -> Cmd<i64> {
+	Cmd::new("BITCOUNT").arg(key)
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum BitUnit {
+	Byte,
+	Bit,
+}
+
+impl BitUnit {
+	fn name(&self) -> &'static str {
+		match self {
+			BitUnit::Byte => "BYTE",
+			BitUnit::Bit => "BIT",
+		}
+	}
+}
+
+pub fn bitcount_range(key: &str, start: i64, end: i64, unit: BitUnit) -> Cmd<i64> {
+	bitcount(key).arg(start).arg(end).arg(unit.name())
+}
+
+pub fn bitpos(key: &str, bit: bool) -> Cmd<i64> {
+	Cmd::new("BITPOS").arg(key).arg(bit as u8)
+}
+
+pub fn bitpos_range(key: &str, bit: bool, start: i64, end: i64, unit: BitUnit) -> Cmd<i64> {
+	bitpos(key, bit).arg(start).arg(end).arg(unit.name())
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum BitOp {
+	And,
+	Or,
+	Xor,
+	Not,
+}
+
+// The length of the destination string
+pub fn bitop(op: BitOp, destination: &str, keys: &[&str]) -> Cmd<i64> {
+	let op = match op {
+		BitOp::And => "AND",
+		BitOp::Or => "OR",
+		BitOp::Xor => "XOR",
+		BitOp::Not => "NOT",
+	};
+	Cmd::new("BITOP").arg(op).arg(destination).args(keys)
+}
+
+// Subcommands such as `["GET", "u8", "0", "INCRBY", "i5", "100", "1"]`, one result per GET, SET and INCRBY
+pub fn bitfield(key: &str, subcommands: &[&str]) -> Cmd<Vec<Option<i64>>> {
+	Cmd::new("BITFIELD").arg(key).args(subcommands)
+}
+
+// HyperLogLog
+
+pub fn pfadd(key: &str, elements: &[&str]) -> Cmd<bool> {
+	Cmd::new("PFADD").arg(key).args(elements)
+}
+
+pub fn pfcount(keys: &[&str]) -> Cmd<u64> {
+	Cmd::new("PFCOUNT").args(keys)
+}
+
+pub fn pfmerge(destination: &str, sources: &[&str]) -> Cmd<()> {
+	Cmd::new("PFMERGE").arg(destination).args(sources)
+}
+
+// Bloom and cuckoo filters
+
+pub fn bf_reserve(key: &str, error_rate: f64, capacity: u64) -> Cmd<()> {
+	Cmd::new("BF.RESERVE").arg(key).arg(error_rate).arg(capacity)
+}
+
+pub fn bf_add(key: &str, item: &str) -> Cmd<bool> {
+	Cmd::new("BF.ADD").arg(key).arg(item)
+}
+
+pub fn bf_madd(key: &str, items: &[&str]) -> Cmd<Vec<bool>> {
+	Cmd::new("BF.MADD").arg(key).args(items)
+}
+
+pub fn bf_exists(key: &str, item: &str) -> Cmd<bool> {
+	Cmd::new("BF.EXISTS").arg(key).arg(item)
+}
+
+pub fn bf_mexists(key: &str, items: &[&str]) -> Cmd<Vec<bool>> {
+	Cmd::new("BF.MEXISTS").arg(key).args(items)
+}
+
+pub fn cf_reserve(key: &str, capacity: u64) -> Cmd<()> {
+	Cmd::new("CF.RESERVE").arg(key).arg(capacity)
+}
+
+pub fn cf_add(key: &str, item: &str) -> Cmd<bool> {
+	Cmd::new("CF.ADD").arg(key).arg(item)
+}
+
+pub fn cf_del(key: &str, item: &str) -> Cmd<bool> {
+	Cmd::new("CF.DEL").arg(key).arg(item)
+}
+
+pub fn cf_exists(key: &str, item: &str) -> Cmd<bool> {
+	Cmd::new("CF.EXISTS").arg(key).arg(item)
+}
+
+// Geospatial indexes
+
+#[derive(Debug, Clone, Copy)]
+pub enum GeoUnit {
+	Meters,
+	Kilometers,
+	Miles,
+	Feet,
+}
+
+impl GeoUnit {
+	fn name(&self) -> &'static str {
+		match self {
+			GeoUnit::Meters => "m",
+			GeoUnit::Kilometers => "km",
+			GeoUnit::Miles => "mi",
+			GeoUnit::Feet => "ft",
+		}
+	}
+}
+
+// Members as `(longitude, latitude, member)`, the reply counts the ones added
+pub fn geoadd(key: &str, members: &[(f64, f64, &str)]) -> Cmd<i64> {
+	members.iter().fold(Cmd::new("GEOADD").arg(key), |cmd, (longitude, latitude, member)| cmd.arg(longitude).arg(latitude).arg(member))
+}
+
+// `(longitude, latitude)` of every member, `None` for missing ones
+pub fn geopos(key: &str, members: &[&str]) -> Cmd<Vec<Option<(f64, f64)>>> {
+	Cmd::new("GEOPOS").arg(key).args(members)
+}
+
+pub fn geodist(key: &str, first: &str, second: &str, unit: GeoUnit) -> Cmd<Option<f64>> {
+	Cmd::new("GEODIST").arg(key).arg(first).arg(second).arg(unit.name())
+}
+
+#[derive(Debug, Clone)]
+pub enum GeoFrom<'a> {
+	Member(&'a str),
+	LonLat(f64, f64),
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum GeoBy {
+	Radius(f64, GeoUnit),
+	Box(f64, f64, GeoUnit),
+}
+
+fn geo_area(cmd: Cmd<Vec<String>>, from: GeoFrom, by: GeoBy) -> Cmd<Vec<String>> {
+	let cmd = match from {
+		GeoFrom::Member(member) => cmd.arg("FROMMEMBER").arg(member),
+		GeoFrom::LonLat(longitude, latitude) => cmd.arg("FROMLONLAT").arg(longitude).arg(latitude),
+	};
+	match by {
+		GeoBy::Radius(radius, unit) => cmd.arg("BYRADIUS").arg(radius).arg(unit.name()),
+		GeoBy::Box(width, height, unit) => cmd.arg("BYBOX").arg(width).arg(height).arg(unit.name()),
+	}
+}
+
+// The members in the area, add ASC, DESC, COUNT and ANY with `arg`, WITHCOORD, WITHDIST and WITHHASH need `returning`
+pub fn geosearch(key: &str, from: GeoFrom, by: GeoBy) -> Cmd<Vec<String>> {
+	geo_area(Cmd::new("GEOSEARCH").arg(key), from, by)
+}
+
+pub fn geosearchstore(destination: &str, source: &str, from: GeoFrom, by: GeoBy) -> Cmd<i64> {
+	geo_area(Cmd::new("GEOSEARCHSTORE").arg(destination).arg(source), from, by).returning()
+}
+
+// JSON documents, values are JSON text
+
+pub fn json_set(key: &str, path: &str, json: &str) -> Cmd<Option<String>> {
+	Cmd::new("JSON.SET").arg(key).arg(path).arg(json)
+}
+
+pub fn json_get(key: &str, paths: &[&str]) -> Cmd<Option<String>> {
+	Cmd::new("JSON.GET").arg(key).args(paths)
+}
+
+pub fn json_del(key: &str, path: &str) -> Cmd<i64> {
+	Cmd::new("JSON.DEL").arg(key).arg(path)
+}
+
+pub fn json_numincrby(key: &str, path: &str, by: f64) -> Cmd<String> {
+	Cmd::new("JSON.NUMINCRBY").arg(key).arg(path).arg(by)
+}
+
+// A legacy path gets a single length, a `$` path one per match
+pub fn json_arrappend(key: &str, path: &str, values: &[&str]) -> Cmd<RESPOutput> {
+	Cmd::new("JSON.ARRAPPEND").arg(key).arg(path).args(values)
+}
+
+pub fn json_objkeys(key: &str, path: &str) -> Cmd<RESPOutput> {
+	Cmd::new("JSON.OBJKEYS").arg(key).arg(path)
+}
+
+pub fn json_type(key: &str, path: &str) -> Cmd<RESPOutput> {
+	Cmd::new("JSON.TYPE").arg(key).arg(path)
+}
+
+// Persistence
+
+pub fn save() -> Cmd<()> {
+	Cmd::new("SAVE")
+}
+
+pub fn bgsave() -> Cmd<String> {
+	Cmd::new("BGSAVE")
+}
+
+// Unix time of the last successful save
+pub fn lastsave() -> Cmd<i64> {
+	Cmd::new("LASTSAVE")
+}
+
+pub fn bgrewriteaof() -> Cmd<String> {
+	Cmd::new("BGREWRITEAOF")
+}
+
+// Migration
+
+// The serialized value, `None` for a missing key
+pub fn dump(key: &str) -> Cmd<Option<String>> {
+	Cmd::new("DUMP").arg(key)
+}
+
+// `ttl` of zero keeps the key forever
+pub fn restore(key: &str, ttl: Duration, payload: &str) -> Cmd<()> {
+	Cmd::new("RESTORE").arg(key).arg(ttl.as_millis()).arg(payload)
+}
+
+// `OK`, or `NOKEY` when none of the keys exists
+pub fn migrate(host: &str, port: u16, keys: &[&str], timeout: Duration) -> Cmd<String> {
+	Cmd::new("MIGRATE").arg(host).arg(port).arg("").arg(0).arg(timeout.as_millis()).arg("KEYS").args(keys)
+}
+
+// Replication
+
+pub fn replicaof(host: &str, port: u16) -> Cmd<String> {
+	Cmd::new("REPLICAOF").arg(host).arg(port)
+}
+
+pub fn replicaof_no_one() -> Cmd<String> {
+	Cmd::new("REPLICAOF").arg("NO").arg("ONE")
+}
+
+pub fn role() -> Cmd<RESPOutput> {
+	Cmd::new("ROLE")
+}
+
+// The number of replicas that acknowledged the writes before `timeout`
+pub fn wait(replicas: u64, timeout: Duration) -> Cmd<i64> {
+	Cmd::new("WAIT").arg(replicas).arg(timeout.as_millis())
+}
+
+// Cluster
+
+pub fn asking() -> Cmd<()> {
+	Cmd::new("ASKING")
+}
+
+pub fn cluster_info() -> Cmd<String> {
+	Cmd::new("CLUSTER").arg("INFO")
+}
+
+pub fn cluster_nodes() -> Cmd<String> {
+	Cmd::new("CLUSTER").arg("NODES")
+}
+
+pub fn cluster_slots() -> Cmd<RESPOutput> {
+	Cmd::new("CLUSTER").arg("SLOTS")
+}
+
+pub fn cluster_keyslot(key: &str) -> Cmd<u16> {
+	Cmd::new("CLUSTER").arg("KEYSLOT").arg(key)
+}
+
+pub fn cluster_myid() -> Cmd<String> {
+	Cmd::new("CLUSTER").arg("MYID")
+}
+
+pub fn cluster_addslots(slots: &[u16]) -> Cmd<()> {
+	Cmd::new("CLUSTER").arg("ADDSLOTS").args(slots)
+}
+
+// Inclusive `(start, end)` ranges
+pub fn cluster_addslotsrange(ranges: &[(u16, u16)]) -> Cmd<()> {
+	ranges.iter().fold(Cmd::new("CLUSTER").arg("ADDSLOTSRANGE"), |cmd, (start, end)| cmd.arg(start).arg(end))
+}
+
+pub fn cluster_delslots(slots: &[u16]) -> Cmd<()> {
+	Cmd::new("CLUSTER").arg("DELSLOTS").args(slots)
+}
+
+#[derive(Debug, Clone)]
+pub enum SetSlot<'a> {
+	Importing(&'a str),
+	Migrating(&'a str),
+	Node(&'a str),
+	Stable,
+}
+
+pub fn cluster_setslot(slot: u16, action: SetSlot) -> Cmd<()> {
+	let cmd = Cmd::new("CLUSTER").arg("SETSLOT").arg(slot);
+	match action {
+		SetSlot::Importing(id) => cmd.arg("IMPORTING").arg(id),
+		SetSlot::Migrating(id) => cmd.arg("MIGRATING").arg(id),
+		SetSlot::Node(id) => cmd.arg("NODE").arg(id),
+		SetSlot::Stable => cmd.arg("STABLE"),
+	}
+}
+
+pub fn cluster_getkeysinslot(slot: u16, count: usize) -> Cmd<Vec<String>> {
+	Cmd::new("CLUSTER").arg("GETKEYSINSLOT").arg(slot).arg(count)
+}
+
+pub fn cluster_countkeysinslot(slot: u16) -> Cmd<usize> {
+	Cmd::new("CLUSTER").arg("COUNTKEYSINSLOT").arg(slot)
+}
+
+pub fn cluster_meet(host: &str, port: u16) -> Cmd<()> {
+	Cmd::new("CLUSTER").arg("MEET").arg(host).arg(port)
+}
@@ -0,0 +1,52 @@
+use std::io::{self, BufReader, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+use crate::parser::parser::RESPOutput;
+use crate::persistence::aof::command_record;
+use crate::sentinel::link::read_reply;
+
+// A connection to one server, opened on the first command and again on the next one after it broke
+// 1. Every step is bounded by `timeout`
+// 2. An error reply of the server is returned as an `io::Error` of kind `Other` and keeps the connection
+pub struct Connection {
+	address: String,
+	timeout: Duration,
+	stream: Option<(BufReader<TcpStream>, TcpStream)>,
+}
+
+impl Connection {
+	pub fn new(address: &str, timeout: Duration) -> Self {
+		Connection { address: address.to_string(), timeout, stream: None }
+	}
+
+	pub fn address(&self) -> &str {
+		&self.address
+	}
+
+	pub fn command(&mut self, args: &[&str]) -> io::Result<RESPOutput> {
+		let result = self.send(args);
+		if matches!(&result, Err(e) if e.kind() != io::ErrorKind::Other) {
+			self.stream = None;
+		}
+		result
+	}
+
+	fn send(&mut self, args: &[&str]) -> io::Result<RESPOutput> {
+		if self.stream.is_none() {
+			self.stream = Some(self.connect()?);
+		}
+		let (reader, writer) = self.stream.as_mut().unwrap();
+		writer.write_all(&command_record(args.iter().map(|arg| arg.to_string()).collect()))?;
+		read_reply(reader)
+	}
+
+	fn connect(&self) -> io::Result<(BufReader<TcpStream>, TcpStream)> {
+		let target = self.address.to_socket_addrs()?.next().ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no address to connect to"))?;
+		let stream = TcpStream::connect_timeout(&target, self.timeout)?;
+		stream.set_read_timeout(Some(self.timeout))?;
+		stream.set_write_timeout(Some(self.timeout))?;
+		stream.set_nodelay(true)?;
+		Ok((BufReader::new(stream.try_clone()?), stream))
+	}
+}
@@ -0,0 +1,8 @@
+pub mod async_connection;
+pub mod commands;
+pub mod connection;
+pub mod pool;
+pub mod reply;
+pub mod resp3;
+pub mod ring;
+pub mod sharded;
@@ -0,0 +1,128 @@
+use std::collections::VecDeque;
+use std::ops::{Deref, DerefMut};
+use std::sync::{Arc, Mutex};
+
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+use crate::client::async_connection::{AsyncConnection, ClientOptions, Pipeline};
+use crate::client::commands::Cmd;
+use crate::client::reply::{ClientError, FromResp, ScanItem, ScanPage};
+use crate::parser::parser::RESPOutput;
+
+struct Pool {
+	address: String,
+	options: ClientOptions,
+	// Open connections nobody uses right now
+	idle: Mutex<Vec<AsyncConnection>>,
+	// One permit per connection in use, at most `pool_size`
+	permits: Arc<Semaphore>,
+}
+
+// Async client of one server, cheap to clone and share between tasks
+// 1. Every command borrows a connection of the pool and gives it back once answered, a command waits while all are in use
+// 2. Broken connections are not given back, the pool opens new ones as needed
+#[derive(Clone)]
+pub struct Client {
+	pool: Arc<Pool>,
+}
+
+impl Client {
+	pub fn new(address: &str) -> Self {
+		Client::with_options(address, ClientOptions::default())
+	}
+
+	pub fn with_options(address: &str, options: ClientOptions) -> Self {
+		let permits = Arc::new(Semaphore::new(options.pool_size.max(1)));
+		Client { pool: Arc::new(Pool { address: address.to_string(), options, idle: Mutex::new(Vec::new()), permits }) }
+	}
+
+	pub fn address(&self) -> &str {
+		&self.pool.address
+	}
+
+	// A connection for several commands in a row, such as ASKING and the command it applies to
+	pub async fn get(&self) -> Result<PooledConnection, ClientError> {
+		let permit = self.pool.permits.clone().acquire_owned().await.map_err(|_| ClientError::PoolClosed)?;
+		let connection = self.pool.idle.lock().unwrap().pop().unwrap_or_else(|| AsyncConnection::new(&self.pool.address, self.pool.options.clone()));
+		Ok(PooledConnection { pool: self.pool.clone(), connection: Some(connection), _permit: permit })
+	}
+
+	pub async fn query<T: FromResp>(&self, cmd: Cmd<T>) -> Result<T, ClientError> {
+		self.get().await?.query(&cmd).await
+	}
+
+	// The replies of `pipeline` decoded together, into a tuple or a `Vec<RESPOutput>` for instance
+	pub async fn pipeline<T: FromResp>(&self, pipeline: &Pipeline) -> Result<T, ClientError> {
+		let replies = self.get().await?.pipeline(pipeline).await?;
+		T::from_resp(RESPOutput::Array(replies))
+	}
+
+	// Later commands fail with `ClientError::PoolClosed`, idle connections are closed right away
+	pub fn close(&self) {
+		self.pool.permits.close();
+		self.pool.idle.lock().unwrap().clear();
+	}
+
+	pub fn idle_connections(&self) -> usize {
+		self.pool.idle.lock().unwrap().len()
+	}
+}
+
+pub struct PooledConnection {
+	pool: Arc<Pool>,
+	connection: Option<AsyncConnection>,
+	_permit: OwnedSemaphorePermit,
+}
+
+impl Deref for PooledConnection {
+	type Target = AsyncConnection;
+
+	fn deref(&self) -> &AsyncConnection {
+		self.connection.as_ref().unwrap()
+	}
+}
+
+impl DerefMut for PooledConnection {
+	fn deref_mut(&mut self) -> &mut AsyncConnection {
+		self.connection.as_mut().unwrap()
+	}
+}
+
+impl Drop for PooledConnection {
+	fn drop(&mut self) {
+		let connection = self.connection.take().unwrap();
+		if connection.is_connected() && !self.pool.permits.is_closed() {
+			self.pool.idle.lock().unwrap().push(connection);
+		}
+	}
+}
+
+// Walks a SCAN family command page by page, such as `ScanCursor::new(|cursor| commands::scan(cursor).arg("MATCH").arg("user:*"))`
+// Items come one at a time, the next page is only asked for once the current one is used up
+pub struct ScanCursor<T, F> {
+	page: F,
+	// `None` once the server returned cursor 0
+	cursor: Option<u64>,
+	items: VecDeque<T>,
+}
+
+impl<T: ScanItem, F: FnMut(u64) -> Cmd<ScanPage<T>>> ScanCursor<T, F> {
+	pub fn new(page: F) -> Self {
+		ScanCursor { page, cursor: Some(0), items: VecDeque::new() }
+	}
+
+	// The next item, `None` after the last page
+	pub async fn next(&mut self, client: &Client) -> Result<Option<T>, ClientError> {
+		loop {
+			if let Some(item) = self.items.pop_front() {
+				return Ok(Some(item));
+			}
+			let Some(cursor) = self.cursor else {
+				return Ok(None);
+			};
+			let page = client.query((self.page)(cursor)).await?;
+			self.cursor = (page.cursor != 0).then_some(page.cursor);
+			self.items.extend(page.items);
+		}
+	}
+}
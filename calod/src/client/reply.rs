@@ -0,0 +1,223 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::hash::Hash;
+use std::io;
+
+use thiserror::Error;
+
+use crate::parser::parser::RESPOutput;
+
+#[derive(Debug, Error)]
+pub enum ClientError {
+	#[error("{0}")]
+	Io(#[from] io::Error),
+
+	#[error("Timed out after {0:?}")]
+	Timeout(std::time::Duration),
+
+	// An error reply of the server, such as `ERR wrong number of arguments for 'get' command`
+	#[error("{0}")]
+	Server(String),
+
+	#[error("Unexpected reply {0:?}")]
+	UnexpectedReply(RESPOutput),
+
+	#[error("The pool is closed")]
+	PoolClosed,
+}
+
+// A Rust type a reply decodes into
+// 1. Error replies become `ClientError::Server` for every type but `RESPOutput` itself
+// 2. RESP2 and RESP3 replies of the same meaning decode the same, a map may come as a flat array and a boolean as 0 or 1
+pub trait FromResp: Sized {
+	fn from_resp(reply: RESPOutput) -> Result<Self, ClientError>;
+}
+
+fn server_error(reply: RESPOutput) -> ClientError {
+	match reply {
+		RESPOutput::Error(message) => ClientError::Server(message),
+		reply => ClientError::UnexpectedReply(reply),
+	}
+}
+
+impl FromResp for RESPOutput {
+	fn from_resp(reply: RESPOutput) -> Result<Self, ClientError> {
+		Ok(reply)
+	}
+}
+
+// Replies of commands that only acknowledge, such as `OK`
+impl FromResp for () {
+	fn from_resp(reply: RESPOutput) -> Result<Self, ClientError> {
+		match reply {
+			RESPOutput::Error(_) => Err(server_error(reply)),
+			_ => Ok(()),
+		}
+	}
+}
+
+impl FromResp for String {
+	fn from_resp(reply: RESPOutput) -> Result<Self, ClientError> {
+		match reply {
+			RESPOutput::SimpleString(text) | RESPOutput::BulkString(text) | RESPOutput::BigNumber(text) | RESPOutput::Verbatim(_, text) => Ok(text),
+			RESPOutput::Integer(num) => Ok(num.to_string()),
+			RESPOutput::Double(num) => Ok(num.to_string()),
+			reply => Err(server_error(reply)),
+		}
+	}
+}
+
+impl FromResp for i64 {
+	fn from_resp(reply: RESPOutput) -> Result<Self, ClientError> {
+		match reply {
+			RESPOutput::Integer(num) => Ok(num),
+			RESPOutput::Boolean(value) => Ok(value as i64),
+			RESPOutput::SimpleString(ref text) | RESPOutput::BulkString(ref text) | RESPOutput::BigNumber(ref text) => text.parse().map_err(|_| server_error(reply)),
+			reply => Err(server_error(reply)),
+		}
+	}
+}
+
+// Other integer types, a reply out of their range is unexpected
+macro_rules! integer_from_resp {
+	($($int:ty),+) => {
+		$(impl FromResp for $int {
+			fn from_resp(reply: RESPOutput) -> Result<Self, ClientError> {
+				let num = i64::from_resp(reply)?;
+				<$int>::try_from(num).map_err(|_| ClientError::UnexpectedReply(RESPOutput::Integer(num)))
+			}
+		})+
+	};
+}
+
+integer_from_resp!(i32, u16, u32, u64, usize);
+
+impl FromResp for f64 {
+	fn from_resp(reply: RESPOutput) -> Result<Self, ClientError> {
+		match reply {
+			RESPOutput::Double(num) => Ok(num),
+			RESPOutput::Integer(num) => Ok(num as f64),
+			RESPOutput::SimpleString(ref text) | RESPOutput::BulkString(ref text) => text.parse().map_err(|_| server_error(reply)),
+			reply => Err(server_error(reply)),
+		}
+	}
+}
+
+impl FromResp for bool {
+	fn from_resp(reply: RESPOutput) -> Result<Self, ClientError> {
+		match reply {
+			RESPOutput::Boolean(value) => Ok(value),
+			RESPOutput::Integer(num) => Ok(num != 0),
+			RESPOutput::SimpleString(ref text) if text == "OK" => Ok(true),
+			reply => Err(server_error(reply)),
+		}
+	}
+}
+
+impl<T: FromResp> FromResp for Option<T> {
+	fn from_resp(reply: RESPOutput) -> Result<Self, ClientError> {
+		match reply {
+			RESPOutput::Null => Ok(None),
+			reply => T::from_resp(reply).map(Some),
+		}
+	}
+}
+
+// The items of an array, a set or a push
+fn items(reply: RESPOutput) -> Result<Vec<RESPOutput>, ClientError> {
+	match reply {
+		RESPOutput::Array(items) | RESPOutput::Set(items) | RESPOutput::Push(items) => Ok(items),
+		reply => Err(server_error(reply)),
+	}
+}
+
+// The entries of a map, or of an array of keys and values in turn
+fn entries(reply: RESPOutput) -> Result<Vec<(RESPOutput, RESPOutput)>, ClientError> {
+	match reply {
+		RESPOutput::Map(entries) => Ok(entries),
+		RESPOutput::Array(items) if items.len() % 2 == 0 => {
+			let mut items = items.into_iter();
+			Ok(std::iter::from_fn(|| Some((items.next()?, items.next()?))).collect())
+		}
+		reply => Err(server_error(reply)),
+	}
+}
+
+impl<T: FromResp> FromResp for Vec<T> {
+	fn from_resp(reply: RESPOutput) -> Result<Self, ClientError> {
+		items(reply)?.into_iter().map(T::from_resp).collect()
+	}
+}
+
+impl<T: FromResp + Eq + Hash> FromResp for HashSet<T> {
+	fn from_resp(reply: RESPOutput) -> Result<Self, ClientError> {
+		items(reply)?.into_iter().map(T::from_resp).collect()
+	}
+}
+
+impl<K: FromResp + Eq + Hash, V: FromResp> FromResp for HashMap<K, V> {
+	fn from_resp(reply: RESPOutput) -> Result<Self, ClientError> {
+		entries(reply)?.into_iter().map(|(key, value)| Ok((K::from_resp(key)?, V::from_resp(value)?))).collect()
+	}
+}
+
+impl<K: FromResp + Ord, V: FromResp> FromResp for BTreeMap<K, V> {
+	fn from_resp(reply: RESPOutput) -> Result<Self, ClientError> {
+		entries(reply)?.into_iter().map(|(key, value)| Ok((K::from_resp(key)?, V::from_resp(value)?))).collect()
+	}
+}
+
+// Arrays of a fixed length, such as the replies of a pipeline or coordinates
+macro_rules! tuple_from_resp {
+	($len:expr, $($name:ident),+) => {
+		impl<$($name: FromResp),+> FromResp for ($($name,)+) {
+			fn from_resp(reply: RESPOutput) -> Result<Self, ClientError> {
+				let items = items(reply)?;
+				if items.len() != $len {
+					return Err(ClientError::UnexpectedReply(RESPOutput::Array(items)));
+				}
+				let mut items = items.into_iter();
+				Ok(($($name::from_resp(items.next().unwrap())?,)+))
+			}
+		}
+	};
+}
+
+tuple_from_resp!(1, A);
+tuple_from_resp!(2, A, B);
+tuple_from_resp!(3, A, B, C);
+tuple_from_resp!(4, A, B, C, D);
+tuple_from_resp!(5, A, B, C, D, E);
+
+// One page of SCAN, HSCAN, SSCAN or ZSCAN, the cursor is 0 after the last page
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScanPage<T> {
+	pub cursor: u64,
+	pub items: Vec<T>,
+}
+
+// What a scan page holds, single keys or members, or fields and members paired with their values
+pub trait ScanItem: Sized {
+	fn from_items(items: Vec<RESPOutput>) -> Result<Vec<Self>, ClientError>;
+}
+
+impl ScanItem for String {
+	fn from_items(items: Vec<RESPOutput>) -> Result<Vec<Self>, ClientError> {
+		items.into_iter().map(String::from_resp).collect()
+	}
+}
+
+// HSCAN and ZSCAN reply with a flat array of names and values in turn
+impl<V: FromResp> ScanItem for (String, V) {
+	fn from_items(items: Vec<RESPOutput>) -> Result<Vec<Self>, ClientError> {
+		entries(RESPOutput::Array(items))?.into_iter().map(|(name, value)| Ok((String::from_resp(name)?, V::from_resp(value)?))).collect()
+	}
+}
+
+// The cursor is a bulk string, it may not fit an `i64`
+impl<T: ScanItem> FromResp for ScanPage<T> {
+	fn from_resp(reply: RESPOutput) -> Result<Self, ClientError> {
+		let (cursor, page) = <(String, RESPOutput)>::from_resp(reply)?;
+		let cursor = cursor.parse().map_err(|_| ClientError::UnexpectedReply(RESPOutput::BulkString(cursor)))?;
+		Ok(ScanPage { cursor, items: T::from_items(items(page)?)? })
+	}
+}
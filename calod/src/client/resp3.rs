@@ -0,0 +1,107 @@
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt};
+
+use crate::parser::parser::{RESPOutput, PROTO_MAX_BULK_LEN};
+
+type ReadResult<'a> = Pin<Box<dyn Future<Output = io::Result<RESPOutput>> + Send + 'a>>;
+
+// Read one RESP2 or RESP3 reply
+// 1. Error replies are returned as `RESPOutput::Error`, so one failing command of a pipeline does not hide the other replies
+// 2. Attributes are read and dropped, the reply they annotate is returned
+pub fn read_value<R: AsyncBufRead + Unpin + Send>(reader: &mut R) -> ReadResult<'_> {
+	Box::pin(async move {
+		let mut line = String::new();
+		if reader.read_line(&mut line).await? == 0 {
+			return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "connection closed"));
+		}
+		let line = line.trim_end_matches(['\r', '\n']);
+		let invalid = || io::Error::new(io::ErrorKind::InvalidData, format!("unexpected reply `{}`", line));
+		if line.is_empty() || !line.is_char_boundary(1) {
+			return Err(invalid());
+		}
+
+		let (kind, rest) = line.split_at(1);
+		let len = || match rest.parse::<i64>() {
+			Ok(len) if len >= -1 => Ok(len),
+			_ => Err(invalid()),
+		};
+		match kind {
+			"+" => Ok(RESPOutput::SimpleString(rest.to_string())),
+			"-" => Ok(RESPOutput::Error(rest.to_string())),
+			":" => rest.parse().map(RESPOutput::Integer).map_err(|_| invalid()),
+			"_" => Ok(RESPOutput::Null),
+			"," => parse_double(rest).map(RESPOutput::Double).ok_or_else(invalid),
+			"#" => match rest {
+				"t" => Ok(RESPOutput::Boolean(true)),
+				"f" => Ok(RESPOutput::Boolean(false)),
+				_ => Err(invalid()),
+			},
+			"(" if !rest.is_empty() && rest.trim_start_matches('-').bytes().all(|byte| byte.is_ascii_digit()) => Ok(RESPOutput::BigNumber(rest.to_string())),
+			"$" | "!" | "=" => {
+				let len = len()?;
+				if len == -1 {
+					return Ok(RESPOutput::Null);
+				}
+				let text = read_blob(reader, len as usize).await?;
+				match kind {
+					"$" => Ok(RESPOutput::BulkString(text)),
+					"!" => Ok(RESPOutput::Error(text)),
+					_ => match text.split_once(':') {
+						Some((format, text)) if format.len() == 3 => Ok(RESPOutput::Verbatim(format.to_string(), text.to_string())),
+						_ => Err(invalid()),
+					},
+				}
+			}
+			"*" | "~" | ">" => {
+				let len = len()?;
+				if len == -1 {
+					return Ok(RESPOutput::Null);
+				}
+				let mut items = Vec::with_capacity(len.min(1024) as usize);
+				for _ in 0..len {
+					items.push(read_value(reader).await?);
+				}
+				Ok(match kind {
+					"*" => RESPOutput::Array(items),
+					"~" => RESPOutput::Set(items),
+					_ => RESPOutput::Push(items),
+				})
+			}
+			"%" | "|" => {
+				let len = len()?;
+				let mut entries = Vec::with_capacity(len.clamp(0, 1024) as usize);
+				for _ in 0..len {
+					entries.push((read_value(reader).await?, read_value(reader).await?));
+				}
+				match kind {
+					"%" => Ok(RESPOutput::Map(entries)),
+					_ => read_value(reader).await,
+				}
+			}
+			_ => Err(invalid()),
+		}
+	})
+}
+
+// The length is checked before allocating, a broken or hostile server cannot make us reserve gigabytes
+async fn read_blob<R: AsyncBufRead + Unpin>(reader: &mut R, len: usize) -> io::Result<String> {
+	if len > PROTO_MAX_BULK_LEN {
+		return Err(io::Error::new(io::ErrorKind::InvalidData, format!("blob of {} bytes is over the limit of {}", len, PROTO_MAX_BULK_LEN)));
+	}
+	let mut bytes = vec![0; len + 2];
+	reader.read_exact(&mut bytes).await?;
+	bytes.truncate(len);
+	Ok(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+fn parse_double(str: &str) -> Option<f64> {
+	match str {
+		"inf" => Some(f64::INFINITY),
+		"-inf" => Some(f64::NEG_INFINITY),
+		"nan" => Some(f64::NAN),
+		_ => str.parse().ok(),
+	}
+}
@@ -0,0 +1,109 @@
+use std::collections::BTreeMap;
+
+// Hash of a key or of a point on the ring, any function spreading its input evenly over `u64` will do
+pub trait KeyHasher: Send + Sync {
+	fn hash(&self, bytes: &[u8]) -> u64;
+}
+
+impl<F: Fn(&[u8]) -> u64 + Send + Sync> KeyHasher for F {
+	fn hash(&self, bytes: &[u8]) -> u64 {
+		self(bytes)
+	}
+}
+
+// 64 bit FNV-1a, the default
+// Its last bytes barely reach the high bits the ring is ordered by, so the result goes through the MurmurHash3 finalizer
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Fnv1a;
+
+impl KeyHasher for Fnv1a {
+	fn hash(&self, bytes: &[u8]) -> u64 {
+		let mut hash: u64 = 0xcbf29ce484222325;
+		for byte in bytes {
+			hash ^= *byte as u64;
+			hash = hash.wrapping_mul(0x100000001b3);
+		}
+		hash ^= hash >> 33;
+		hash = hash.wrapping_mul(0xff51afd7ed558ccd);
+		hash ^= hash >> 33;
+		hash = hash.wrapping_mul(0xc4ceb9fe1a85ec53);
+		hash ^ (hash >> 33)
+	}
+}
+
+// CRC32 widened to 64 bits, for rings shared with clients that hash with CRC32
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Crc32;
+
+impl KeyHasher for Crc32 {
+	fn hash(&self, bytes: &[u8]) -> u64 {
+		crc32fast::hash(bytes) as u64
+	}
+}
+
+pub const DEFAULT_VNODES: usize = 160;
+
+// Consistent hashing over a set of servers
+// 1. Every server is placed on the ring `vnodes` times as `<server>-<i>`, a key belongs to the first point at or after its hash
+// 2. Adding or removing a server only moves the keys between its points and the ones before them
+pub struct HashRing<H: KeyHasher = Fnv1a> {
+	hasher: H,
+	vnodes: usize,
+	points: BTreeMap<u64, String>,
+}
+
+impl HashRing<Fnv1a> {
+	pub fn new(vnodes: usize) -> Self {
+		HashRing::with_hasher(Fnv1a, vnodes)
+	}
+}
+
+impl<H: KeyHasher> HashRing<H> {
+	pub fn with_hasher(hasher: H, vnodes: usize) -> Self {
+		HashRing { hasher, vnodes: vnodes.max(1), points: BTreeMap::new() }
+	}
+
+	pub fn hash(&self, bytes: &[u8]) -> u64 {
+		self.hasher.hash(bytes)
+	}
+
+	// A point already taken by another server stays with the server that has the smaller name, whatever the order of adding
+	pub fn add(&mut self, server: &str) {
+		for i in 0..self.vnodes {
+			let point = self.hash(format!("{}-{}", server, i).as_bytes());
+			let owner = self.points.entry(point).or_insert_with(|| server.to_string());
+			if server < owner.as_str() {
+				*owner = server.to_string();
+			}
+		}
+	}
+
+	// Points the server won from another one go back to that one
+	pub fn remove(&mut self, server: &str) -> bool {
+		let before = self.points.len();
+		self.points.retain(|_, owner| owner != server);
+		if before == self.points.len() {
+			return false;
+		}
+		for other in self.servers().into_iter().map(String::from).collect::<Vec<String>>() {
+			self.add(&other);
+		}
+		true
+	}
+
+	pub fn servers(&self) -> Vec<&str> {
+		let mut servers: Vec<&str> = self.points.values().map(String::as_str).collect();
+		servers.sort_unstable();
+		servers.dedup();
+		servers
+	}
+
+	// The server for bytes that were hashed already
+	pub fn server_for_hash(&self, hash: u64) -> Option<&str> {
+		self.points.range(hash..).next().or_else(|| self.points.iter().next()).map(|(_, server)| server.as_str())
+	}
+
+	pub fn server_for(&self, bytes: &[u8]) -> Option<&str> {
+		self.server_for_hash(self.hash(bytes))
+	}
+}
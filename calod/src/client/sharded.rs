@@ -0,0 +1,82 @@
+use std::collections::HashMap;
+use std::io;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::client::connection::Connection;
+use crate::client::ring::{Fnv1a, HashRing, KeyHasher, DEFAULT_VNODES};
+use crate::cluster::slots::hash_tag;
+use crate::parser::parser::RESPOutput;
+
+pub struct ShardOptions {
+	// Points of every server on the ring, more spread the keys more evenly
+	pub vnodes: usize,
+	pub timeout: Duration,
+}
+
+impl Default for ShardOptions {
+	fn default() -> Self {
+		ShardOptions { vnodes: DEFAULT_VNODES, timeout: Duration::from_secs(5) }
+	}
+}
+
+// Client sharding keys over independent servers with consistent hashing
+// 1. A key is hashed like in cluster mode, only its `{hashtag}` when it has one, so related keys share a server
+// 2. Each server has one connection, commands to the same server wait for each other
+pub struct ShardedClient<H: KeyHasher = Fnv1a> {
+	ring: HashRing<H>,
+	connections: HashMap<String, Mutex<Connection>>,
+	timeout: Duration,
+}
+
+impl ShardedClient<Fnv1a> {
+	pub fn new(servers: &[&str]) -> Self {
+		ShardedClient::with_hasher(servers, Fnv1a, ShardOptions::default())
+	}
+}
+
+impl<H: KeyHasher> ShardedClient<H> {
+	pub fn with_hasher(servers: &[&str], hasher: H, options: ShardOptions) -> Self {
+		let mut client = ShardedClient { ring: HashRing::with_hasher(hasher, options.vnodes), connections: HashMap::new(), timeout: options.timeout };
+		for server in servers {
+			client.add_server(server);
+		}
+		client
+	}
+
+	pub fn add_server(&mut self, address: &str) {
+		self.ring.add(address);
+		self.connections.entry(address.to_string()).or_insert_with(|| Mutex::new(Connection::new(address, self.timeout)));
+	}
+
+	pub fn remove_server(&mut self, address: &str) -> bool {
+		self.connections.remove(address);
+		self.ring.remove(address)
+	}
+
+	pub fn servers(&self) -> Vec<&str> {
+		self.ring.servers()
+	}
+
+	pub fn server_for(&self, key: &str) -> Option<&str> {
+		self.ring.server_for(hash_tag(key))
+	}
+
+	// Run `args` on the server owning `key`
+	pub fn command(&self, key: &str, args: &[&str]) -> io::Result<RESPOutput> {
+		let server = self.server_for(key).ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no servers to shard over"))?;
+		self.connections[server].lock().unwrap().command(args)
+	}
+
+	pub fn get(&self, key: &str) -> io::Result<Option<String>> {
+		match self.command(key, &["GET", key])? {
+			RESPOutput::BulkString(value) => Ok(Some(value)),
+			RESPOutput::Null => Ok(None),
+			_ => Err(io::Error::new(io::ErrorKind::InvalidData, "unexpected reply to GET")),
+		}
+	}
+
+	pub fn set(&self, key: &str, value: &str) -> io::Result<()> {
+		self.command(key, &["SET", key, value]).map(|_| ())
+	}
+}
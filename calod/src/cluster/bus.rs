@@ -0,0 +1,122 @@
+use std::io::{self, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::cluster::gossip::{Message, MessageKind};
+use crate::cluster::state::ClusterError;
+use crate::parser::parser::RESPOutput;
+use crate::persistence::aof::{command_record, read_record};
+use crate::sentinel::link::{join_address, query};
+use crate::store::calod_store::CalodStore;
+
+pub const DEFAULT_NODE_TIMEOUT: Duration = Duration::from_secs(15);
+
+// How long CLUSTER MEET waits for the other node to answer
+const MEET_TIMEOUT: Duration = Duration::from_secs(1);
+
+// Nodes are pinged this often, so several pings fit in the node timeout
+fn ping_interval(node_timeout: Duration) -> Duration {
+	(node_timeout / 10).min(Duration::from_secs(1))
+}
+
+// Send one message to the bus at `address` and wait for the PONG
+fn exchange(address: &str, message: &Message, timeout: Duration) -> io::Result<Message> {
+	let args = message.encode();
+	let reply = query(address, &args.iter().map(String::as_str).collect::<Vec<&str>>(), timeout)?;
+	let args = match reply {
+		RESPOutput::Array(args) => args
+			.into_iter()
+			.map(|arg| match arg {
+				RESPOutput::BulkString(arg) => Some(arg),
+				_ => None,
+			})
+			.collect::<Option<Vec<String>>>(),
+		_ => None,
+	};
+	args.as_deref().and_then(Message::decode).ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "unexpected reply on the cluster bus"))
+}
+
+impl CalodStore {
+	// Talk to the other nodes of the cluster on `listener` until the process exits
+	// 1. Every other node is pinged several times per `node_timeout`, each answer carries what it knows about the cluster
+	// 2. A node we and a majority of the nodes serving slots see failing is announced with FAIL
+	pub fn start_cluster_bus(self: &Arc<Self>, listener: TcpListener, node_timeout: Duration) {
+		let store = self.clone();
+		thread::spawn(move || {
+			for stream in listener.incoming().flatten() {
+				let store = store.clone();
+				thread::spawn(move || store.serve_cluster_link(stream));
+			}
+		});
+		let store = self.clone();
+		thread::spawn(move || loop {
+			store.cluster_tick(node_timeout);
+			thread::sleep(ping_interval(node_timeout));
+		});
+	}
+
+	// Answer every message another node sends on `stream` with a PONG
+	pub fn serve_cluster_link(&self, mut stream: TcpStream) {
+		let Ok(reader) = stream.try_clone() else {
+			return;
+		};
+		let mut reader = BufReader::new(reader);
+		let mut consumed = 0;
+		while let Ok(Some(args)) = read_record(&mut reader, &mut consumed) {
+			let Some(message) = Message::decode(&args) else {
+				return;
+			};
+			self.cluster_received(&message);
+			let pong = command_record(self.cluster.message(MessageKind::Pong).encode());
+			if stream.write_all(&pong).is_err() {
+				return;
+			}
+		}
+	}
+
+	// One round of pings and failure detection
+	pub fn cluster_tick(&self, node_timeout: Duration) {
+		let timeout = ping_interval(node_timeout);
+		for node in self.cluster.ping_targets(Instant::now()) {
+			let Some(address) = node.bus_address() else {
+				continue;
+			};
+			if let Ok(pong) = exchange(&address, &self.cluster.message(MessageKind::Ping), timeout) {
+				self.cluster_received(&pong);
+			}
+		}
+
+		let failed = self.cluster.check_failures(node_timeout, Instant::now());
+		if failed.is_empty() {
+			return;
+		}
+		println!("Marked {} as failing", failed.join(", "));
+		let message = self.cluster.fail_message(&failed);
+		for node in self.cluster.nodes() {
+			if node.id == self.cluster.myself().id || failed.contains(&node.id) {
+				continue;
+			}
+			if let Some(address) = node.bus_address() {
+				let _ = exchange(&address, &message, timeout);
+			}
+		}
+	}
+
+	// CLUSTER MEET, introduce ourselves to the node with its bus at `host:bus_port` and learn about it from its answer
+	pub fn cluster_meet(&self, host: &str, bus_port: u16) -> Result<(), ClusterError> {
+		let address = join_address(host, bus_port);
+		let pong = exchange(&address, &self.cluster.message(MessageKind::Meet), MEET_TIMEOUT).map_err(|e| ClusterError::Meet(address, e))?;
+		self.cluster.receive(&pong, Instant::now());
+		self.save_cluster_config()
+	}
+
+	fn cluster_received(&self, message: &Message) {
+		if self.cluster.receive(message, Instant::now()) {
+			if let Err(e) = self.save_cluster_config() {
+				println!("Failed to save the cluster config: {}", e);
+			}
+		}
+	}
+}
@@ -0,0 +1,38 @@
+use std::io::{Read, Write};
+
+use crate::cluster::state::ClusterError;
+use crate::store::calod_store::CalodStore;
+
+pub const DEFAULT_CLUSTER_CONFIG_PATH: &str = "nodes.conf";
+
+impl CalodStore {
+	// Start serving in cluster mode, reachable by clients at `address` and by other nodes on `bus_port`
+	// 1. The node configuration in `path` is loaded when it exists, a new node starts without slots
+	// 2. The file is rewritten on every change of the configuration
+	pub fn enable_cluster(&self, path: &str, address: &str, bus_port: u16) -> Result<(), ClusterError> {
+		let storage = self.storage();
+		if storage.exists(path) {
+			let mut text = String::new();
+			storage.open(path)?.read_to_string(&mut text)?;
+			self.cluster.load_config(&text)?;
+		}
+		self.cluster.set_config_path(path);
+		self.cluster.enable(address);
+		self.cluster.set_bus_port(bus_port);
+		self.save_cluster_config()
+	}
+
+	// Replace the config file through a temporary file so a crash never leaves half of it behind
+	pub fn save_cluster_config(&self) -> Result<(), ClusterError> {
+		let Some(path) = self.cluster.config_path() else {
+			return Ok(());
+		};
+		let storage = self.storage();
+		let temp_path = format!("{}.tmp-{}", path, std::process::id());
+		let mut file = storage.create(&temp_path)?;
+		file.write_all(self.cluster.config_text().as_bytes())?;
+		file.sync()?;
+		storage.rename(&temp_path, &path)?;
+		Ok(())
+	}
+}
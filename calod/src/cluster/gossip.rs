@@ -0,0 +1,247 @@
+use std::collections::BTreeSet;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::cluster::slots::{parse_slot, slot_ranges};
+use crate::cluster::state::{ClusterNode, ClusterState, Failure};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MessageKind {
+	// Introduces the sender to a node that does not know it yet
+	Meet,
+	Ping,
+	// The answer to every message
+	Pong,
+	// The gossiped nodes failed
+	Fail,
+}
+
+impl MessageKind {
+	fn name(&self) -> &'static str {
+		match self {
+			MessageKind::Meet => "MEET",
+			MessageKind::Ping => "PING",
+			MessageKind::Pong => "PONG",
+			MessageKind::Fail => "FAIL",
+		}
+	}
+
+	fn from(name: &str) -> Option<Self> {
+		match name {
+			"MEET" => Some(MessageKind::Meet),
+			"PING" => Some(MessageKind::Ping),
+			"PONG" => Some(MessageKind::Pong),
+			"FAIL" => Some(MessageKind::Fail),
+			_ => None,
+		}
+	}
+}
+
+// Another node as the sender sees it
+#[derive(Debug, Clone, PartialEq)]
+pub struct Gossip {
+	pub id: String,
+	pub address: String,
+	pub bus_port: u16,
+	pub failure: Failure,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Message {
+	pub kind: MessageKind,
+	// The sender with its config epoch
+	pub sender: ClusterNode,
+	pub current_epoch: u64,
+	// Ranges of the slots the sender serves
+	pub slots: Vec<(u16, u16)>,
+	pub gossip: Vec<Gossip>,
+}
+
+impl Message {
+	// One RESP array of bulk strings on the bus
+	// `<kind> <id> <address> <bus port> <config epoch> <current epoch> <slots> [<id> <address> <bus port> <flag>]...`
+	// 1. `slots` is a comma separated list of ranges, `-` for none
+	// 2. The flag of a gossiped node is `-`, `fail?` or `fail`
+	pub fn encode(&self) -> Vec<String> {
+		let slots = match self.slots.is_empty() {
+			true => String::from("-"),
+			false => self.slots.iter().map(|(start, end)| format!("{}-{}", start, end)).collect::<Vec<String>>().join(","),
+		};
+		let mut args = vec![
+			self.kind.name().to_string(),
+			self.sender.id.clone(),
+			self.sender.address.clone(),
+			self.sender.bus_port.to_string(),
+			self.sender.config_epoch.to_string(),
+			self.current_epoch.to_string(),
+			slots,
+		];
+		for gossip in &self.gossip {
+			args.extend([gossip.id.clone(), gossip.address.clone(), gossip.bus_port.to_string(), gossip.failure.flag().unwrap_or("-").to_string()]);
+		}
+		args
+	}
+
+	pub fn decode(args: &[String]) -> Option<Message> {
+		let [kind, id, address, bus_port, config_epoch, current_epoch, slots, gossip @ ..] = args else {
+			return None;
+		};
+		if gossip.len() % 4 != 0 {
+			return None;
+		}
+		let sender = ClusterNode { id: id.clone(), address: address.clone(), bus_port: bus_port.parse().ok()?, config_epoch: config_epoch.parse().ok()? };
+		let slots = match slots.as_str() {
+			"-" => Vec::new(),
+			slots => slots
+				.split(',')
+				.map(|range| {
+					let (start, end) = range.split_once('-')?;
+					Some((parse_slot(start)?, parse_slot(end)?))
+				})
+				.collect::<Option<Vec<(u16, u16)>>>()?,
+		};
+		let gossip = gossip
+			.chunks(4)
+			.map(|fields| {
+				let failure = match fields[3].as_str() {
+					"-" => Failure::Ok,
+					"fail?" => Failure::PFail,
+					"fail" => Failure::Fail,
+					_ => return None,
+				};
+				Some(Gossip { id: fields[0].clone(), address: fields[1].clone(), bus_port: fields[2].parse().ok()?, failure })
+			})
+			.collect::<Option<Vec<Gossip>>>()?;
+		Some(Message { kind: MessageKind::from(kind)?, sender, current_epoch: current_epoch.parse().ok()?, slots, gossip })
+	}
+}
+
+impl ClusterState {
+	// What we tell other nodes: ourselves, our slots and every other node we know
+	pub fn message(&self, kind: MessageKind) -> Message {
+		let view = self.view.read().unwrap();
+		let slots = slot_ranges(view.slots_by_node().remove(view.myself.as_str()).unwrap_or_default());
+		let gossip = view
+			.nodes
+			.values()
+			.filter(|node| node.id != view.myself)
+			.map(|node| Gossip { id: node.id.clone(), address: node.address.clone(), bus_port: node.bus_port, failure: view.failure(&node.id) })
+			.collect();
+		Message { kind, sender: view.nodes[&view.myself].clone(), current_epoch: view.current_epoch, slots, gossip }
+	}
+
+	// FAIL about the nodes in `failed`
+	pub fn fail_message(&self, failed: &[String]) -> Message {
+		let mut message = self.message(MessageKind::Fail);
+		message.gossip.retain(|gossip| failed.contains(&gossip.id));
+		message
+	}
+
+	// Every other node to ping now, a node still owing us a pong keeps the time of the first ping
+	pub fn ping_targets(&self, now: Instant) -> Vec<ClusterNode> {
+		let mut view = self.view.write().unwrap();
+		for health in view.health.values_mut() {
+			health.ping_sent.get_or_insert(now);
+		}
+		view.health.keys().map(|id| view.nodes[id].clone()).collect()
+	}
+
+	// Apply a message from another node, returns whether the configuration changed
+	// 1. Only MEET and the PONG answering it introduce an unknown sender, anything heard from a node proves it is alive
+	// 2. Slots the sender claims with a newer config epoch than their owner's become the sender's, unless we import them
+	// 3. Two nodes with the same config epoch tell their claims apart by the one with the greater ID taking a new epoch
+	// 4. Gossip from a node serving slots counts as its failure report, gossiped nodes we do not know yet are added
+	pub fn receive(&self, message: &Message, now: Instant) -> bool {
+		let mut view = self.view.write().unwrap();
+		let sender = &message.sender;
+		let known = view.nodes.get(&sender.id);
+		if sender.id == view.myself || (known.is_none() && !matches!(message.kind, MessageKind::Meet | MessageKind::Pong)) {
+			return false;
+		}
+		let mut changed = known.is_none_or(|node| node.address != sender.address || node.bus_port != sender.bus_port || node.config_epoch != sender.config_epoch);
+		view.add_node(&sender.id, &sender.address, sender.bus_port);
+		view.nodes.get_mut(&sender.id).unwrap().config_epoch = sender.config_epoch;
+		let health = view.health.get_mut(&sender.id).unwrap();
+		health.ping_sent = None;
+		health.pong_received = Some(now);
+		health.failure = Failure::Ok;
+		if message.current_epoch > view.current_epoch {
+			view.current_epoch = message.current_epoch;
+			changed = true;
+		}
+
+		if message.kind == MessageKind::Fail {
+			for gossip in &message.gossip {
+				if let Some(health) = view.health.get_mut(&gossip.id) {
+					health.failure = Failure::Fail;
+				}
+			}
+			return changed;
+		}
+
+		let claimer: Arc<str> = Arc::from(sender.id.as_str());
+		for slot in message.slots.iter().flat_map(|(start, end)| *start..=*end) {
+			let owner = view.slots[slot as usize].clone();
+			if owner.as_deref() == Some(sender.id.as_str()) || view.importing.contains_key(&slot) {
+				continue;
+			}
+			if owner.is_none_or(|owner| view.nodes[&*owner].config_epoch < sender.config_epoch) {
+				view.slots[slot as usize] = Some(claimer.clone());
+				view.migrating.remove(&slot);
+				changed = true;
+			}
+		}
+
+		let myself = view.myself.clone();
+		if sender.config_epoch == view.nodes[&myself].config_epoch && sender.id < myself {
+			view.current_epoch += 1;
+			let epoch = view.current_epoch;
+			view.nodes.get_mut(&myself).unwrap().config_epoch = epoch;
+			changed = true;
+		}
+
+		let reporter = view.slots.iter().flatten().any(|owner| **owner == *sender.id);
+		for gossip in message.gossip.iter().filter(|gossip| gossip.id != myself) {
+			let Some(health) = view.health.get_mut(&gossip.id) else {
+				view.add_node(&gossip.id, &gossip.address, gossip.bus_port);
+				changed = true;
+				continue;
+			};
+			if !reporter {
+				continue;
+			}
+			match gossip.failure {
+				Failure::Ok => health.reports.remove(&sender.id),
+				_ => health.reports.insert(sender.id.clone(), now),
+			};
+		}
+		changed
+	}
+
+	// Mark the nodes that stopped answering, returns the ones that just failed
+	// 1. A node owing us a pong for longer than `node_timeout` is PFail
+	// 2. It fails once we and the reports of other nodes serving slots make a majority of those nodes
+	// 3. Reports older than twice the node timeout no longer count
+	pub fn check_failures(&self, node_timeout: Duration, now: Instant) -> Vec<String> {
+		let mut view = self.view.write().unwrap();
+		let voters: BTreeSet<String> = view.slots.iter().flatten().map(|owner| owner.to_string()).collect();
+		let needed = voters.len() / 2 + 1;
+		let mut failed = Vec::new();
+		for (id, health) in view.health.iter_mut() {
+			if health.failure == Failure::Ok && health.ping_sent.is_some_and(|sent| now.duration_since(sent) > node_timeout) {
+				health.failure = Failure::PFail;
+			}
+			health.reports.retain(|_, at| now.duration_since(*at) <= node_timeout * 2);
+			let reports = health.reports.keys().filter(|reporter| voters.contains(*reporter)).count();
+			if health.failure == Failure::PFail && reports + 1 >= needed {
+				health.failure = Failure::Fail;
+				failed.push(id.clone());
+			}
+		}
+		failed
+	}
+
+	pub fn failure(&self, id: &str) -> Failure {
+		self.view.read().unwrap().failure(id)
+	}
+}
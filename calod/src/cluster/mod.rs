@@ -0,0 +1,6 @@
+pub mod slots;
+pub mod state;
+pub mod config;
+pub mod rebalance;
+pub mod gossip;
+pub mod bus;
@@ -0,0 +1,256 @@
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::io::{self, BufReader, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+use thiserror::Error;
+
+use crate::cluster::slots::parse_slot;
+use crate::parser::parser::RESPOutput;
+use crate::persistence::aof::command_record;
+use crate::sentinel::link::{read_reply, split_address};
+
+#[derive(Debug, Error)]
+pub enum RebalanceError {
+	#[error("{0}: {1}")]
+	Node(String, io::Error),
+
+	#[error("Unexpected reply from {0}")]
+	BadReply(String),
+
+	#[error("Slot {0} is claimed by both {1} and {2}")]
+	Conflict(u16, String, String),
+
+	#[error("Slot {0} of {1} is still migrating, settle it with CLUSTER SETSLOT first")]
+	OpenSlot(u16, String),
+}
+
+pub struct RebalanceOptions {
+	// Keys moved with one MIGRATE
+	pub pipeline: usize,
+	// Bound on every request, a MIGRATE of a full batch included
+	pub timeout: Duration,
+}
+
+impl Default for RebalanceOptions {
+	fn default() -> Self {
+		RebalanceOptions { pipeline: 10, timeout: Duration::from_secs(60) }
+	}
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SlotMove {
+	pub slot: u16,
+	pub from: String,
+	pub to: String,
+}
+
+// Slots to move so every node serves the same number of slots, give or take one
+// 1. The nodes owning the most slots keep the remainder, so as few slots as possible move
+// 2. Overloaded nodes give away their highest slots, underloaded nodes take them in order of ID
+pub fn plan(owned: &BTreeMap<String, Vec<u16>>) -> Vec<SlotMove> {
+	if owned.is_empty() {
+		return Vec::new();
+	}
+	let total: usize = owned.values().map(Vec::len).sum();
+	let mut by_load: Vec<(&String, &Vec<u16>)> = owned.iter().collect();
+	by_load.sort_by(|(a, a_slots), (b, b_slots)| b_slots.len().cmp(&a_slots.len()).then_with(|| a.cmp(b)));
+	let targets: BTreeMap<&String, usize> =
+		by_load.iter().enumerate().map(|(i, (id, _))| (*id, total / owned.len() + usize::from(i < total % owned.len()))).collect();
+
+	let mut surplus: VecDeque<(u16, &String)> = VecDeque::new();
+	for (id, slots) in owned {
+		let mut slots = slots.clone();
+		slots.sort_unstable();
+		surplus.extend(slots[targets[id].min(slots.len())..].iter().map(|slot| (*slot, id)));
+	}
+
+	let mut moves = Vec::new();
+	for (id, slots) in owned {
+		for _ in slots.len()..targets[id] {
+			let (slot, from) = surplus.pop_front().expect("surplus matches the deficit");
+			moves.push(SlotMove { slot, from: from.clone(), to: id.clone() });
+		}
+	}
+	moves
+}
+
+// Connection to one node, kept for the whole rebalance
+struct NodeLink {
+	address: String,
+	reader: BufReader<TcpStream>,
+	writer: TcpStream,
+}
+
+impl NodeLink {
+	fn connect(address: &str, timeout: Duration) -> Result<Self, RebalanceError> {
+		let failed = |e: io::Error| RebalanceError::Node(address.to_string(), e);
+		let target = address.to_socket_addrs().map_err(failed)?.next().ok_or_else(|| failed(io::Error::new(io::ErrorKind::NotFound, "no address to connect to")))?;
+		let writer = TcpStream::connect_timeout(&target, timeout).map_err(failed)?;
+		writer.set_read_timeout(Some(timeout)).map_err(failed)?;
+		writer.set_write_timeout(Some(timeout)).map_err(failed)?;
+		writer.set_nodelay(true).map_err(failed)?;
+		let reader = BufReader::new(writer.try_clone().map_err(failed)?);
+		Ok(NodeLink { address: address.to_string(), reader, writer })
+	}
+
+	fn call(&mut self, args: &[&str]) -> Result<RESPOutput, RebalanceError> {
+		let sent = self.writer.write_all(&command_record(args.iter().map(|arg| arg.to_string()).collect()));
+		sent.and_then(|_| read_reply(&mut self.reader)).map_err(|e| RebalanceError::Node(self.address.clone(), e))
+	}
+
+	fn text(&mut self, args: &[&str]) -> Result<String, RebalanceError> {
+		match self.call(args)? {
+			RESPOutput::BulkString(text) => Ok(text),
+			_ => Err(RebalanceError::BadReply(self.address.clone())),
+		}
+	}
+}
+
+// What CLUSTER NODES of one node tells about the cluster
+struct NodeView {
+	id: String,
+	link: NodeLink,
+	// Every node it knows with its address, itself included
+	known: BTreeMap<String, String>,
+	// The owner of every assigned slot as far as it knows
+	owners: BTreeMap<u16, String>,
+}
+
+impl NodeView {
+	fn load(mut link: NodeLink) -> Result<Self, RebalanceError> {
+		let text = link.text(&["CLUSTER", "NODES"])?;
+		let (mut myself, mut known, mut owners) = (None, BTreeMap::new(), BTreeMap::new());
+		for line in text.lines() {
+			let fields: Vec<&str> = line.split_whitespace().collect();
+			if fields.len() < 8 {
+				continue;
+			}
+			let id = fields[0].to_string();
+			known.insert(id.clone(), fields[1].split('@').next().unwrap_or_default().to_string());
+			let is_myself = fields[2].split(',').any(|flag| flag == "myself");
+			if is_myself {
+				myself = Some(id.clone());
+			}
+
+			for slots in &fields[8..] {
+				if slots.starts_with('[') {
+					let slot = slots.trim_matches(['[', ']']).split('-').next().and_then(parse_slot).unwrap_or_default();
+					return Err(RebalanceError::OpenSlot(slot, link.address));
+				}
+				let (start, end) = slots.split_once('-').unwrap_or((slots, slots));
+				let (Some(start), Some(end)) = (parse_slot(start), parse_slot(end)) else {
+					return Err(RebalanceError::BadReply(link.address));
+				};
+				owners.extend((start..=end).map(|slot| (slot, id.clone())));
+			}
+		}
+		let id = myself.ok_or_else(|| RebalanceError::BadReply(link.address.clone()))?;
+		Ok(NodeView { id, link, known, owners })
+	}
+
+	// The slots it serves, each node is the authority on its own slots
+	fn claimed(&self) -> Vec<u16> {
+		self.owners.iter().filter(|(_, owner)| **owner == self.id).map(|(slot, _)| *slot).collect()
+	}
+
+	fn ok(&mut self, args: &[&str]) -> Result<(), RebalanceError> {
+		self.link.call(args).map(|_| ())
+	}
+}
+
+// Every node reachable from `seeds`, following the nodes each one knows
+fn discover(seeds: &[String], timeout: Duration) -> Result<BTreeMap<String, NodeView>, RebalanceError> {
+	let mut nodes: BTreeMap<String, NodeView> = BTreeMap::new();
+	let mut visited = BTreeSet::new();
+	let mut pending: VecDeque<String> = seeds.iter().cloned().collect();
+	while let Some(address) = pending.pop_front() {
+		if !visited.insert(address.clone()) {
+			continue;
+		}
+		let view = NodeView::load(NodeLink::connect(&address, timeout)?)?;
+		pending.extend(view.known.values().filter(|address| !address.is_empty()).cloned());
+		nodes.entry(view.id.clone()).or_insert(view);
+	}
+	Ok(nodes)
+}
+
+// Make every node know every other node and the owner of every slot
+fn introduce(nodes: &mut BTreeMap<String, NodeView>) -> Result<(), RebalanceError> {
+	let mut owners: BTreeMap<u16, String> = BTreeMap::new();
+	for node in nodes.values() {
+		for slot in node.claimed() {
+			if let Some(other) = owners.insert(slot, node.id.clone()) {
+				return Err(RebalanceError::Conflict(slot, other, node.id.clone()));
+			}
+		}
+	}
+	let addresses: Vec<(String, String)> = nodes.values().map(|node| (node.id.clone(), node.link.address.clone())).collect();
+
+	for node in nodes.values_mut() {
+		for (id, address) in &addresses {
+			if node.known.contains_key(id) {
+				continue;
+			}
+			let (host, port) = split_address(address).ok_or_else(|| RebalanceError::BadReply(address.clone()))?;
+			node.ok(&["CLUSTER", "MEET", &host, &port.to_string()])?;
+		}
+		for (slot, owner) in &owners {
+			if node.owners.get(slot) != Some(owner) {
+				node.ok(&["CLUSTER", "SETSLOT", &slot.to_string(), "NODE", owner])?;
+			}
+		}
+	}
+	Ok(())
+}
+
+// Move one slot while it keeps being served
+// 1. The target imports the slot and the source migrates it, clients are sent to the target with ASK for keys that left already
+// 2. Keys move in batches of `pipeline` until the source has none left in the slot
+// 3. The target claims the slot first, then the source and every other node learn the new owner
+fn move_slot(nodes: &mut BTreeMap<String, NodeView>, slot_move: &SlotMove, options: &RebalanceOptions) -> Result<(), RebalanceError> {
+	let slot = slot_move.slot.to_string();
+	let (from, to) = (slot_move.from.as_str(), slot_move.to.as_str());
+	let (host, port) = split_address(&nodes[to].link.address).ok_or_else(|| RebalanceError::BadReply(nodes[to].link.address.clone()))?;
+	let (port, timeout, pipeline) = (port.to_string(), options.timeout.as_millis().to_string(), options.pipeline.to_string());
+
+	nodes.get_mut(to).unwrap().ok(&["CLUSTER", "SETSLOT", &slot, "IMPORTING", from])?;
+	let source = nodes.get_mut(from).unwrap();
+	source.ok(&["CLUSTER", "SETSLOT", &slot, "MIGRATING", to])?;
+	loop {
+		let keys = match source.link.call(&["CLUSTER", "GETKEYSINSLOT", &slot, &pipeline])? {
+			RESPOutput::Array(keys) => keys,
+			_ => return Err(RebalanceError::BadReply(source.link.address.clone())),
+		};
+		if keys.is_empty() {
+			break;
+		}
+		let mut args = vec!["MIGRATE", &host, &port, "", "0", &timeout, "KEYS"];
+		args.extend(keys.iter().filter_map(|key| match key {
+			RESPOutput::BulkString(key) => Some(key.as_str()),
+			_ => None,
+		}));
+		source.ok(&args)?;
+	}
+
+	let order = [to.to_string(), from.to_string()].into_iter().chain(nodes.keys().filter(|id| *id != to && *id != from).cloned()).collect::<Vec<String>>();
+	for id in order {
+		nodes.get_mut(&id).unwrap().ok(&["CLUSTER", "SETSLOT", &slot, "NODE", to])?;
+	}
+	Ok(())
+}
+
+// Spread the slots evenly over every node reachable from `seeds`, returns the slots moved
+// 1. Nodes are discovered through CLUSTER NODES and introduced to each other with CLUSTER MEET
+// 2. Nodes without slots take part, so adding a node and rebalancing fills it
+// 3. Nothing moves while a slot is still migrating from an earlier run
+pub fn rebalance(seeds: &[String], options: &RebalanceOptions) -> Result<Vec<SlotMove>, RebalanceError> {
+	let mut nodes = discover(seeds, options.timeout)?;
+	introduce(&mut nodes)?;
+	let owned: BTreeMap<String, Vec<u16>> = nodes.values().map(|node| (node.id.clone(), node.claimed())).collect();
+	let moves = plan(&owned);
+	for slot_move in &moves {
+		move_slot(&mut nodes, slot_move, options)?;
+	}
+	Ok(moves)
+}
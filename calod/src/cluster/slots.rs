@@ -0,0 +1,47 @@
+// Keys are spread over this many hash slots, nodes own ranges of slots
+pub const SLOT_COUNT: usize = 16384;
+
+// CRC16/XMODEM, the checksum Redis Cluster hashes keys with
+pub fn crc16(bytes: &[u8]) -> u16 {
+	let mut crc: u16 = 0;
+	for byte in bytes {
+		crc ^= (*byte as u16) << 8;
+		for _ in 0..8 {
+			crc = if crc & 0x8000 != 0 { (crc << 1) ^ 0x1021 } else { crc << 1 };
+		}
+	}
+	crc
+}
+
+// The part of a key that is hashed
+// 1. A non-empty `{hashtag}` is hashed instead of the whole key so related keys can share a slot
+// 2. Only the first `{` and the first `}` after it count
+pub fn hash_tag(key: &str) -> &[u8] {
+	let bytes = key.as_bytes();
+	let tag = bytes.iter().position(|byte| *byte == b'{').and_then(|open| {
+		let close = bytes[open + 1..].iter().position(|byte| *byte == b'}')?;
+		Some(&bytes[open + 1..open + 1 + close]).filter(|tag| !tag.is_empty())
+	});
+	tag.unwrap_or(bytes)
+}
+
+// Hash slot of a key
+pub fn key_slot(key: &str) -> u16 {
+	crc16(hash_tag(key)) % SLOT_COUNT as u16
+}
+
+pub fn parse_slot(slot: &str) -> Option<u16> {
+	slot.parse::<u16>().ok().filter(|slot| (*slot as usize) < SLOT_COUNT)
+}
+
+// Collapse sorted slots into inclusive ranges
+pub fn slot_ranges(slots: impl IntoIterator<Item = u16>) -> Vec<(u16, u16)> {
+	let mut ranges: Vec<(u16, u16)> = Vec::new();
+	for slot in slots {
+		match ranges.last_mut() {
+			Some((_, end)) if *end + 1 == slot => *end = slot,
+			_ => ranges.push((slot, slot)),
+		}
+	}
+	ranges
+}
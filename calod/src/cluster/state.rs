@@ -0,0 +1,533 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::Instant;
+
+use thiserror::Error;
+
+use crate::cluster::slots::{key_slot, parse_slot, slot_ranges, SLOT_COUNT};
+use crate::replication::state::new_replid;
+use crate::sentinel::link::{join_address, split_address};
+
+// Nodes listen for each other on their client port plus this, unless told otherwise
+pub const BUS_PORT_OFFSET: u16 = 10000;
+
+#[derive(Debug, Error)]
+pub enum ClusterError {
+	#[error("Cluster config I/O error: {0}")]
+	Io(#[from] io::Error),
+
+	#[error("Invalid cluster config at line {0}: {1}")]
+	BadConfig(usize, String),
+
+	#[error("Slot {0} is already busy")]
+	SlotBusy(u16),
+
+	#[error("Slot {0} is already unassigned")]
+	SlotUnassigned(u16),
+
+	#[error("Unknown node {0}")]
+	UnknownNode(String),
+
+	#[error("I'm not the owner of hash slot {0}")]
+	NotOwner(u16),
+
+	#[error("I'm already the owner of hash slot {0}")]
+	AlreadyOwner(u16),
+
+	#[error("Can't assign hashslot {0} to a different node while I still hold keys for this hash slot")]
+	SlotNotEmpty(u16),
+
+	#[error("Failed to meet {0}: {1}")]
+	Meet(String, io::Error),
+}
+
+// Why a command is not run on this node, sent to the client as the error reply
+#[derive(Debug, Error, PartialEq)]
+pub enum RouteError {
+	#[error("MOVED {0} {1}")]
+	Moved(u16, String),
+
+	#[error("ASK {0} {1}")]
+	Ask(u16, String),
+
+	#[error("CROSSSLOT Keys in request don't hash to the same slot")]
+	CrossSlot,
+
+	#[error("TRYAGAIN Multiple keys request during rehashing of slot")]
+	TryAgain,
+
+	#[error("CLUSTERDOWN Hash slot not served")]
+	Unassigned,
+
+	#[error("CLUSTERDOWN The cluster is down")]
+	Down,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClusterNode {
+	pub id: String,
+	// `host:port` clients reach the node on
+	pub address: String,
+	// Port of the cluster bus on the same host
+	pub bus_port: u16,
+	pub config_epoch: u64,
+}
+
+impl ClusterNode {
+	pub(crate) fn new(id: &str, address: &str, bus_port: u16) -> Self {
+		ClusterNode { id: id.to_string(), address: address.to_string(), bus_port, config_epoch: 0 }
+	}
+
+	pub fn bus_address(&self) -> Option<String> {
+		let (host, _) = split_address(&self.address)?;
+		Some(join_address(&host, self.bus_port))
+	}
+}
+
+// The bus port of a node that did not tell us its own
+pub fn default_bus_port(address: &str) -> u16 {
+	split_address(address).and_then(|(_, port)| port.checked_add(BUS_PORT_OFFSET)).unwrap_or(0)
+}
+
+// How a node looks from here
+// 1. PFail: it did not answer our pings within the node timeout
+// 2. Fail: a majority of the nodes serving slots agreed it is down
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum Failure {
+	#[default]
+	Ok,
+	PFail,
+	Fail,
+}
+
+impl Failure {
+	pub fn flag(&self) -> Option<&'static str> {
+		match self {
+			Failure::Ok => None,
+			Failure::PFail => Some("fail?"),
+			Failure::Fail => Some("fail"),
+		}
+	}
+}
+
+// What we know about the link to another node
+#[derive(Debug, Default)]
+pub(crate) struct Health {
+	// Sending time of the oldest ping still waiting for an answer
+	pub(crate) ping_sent: Option<Instant>,
+	pub(crate) pong_received: Option<Instant>,
+	pub(crate) failure: Failure,
+	// Nodes that told us they see this one failing, and when they last did
+	pub(crate) reports: BTreeMap<String, Instant>,
+}
+
+#[derive(Debug)]
+pub(crate) struct ClusterView {
+	pub(crate) myself: String,
+	pub(crate) nodes: BTreeMap<String, ClusterNode>,
+	// Owner of every slot, by node ID
+	pub(crate) slots: Vec<Option<Arc<str>>>,
+	// Slots we own that are moving to another node, and slots another node is moving to us
+	pub(crate) migrating: BTreeMap<u16, String>,
+	pub(crate) importing: BTreeMap<u16, String>,
+	pub(crate) current_epoch: u64,
+	// Every other node, kept apart from the configuration since it is never saved
+	pub(crate) health: BTreeMap<String, Health>,
+}
+
+impl ClusterView {
+	fn new(myself: ClusterNode) -> Self {
+		ClusterView {
+			myself: myself.id.clone(),
+			nodes: BTreeMap::from([(myself.id.clone(), myself)]),
+			slots: vec![None; SLOT_COUNT],
+			migrating: BTreeMap::new(),
+			importing: BTreeMap::new(),
+			current_epoch: 0,
+			health: BTreeMap::new(),
+		}
+	}
+
+	pub(crate) fn failure(&self, id: &str) -> Failure {
+		self.health.get(id).map_or(Failure::Ok, |health| health.failure)
+	}
+
+	// Learn about another node or its new address
+	pub(crate) fn add_node(&mut self, id: &str, address: &str, bus_port: u16) {
+		let node = self.nodes.entry(id.to_string()).or_insert_with(|| ClusterNode::new(id, address, bus_port));
+		node.address = address.to_string();
+		node.bus_port = bus_port;
+		if id != self.myself {
+			self.health.entry(id.to_string()).or_default();
+		}
+	}
+
+	fn address_of(&self, id: &str) -> String {
+		self.nodes.get(id).map(|node| node.address.clone()).unwrap_or_default()
+	}
+
+	pub(crate) fn owns(&self, slot: u16) -> bool {
+		self.slots[slot as usize].as_deref() == Some(self.myself.as_str())
+	}
+
+	fn known(&self, id: &str) -> Result<Arc<str>, ClusterError> {
+		match self.nodes.get(id) {
+			Some(node) => Ok(Arc::from(node.id.as_str())),
+			None => Err(ClusterError::UnknownNode(id.to_string())),
+		}
+	}
+
+	// Slots of every node that owns at least one
+	pub(crate) fn slots_by_node(&self) -> BTreeMap<&str, Vec<u16>> {
+		let mut owned: BTreeMap<&str, Vec<u16>> = BTreeMap::new();
+		for (slot, owner) in self.slots.iter().enumerate() {
+			if let Some(owner) = owner {
+				owned.entry(owner).or_default().push(slot as u16);
+			}
+		}
+		owned
+	}
+}
+
+// Cluster mode of a store, the slots every node owns and the slots moving between nodes
+// 1. Disabled until `enable`, a disabled store serves every key
+// 2. Configuration is kept in the format of CLUSTER NODES so the file and the command agree
+#[derive(Debug)]
+pub struct ClusterState {
+	enabled: AtomicBool,
+	pub(crate) view: RwLock<ClusterView>,
+	config_path: RwLock<Option<String>>,
+}
+
+impl Default for ClusterState {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+impl ClusterState {
+	pub fn new() -> Self {
+		let myself = ClusterNode::new(&new_replid(), "", 0);
+		ClusterState { enabled: AtomicBool::new(false), view: RwLock::new(ClusterView::new(myself)), config_path: RwLock::new(None) }
+	}
+
+	pub fn is_enabled(&self) -> bool {
+		self.enabled.load(Ordering::SeqCst)
+	}
+
+	// Serve only the slots we own from now on, `address` is where clients are redirected to reach us
+	pub fn enable(&self, address: &str) {
+		self.set_address(address);
+		self.enabled.store(true, Ordering::SeqCst);
+	}
+
+	pub fn config_path(&self) -> Option<String> {
+		self.config_path.read().unwrap().clone()
+	}
+
+	pub fn set_config_path(&self, path: &str) {
+		*self.config_path.write().unwrap() = Some(path.to_string());
+	}
+
+	pub fn myself(&self) -> ClusterNode {
+		let view = self.view.read().unwrap();
+		view.nodes[&view.myself].clone()
+	}
+
+	pub fn nodes(&self) -> Vec<ClusterNode> {
+		self.view.read().unwrap().nodes.values().cloned().collect()
+	}
+
+	// Learn about another node or its new address, its bus port follows the client port
+	pub fn add_node(&self, id: &str, address: &str) {
+		self.view.write().unwrap().add_node(id, address, default_bus_port(address));
+	}
+
+	pub fn owner(&self, slot: u16) -> Option<ClusterNode> {
+		let view = self.view.read().unwrap();
+		view.slots[slot as usize].as_deref().map(|owner| view.nodes[owner].clone())
+	}
+
+	// CLUSTER ADDSLOTS, nothing is assigned unless every slot was free
+	pub fn add_slots(&self, slots: &[u16]) -> Result<(), ClusterError> {
+		let mut view = self.view.write().unwrap();
+		if let Some(slot) = slots.iter().find(|slot| view.slots[**slot as usize].is_some()) {
+			return Err(ClusterError::SlotBusy(*slot));
+		}
+		let myself: Arc<str> = Arc::from(view.myself.as_str());
+		for slot in slots {
+			view.slots[*slot as usize] = Some(myself.clone());
+		}
+		Ok(())
+	}
+
+	// CLUSTER DELSLOTS, nothing is removed unless every slot was assigned
+	pub fn del_slots(&self, slots: &[u16]) -> Result<(), ClusterError> {
+		let mut view = self.view.write().unwrap();
+		if let Some(slot) = slots.iter().find(|slot| view.slots[**slot as usize].is_none()) {
+			return Err(ClusterError::SlotUnassigned(*slot));
+		}
+		for slot in slots {
+			view.slots[*slot as usize] = None;
+			view.migrating.remove(slot);
+			view.importing.remove(slot);
+		}
+		Ok(())
+	}
+
+	// Record that `slot` is served by the node `id`
+	pub fn assign_slot(&self, slot: u16, id: &str) -> Result<(), ClusterError> {
+		let mut view = self.view.write().unwrap();
+		view.slots[slot as usize] = Some(view.known(id)?);
+		Ok(())
+	}
+
+	// Keys of one of our slots are moving to `id`, missing ones are looked up there with ASK
+	pub fn set_migrating(&self, slot: u16, id: &str) -> Result<(), ClusterError> {
+		let mut view = self.view.write().unwrap();
+		let target = view.known(id)?;
+		if !view.owns(slot) {
+			return Err(ClusterError::NotOwner(slot));
+		}
+		view.migrating.insert(slot, target.to_string());
+		Ok(())
+	}
+
+	// Keys of a slot of `id` are moving to us, clients that sent ASKING may use them already
+	pub fn set_importing(&self, slot: u16, id: &str) -> Result<(), ClusterError> {
+		let mut view = self.view.write().unwrap();
+		let source = view.known(id)?;
+		if view.owns(slot) {
+			return Err(ClusterError::AlreadyOwner(slot));
+		}
+		view.importing.insert(slot, source.to_string());
+		Ok(())
+	}
+
+	// CLUSTER SETSLOT STABLE, give up a migration of `slot` in either direction
+	pub fn set_stable(&self, slot: u16) {
+		let mut view = self.view.write().unwrap();
+		view.migrating.remove(&slot);
+		view.importing.remove(&slot);
+	}
+
+	// CLUSTER SETSLOT NODE, `id` serves `slot` from now on
+	// 1. We only hand one of our slots to another node once none of its keys are left here
+	// 2. A node that finishes importing a slot claims it with a new config epoch, so its claim wins over older ones
+	pub fn set_slot_node(&self, slot: u16, id: &str, holds_keys: bool) -> Result<(), ClusterError> {
+		let mut view = self.view.write().unwrap();
+		let owner = view.known(id)?;
+		let to_myself = *owner == *view.myself;
+		if view.owns(slot) && !to_myself && holds_keys {
+			return Err(ClusterError::SlotNotEmpty(slot));
+		}
+
+		if to_myself && view.importing.remove(&slot).is_some() {
+			view.current_epoch += 1;
+			let (epoch, myself) = (view.current_epoch, view.myself.clone());
+			view.nodes.get_mut(&myself).unwrap().config_epoch = epoch;
+		}
+		if !to_myself {
+			view.migrating.remove(&slot);
+		}
+		view.slots[slot as usize] = Some(owner);
+		Ok(())
+	}
+
+	// Decide whether a command on `keys` runs here
+	// 1. Every key has to hash to the same slot
+	// 2. A slot of another node is answered with MOVED, unless we import it and the client sent ASKING
+	// 3. In a slot we migrate away, keys that are gone already are answered with ASK
+	pub fn route(&self, keys: &[&str], asking: bool, exists: impl Fn(&str) -> bool) -> Result<(), RouteError> {
+		if !self.is_enabled() || keys.is_empty() {
+			return Ok(());
+		}
+		let slot = key_slot(keys[0]);
+		if keys[1..].iter().any(|key| key_slot(key) != slot) {
+			return Err(RouteError::CrossSlot);
+		}
+
+		let view = self.view.read().unwrap();
+		let owner = view.slots[slot as usize].as_deref().ok_or(RouteError::Unassigned)?;
+		if view.failure(owner) == Failure::Fail {
+			return Err(RouteError::Down);
+		}
+		if owner != view.myself {
+			if asking && view.importing.contains_key(&slot) {
+				return Ok(());
+			}
+			return Err(RouteError::Moved(slot, view.address_of(owner)));
+		}
+
+		if let Some(target) = view.migrating.get(&slot) {
+			let present = keys.iter().filter(|key| exists(key)).count();
+			if present == 0 {
+				return Err(RouteError::Ask(slot, view.address_of(target)));
+			}
+			if present < keys.len() {
+				return Err(RouteError::TryAgain);
+			}
+		}
+		Ok(())
+	}
+
+	// CLUSTER SLOTS, every range of slots with the node serving it
+	pub fn slot_table(&self) -> Vec<(u16, u16, ClusterNode)> {
+		let view = self.view.read().unwrap();
+		let mut table: Vec<(u16, u16, ClusterNode)> = view
+			.slots_by_node()
+			.into_iter()
+			.flat_map(|(owner, slots)| slot_ranges(slots).into_iter().map(move |(start, end)| (start, end, owner)))
+			.map(|(start, end, owner)| (start, end, view.nodes[owner].clone()))
+			.collect();
+		table.sort_by_key(|(start, _, _)| *start);
+		table
+	}
+
+	// CLUSTER NODES, one line per node
+	// `<id> <host:port@bus port> <flags> <leader> <ping sent> <pong received> <config epoch> <link state> <slots>...`
+	pub fn nodes_text(&self) -> String {
+		let view = self.view.read().unwrap();
+		let owned = view.slots_by_node();
+		let mut text = String::new();
+		for node in view.nodes.values() {
+			let mut flags = String::from(if node.id == view.myself { "myself,master" } else { "master" });
+			let failure = view.failure(&node.id);
+			if let Some(flag) = failure.flag() {
+				flags.push(',');
+				flags.push_str(flag);
+			}
+			let link = if failure == Failure::Ok { "connected" } else { "disconnected" };
+			text.push_str(&format!("{} {}@{} {} - 0 0 {} {}", node.id, node.address, node.bus_port, flags, node.config_epoch, link));
+			for (start, end) in slot_ranges(owned.get(node.id.as_str()).cloned().unwrap_or_default()) {
+				match start == end {
+					true => text.push_str(&format!(" {}", start)),
+					false => text.push_str(&format!(" {}-{}", start, end)),
+				}
+			}
+			if node.id == view.myself {
+				for (slot, target) in &view.migrating {
+					text.push_str(&format!(" [{}->-{}]", slot, target));
+				}
+				for (slot, source) in &view.importing {
+					text.push_str(&format!(" [{}-<-{}]", slot, source));
+				}
+			}
+			text.push('\n');
+		}
+		text
+	}
+
+	// CLUSTER INFO
+	pub fn info_text(&self) -> String {
+		let view = self.view.read().unwrap();
+		let assigned = view.slots.iter().filter(|owner| owner.is_some()).count();
+		let size = view.slots.iter().flatten().collect::<BTreeSet<&Arc<str>>>().len();
+		let failing = |failure: Failure| view.slots.iter().flatten().filter(|owner| view.failure(owner) == failure).count();
+		let (pfail, fail) = (failing(Failure::PFail), failing(Failure::Fail));
+		let state = if assigned == SLOT_COUNT && fail == 0 { "ok" } else { "fail" };
+		[
+			format!("cluster_state:{}", state),
+			format!("cluster_slots_assigned:{}", assigned),
+			format!("cluster_slots_ok:{}", assigned - pfail - fail),
+			format!("cluster_slots_pfail:{}", pfail),
+			format!("cluster_slots_fail:{}", fail),
+			format!("cluster_known_nodes:{}", view.nodes.len()),
+			format!("cluster_size:{}", size),
+			format!("cluster_current_epoch:{}", view.current_epoch),
+			format!("cluster_my_epoch:{}", view.nodes[&view.myself].config_epoch),
+		]
+		.iter()
+		.map(|line| format!("{}\r\n", line))
+		.collect()
+	}
+
+	// Contents of the cluster config file, CLUSTER NODES followed by the current epoch
+	pub fn config_text(&self) -> String {
+		format!("{}vars currentEpoch {}\n", self.nodes_text(), self.view.read().unwrap().current_epoch)
+	}
+
+	// Replace the configuration with one written by `config_text`
+	pub fn load_config(&self, text: &str) -> Result<(), ClusterError> {
+		let mut myself = None;
+		let mut nodes = BTreeMap::new();
+		let mut owned = Vec::new();
+		let mut current_epoch = 0;
+
+		for (number, line) in text.lines().enumerate().filter(|(_, line)| !line.trim().is_empty()) {
+			let bad = |reason: &str| ClusterError::BadConfig(number + 1, reason.to_string());
+			let fields: Vec<&str> = line.split_whitespace().collect();
+			if fields[0] == "vars" {
+				if let Some(epoch) = fields.windows(2).find(|pair| pair[0] == "currentEpoch").map(|pair| pair[1]) {
+					current_epoch = epoch.parse().map_err(|_| bad("invalid current epoch"))?;
+				}
+				continue;
+			}
+			if fields.len() < 8 {
+				return Err(bad("too few fields"));
+			}
+
+			let id = fields[0].to_string();
+			let (address, bus_port) = fields[1].split_once('@').ok_or_else(|| bad("missing bus port"))?;
+			let bus_port = bus_port.parse().map_err(|_| bad("invalid bus port"))?;
+			let config_epoch = fields[6].parse().map_err(|_| bad("invalid config epoch"))?;
+			if fields[2].split(',').any(|flag| flag == "myself") {
+				myself = Some(id.clone());
+			}
+			for slots in &fields[8..] {
+				owned.push((number + 1, id.clone(), slots.to_string()));
+			}
+			nodes.insert(id.clone(), ClusterNode { id, address: address.to_string(), bus_port, config_epoch });
+		}
+
+		let myself = myself.ok_or(ClusterError::BadConfig(0, String::from("no node is flagged myself")))?;
+		let mut view = ClusterView::new(nodes[&myself].clone());
+		view.health = nodes.keys().filter(|id| **id != myself).map(|id| (id.clone(), Health::default())).collect();
+		view.nodes = nodes;
+		view.current_epoch = current_epoch;
+		for (number, id, slots) in owned {
+			let bad = || ClusterError::BadConfig(number, format!("invalid slots `{}`", slots));
+			if let Some(migration) = slots.strip_prefix('[').and_then(|slots| slots.strip_suffix(']')) {
+				let (slot, direction, other) = match (migration.split_once("->-"), migration.split_once("-<-")) {
+					(Some((slot, target)), _) => (slot, &mut view.migrating, target),
+					(_, Some((slot, source))) => (slot, &mut view.importing, source),
+					_ => return Err(bad()),
+				};
+				direction.insert(parse_slot(slot).ok_or_else(bad)?, other.to_string());
+				continue;
+			}
+			let (start, end) = slots.split_once('-').unwrap_or((&slots, &slots));
+			let (start, end) = (parse_slot(start).ok_or_else(bad)?, parse_slot(end).ok_or_else(bad)?);
+			let owner: Arc<str> = Arc::from(id.as_str());
+			for slot in start..=end {
+				view.slots[slot as usize] = Some(owner.clone());
+			}
+		}
+
+		// The addresses we were enabled with win over the ones in the file
+		let current = self.myself();
+		*self.view.write().unwrap() = view;
+		if !current.address.is_empty() {
+			self.set_address(&current.address);
+			self.set_bus_port(current.bus_port);
+		}
+		Ok(())
+	}
+
+	// Listen for other nodes on `port` instead of the client port plus `BUS_PORT_OFFSET`
+	pub fn set_bus_port(&self, port: u16) {
+		let mut view = self.view.write().unwrap();
+		let myself = view.myself.clone();
+		view.nodes.get_mut(&myself).unwrap().bus_port = port;
+	}
+
+	fn set_address(&self, address: &str) {
+		let mut view = self.view.write().unwrap();
+		let myself = view.myself.clone();
+		let node = view.nodes.get_mut(&myself).unwrap();
+		node.address = address.to_string();
+		node.bus_port = default_bus_port(address);
+	}
+}
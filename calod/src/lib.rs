@@ -1,16 +1,24 @@
-use std::convert::TryInto;
-use std::io::{Error, ErrorKind, Read, Write};
+use std::io::{Read, Write};
 use std::net::TcpStream;
+use std::sync::Arc;
 
 pub mod request_response;
 pub mod store;
 pub mod parser;
+pub mod persistence;
+pub mod replication;
+pub mod sentinel;
+pub mod cluster;
+pub mod client;
 
 use crate::request_response::client_input::HandleClientInput;
 use request_response::client_input::ClientInput;
 use crate::parser::parser::{ParseError, RESPOutput};
 use crate::request_response::command::Command;
+use crate::request_response::response_helper;
 use crate::request_response::parsed_command::ParsedCommand;
+use crate::persistence::aof::aof_record;
+use crate::store::calod_store::CalodStore;
 
 #[derive(Debug, PartialEq)]
 pub enum AppError {
@@ -18,6 +26,8 @@ pub enum AppError {
 	ParseError(String),
 	IncompleteInput(String),
 	Error(String),
+	// PSYNC, the connection is handed over to replication
+	ReplicaSync(Vec<String>),
 }
 
 impl From<ParseError> for AppError {
@@ -26,86 +36,114 @@ impl From<ParseError> for AppError {
 			ParseError::InvalidInput => AppError::ParseError(String::from("Invalid Input")),
 			ParseError::CRLFNotFound => AppError::ParseError(String::from("CRLF is not found")),
 			ParseError::UnrecognizedSymbol => AppError::ParseError(String::from("Unrecognized symbol")),
-			ParseError::IncompleteInput => AppError::ParseError(String::from("Incomplete input"))
+			// The rest of the request has not arrived yet
+			ParseError::IncompleteInput => AppError::IncompleteInput(String::from("Incomplete input"))
 		}
 	}
 }
 
-pub fn handle_connection(mut stream: TcpStream) {
-	let mut client_input = ClientInput::new();
+// Serve one client until it disconnects, every command runs against `store`
+pub fn handle_connection(mut stream: TcpStream, store: Arc<CalodStore>) {
+	let mut client_input = ClientInput::new(store);
 	loop {
-		let result = handle_connection_helper(&mut stream, &mut client_input);
-		if result.is_err() {
-			let error = result.unwrap_err();
-			if let AppError::IncompleteInput(_) = error {
-				println!("Incomplete input. Waiting for more input.");
-				continue;
-			}
-
-			println!("Error: {:?}", error);
-
+		if let Err(error) = handle_connection_helper(&mut stream, &mut client_input) {
 			match error {
 				AppError::ParseError(e) | AppError::Error(e) => {
 					client_input.respond_error(&mut stream, e.as_str());
 					break;
 				},
 				AppError::ConnectionClosed(_) => { break; },
-				_ => {}
+				AppError::ReplicaSync(args) => {
+					if let Err(e) = client_input.store().serve_replica(&mut stream, &args) {
+						eprintln!("Replication stream error: {}", e);
+					}
+					break;
+				},
+				AppError::IncompleteInput(_) => {},
 			}
 		}
 	}
 }
 
+// Read once from the client and run every complete request buffered so far
+// `IncompleteInput` is returned once the buffer holds no further complete request
 pub fn handle_connection_helper<T: Read + Write + Send>(mut stream: T, client_input: &mut ClientInput) -> Result<(), AppError> {
-	let mut buffer: [u8, 1024] = [0; 1024];
-
-	match stream.read(&mut buffer) {
-		Ok(size) => {
-			println!("Read {} bytes from input", size);
-
-			if size == 0 {
-				return Err(AppError::ConnectionClosed(String::from("Connection closed")));
-			}
+	let mut buffer = [0u8; 1024];
+	let size = stream.read(&mut buffer).map_err(|e| AppError::Error(e.to_string()))?;
+	if size == 0 {
+		return Err(AppError::ConnectionClosed(String::from("Connection closed")));
+	}
 
-			let parsed = client_input.parse_input(&buffer[...size])?;
-			let parsed_command = resp_output_to_parsed_command(&parsed);
+	let mut input = &buffer[..size];
+	loop {
+		let parsed = client_input.parse_input(input)?;
+		input = &[];
+		handle_request(&mut stream, client_input, &parsed)?;
+	}
+}
 
-			client_input.respond(&mut stream, parsed_command);
-			client_input.reset();
-			Ok(())
+fn handle_request<T: Write>(stream: &mut T, client_input: &mut ClientInput, parsed: &RESPOutput) -> Result<(), AppError> {
+	let parsed_command = resp_output_to_parsed_command(parsed);
+	if parsed_command.command() == &Some(Command::PSYNC) {
+		return Err(AppError::ReplicaSync(parsed_command.args().clone()));
+	}
+	if parsed_command.command() == &Some(Command::ASKING) {
+		client_input.set_asking();
+		response_helper::send_simple_string_response(stream, "OK");
+		return Ok(());
+	}
+	if parsed_command.command() == &Some(Command::SELECT) {
+		client_input.select(stream, parsed_command.args());
+		return Ok(());
+	}
+	let record = aof_record(parsed, parsed_command.command());
+
+	// In cluster mode, keys served by another node are redirected before anything runs
+	let store = client_input.store().clone();
+	let asking = client_input.take_asking();
+	if let Some(command) = parsed_command.command() {
+		if let Err(e) = store.cluster().route(&command.keys(parsed_command.args()), asking, |key| store.contains_key(key)) {
+			client_input.respond_error(stream, &e.to_string());
+			return Ok(());
 		}
-
-		Err(e) => Err(AppError::Error(e.to_string())),
 	}
+	if record.is_some() && store.replication().is_read_only_replica() {
+		client_input.respond_error(stream, "READONLY You can't write against a read only replica.");
+		return Ok(());
+	}
+	if record.is_some() && !store.replication().enough_good_replicas() {
+		client_input.respond_error(stream, "NOREPLICAS Not enough good replicas to write.");
+		return Ok(());
+	}
+	// A swap has to wait for a running snapshot, it does so before holding up the other writes
+	if parsed_command.command() == &Some(Command::SWAPDB) {
+		store.snapshot_state().wait_until_idle();
+	}
+	match record {
+		Some(record) => store.logged_reply(stream, &record, |reply| client_input.respond(reply, parsed_command)),
+		None => client_input.respond(stream, parsed_command),
+	}
+	Ok(())
 }
 
 pub fn resp_output_to_parsed_command(resp_output: &RESPOutput) -> ParsedCommand {
 	let mut parsed_command = ParsedCommand::new();
 
 	if let RESPOutput::Array(arr) = resp_output {
-		let command_resp = &arr[0];
-		let args_resp = &arr[1..];
-
-		println!("command_resp: {:?}, args_rep: {:?}", command_resp, args_resp);
+		let Some((command_resp, args_resp)) = arr.split_first() else {
+			return parsed_command;
+		};
 
 		if let RESPOutput::BulkString(command) = command_resp {
-			if command.to_lowercase() == "ping" {
-				parsed_command.set_command(Some(Command::PING))
-			} else if command.to_lowercase() == "echo" {
-				parsed_command.set_command(Some(Command::ECHO))
-			} else if command.to_lowercase() == "get" {
-				parsed_command.set_command(Some(Command::GET))
-			} else if command.to_lowercase() == "set" {
-				parsed_command.set_command(Some(COmmand::SET))
-			}
+			parsed_command.set_command(Command::from(command));
 		}
 
 		for arg_resp in args_resp {
-			if let RESPOutput::BulkString(arg) = arg_resp {
-				parsed_command.append_arg(String::from(arg));
+			if let Some(arg) = arg_resp.bulk_bytes() {
+				parsed_command.append_arg(arg.to_vec());
 			}
 		}
 	}
 
 	parsed_command
-}
\ No newline at end of file
+}
@@ -5,13 +5,27 @@ use std::env;
 
 #[allow(unused_imports)]
 use std::fs;
+use std::io;
+use std::sync::Arc;
+use std::time::Duration;
 
-#[allow(unused_imports)]
-use tokio::net::{TcpListener, TcpStream};
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use serde_json::Value;
+
+use std::net::TcpListener;
+use std::thread;
 
 use calod::handle_connection;
-use calod::store::calod_store::{CalodStore, Store}
+use calod::store::cache::EvictionPolicy;
+use calod::store::calod_store::{CalodStore, DEFAULT_DATABASES};
+use calod::persistence::aof::{AppendOnlyFile, FsyncPolicy, DEFAULT_AOF_PATH};
+use calod::persistence::background_save::{parse_save_rules, spawn_save_scheduler, DEFAULT_SAVE_RULES, DEFAULT_SNAPSHOT_PATH};
+use calod::persistence::export::ExportFormat;
+use calod::replication::state::DEFAULT_MAX_LAG;
+use calod::cluster::bus::DEFAULT_NODE_TIMEOUT;
+use calod::cluster::config::DEFAULT_CLUSTER_CONFIG_PATH;
+use calod::cluster::state::default_bus_port;
+
+const DEFAULT_PORT: u16 = 8857;
 
 #[derive(Debug)]
 pub enum ConfigError {
@@ -20,9 +34,11 @@ pub enum ConfigError {
     JsonParseError,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug)]
 pub struct Config {
+    pub port: Option<u16>,
     pub cache_capacity: usize,
+    pub databases: Option<usize>,
     pub ttl_seconds: Option<u64>,
     pub log_level: String,
     pub eviction_strategy: String,
@@ -31,24 +47,55 @@ pub struct Config {
     pub max_cache_size_bytes: Option<u64>,
     pub log_file_path: Option<String>,
     pub metrics_enabled: bool,
+    pub snapshot_path: Option<String>,
+    pub save_rules: Option<String>,
+    pub aof_path: Option<String>,
+    pub append_fsync: Option<String>,
+    pub import_rdb: Option<String>,
+    pub replicaof: Option<String>,
+    pub replica_read_only: Option<bool>,
+    pub min_replicas_to_write: Option<usize>,
+    pub min_replicas_max_lag: Option<u64>,
+    // Missing in configs written before cluster mode existed
+    pub cluster_enabled: Option<bool>,
+    pub cluster_config_file: Option<String>,
+    pub cluster_port: Option<u16>,
+    pub cluster_node_timeout: Option<u64>,
 }
 
 impl Config {
     // Load config from either environement variables or a JSON file
     pub fn from_env_or_file() -> Result<Self, ConfigError> {
-        if let OK(capacity) = env::var("CACHE_CAPACITY") {
+        if let Ok(capacity) = env::var("CACHE_CAPACITY") {
             let cache_capacity: usize = capacity.parse().map_err(|_| ConfigError::InvalidEnvVar("CACHE_CAPACITY".to_string()))?;
+            let databases = env::var("DATABASES").ok().and_then(|v| v.parse().ok());
             let ttl_seconds = env::var("TTL_SECONDS").ok().and_then(|v| v.parse().ok());
             let log_level = env::var("LOG_LEVEL").unwrap_or_else(|_| "INFO".to_string());
             let eviction_strategy = env::var("EVICTION_STRATEGY").unwrap_or_else(|_| "LRU".to_string());
+            let port = env::var("PORT").ok().and_then(|v| v.parse().ok());
             let default_ttl = env::var("DEFAULT_TTL").ok().and_then(|v| v.parse().ok());
             let persistence_enabled = env::var("PERSISTENCE_ENABLED").unwrap_or_else(|_| "false".to_string()) == "true";
             let max_cache_size_bytes = env::var("MAX_CACHE_SIZE_BYTES").ok().and_then(|v| v.parse().ok());
             let log_file_path = env::var("LOG_FILE_PATH").ok();
             let metrics_enabled = env::var("METRICS_ENABLED").unwrap_or_else(|_| "false".to_string()) == "true";
+            let snapshot_path = env::var("SNAPSHOT_PATH").ok();
+            let save_rules = env::var("SAVE_RULES").ok();
+            let aof_path = env::var("AOF_PATH").ok();
+            let append_fsync = env::var("APPEND_FSYNC").ok();
+            let import_rdb = env::var("IMPORT_RDB").ok();
+            let replicaof = env::var("REPLICAOF").ok();
+            let replica_read_only = env::var("REPLICA_READ_ONLY").ok().map(|v| v == "true");
+            let min_replicas_to_write = env::var("MIN_REPLICAS_TO_WRITE").ok().and_then(|v| v.parse().ok());
+            let min_replicas_max_lag = env::var("MIN_REPLICAS_MAX_LAG").ok().and_then(|v| v.parse().ok());
+            let cluster_enabled = env::var("CLUSTER_ENABLED").ok().map(|v| v == "true");
+            let cluster_config_file = env::var("CLUSTER_CONFIG_FILE").ok();
+            let cluster_port = env::var("CLUSTER_PORT").ok().and_then(|v| v.parse().ok());
+            let cluster_node_timeout = env::var("CLUSTER_NODE_TIMEOUT").ok().and_then(|v| v.parse().ok());
 
             return Ok(Config {
+                port,
                 cache_capacity,
+                databases,
                 ttl_seconds,
                 log_level,
                 eviction_strategy,
@@ -57,6 +104,19 @@ impl Config {
                 max_cache_size_bytes,
                 log_file_path,
                 metrics_enabled,
+                snapshot_path,
+                save_rules,
+                aof_path,
+                append_fsync,
+                import_rdb,
+                replicaof,
+                replica_read_only,
+                min_replicas_to_write,
+                min_replicas_max_lag,
+                cluster_enabled,
+                cluster_config_file,
+                cluster_port,
+                cluster_node_timeout,
             });
         }
 
@@ -64,23 +124,199 @@ impl Config {
         Config::from_json("config.json")
     }
 
+    // Optional fields may be left out, older config files stay valid when new settings are added
     pub fn from_json(path: &str) -> Result<Self, ConfigError> {
         let config_data = fs::read_to_string(path).map_err(|_| ConfigError::FileReadError)?;
-        let config: Config = serde_json::from_str(&config_data).map_err(|_| ConfigError::JsonParseError)?;
-        Ok(config)
+        let json: Value = serde_json::from_str(&config_data).map_err(|_| ConfigError::JsonParseError)?;
+        let string = |name: &str| json_field(&json, name, |value| value.as_str().map(str::to_string));
+        let number = |name: &str| json_field(&json, name, Value::as_u64);
+        let flag = |name: &str| json_field(&json, name, Value::as_bool);
+        let port = |name: &str| json_field(&json, name, |value| value.as_u64().and_then(|port| u16::try_from(port).ok()));
+
+        Ok(Config {
+            port: port("port")?,
+            cache_capacity: number("cache_capacity")?.ok_or(ConfigError::JsonParseError)? as usize,
+            databases: number("databases")?.map(|databases| databases as usize),
+            ttl_seconds: number("ttl_seconds")?,
+            log_level: string("log_level")?.ok_or(ConfigError::JsonParseError)?,
+            eviction_strategy: string("eviction_strategy")?.ok_or(ConfigError::JsonParseError)?,
+            default_ttl: number("default_ttl")?,
+            persistence_enabled: flag("persistence_enabled")?.ok_or(ConfigError::JsonParseError)?,
+            max_cache_size_bytes: number("max_cache_size_bytes")?,
+            log_file_path: string("log_file_path")?,
+            metrics_enabled: flag("metrics_enabled")?.ok_or(ConfigError::JsonParseError)?,
+            snapshot_path: string("snapshot_path")?,
+            save_rules: string("save_rules")?,
+            aof_path: string("aof_path")?,
+            append_fsync: string("append_fsync")?,
+            import_rdb: string("import_rdb")?,
+            replicaof: string("replicaof")?,
+            replica_read_only: flag("replica_read_only")?,
+            min_replicas_to_write: number("min_replicas_to_write")?.map(|count| count as usize),
+            min_replicas_max_lag: number("min_replicas_max_lag")?,
+            cluster_enabled: flag("cluster_enabled")?,
+            cluster_config_file: string("cluster_config_file")?,
+            cluster_port: port("cluster_port")?,
+            cluster_node_timeout: number("cluster_node_timeout")?,
+        })
     }
 }
 
-#[tokio::main]
-async fn main() {
+// A field of the config file, `None` when it is missing or null and an error when it has the wrong type
+fn json_field<T>(json: &Value, name: &str, convert: impl Fn(&Value) -> Option<T>) -> Result<Option<T>, ConfigError> {
+    match json.get(name) {
+        None | Some(Value::Null) => Ok(None),
+        Some(value) => convert(value).map(Some).ok_or(ConfigError::JsonParseError),
+    }
+}
+
+// Offline tools working on a snapshot file
+// calod export [--format jsonl|csv] [--match <pattern>] [--snapshot <path>] [<output>]
+// calod import [--format jsonl|csv] [--snapshot <path>] <input>
+fn run_transfer_tool(args: &[String]) -> Result<(), String> {
+    let mut format = ExportFormat::JsonLines;
+    let mut pattern = None;
+    let mut snapshot_path = DEFAULT_SNAPSHOT_PATH.to_string();
+    let mut file = None;
+
+    let mut options = args[1..].iter();
+    while let Some(option) = options.next() {
+        let mut value = || options.next().cloned().ok_or_else(|| format!("{} needs a value", option));
+        match option.as_str() {
+            "--format" => format = ExportFormat::from(&value()?).ok_or("--format must be jsonl or csv")?,
+            "--match" => pattern = Some(value()?),
+            "--snapshot" => snapshot_path = value()?,
+            path if file.is_none() => file = Some(path.to_string()),
+            other => return Err(format!("unexpected argument `{}`", other)),
+        }
+    }
+
+    let store = CalodStore::new(usize::MAX);
+    let loaded = store.load_from_file(&snapshot_path);
+    if args[0] == "export" {
+        loaded.map_err(|e| format!("Failed to load {}: {}", snapshot_path, e))?;
+        let exported = match &file {
+            Some(path) => {
+                let mut out = io::BufWriter::new(fs::File::create(path).map_err(|e| e.to_string())?);
+                store.export(&mut out, format, pattern.as_deref())
+            }
+            None => store.export(&mut io::stdout().lock(), format, pattern.as_deref()),
+        }.map_err(|e| e.to_string())?;
+        eprintln!("Exported {} keys", exported);
+    } else {
+        // Import on top of an existing snapshot, but never replace one that failed to load
+        if let (Err(e), true) = (loaded, store.storage().exists(&snapshot_path)) {
+            return Err(format!("Failed to load {}: {}", snapshot_path, e));
+        }
+        let path = file.ok_or("import needs an input file")?;
+        let input = fs::File::open(&path).map_err(|e| format!("Failed to open {}: {}", path, e))?;
+        let stats = store.import(&mut io::BufReader::new(input), format).map_err(|e| e.to_string())?;
+        let saved = store.save_to_file(&snapshot_path).map_err(|e| e.to_string())?;
+        eprintln!("Imported {} keys ({} expired), {} keys saved to {}", stats.keys, stats.expired, saved, snapshot_path);
+    }
+    Ok(())
+}
+
+fn main() {
+    // `calod export` and `calod import` run instead of the server
+    let args: Vec<String> = env::args().skip(1).collect();
+    if matches!(args.first().map(String::as_str), Some("export") | Some("import")) {
+        if let Err(e) = run_transfer_tool(&args) {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
     println!("Logs from your program will appear here!");
 
-    let listener = TcpListener::bind("127.0.0.1:8857").await().unwrap();
+    let config = Config::from_env_or_file().expect("Failed to load config");
+    let port = config.port.unwrap_or(DEFAULT_PORT);
+    let listener = TcpListener::bind(("127.0.0.1", port)).unwrap();
+
+    // One store shared by every connection and background task
+    let store = Arc::new(CalodStore::new(config.cache_capacity));
+    store.set_eviction_policy(EvictionPolicy::from_name(&config.eviction_strategy).unwrap_or_default());
+    store.set_database_count(config.databases.unwrap_or(DEFAULT_DATABASES));
+    println!("Store is initialized with {} databases of capacity: {}", store.database_count(), config.cache_capacity);
+
+    // Restore the dataset, the append only file wins over the snapshot when it exists
+    let snapshot_path = config.snapshot_path.clone().unwrap_or_else(|| DEFAULT_SNAPSHOT_PATH.to_string());
+    let aof_path = config.aof_path.clone().unwrap_or_else(|| DEFAULT_AOF_PATH.to_string());
+    store.snapshot_state().set_path(&snapshot_path);
+
+    let aof_exists = config.persistence_enabled && store.storage().exists(&aof_path);
+    let restored = if aof_exists {
+        let stats = store.load_aof(&aof_path).expect("Failed to load the append only file");
+        println!("Loaded {} keys and replayed {} commands from {}", stats.keys, stats.commands, aof_path);
+        true
+    } else {
+        match store.load_from_file(&snapshot_path) {
+            Ok(loaded) => {
+                println!("Loaded {} keys from {}", loaded, snapshot_path);
+                true
+            }
+            Err(e) => {
+                println!("No snapshot loaded from {}: {}", snapshot_path, e);
+                false
+            }
+        }
+    };
+
+    // Migrating from Redis, the RDB file only seeds a server that has nothing to restore
+    if let (false, Some(rdb_path)) = (restored, config.import_rdb.as_deref()) {
+        let stats = store.import_rdb(rdb_path).expect("Failed to import the RDB file");
+        println!("Imported {} keys from {} (RDB version {}), {} already expired", stats.keys, rdb_path, stats.version, stats.expired);
+        for (kind, count) in &stats.skipped {
+            println!("Skipped {} keys: {}", count, kind);
+        }
+    }
+
+    // Log write commands from now on, a fresh log starts with a rewrite of the loaded dataset
+    if config.persistence_enabled {
+        let policy = FsyncPolicy::from(config.append_fsync.as_deref().unwrap_or("everysec")).expect("Invalid fsync policy");
+        store.enable_aof(AppendOnlyFile::open(store.storage(), &aof_path, policy).expect("Failed to open the append only file"));
+        if !aof_exists {
+            store.bgrewriteaof().expect("Failed to start the append only file rewrite");
+        }
+    }
+
+    // A follower replaces whatever it restored with the leader's data set once the link is up
+    store.replication().set_read_only(config.replica_read_only.unwrap_or(true));
+    store.replication().set_listening_port(port);
+    let max_lag = config.min_replicas_max_lag.map(Duration::from_secs).unwrap_or(DEFAULT_MAX_LAG);
+    store.replication().set_min_replicas(config.min_replicas_to_write.unwrap_or(0), max_lag);
+    if let Some(leader) = config.replicaof.as_deref() {
+        let (host, port) = leader.split_once(' ').and_then(|(host, port)| Some((host, port.parse::<u16>().ok()?))).expect("REPLICAOF must be `<host> <port>`");
+        println!("Replicating from {}:{}", host, port);
+        store.replicate_from(host, port);
+    }
+
+    // A cluster node only serves the slots its configuration assigns to it and gossips with the other nodes on its bus port
+    if config.cluster_enabled.unwrap_or(false) {
+        let path = config.cluster_config_file.as_deref().unwrap_or(DEFAULT_CLUSTER_CONFIG_PATH);
+        let address = format!("127.0.0.1:{}", port);
+        let bus_port = config.cluster_port.unwrap_or_else(|| default_bus_port(&address));
+        store.enable_cluster(path, &address, bus_port).expect("Failed to load the cluster config");
+        let bus = TcpListener::bind(("127.0.0.1", bus_port)).expect("Failed to listen on the cluster bus port");
+        let node_timeout = config.cluster_node_timeout.map(Duration::from_millis).unwrap_or(DEFAULT_NODE_TIMEOUT);
+        store.start_cluster_bus(bus, node_timeout);
+        println!("Cluster mode enabled, node {} with its bus on port {}", store.cluster().myself().id, bus_port);
+    }
 
-    CalodStore::initialize();
+    let save_rules = parse_save_rules(config.save_rules.as_deref().unwrap_or(DEFAULT_SAVE_RULES)).expect("Invalid save rules");
+    spawn_save_scheduler(store.clone(), save_rules);
 
+    // Connections block on their socket, each one gets its own thread
     for wrapped_stream in listener.incoming() {
-        let stream = wrapped_stream.unwrap();
-        tokio::spawn(move || handle_connection(stream));
+        let stream = match wrapped_stream {
+            Ok(stream) => stream,
+            Err(e) => {
+                eprintln!("Failed to accept a connection: {}", e);
+                continue;
+            }
+        };
+        let store = store.clone();
+        thread::spawn(move || handle_connection(stream, store));
     }
 }
\ No newline at end of file
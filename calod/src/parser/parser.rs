@@ -1,13 +1,42 @@
-use std::io::{ErrorKind};
-
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub enum RESPOutput {
 	SimpleString(String),
 	Error(String),
 	BulkString(String),
+	// A bulk string that is not valid UTF-8, kept byte for byte
+	BulkBytes(Vec<u8>),
 	Integer(i64),
 	Array(Vec<RESPOutput>),
-	Nullm
+	Null,
+	// RESP3 only, the server speaks RESP2 but clients decode these from other servers
+	Double(f64),
+	Boolean(bool),
+	BigNumber(String),
+	// Format such as `txt` and the text
+	Verbatim(String, String),
+	Map(Vec<(RESPOutput, RESPOutput)>),
+	Set(Vec<RESPOutput>),
+	// Out of band data such as pub/sub messages
+	Push(Vec<RESPOutput>),
+}
+
+impl RESPOutput {
+	// A bulk string of any bytes, text when they are valid UTF-8
+	pub fn bulk(bytes: Vec<u8>) -> RESPOutput {
+		match String::from_utf8(bytes) {
+			Ok(text) => RESPOutput::BulkString(text),
+			Err(e) => RESPOutput::BulkBytes(e.into_bytes()),
+		}
+	}
+
+	// The bytes of a bulk string, `None` for any other reply
+	pub fn bulk_bytes(&self) -> Option<&[u8]> {
+		match self {
+			RESPOutput::BulkString(text) => Some(text.as_bytes()),
+			RESPOutput::BulkBytes(bytes) => Some(bytes),
+			_ => None,
+		}
+	}
 }
 
 #[derive(Debug, PartialEq)]
@@ -21,128 +50,96 @@ pub enum ParseError {
 pub type ParseResult<'a> = std::result::Result<(RESPOutput, &'a [u8]), ParseError>;
 pub type ParseCRLFResult<'a> = std::result::Result<(&'a [u8], &'a [u8]), ParseError>;
 
+// Longest bulk string accepted, like proto-max-bulk-len in Redis
+pub const PROTO_MAX_BULK_LEN: usize = 512 * 1024 * 1024;
+
 const CR: u8 = b'\r';
 const LF: u8 = b'\n';
 
-pub struct Parer {}
+pub struct Parser {}
 
 impl Parser {
-	pub fn parse_resp(input: &[u8]) -> ParseResult {
-		if input.len() == 0 || input[0] == 0 {
+	// Parse one value from the start of `input`, returns it and the bytes after it
+	// `IncompleteInput` means the value is cut off and more bytes have to be read first
+	pub fn parse_resp(input: &[u8]) -> ParseResult<'_> {
+		let Some((symbol, remaining)) = input.split_first() else {
 			return Err(ParseError::IncompleteInput);
-		}
-
-		let symbol_temp = String::from_utf8_lossy(&input[0..1]);
-		let symbol = symbol_temp.as_ref();
-		let remaining = &input[1..];
+		};
 
 		match symbol {
-			"+" => Parser::parse_simple_string(remaining),
-			"-" => Parser::parse_error(remaining),
-			"$" => Parser::parse_bulk_string(remaining),
-			":" => Parser::parse_integer(remaining),
-			"*" => Parser::parse_array(remaining),
-			_ => return Err(ParseError::UnrecognizedSymbol),
+			b'+' => Parser::parse_simple_string(remaining),
+			b'-' => Parser::parse_error(remaining),
+			b'$' => Parser::parse_bulk_string(remaining),
+			b':' => Parser::parse_integer(remaining),
+			b'*' => Parser::parse_array(remaining),
+			_ => Err(ParseError::UnrecognizedSymbol),
 		}
 	}
 
-	fn parse_simple_string(input: &[u8]) -> ParseResult {
-		Parser::parse_until_crlf(input).map(|(result, remaining)| {
-			let string = String::from(String::from_utf8_lossy(result));
-			(RESPOutput::SimpleString(string), remaining)
-		})
+	fn parse_simple_string(input: &[u8]) -> ParseResult<'_> {
+		let (result, remaining) = Parser::parse_until_crlf(input)?;
+		Ok((RESPOutput::SimpleString(String::from_utf8_lossy(result).into_owned()), remaining))
 	}
 
-	fn parse_bulk_string(input: &[u8]) -> ParseResult {
-		let parsed = Parser::parse_until_crlf(input);
-		if parsed.is_err() {
-			return Err(parsed.unwrap_err());
-		}
+	fn parse_error(input: &[u8]) -> ParseResult<'_> {
+		let (result, remaining) = Parser::parse_until_crlf(input)?;
+		Ok((RESPOutput::Error(String::from_utf8_lossy(result).into_owned()), remaining))
+	}
 
-		let (num_bytes, remaining) = parsed.unwrap();
-		if String::from_utf8_lossy(num_bytes) == "-1" {
-			return Ok((RESPOutput::Null, "".as_bytes()));
+	// The payload is taken by its length, so it may hold any bytes including `\r\n`
+	fn parse_bulk_string(input: &[u8]) -> ParseResult<'_> {
+		let (num_bytes, remaining) = Parser::parse_until_crlf(input)?;
+		if num_bytes == b"-1" {
+			return Ok((RESPOutput::Null, remaining));
 		}
 
-		let (result, remaining) = parsed.unwrap();
-
-		let num_bytes_int: usize = String::from_utf8_lossy(num_bytes).parse().unwrap();
-		if result.len().lt(&num_bytes_int) {
+		let num_bytes = Parser::parse_number::<usize>(num_bytes)?;
+		if num_bytes > PROTO_MAX_BULK_LEN {
+			return Err(ParseError::InvalidInput);
+		}
+		if remaining.len() < num_bytes + 2 {
 			return Err(ParseError::IncompleteInput);
 		}
-
-		if result.len().get(&num_bytes_int) {
+		if remaining[num_bytes] != CR || remaining[num_bytes + 1] != LF {
 			return Err(ParseError::InvalidInput);
 		}
 
-		let res = String::from(String::from_utf8_lossy(result));
-		Ok((RESPOutput::BulkString(res), remaining))
+		Ok((RESPOutput::bulk(remaining[..num_bytes].to_vec()), &remaining[num_bytes + 2..]))
 	}
 
-	fn parse_integer(input: &[u8]) -> ParseResult {
-		let parsed = Parser::parse_until_crlf(input);
-		if parsed.is_err() {
-			return Err(parsed.unwrap_err());
-		}
-
-		let (result, remaining) = parsed.unwrap();
-		let string = String::from(String::from_utf8_lossy(result));
-		let mun: i64 = match string.parse() {
-			Ok(res) => res,
-			Err(_) => return Err(ParseError::InvalidInput),
-		};
-
-		Ok((RESPOutput::Integer(num), remaining))
+	fn parse_integer(input: &[u8]) -> ParseResult<'_> {
+		let (result, remaining) = Parser::parse_until_crlf(input)?;
+		Ok((RESPOutput::Integer(Parser::parse_number(result)?), remaining))
 	}
 
-	fn parse_array(input: &[u8]) -> ParseResult {
-		let parsed = Parser::parse_until_crlf(input);
-		if parsed.is_Err() {
-			return Err(parsed.unwrap_err());
-		}
-
-		let (num_elements, remaining) = parsed.unwrap();
-		if String::from_utf8_lossy(num_elements) == "-1" {
-			return Ok((RESPOutput::Null, "".as_bytes()));
+	fn parse_array(input: &[u8]) -> ParseResult<'_> {
+		let (num_elements, remaining) = Parser::parse_until_crlf(input)?;
+		if num_elements == b"-1" {
+			return Ok((RESPOutput::Null, remaining));
 		}
 
-		let num_elements_int: u32 = match String::from(String::from_utf8_lossy(num_elements)).parse() {
-			Ok(res) => res,
-			Err(_) => return Err(ParseError::InvalidInput),
-		};
-
-		let mut resp_result: Vec<RESPOutput> = vec![];
+		let num_elements = Parser::parse_number::<usize>(num_elements)?;
+		let mut resp_result: Vec<RESPOutput> = Vec::with_capacity(num_elements.min(1024));
 		let mut remaining = remaining;
 
-		for _ in 0..num_elements_int {
-			let parsed = Parser::parse_resp(remaining);
-			if parsed.is_err() {
-				return Err(parsed.unwrap_err());
-			}
-			let (result, rem) = parsed.unwrap();
+		for _ in 0..num_elements {
+			let (result, rem) = Parser::parse_resp(remaining)?;
 			resp_result.push(result);
 			remaining = rem;
 		}
 
-		return Ok((RESPOutput::Array(resp_result), remaining));
+		Ok((RESPOutput::Array(resp_result), remaining))
 	}
 
-	fn parse_until_crlf(input: &[u8]) -> ParseCRLFResult {
-		if input.len() == 0 {
-			return Ok((&[0], &[0]));
-		}
-
-		for i in 0..input.len() - 1 {
-			if input[i] == 0 {
-				return Ok((&[0], &[0]))
-			}
+	fn parse_number<N: std::str::FromStr>(input: &[u8]) -> Result<N, ParseError> {
+		std::str::from_utf8(input).ok().and_then(|number| number.parse().ok()).ok_or(ParseError::InvalidInput)
+	}
 
-			if input[i] == CR && input[i + 1] == LF {
-				return Ok((&input[0..i], &input[i + 2..]));
-			}
+	// Split at the first `\r\n`, a line without one is still being received
+	fn parse_until_crlf(input: &[u8]) -> ParseCRLFResult<'_> {
+		match input.windows(2).position(|window| window[0] == CR && window[1] == LF) {
+			Some(i) => Ok((&input[..i], &input[i + 2..])),
+			None => Err(ParseError::IncompleteInput),
 		}
-
-		Err(ParseError::CRLFNotFound)
 	}
 }
-
@@ -0,0 +1,512 @@
+use std::borrow::Cow;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::thread;
+use std::time::Duration;
+
+use chrono::Utc;
+use thiserror::Error;
+
+use crate::parser::parser::RESPOutput;
+use crate::persistence::snapshot::{SnapshotError, SnapshotReader, SNAPSHOT_MAGIC};
+use crate::persistence::storage::{Storage, StorageFile};
+use crate::request_response::client_input::{ClientInput, HandleClientInput};
+use crate::request_response::command::Command;
+use crate::replication::state::Role;
+use crate::request_response::response_helper;
+use crate::resp_output_to_parsed_command;
+use crate::store::calod_store::CalodStore;
+
+pub const DEFAULT_AOF_PATH: &str = "calod.aof";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FsyncPolicy {
+	// fsync after every write command
+	Always,
+	// fsync once a second from a background thread
+	EverySec,
+	// leave flushing to the operating system
+	No,
+}
+
+impl FsyncPolicy {
+	pub fn from(str: &str) -> Option<FsyncPolicy> {
+		match str.to_lowercase().as_str() {
+			"always" => Some(FsyncPolicy::Always),
+			"everysec" => Some(FsyncPolicy::EverySec),
+			"no" => Some(FsyncPolicy::No),
+			_ => None,
+		}
+	}
+}
+
+#[derive(Debug, Error)]
+pub enum AofError {
+	#[error("AOF I/O error: {0}")]
+	Io(#[from] io::Error),
+
+	#[error("{0}")]
+	Snapshot(#[from] SnapshotError),
+
+	#[error("AOF is corrupt at offset {0}")]
+	Corrupt(u64),
+
+	#[error("Append only file is not enabled")]
+	Disabled,
+
+	#[error("Background append only file rewriting already in progress")]
+	RewriteInProgress,
+
+	#[error("AOF selects database {0} but the server has fewer databases")]
+	DatabaseOutOfRange(usize),
+}
+
+#[derive(Debug)]
+struct AofWriter {
+	file: Box<dyn StorageFile>,
+	// Commands logged while a rewrite runs, appended to the rewritten file before it replaces the old one
+	rewrite_buffer: Option<Vec<u8>>,
+	needs_fsync: bool,
+	// Database of the last logged command, `None` until the log says
+	db: Option<usize>,
+}
+
+impl AofWriter {
+	fn write(&mut self, bytes: &[u8]) -> io::Result<()> {
+		self.file.write_all(bytes)?;
+		if let Some(buffer) = self.rewrite_buffer.as_mut() {
+			buffer.extend_from_slice(bytes);
+		}
+		Ok(())
+	}
+}
+
+// The append only file a running server logs its write commands to
+#[derive(Debug)]
+pub struct AppendOnlyFile {
+	storage: Arc<dyn Storage>,
+	path: String,
+	policy: FsyncPolicy,
+	writer: Mutex<AofWriter>,
+	// Write commands hold it shared while they execute and log, a rewrite takes it
+	// exclusively to start at a point where every executed command is also logged
+	// Writes are ordered among themselves by the write lock of the store
+	barrier: RwLock<()>,
+	rewriting: AtomicBool,
+}
+
+impl AppendOnlyFile {
+	// Open (or create) the log for appending, EverySec also starts the fsync thread
+	pub fn open(storage: Arc<dyn Storage>, path: &str, policy: FsyncPolicy) -> Result<Arc<AppendOnlyFile>, AofError> {
+		let file = storage.append(path)?;
+		let aof = Arc::new(AppendOnlyFile {
+			storage,
+			path: path.to_string(),
+			policy,
+			writer: Mutex::new(AofWriter { file, rewrite_buffer: None, needs_fsync: false, db: None }),
+			barrier: RwLock::new(()),
+			rewriting: AtomicBool::new(false),
+		});
+
+		if policy == FsyncPolicy::EverySec {
+			let weak = Arc::downgrade(&aof);
+			thread::spawn(move || loop {
+				thread::sleep(Duration::from_secs(1));
+				match weak.upgrade() {
+					Some(aof) => {
+						if let Err(e) = aof.fsync() {
+							println!("AOF fsync failed: {}", e);
+						}
+					}
+					None => break,
+				}
+			});
+		}
+		Ok(aof)
+	}
+
+	pub fn path(&self) -> &str {
+		&self.path
+	}
+
+	pub fn is_rewriting(&self) -> bool {
+		self.rewriting.load(Ordering::SeqCst)
+	}
+
+	// Append one encoded command that ran in database `db`, preceded by a SELECT when the log is in another one
+	pub fn append(&self, db: usize, record: &[u8]) -> Result<(), AofError> {
+		let mut writer = self.writer.lock().unwrap();
+		if writer.db != Some(db) {
+			writer.write(&select_record(db))?;
+			writer.db = Some(db);
+		}
+		writer.write(record)?;
+
+		if self.policy == FsyncPolicy::Always {
+			writer.file.sync()?;
+		} else {
+			writer.needs_fsync = true;
+		}
+		Ok(())
+	}
+
+	pub fn fsync(&self) -> Result<(), AofError> {
+		let mut writer = self.writer.lock().unwrap();
+		if writer.needs_fsync {
+			writer.file.sync()?;
+			writer.needs_fsync = false;
+		}
+		Ok(())
+	}
+
+	// Swap in the rewritten log
+	// 1. Block appends, add the commands logged since the rewrite started
+	// 2. Fsync and rename it over the old log, keep appending to the new file
+	fn finish_rewrite(&self, temp_path: &str) -> Result<(), AofError> {
+		let mut writer = self.writer.lock().unwrap();
+		let buffer = writer.rewrite_buffer.take().unwrap_or_default();
+
+		let mut file = self.storage.append(temp_path)?;
+		file.write_all(&buffer)?;
+		file.sync()?;
+		self.storage.rename(temp_path, &self.path)?;
+
+		writer.file = file;
+		writer.needs_fsync = false;
+		Ok(())
+	}
+
+	fn abort_rewrite(&self) {
+		self.writer.lock().unwrap().rewrite_buffer = None;
+		self.rewriting.store(false, Ordering::SeqCst);
+	}
+}
+
+// Encode a client request for the log, `None` for commands that do not change the dataset
+// 1. Relative expirations are turned into absolute ones (PXAT, ABSTTL) so a replay keeps the original deadline
+// 2. Arguments are logged byte for byte, values do not have to be UTF-8
+pub fn aof_record(request: &RESPOutput, command: &Option<Command>) -> Option<Vec<u8>> {
+	if !command.as_ref().is_some_and(|c| c.is_write()) {
+		return None;
+	}
+
+	let mut args: Vec<Vec<u8>> = match request {
+		RESPOutput::Array(items) => items.iter().filter_map(|item| item.bulk_bytes().map(<[u8]>::to_vec)).collect(),
+		_ => return None,
+	};
+	let number = |arg: &[u8]| std::str::from_utf8(arg).ok().and_then(|arg| arg.parse::<i64>().ok());
+
+	if command == &Some(Command::SET) && args.len() == 5 {
+		let unit = args[3].to_ascii_lowercase();
+		if let (true, Some(amount)) = (unit == b"ex" || unit == b"px", number(&args[4])) {
+			let millis = if unit == b"ex" { amount.saturating_mul(1000) } else { amount };
+			args[3] = b"PXAT".to_vec();
+			args[4] = Utc::now().timestamp_millis().saturating_add(millis).to_string().into_bytes();
+		}
+	}
+
+	if command == &Some(Command::RESTORE) && args.len() >= 4 && !args[4..].iter().any(|arg| arg.eq_ignore_ascii_case(b"absttl")) {
+		if let Some(ttl @ 1..) = number(&args[2]) {
+			args[2] = Utc::now().timestamp_millis().saturating_add(ttl).to_string().into_bytes();
+			args.push(b"ABSTTL".to_vec());
+		}
+	}
+
+	Some(command_record(args))
+}
+
+// Encode a command as a log record
+pub fn command_record<A: AsRef<[u8]>>(args: Vec<A>) -> Vec<u8> {
+	let mut record = format!("*{}\r\n", args.len()).into_bytes();
+	for arg in &args {
+		let arg = arg.as_ref();
+		record.extend_from_slice(format!("${}\r\n", arg.len()).as_bytes());
+		record.extend_from_slice(arg);
+		record.extend_from_slice(b"\r\n");
+	}
+	record
+}
+
+// The SELECT that switches a log or a replication stream to database `db`
+pub fn select_record(db: usize) -> Vec<u8> {
+	command_record(vec![String::from("SELECT"), db.to_string()])
+}
+
+// The database a logged SELECT switches to, `None` for any other record
+pub(crate) fn selected_db<A: AsRef<[u8]>>(args: &[A]) -> Option<usize> {
+	match args {
+		[command, db] if command.as_ref().eq_ignore_ascii_case(b"select") => std::str::from_utf8(db.as_ref()).ok()?.parse().ok(),
+		_ => None,
+	}
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct AofLoadStats {
+	pub keys: u64,
+	pub commands: u64,
+	// Bytes of a torn last record that were cut off
+	pub truncated: u64,
+}
+
+pub(crate) enum RecordError {
+	// The file ended inside a record, usually a crash during the last write
+	Truncated,
+	Corrupt,
+	Io(io::Error),
+}
+
+impl From<io::Error> for RecordError {
+	fn from(e: io::Error) -> Self {
+		RecordError::Io(e)
+	}
+}
+
+// Read a `\r\n` terminated header line like `*3` or `$5` and return its number
+fn read_header<R: BufRead>(reader: &mut R, prefix: u8, consumed: &mut u64) -> Result<Option<usize>, RecordError> {
+	let mut line = Vec::new();
+	let read = reader.read_until(b'\n', &mut line)?;
+	*consumed += read as u64;
+	if read == 0 {
+		return Ok(None);
+	}
+	if !line.ends_with(b"\r\n") {
+		return Err(RecordError::Truncated);
+	}
+	if line[0] != prefix {
+		return Err(RecordError::Corrupt);
+	}
+
+	std::str::from_utf8(&line[1..line.len() - 2]).ok()
+		.and_then(|n| n.parse::<usize>().ok())
+		.map(Some)
+		.ok_or(RecordError::Corrupt)
+}
+
+// Read one record of text arguments, see `read_raw_record`
+pub(crate) fn read_record<R: BufRead>(reader: &mut R, consumed: &mut u64) -> Result<Option<Vec<String>>, RecordError> {
+	match read_raw_record(reader, consumed)? {
+		Some(args) => args.into_iter().map(|arg| String::from_utf8(arg).map_err(|_| RecordError::Corrupt)).collect::<Result<_, _>>().map(Some),
+		None => Ok(None),
+	}
+}
+
+// Read one `*<n>\r\n($<len>\r\n<bytes>\r\n)*` record, `None` at a clean end of file
+pub(crate) fn read_raw_record<R: BufRead>(reader: &mut R, consumed: &mut u64) -> Result<Option<Vec<Vec<u8>>>, RecordError> {
+	let count = match read_header(reader, b'*', consumed)? {
+		Some(count) => count,
+		None => return Ok(None),
+	};
+
+	let mut args = Vec::new();
+	for _ in 0..count {
+		let len = read_header(reader, b'$', consumed)?.ok_or(RecordError::Truncated)?;
+		let mut bulk = Vec::new();
+		let read = (&mut *reader).take(len as u64 + 2).read_to_end(&mut bulk)?;
+		*consumed += read as u64;
+		if read < len + 2 {
+			return Err(RecordError::Truncated);
+		}
+		if !bulk.ends_with(b"\r\n") {
+			return Err(RecordError::Corrupt);
+		}
+		bulk.truncate(len);
+		args.push(bulk);
+	}
+	Ok(Some(args))
+}
+
+// Execute a logged command exactly like a client request, the reply is discarded
+pub(crate) fn replay_command(store: &Arc<CalodStore>, args: Vec<Vec<u8>>) {
+	let request = RESPOutput::Array(args.into_iter().map(RESPOutput::bulk).collect());
+	let parsed_command = resp_output_to_parsed_command(&request);
+	ClientInput::new(store.clone()).respond(&mut io::sink(), parsed_command);
+}
+
+impl CalodStore {
+	pub fn enable_aof(&self, aof: Arc<AppendOnlyFile>) {
+		*self.aof.write().unwrap() = Some(aof);
+	}
+
+	pub fn aof(&self) -> Option<Arc<AppendOnlyFile>> {
+		self.aof.read().unwrap().clone()
+	}
+
+	// Run a write command, log it and feed it to the followers as one step
+	// so neither a rewrite nor a full resync sees one without the other
+	// Both get a SELECT first when the selected database differs from their last record
+	pub fn logged_write<R>(&self, record: Option<&[u8]>, f: impl FnOnce() -> R) -> R {
+		let Some(record) = record else {
+			return f();
+		};
+		self.logged_change(|| (f(), Some(Cow::Borrowed(record))))
+	}
+
+	// Like `logged_write` for a command whose record is only known once it ran, `None` logs nothing
+	pub(crate) fn logged_change<'a, R>(&self, f: impl FnOnce() -> (R, Option<Cow<'a, [u8]>>)) -> R {
+		let (result, appended) = self.run_logged(f);
+		if let Err(e) = appended {
+			println!("Failed to append to the AOF: {}", e);
+		}
+		result
+	}
+
+	// Log a key a leader evicted from database `id` as a DEL, so a replay and the followers drop it too
+	// Evictions make room for a write, so this runs inside its `run_logged` step and lands right before its record
+	pub(crate) fn log_eviction(&self, id: usize, key: &str) {
+		if self.replication.role() != Role::Leader {
+			return;
+		}
+		let Some(db) = self.index_of(id) else {
+			return;
+		};
+		let record = command_record(vec!["DEL", key]);
+		if let Some(Err(e)) = self.aof().map(|aof| aof.append(db, &record)) {
+			println!("Failed to append to the AOF: {}", e);
+		}
+		self.replication.feed_write(db, &record);
+	}
+
+	// Run a client's write command, `f` writes the reply into a buffer that is held back until the command is logged
+	// 1. An error reply means the command changed nothing, it is neither logged nor fed to the followers
+	// 2. The client only sees the reply once the record is appended, and fsynced with appendfsync always
+	// 3. A record that could not be appended turns the reply into an error
+	pub fn logged_reply<T: Write>(&self, stream: &mut T, record: &[u8], f: impl FnOnce(&mut Vec<u8>)) {
+		let mut reply = Vec::new();
+		let (_, appended) = self.run_logged(|| {
+			f(&mut reply);
+			((), (!reply.starts_with(b"-")).then_some(Cow::Borrowed(record)))
+		});
+		match appended {
+			Ok(()) => response_helper::send_encoded_response(stream, &reply),
+			Err(e) => response_helper::send_error_response(stream, &format!("MISCONF Errors writing to the AOF file: {}", e)),
+		}
+	}
+
+	// Write commands run one at a time from executing to logging, so the log and the followers
+	// see them in the order they changed the dataset, `f` also returns the record to log, if any
+	// 1. Without an AOF and before any follower could read the stream, the order does not matter and writes run concurrently
+	// 2. The check happens under the barriers, a follower attaches and an AOF rewrite starts only while no write is running
+	fn run_logged<'a, R>(&self, f: impl FnOnce() -> (R, Option<Cow<'a, [u8]>>)) -> (R, Result<(), AofError>) {
+		let aof = self.aof();
+		let _barrier = aof.as_ref().map(|aof| aof.barrier.read().unwrap());
+		let _replication_barrier = self.replication.barrier.read().unwrap();
+		let _writes = (aof.is_some() || self.replication.is_streaming()).then(|| self.writes.lock().unwrap());
+		let (result, record) = f();
+		let Some(record) = record else {
+			return (result, Ok(()));
+		};
+
+		let appended = aof.as_ref().map_or(Ok(()), |aof| aof.append(self.db(), &record));
+		self.replication.feed_write(self.db(), &record);
+		(result, appended)
+	}
+
+	// Rebuild the dataset from an append only file
+	// 1. Load the snapshot preamble a rewrite leaves at the start of the file
+	// 2. Replay the logged commands after it, a logged SELECT switches the database the next ones run in
+	// 3. A torn last record is cut off the file, corruption anywhere else is an error
+	pub fn load_aof(self: &Arc<Self>, path: &str) -> Result<AofLoadStats, AofError> {
+		let mut reader = BufReader::new(self.storage().open(path)?);
+		let mut stats = AofLoadStats::default();
+		let mut consumed: u64 = 0;
+		let mut store = self.clone();
+
+		if reader.fill_buf()?.starts_with(SNAPSHOT_MAGIC) {
+			let mut counting = CountingReader { inner: &mut reader, count: 0 };
+			let mut snapshot = SnapshotReader::new(&mut counting)?;
+			stats.keys = self.load_snapshot_entries(&mut snapshot)?;
+			consumed = counting.count;
+		}
+
+		loop {
+			let start = consumed;
+			match read_raw_record(&mut reader, &mut consumed) {
+				Ok(Some(args)) => {
+					match selected_db(&args) {
+						Some(db) => store = self.select(db).map_err(|_| AofError::DatabaseOutOfRange(db))?,
+						None => {
+							replay_command(&store, args);
+							stats.commands += 1;
+						}
+					}
+				}
+				Ok(None) => break,
+				Err(RecordError::Truncated) => {
+					let storage = self.storage();
+					stats.truncated = storage.len(path)? - start;
+					storage.truncate(path, start)?;
+					println!("AOF ended with an incomplete command, truncated {} bytes", stats.truncated);
+					break;
+				}
+				Err(RecordError::Corrupt) => return Err(AofError::Corrupt(start)),
+				Err(RecordError::Io(e)) => return Err(AofError::Io(e)),
+			}
+		}
+		Ok(stats)
+	}
+
+	// BGREWRITEAOF: write the current dataset as a compact log while writes continue
+	// 1. Under the barrier, open a snapshot epoch and start buffering newly logged commands
+	//    The next command logs its SELECT again so the buffer does not depend on the old log
+	// 2. On a thread, write the epoch's snapshot as preamble of a temp log
+	// 3. Append the buffered commands and rename the temp log over the old one
+	pub fn bgrewriteaof(self: &Arc<Self>) -> Result<(), AofError> {
+		let aof = self.aof().ok_or(AofError::Disabled)?;
+		if aof.rewriting.swap(true, Ordering::SeqCst) {
+			return Err(AofError::RewriteInProgress);
+		}
+
+		let epoch = {
+			let _barrier = aof.barrier.write().unwrap();
+			let epoch = match self.snapshot_state().begin() {
+				Ok(epoch) => epoch,
+				Err(e) => {
+					aof.rewriting.store(false, Ordering::SeqCst);
+					return Err(e.into());
+				}
+			};
+			let mut writer = aof.writer.lock().unwrap();
+			writer.rewrite_buffer = Some(Vec::new());
+			writer.db = None;
+			epoch
+		};
+
+		let store = self.clone();
+		thread::spawn(move || {
+			match store.rewrite_aof(&aof, epoch) {
+				Ok(keys) => println!("Background AOF rewrite terminated with success, {} keys written", keys),
+				Err(e) => println!("Background AOF rewrite failed: {}", e),
+			}
+		});
+		Ok(())
+	}
+
+	fn rewrite_aof(&self, aof: &AppendOnlyFile, epoch: u64) -> Result<u64, AofError> {
+		let temp_path = format!("{}.rewrite-{}", aof.path, std::process::id());
+		let written = self.write_snapshot_file(&temp_path, epoch, Utc::now());
+		self.snapshot_state().release();
+
+		let result = written.map_err(AofError::from).and_then(|keys| aof.finish_rewrite(&temp_path).map(|_| keys));
+		if result.is_err() {
+			let _ = aof.storage.remove(&temp_path);
+			aof.abort_rewrite();
+		}
+		aof.rewriting.store(false, Ordering::SeqCst);
+		result
+	}
+}
+
+// Counts the bytes the snapshot reader pulls so the log records can be located after it
+struct CountingReader<R: Read> {
+	inner: R,
+	count: u64,
+}
+
+impl<R: Read> Read for CountingReader<R> {
+	fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+		let read = self.inner.read(buf)?;
+		self.count += read as u64;
+		Ok(read)
+	}
+}
@@ -0,0 +1,242 @@
+use std::sync::atomic::{AtomicBool, AtomicI64, AtomicU64, Ordering};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, RwLock};
+use std::thread;
+use std::time::Duration;
+
+use chrono::Utc;
+use dashmap::DashMap;
+
+use crate::persistence::snapshot::SnapshotError;
+use crate::store::calod_data::CacheEntry;
+use crate::store::calod_store::CalodStore;
+
+pub const DEFAULT_SNAPSHOT_PATH: &str = "calod.snapshot";
+pub const DEFAULT_SAVE_RULES: &str = "3600 1 300 100 60 10000";
+
+// Seconds the scheduler waits before retrying after a failed save
+const SAVE_RETRY_DELAY: i64 = 5;
+
+// "Save after `changes` writes once `seconds` have passed since the last save"
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SaveRule {
+	pub seconds: u64,
+	pub changes: u64,
+}
+
+// Parse rules written as "<seconds> <changes> [<seconds> <changes> ...]", e.g. "900 1 300 10"
+pub fn parse_save_rules(rules: &str) -> Result<Vec<SaveRule>, String> {
+	let numbers: Vec<&str> = rules.split_whitespace().collect();
+	if !numbers.len().is_multiple_of(2) {
+		return Err(String::from("save rules must be pairs of <seconds> <changes>"));
+	}
+
+	numbers.chunks(2).map(|pair| {
+		let seconds = pair[0].parse::<u64>().map_err(|_| format!("invalid seconds `{}`", pair[0]))?;
+		let changes = pair[1].parse::<u64>().map_err(|_| format!("invalid changes `{}`", pair[1]))?;
+		Ok(SaveRule { seconds, changes })
+	}).collect()
+}
+
+// Bookkeeping for point-in-time snapshots taken while writes continue
+// 1. A snapshot opens a new epoch, the writer claims every entry it saves for that epoch
+// 2. A write to an entry not yet claimed first copies it into `preserved` and claims it,
+//    so the snapshot sees the value the entry had when the epoch started
+// 3. Entries created during the epoch are claimed at creation and therefore skipped
+// 4. Pre-images are kept per database id, a database keeps its id when SWAPDB moves it
+#[derive(Debug)]
+pub struct SnapshotState {
+	// Epoch of the snapshot in progress, 0 while idle
+	epoch: AtomicU64,
+	last_epoch: AtomicU64,
+	preserved: DashMap<(usize, String), CacheEntry>,
+	// Writes since the last successful save
+	dirty: AtomicU64,
+	dirty_at_begin: AtomicU64,
+	// Unix times of the running and the last successful save
+	begun_at: AtomicI64,
+	last_save: AtomicI64,
+	last_save_ok: AtomicBool,
+	path: RwLock<String>,
+	// SWAPDB holds it once no epoch is open, a snapshot maps databases to indexes for its whole epoch
+	// Epochs open and close under it too, `closed` is signalled when one closes
+	swap: Mutex<()>,
+	closed: Condvar,
+}
+
+impl SnapshotState {
+	pub fn new() -> Self {
+		SnapshotState {
+			epoch: AtomicU64::new(0),
+			last_epoch: AtomicU64::new(0),
+			preserved: DashMap::new(),
+			dirty: AtomicU64::new(0),
+			dirty_at_begin: AtomicU64::new(0),
+			begun_at: AtomicI64::new(0),
+			last_save: AtomicI64::new(Utc::now().timestamp()),
+			last_save_ok: AtomicBool::new(true),
+			path: RwLock::new(String::from(DEFAULT_SNAPSHOT_PATH)),
+			swap: Mutex::new(()),
+			closed: Condvar::new(),
+		}
+	}
+
+	// Call with the entry locked before it is modified or removed, `db` is the id of its database
+	pub fn before_write(&self, db: usize, key: &str, entry: &CacheEntry) {
+		self.dirty.fetch_add(1, Ordering::Relaxed);
+		let epoch = self.epoch.load(Ordering::SeqCst);
+		if epoch != 0 && entry.snapshot_epoch.swap(epoch, Ordering::SeqCst) != epoch {
+			self.preserved.insert((db, key.to_string()), entry.clone());
+		}
+	}
+
+	// Call for a newly created entry before it becomes visible
+	pub fn before_insert(&self, entry: &CacheEntry) {
+		self.dirty.fetch_add(1, Ordering::Relaxed);
+		entry.snapshot_epoch.store(self.epoch.load(Ordering::SeqCst), Ordering::SeqCst);
+	}
+
+	// Call with the old entry locked before it is overwritten by `new`
+	pub fn before_replace(&self, db: usize, key: &str, old: &CacheEntry, new: &CacheEntry) {
+		self.before_write(db, key, old);
+		new.snapshot_epoch.store(self.epoch.load(Ordering::SeqCst), Ordering::SeqCst);
+	}
+
+	// Claim a live entry for the running epoch, true if the snapshot should write it
+	pub fn claim(&self, entry: &CacheEntry, epoch: u64) -> bool {
+		entry.snapshot_epoch.swap(epoch, Ordering::SeqCst) != epoch
+	}
+
+	// Start a new epoch, fails while another snapshot is running
+	pub fn begin(&self) -> Result<u64, SnapshotError> {
+		let _swap = self.swap.lock().unwrap();
+		let epoch = self.last_epoch.load(Ordering::SeqCst) + 1;
+		self.epoch.compare_exchange(0, epoch, Ordering::SeqCst, Ordering::SeqCst)
+			.map_err(|_| SnapshotError::SaveInProgress)?;
+		self.last_epoch.store(epoch, Ordering::SeqCst);
+		self.dirty_at_begin.store(self.dirty(), Ordering::SeqCst);
+		self.begun_at.store(Utc::now().timestamp(), Ordering::SeqCst);
+		Ok(epoch)
+	}
+
+	// Take the pre-images collected so far, the caller writes them after the live entries
+	pub fn take_preserved(&self) -> Vec<(usize, String, CacheEntry)> {
+		let keys: Vec<(usize, String)> = self.preserved.iter().map(|entry| entry.key().clone()).collect();
+		keys.into_iter().filter_map(|key| self.preserved.remove(&key)).map(|((db, key), entry)| (db, key, entry)).collect()
+	}
+
+	// Close the epoch of a save and record the outcome, writes made while saving stay dirty for the next save
+	pub fn end(&self, saved: bool) {
+		if saved {
+			self.dirty.fetch_sub(self.dirty_at_begin.load(Ordering::SeqCst), Ordering::SeqCst);
+			self.last_save.store(self.begun_at.load(Ordering::SeqCst), Ordering::SeqCst);
+		}
+		self.last_save_ok.store(saved, Ordering::SeqCst);
+		self.release();
+	}
+
+	// Close the epoch without counting it as a save
+	pub fn release(&self) {
+		self.preserved.clear();
+		let _swap = self.swap.lock().unwrap();
+		self.epoch.store(0, Ordering::SeqCst);
+		self.closed.notify_all();
+	}
+
+	// Keep snapshots from beginning until the guard is dropped, `None` while one is running
+	pub fn lock_databases(&self) -> Option<MutexGuard<'_, ()>> {
+		let swap = self.swap.lock().unwrap();
+		(!self.in_progress()).then_some(swap)
+	}
+
+	// Block until no snapshot is running, one may begin right after
+	pub fn wait_until_idle(&self) {
+		drop(self.closed.wait_while(self.swap.lock().unwrap(), |_| self.in_progress()).unwrap());
+	}
+
+	pub fn in_progress(&self) -> bool {
+		self.epoch.load(Ordering::SeqCst) != 0
+	}
+
+	pub fn dirty(&self) -> u64 {
+		self.dirty.load(Ordering::SeqCst)
+	}
+
+	pub fn last_save(&self) -> i64 {
+		self.last_save.load(Ordering::SeqCst)
+	}
+
+	pub fn last_save_ok(&self) -> bool {
+		self.last_save_ok.load(Ordering::SeqCst)
+	}
+
+	pub fn path(&self) -> String {
+		self.path.read().unwrap().clone()
+	}
+
+	pub fn set_path(&self, path: &str) {
+		*self.path.write().unwrap() = path.to_string();
+	}
+
+	// True if any rule has collected enough changes over its period
+	pub fn is_due(&self, rules: &[SaveRule]) -> bool {
+		let now = Utc::now().timestamp();
+		if !self.last_save_ok() && now - self.begun_at.load(Ordering::SeqCst) < SAVE_RETRY_DELAY {
+			return false;
+		}
+		let elapsed = (now - self.last_save()).max(0) as u64;
+		let dirty = self.dirty();
+		dirty > 0 && rules.iter().any(|rule| dirty >= rule.changes && elapsed >= rule.seconds)
+	}
+}
+
+impl Default for SnapshotState {
+	fn default() -> Self {
+		SnapshotState::new()
+	}
+}
+
+impl CalodStore {
+	// SAVE: write a snapshot on the calling thread
+	pub fn save(&self) -> Result<u64, SnapshotError> {
+		let path = self.snapshot_state().path();
+		self.save_to_file(&path)
+	}
+
+	// BGSAVE: open the epoch here so a second BGSAVE fails right away, write on a thread
+	pub fn bgsave(self: &Arc<Self>) -> Result<(), SnapshotError> {
+		let state = self.snapshot_state();
+		let epoch = state.begin()?;
+		let path = state.path();
+
+		let store = self.clone();
+		thread::spawn(move || {
+			match store.write_snapshot(&path, epoch) {
+				Ok(saved) => println!("Background saving terminated with success, {} keys saved", saved),
+				Err(e) => println!("Background saving error: {}", e),
+			}
+		});
+		Ok(())
+	}
+
+	// LASTSAVE: unix time of the last successful save
+	pub fn lastsave(&self) -> i64 {
+		self.snapshot_state().last_save()
+	}
+}
+
+// Check the save rules once a second and start a BGSAVE when one of them is due
+pub fn spawn_save_scheduler(store: Arc<CalodStore>, rules: Vec<SaveRule>) {
+	if rules.is_empty() {
+		return;
+	}
+
+	thread::spawn(move || loop {
+		thread::sleep(Duration::from_secs(1));
+		let state = store.snapshot_state();
+		if !state.in_progress() && state.is_due(&rules) {
+			if let Err(e) = store.bgsave() {
+				println!("Scheduled save failed to start: {}", e);
+			}
+		}
+	});
+}
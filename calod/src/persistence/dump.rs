@@ -0,0 +1,206 @@
+use std::borrow::Cow;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpStream, ToSocketAddrs};
+use std::time;
+
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use thiserror::Error;
+
+use crate::parser::parser::RESPOutput;
+use crate::persistence::aof::{command_record, select_record};
+use crate::persistence::encoding::{decode_value, encode_value, from_hex, to_hex};
+use crate::request_response::response_helper::format_resp_output;
+use crate::store::calod_data::DataType;
+use crate::store::calod_store::CalodStore;
+
+// Payload layout, sent hex encoded since RESP bulk strings are text here
+// 1. Type tag followed by the value encoded like a snapshot entry
+// 2. Trailer: `DUMP_VERSION` (u16 LE) and the CRC32 of every byte before the checksum (u32 LE)
+pub const DUMP_VERSION: u16 = 1;
+
+const TRAILER_LEN: usize = 6;
+
+#[derive(Debug, Error)]
+pub enum DumpError {
+	#[error("DUMP payload version or checksum are wrong")]
+	BadPayload,
+
+	#[error("Bad data format")]
+	BadFormat,
+
+	#[error("IOERR error or timeout talking to the target instance: {0}")]
+	Io(#[from] io::Error),
+
+	#[error("Target instance replied with error: {0}")]
+	Target(String),
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct MigrateOptions {
+	// Keep the local keys
+	pub copy: bool,
+	// Overwrite keys that already exist on the target
+	pub replace: bool,
+	// Database the keys are restored into on the target
+	pub db: usize,
+	// Connect, read and write timeout, `None` waits forever
+	pub timeout: Option<time::Duration>,
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct MigrateOutcome {
+	// Keys restored on the target, in request order
+	pub moved: Vec<String>,
+	// Keys that did not exist locally
+	pub missing: u64,
+}
+
+// Serialize a single value
+pub fn dump_payload(value: &DataType) -> String {
+	let mut out = vec![0];
+	out[0] = encode_value(value, &mut out);
+	out.extend_from_slice(&DUMP_VERSION.to_le_bytes());
+	let checksum = crc32fast::hash(&out);
+	out.extend_from_slice(&checksum.to_le_bytes());
+	to_hex(&out)
+}
+
+// Parse a payload written by `dump_payload`
+// 1. The version and checksum are verified before anything is decoded
+// 2. Payloads of a newer version are rejected rather than guessed at
+pub fn restore_payload(payload: &str) -> Result<DataType, DumpError> {
+	let bytes = from_hex(payload).map_err(|_| DumpError::BadPayload)?;
+	if bytes.len() < TRAILER_LEN + 1 {
+		return Err(DumpError::BadPayload);
+	}
+
+	let (body, checksum) = bytes.split_at(bytes.len() - 4);
+	if crc32fast::hash(body) != u32::from_le_bytes(checksum.try_into().unwrap()) {
+		return Err(DumpError::BadPayload);
+	}
+	let (value, version) = body.split_at(body.len() - 2);
+	if u16::from_le_bytes(version.try_into().unwrap()) > DUMP_VERSION {
+		return Err(DumpError::BadPayload);
+	}
+
+	decode_value(value[0], &value[1..]).map_err(|_| DumpError::BadFormat)
+}
+
+// Expiry of a restored key, 0 means none and a deadline past the representable range never expires
+pub fn restore_deadline(ttl: i64, absolute: bool) -> Option<DateTime<Utc>> {
+	match (ttl, absolute) {
+		(0, _) => None,
+		(ttl, true) => Utc.timestamp_millis_opt(ttl).single(),
+		(ttl, false) => Utc::now().checked_add_signed(Duration::milliseconds(ttl)),
+	}
+}
+
+// Read the first line of a RESP reply, error replies become `DumpError::Target`
+fn read_reply<R: BufRead>(reader: &mut R) -> Result<String, DumpError> {
+	let mut line = String::new();
+	if reader.read_line(&mut line)? == 0 {
+		return Err(DumpError::Io(io::Error::new(io::ErrorKind::UnexpectedEof, "connection closed")));
+	}
+	let line = line.trim_end_matches(['\r', '\n']);
+	match line.strip_prefix('-') {
+		Some(error) => Err(DumpError::Target(error.to_string())),
+		None => Ok(line.to_string()),
+	}
+}
+
+fn connect(target: impl ToSocketAddrs, timeout: Option<time::Duration>) -> Result<TcpStream, DumpError> {
+	let addresses: Vec<SocketAddr> = target.to_socket_addrs()?.collect();
+	let mut last_error = io::Error::new(io::ErrorKind::NotFound, "no address to connect to");
+	for address in addresses {
+		let connected = match timeout {
+			Some(timeout) => TcpStream::connect_timeout(&address, timeout),
+			None => TcpStream::connect(address),
+		};
+		match connected {
+			Ok(stream) => {
+				stream.set_read_timeout(timeout)?;
+				stream.set_write_timeout(timeout)?;
+				return Ok(stream);
+			}
+			Err(e) => last_error = e,
+		}
+	}
+	Err(DumpError::Io(last_error))
+}
+
+impl CalodStore {
+	// DUMP, `None` if the key does not exist
+	pub fn dump(&self, key: &str) -> Option<String> {
+		self.read_value(key, |value| value.map(dump_payload))
+	}
+
+	// Move or copy keys to another instance with one RESTORE ... ABSTTL per key
+	// 1. A moved key is taken out of the store while it is in flight, so it lives on exactly one side
+	// 2. The key is only dropped once the target acknowledged the RESTORE, otherwise it is put back
+	// 3. The first failure stops the migration, keys already restored stay on the target
+	// 4. In cluster mode every RESTORE is preceded by ASKING, the target serves the slot it imports only then
+	// 5. A target database other than 0 is selected once before the first key
+	// 6. Moved keys are logged as a DEL in the same step that took them out, so a replay or a follower
+	//    never keeps a key the target now owns, nor misses a write to it that came in between
+	pub fn migrate(&self, target: impl ToSocketAddrs, keys: &[String], options: &MigrateOptions) -> (MigrateOutcome, Result<(), DumpError>) {
+		let mut outcome = MigrateOutcome::default();
+		let stream = match connect(target, options.timeout) {
+			Ok(stream) => stream,
+			Err(e) => return (outcome, Err(e)),
+		};
+		let mut reader = BufReader::new(match stream.try_clone() {
+			Ok(reader) => reader,
+			Err(e) => return (outcome, Err(e.into())),
+		});
+		let mut writer = stream;
+
+		if options.db != 0 {
+			if let Err(e) = writer.write_all(&select_record(options.db)).map_err(DumpError::from).and_then(|_| read_reply(&mut reader)) {
+				return (outcome, Err(e));
+			}
+		}
+
+		self.logged_change(|| {
+			let result = self.migrate_keys(&mut reader, &mut writer, keys, options, &mut outcome);
+			let record = (!options.copy && !outcome.moved.is_empty())
+				.then(|| Cow::Owned(command_record([String::from("DEL")].into_iter().chain(outcome.moved.iter().cloned()).collect())));
+			((outcome, result), record)
+		})
+	}
+
+	fn migrate_keys(&self, reader: &mut BufReader<TcpStream>, writer: &mut TcpStream, keys: &[String], options: &MigrateOptions, outcome: &mut MigrateOutcome) -> Result<(), DumpError> {
+		for key in keys {
+			let entry = if options.copy { self.clone_entry(key) } else { self.take_entry(key) };
+			let entry = match entry {
+				Some(entry) => entry,
+				None => {
+					outcome.missing += 1;
+					continue;
+				}
+			};
+
+			let deadline = entry.ttl.map_or(0, |ttl| ttl.timestamp_millis());
+			let mut args = vec![String::from("RESTORE"), key.clone(), deadline.to_string(), dump_payload(&entry.value), String::from("ABSTTL")];
+			if options.replace {
+				args.push(String::from("REPLACE"));
+			}
+			let mut requests = Vec::new();
+			if self.cluster.is_enabled() {
+				requests.push(RESPOutput::Array(vec![RESPOutput::BulkString(String::from("ASKING"))]));
+			}
+			requests.push(RESPOutput::Array(args.into_iter().map(RESPOutput::BulkString).collect()));
+			let request: String = requests.iter().map(format_resp_output).collect();
+
+			let sent = writer.write_all(request.as_bytes()).map_err(DumpError::from)
+				.and_then(|_| requests.iter().try_for_each(|_| read_reply(reader).map(|_| ())));
+			if let Err(e) = sent {
+				if !options.copy {
+					self.return_entry(key, entry);
+				}
+				return Err(e);
+			}
+			outcome.moved.push(key.clone());
+		}
+		Ok(())
+	}
+}
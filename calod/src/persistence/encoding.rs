@@ -0,0 +1,237 @@
+use std::collections::LinkedList;
+
+use crate::persistence::snapshot::SnapshotError;
+use crate::store::bloom_filter::BloomFilter;
+use crate::store::calod_data::{DataType, Hash, Set};
+use crate::store::cuckoo_filter::CuckooFilter;
+use crate::store::hyperloglog::HyperLogLog;
+use crate::store::sorted_set::SortedSet;
+
+// Type tags of the encoded values, never reuse a retired tag
+pub const TYPE_STRING: u8 = 1;
+pub const TYPE_LIST: u8 = 2;
+pub const TYPE_SET: u8 = 3;
+pub const TYPE_HASH: u8 = 4;
+pub const TYPE_SORTED_SET: u8 = 5;
+pub const TYPE_HYPERLOGLOG: u8 = 6;
+pub const TYPE_BLOOM_FILTER: u8 = 7;
+pub const TYPE_CUCKOO_FILTER: u8 = 8;
+pub const TYPE_JSON: u8 = 9;
+
+// LEB128 unsigned varint, small lengths and counts take a single byte
+pub fn put_varint(out: &mut Vec<u8>, mut value: u64) {
+	while value >= 0x80 {
+		out.push((value as u8) | 0x80);
+		value >>= 7;
+	}
+	out.push(value as u8);
+}
+
+pub fn put_u8(out: &mut Vec<u8>, value: u8) {
+	out.push(value);
+}
+
+pub fn put_u64(out: &mut Vec<u8>, value: u64) {
+	out.extend_from_slice(&value.to_le_bytes());
+}
+
+pub fn put_f64(out: &mut Vec<u8>, value: f64) {
+	out.extend_from_slice(&value.to_le_bytes());
+}
+
+// Length prefixed byte string
+pub fn put_bytes(out: &mut Vec<u8>, bytes: &[u8]) {
+	put_varint(out, bytes.len() as u64);
+	out.extend_from_slice(bytes);
+}
+
+// Lower case hex, used where binary payloads have to travel as text
+pub fn to_hex(bytes: &[u8]) -> String {
+	bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+pub fn from_hex(text: &str) -> Result<Vec<u8>, String> {
+	if !text.len().is_multiple_of(2) {
+		return Err(String::from("hex value has an odd length"));
+	}
+	let digit = |c: u8| (c as char).to_digit(16).ok_or_else(|| String::from("invalid hex value"));
+	text.as_bytes().chunks(2)
+		.map(|pair| Ok((digit(pair[0])? << 4 | digit(pair[1])?) as u8))
+		.collect()
+}
+
+// Cursor over an encoded payload, every read is bounds checked
+pub struct Decoder<'a> {
+	bytes: &'a [u8],
+	pos: usize,
+}
+
+impl<'a> Decoder<'a> {
+	pub fn new(bytes: &'a [u8]) -> Self {
+		Decoder { bytes, pos: 0 }
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.pos == self.bytes.len()
+	}
+
+	fn take(&mut self, len: usize) -> Result<&'a [u8], SnapshotError> {
+		if self.bytes.len() - self.pos < len {
+			return Err(SnapshotError::Corrupt(String::from("payload is truncated")));
+		}
+		let slice = &self.bytes[self.pos..self.pos + len];
+		self.pos += len;
+		Ok(slice)
+	}
+
+	pub fn varint(&mut self) -> Result<u64, SnapshotError> {
+		let mut value: u64 = 0;
+		for shift in (0..64).step_by(7) {
+			let byte = self.u8()?;
+			value |= ((byte & 0x7f) as u64) << shift;
+			if byte & 0x80 == 0 {
+				return Ok(value);
+			}
+		}
+		Err(SnapshotError::Corrupt(String::from("varint is too long")))
+	}
+
+	// A varint used as an in-memory length, rejected if it could not possibly fit the payload
+	pub fn length(&mut self) -> Result<usize, SnapshotError> {
+		let len = self.varint()?;
+		if len > (self.bytes.len() - self.pos) as u64 {
+			return Err(SnapshotError::Corrupt(String::from("length exceeds payload")));
+		}
+		Ok(len as usize)
+	}
+
+	pub fn u8(&mut self) -> Result<u8, SnapshotError> {
+		Ok(self.take(1)?[0])
+	}
+
+	pub fn u64(&mut self) -> Result<u64, SnapshotError> {
+		Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+	}
+
+	pub fn f64(&mut self) -> Result<f64, SnapshotError> {
+		Ok(f64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+	}
+
+	pub fn bytes(&mut self) -> Result<&'a [u8], SnapshotError> {
+		let len = self.length()?;
+		self.take(len)
+	}
+
+	pub fn string(&mut self) -> Result<String, SnapshotError> {
+		String::from_utf8(self.bytes()?.to_vec()).map_err(|_| SnapshotError::Corrupt(String::from("invalid utf-8 string")))
+	}
+}
+
+// Encode a value into `out` and return its type tag
+pub fn encode_value(value: &DataType, out: &mut Vec<u8>) -> u8 {
+	match value {
+		DataType::String(bytes) => {
+			out.extend_from_slice(bytes);
+			TYPE_STRING
+		}
+		DataType::List(items) => {
+			put_varint(out, items.len() as u64);
+			for item in items {
+				put_bytes(out, item.as_bytes());
+			}
+			TYPE_LIST
+		}
+		DataType::Set(set) => {
+			let members = set.members();
+			put_varint(out, members.len() as u64);
+			for member in members {
+				put_bytes(out, member.as_bytes());
+			}
+			TYPE_SET
+		}
+		DataType::Hash(hash) => {
+			let entries = hash.entries();
+			put_varint(out, entries.len() as u64);
+			for (field, value) in entries {
+				put_bytes(out, field.as_bytes());
+				put_bytes(out, value.as_bytes());
+			}
+			TYPE_HASH
+		}
+		DataType::SortedSet(set) => {
+			put_varint(out, set.len() as u64);
+			for (member, score) in set.iter() {
+				put_bytes(out, member.as_bytes());
+				put_f64(out, score);
+			}
+			TYPE_SORTED_SET
+		}
+		DataType::HyperLogLog(hll) => {
+			hll.encode(out);
+			TYPE_HYPERLOGLOG
+		}
+		DataType::BloomFilter(filter) => {
+			filter.encode(out);
+			TYPE_BLOOM_FILTER
+		}
+		DataType::CuckooFilter(filter) => {
+			filter.encode(out);
+			TYPE_CUCKOO_FILTER
+		}
+		DataType::Json(document) => {
+			out.extend_from_slice(document.to_string().as_bytes());
+			TYPE_JSON
+		}
+	}
+}
+
+// Decode a payload written by `encode_value`, the whole payload must be consumed
+pub fn decode_value(tag: u8, payload: &[u8]) -> Result<DataType, SnapshotError> {
+	let mut decoder = Decoder::new(payload);
+	let value = match tag {
+		TYPE_STRING => return Ok(DataType::String(payload.to_vec())),
+		TYPE_LIST => {
+			let mut items = LinkedList::new();
+			for _ in 0..decoder.varint()? {
+				items.push_back(decoder.string()?);
+			}
+			DataType::List(items)
+		}
+		TYPE_SET => {
+			let set = Set::new();
+			for _ in 0..decoder.varint()? {
+				set.insert(decoder.string()?);
+			}
+			DataType::Set(set)
+		}
+		TYPE_HASH => {
+			let hash = Hash::new();
+			for _ in 0..decoder.varint()? {
+				let field = decoder.string()?;
+				hash.insert(field, decoder.string()?);
+			}
+			DataType::Hash(hash)
+		}
+		TYPE_SORTED_SET => {
+			let mut set = SortedSet::new();
+			for _ in 0..decoder.varint()? {
+				let member = decoder.string()?;
+				set.insert(&member, decoder.f64()?);
+			}
+			DataType::SortedSet(set)
+		}
+		TYPE_HYPERLOGLOG => DataType::HyperLogLog(HyperLogLog::decode(&mut decoder)?),
+		TYPE_BLOOM_FILTER => DataType::BloomFilter(BloomFilter::decode(&mut decoder)?),
+		TYPE_CUCKOO_FILTER => DataType::CuckooFilter(CuckooFilter::decode(&mut decoder)?),
+		TYPE_JSON => {
+			let document = serde_json::from_slice(payload).map_err(|e| SnapshotError::Corrupt(e.to_string()))?;
+			return Ok(DataType::Json(document));
+		}
+		_ => return Err(SnapshotError::UnknownType(tag)),
+	};
+
+	if !decoder.is_empty() {
+		return Err(SnapshotError::Corrupt(String::from("trailing bytes after value")));
+	}
+	Ok(value)
+}
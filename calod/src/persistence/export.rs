@@ -0,0 +1,338 @@
+use std::collections::LinkedList;
+use std::io::{self, BufRead, Write};
+
+use chrono::{Duration, Utc};
+use serde_json::{json, Map, Number, Value};
+use thiserror::Error;
+
+use crate::persistence::encoding::{decode_value, encode_value, from_hex, to_hex, TYPE_BLOOM_FILTER, TYPE_CUCKOO_FILTER, TYPE_HYPERLOGLOG};
+use crate::store::calod_data::{CacheEntry, DataType, Hash, Set};
+use crate::store::calod_store::CalodStore;
+use crate::store::glob::glob_match;
+use crate::store::sorted_set::SortedSet;
+
+pub const CSV_HEADER: &str = "key,type,ttl_ms,encoding,value";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExportFormat {
+	JsonLines,
+	Csv,
+}
+
+impl ExportFormat {
+	pub fn from(str: &str) -> Option<ExportFormat> {
+		match str.to_lowercase().as_str() {
+			"jsonl" | "json" => Some(ExportFormat::JsonLines),
+			"csv" => Some(ExportFormat::Csv),
+			_ => None,
+		}
+	}
+}
+
+#[derive(Debug, Error)]
+pub enum ExportError {
+	#[error("Export I/O error: {0}")]
+	Io(#[from] io::Error),
+
+	#[error("Invalid record on line {line}: {reason}")]
+	Record { line: u64, reason: String },
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct ImportStats {
+	pub keys: u64,
+	// Records whose TTL had already run out
+	pub expired: u64,
+}
+
+// One key as it appears in an export
+// 1. Strings are kept as text, binary strings and the probabilistic types as hex of their snapshot encoding
+// 2. Collections and JSON documents are JSON values
+struct Record {
+	key: String,
+	kind: String,
+	ttl_ms: Option<i64>,
+	hex: bool,
+	value: Value,
+}
+
+// JSON has no infinities, they are written as strings
+fn score_to_json(score: f64) -> Value {
+	Number::from_f64(score).map(Value::Number).unwrap_or_else(|| Value::String(score.to_string()))
+}
+
+fn score_from_json(value: &Value) -> Option<f64> {
+	match value {
+		Value::Number(number) => number.as_f64(),
+		Value::String(text) => text.parse().ok(),
+		_ => None,
+	}
+}
+
+fn strings_from_json(value: &Value) -> Result<Vec<String>, String> {
+	value.as_array().ok_or_else(|| String::from("expected an array"))?
+		.iter()
+		.map(|item| item.as_str().map(str::to_string).ok_or_else(|| String::from("expected an array of strings")))
+		.collect()
+}
+
+impl Record {
+	fn from_entry(key: &str, entry: &CacheEntry, ttl_ms: Option<i64>) -> Record {
+		let mut hex = false;
+		let value = match &entry.value {
+			DataType::String(bytes) => match std::str::from_utf8(bytes) {
+				Ok(text) => Value::String(text.to_string()),
+				Err(_) => {
+					hex = true;
+					Value::String(to_hex(bytes))
+				}
+			},
+			DataType::List(items) => json!(items),
+			DataType::Set(set) => {
+				let mut members = set.members();
+				members.sort();
+				json!(members)
+			}
+			DataType::Hash(hash) => {
+				let mut entries = hash.entries();
+				entries.sort();
+				Value::Object(entries.into_iter().map(|(field, value)| (field, Value::String(value))).collect::<Map<String, Value>>())
+			}
+			DataType::SortedSet(set) => Value::Array(set.iter().map(|(member, score)| json!([member, score_to_json(score)])).collect()),
+			DataType::Json(document) => document.clone(),
+			DataType::HyperLogLog(_) | DataType::BloomFilter(_) | DataType::CuckooFilter(_) => {
+				let mut payload = Vec::new();
+				encode_value(&entry.value, &mut payload);
+				hex = true;
+				Value::String(to_hex(&payload))
+			}
+		};
+		// TYPE calls a HyperLogLog a string, the export keeps it apart so it loads back as one
+		let kind = match &entry.value {
+			DataType::HyperLogLog(_) => "hyperloglog",
+			value => value.type_name(),
+		};
+		Record { key: key.to_string(), kind: kind.to_string(), ttl_ms, hex, value }
+	}
+
+	fn to_value(&self) -> Result<DataType, String> {
+		let text = || self.value.as_str().ok_or_else(|| String::from("expected a string value"));
+		let payload = || from_hex(text()?);
+
+		let value = match self.kind.as_str() {
+			"string" if self.hex => DataType::String(payload()?),
+			"string" => DataType::String(text()?.as_bytes().to_vec()),
+			"list" => DataType::List(strings_from_json(&self.value)?.into_iter().collect::<LinkedList<String>>()),
+			"set" => {
+				let set = Set::new();
+				for member in strings_from_json(&self.value)? {
+					set.insert(member);
+				}
+				DataType::Set(set)
+			}
+			"hash" => {
+				let hash = Hash::new();
+				for (field, value) in self.value.as_object().ok_or_else(|| String::from("expected an object"))? {
+					hash.insert(field.clone(), value.as_str().ok_or_else(|| String::from("expected string hash values"))?.to_string());
+				}
+				DataType::Hash(hash)
+			}
+			"zset" => {
+				let mut set = SortedSet::new();
+				for pair in self.value.as_array().ok_or_else(|| String::from("expected an array"))? {
+					match (pair.get(0).and_then(Value::as_str), pair.get(1).and_then(score_from_json)) {
+						(Some(member), Some(score)) => set.insert(member, score),
+						_ => return Err(String::from("expected [member, score] pairs")),
+					};
+				}
+				DataType::SortedSet(set)
+			}
+			"json" => DataType::Json(self.value.clone()),
+			"hyperloglog" => decode_value(TYPE_HYPERLOGLOG, &payload()?).map_err(|e| e.to_string())?,
+			"bloom" => decode_value(TYPE_BLOOM_FILTER, &payload()?).map_err(|e| e.to_string())?,
+			"cuckoo" => decode_value(TYPE_CUCKOO_FILTER, &payload()?).map_err(|e| e.to_string())?,
+			kind => return Err(format!("unknown type `{}`", kind)),
+		};
+		Ok(value)
+	}
+
+	fn write_json_line<W: Write>(&self, out: &mut W) -> io::Result<()> {
+		let mut line = json!({ "key": self.key, "type": self.kind, "ttl_ms": self.ttl_ms, "value": self.value });
+		if self.hex {
+			line["encoding"] = json!("hex");
+		}
+		writeln!(out, "{}", line)
+	}
+
+	fn from_json_line(line: &str) -> Result<Record, String> {
+		let line: Value = serde_json::from_str(line).map_err(|e| e.to_string())?;
+		let field = |name: &str| line.get(name).and_then(Value::as_str).map(str::to_string).ok_or_else(|| format!("missing `{}`", name));
+		Ok(Record {
+			key: field("key")?,
+			kind: field("type")?,
+			ttl_ms: line.get("ttl_ms").and_then(Value::as_i64),
+			hex: line.get("encoding").and_then(Value::as_str) == Some("hex"),
+			value: line.get("value").cloned().ok_or_else(|| String::from("missing `value`"))?,
+		})
+	}
+
+	// Text and hex values go into the value column as they are, other values as JSON text
+	fn write_csv<W: Write>(&self, out: &mut W) -> io::Result<()> {
+		let value = match (&self.value, is_text_kind(&self.kind)) {
+			(Value::String(text), true) => text.clone(),
+			(value, _) => value.to_string(),
+		};
+		let ttl_ms = self.ttl_ms.map(|ttl| ttl.to_string()).unwrap_or_default();
+		let encoding = if self.hex { "hex" } else { "" };
+		let fields = [self.key.as_str(), self.kind.as_str(), ttl_ms.as_str(), encoding, value.as_str()];
+		writeln!(out, "{}", fields.iter().map(|field| csv_field(field)).collect::<Vec<_>>().join(","))
+	}
+
+	fn from_csv(fields: Vec<String>) -> Result<Record, String> {
+		let [key, kind, ttl_ms, encoding, value]: [String; 5] = fields.try_into()
+			.map_err(|fields: Vec<String>| format!("expected 5 fields, found {}", fields.len()))?;
+		let ttl_ms = match ttl_ms.as_str() {
+			"" => None,
+			ttl => Some(ttl.parse::<i64>().map_err(|_| format!("invalid ttl `{}`", ttl))?),
+		};
+		let hex = encoding == "hex";
+		let value = match is_text_kind(&kind) {
+			true => Value::String(value),
+			false => serde_json::from_str(&value).map_err(|e| e.to_string())?,
+		};
+		Ok(Record { key, kind, ttl_ms, hex, value })
+	}
+}
+
+// Types whose value is a plain or hex string rather than JSON
+fn is_text_kind(kind: &str) -> bool {
+	matches!(kind, "string" | "hyperloglog" | "bloom" | "cuckoo")
+}
+
+// Quote a field when it holds a separator, a quote or a line break
+fn csv_field(field: &str) -> String {
+	if field.contains([',', '"', '\n', '\r']) {
+		format!("\"{}\"", field.replace('"', "\"\""))
+	} else {
+		field.to_string()
+	}
+}
+
+// Read one CSV record, quoted fields may span lines, `None` at the end of the input
+fn read_csv_record<R: BufRead>(input: &mut R, line: &mut u64) -> Result<Option<Vec<String>>, ExportError> {
+	let mut fields = Vec::new();
+	let mut field = String::new();
+	let mut quoted = false;
+	let start = *line + 1;
+
+	loop {
+		let mut text = String::new();
+		if input.read_line(&mut text)? == 0 {
+			return match quoted {
+				true => Err(ExportError::Record { line: start, reason: String::from("unterminated quoted field") }),
+				false => Ok(None),
+			};
+		}
+		*line += 1;
+
+		let mut chars = text.chars().peekable();
+		while let Some(c) = chars.next() {
+			match (quoted, c) {
+				(true, '"') if chars.peek() == Some(&'"') => {
+					chars.next();
+					field.push('"');
+				}
+				(true, '"') => quoted = false,
+				(true, c) => field.push(c),
+				(false, '"') => quoted = true,
+				(false, ',') => fields.push(std::mem::take(&mut field)),
+				(false, '\r') | (false, '\n') => {}
+				(false, c) => field.push(c),
+			}
+		}
+
+		if !quoted {
+			fields.push(field);
+			return Ok(Some(fields));
+		}
+	}
+}
+
+impl CalodStore {
+	// Write every live key matching `pattern` (all keys without one), returns the number written
+	pub fn export<W: Write>(&self, out: &mut W, format: ExportFormat, pattern: Option<&str>) -> Result<u64, ExportError> {
+		let now = Utc::now();
+		let mut exported = 0;
+		if format == ExportFormat::Csv {
+			writeln!(out, "{}", CSV_HEADER)?;
+		}
+
+		self.try_for_each_entry(|key, entry| {
+			if !pattern.is_none_or(|pattern| glob_match(pattern, key)) {
+				return Ok(());
+			}
+			let ttl_ms = entry.ttl.map(|ttl| (ttl - now).num_milliseconds());
+			if ttl_ms.is_some_and(|ttl| ttl <= 0) {
+				return Ok(());
+			}
+
+			let record = Record::from_entry(key, entry, ttl_ms);
+			match format {
+				ExportFormat::JsonLines => record.write_json_line(out)?,
+				ExportFormat::Csv => record.write_csv(out)?,
+			}
+			exported += 1;
+			Ok::<(), ExportError>(())
+		})?;
+		out.flush()?;
+		Ok(exported)
+	}
+
+	// Load an export, keys that already exist are replaced and TTLs count from now
+	pub fn import<R: BufRead>(&self, input: &mut R, format: ExportFormat) -> Result<ImportStats, ExportError> {
+		let mut stats = ImportStats::default();
+		let mut line: u64 = 0;
+
+		if format == ExportFormat::Csv {
+			match read_csv_record(input, &mut line)? {
+				Some(header) if header.join(",") == CSV_HEADER => {}
+				_ => return Err(ExportError::Record { line: 1, reason: format!("expected the header `{}`", CSV_HEADER) }),
+			}
+		}
+
+		loop {
+			let start = line + 1;
+			let record = match format {
+				ExportFormat::JsonLines => {
+					let mut text = String::new();
+					if input.read_line(&mut text)? == 0 {
+						break;
+					}
+					line += 1;
+					if text.trim().is_empty() {
+						continue;
+					}
+					Record::from_json_line(&text)
+				}
+				ExportFormat::Csv => match read_csv_record(input, &mut line)? {
+					None => break,
+					Some(fields) if fields.len() == 1 && fields[0].is_empty() => continue,
+					Some(fields) => Record::from_csv(fields),
+				},
+			};
+
+			let record = record.map_err(|reason| ExportError::Record { line: start, reason })?;
+			if record.ttl_ms.is_some_and(|ttl| ttl <= 0) {
+				stats.expired += 1;
+				continue;
+			}
+			let value = record.to_value().map_err(|reason| ExportError::Record { line: start, reason })?;
+
+			let mut entry = CacheEntry::new(value);
+			entry.ttl = record.ttl_ms.map(|ttl| Utc::now() + Duration::milliseconds(ttl));
+			self.load_entry(record.key, entry);
+			stats.keys += 1;
+		}
+		Ok(stats)
+	}
+}
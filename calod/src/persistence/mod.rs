@@ -0,0 +1,9 @@
+pub mod encoding;
+pub mod storage;
+pub mod sim_storage;
+pub mod snapshot;
+pub mod background_save;
+pub mod aof;
+pub mod rdb;
+pub mod export;
+pub mod dump;
\ No newline at end of file
@@ -0,0 +1,718 @@
+use std::collections::{BTreeMap, LinkedList};
+use std::io::{self, BufReader, Read};
+
+use chrono::{TimeZone, Utc};
+use thiserror::Error;
+
+use crate::store::calod_data::{CacheEntry, DataType, Hash, Set};
+use crate::store::calod_store::CalodStore;
+use crate::store::sorted_set::SortedSet;
+
+pub const RDB_MIN_VERSION: u16 = 9;
+pub const RDB_MAX_VERSION: u16 = 11;
+
+const RDB_BUFFER_SIZE: usize = 8 * 1024 * 1024;
+
+// Opcodes between the keys
+const OPCODE_FUNCTION2: u8 = 0xf5;
+const OPCODE_FUNCTION_PRE_GA: u8 = 0xf6;
+const OPCODE_MODULE_AUX: u8 = 0xf7;
+const OPCODE_IDLE: u8 = 0xf8;
+const OPCODE_FREQ: u8 = 0xf9;
+const OPCODE_AUX: u8 = 0xfa;
+const OPCODE_RESIZEDB: u8 = 0xfb;
+const OPCODE_EXPIRETIME_MS: u8 = 0xfc;
+const OPCODE_EXPIRETIME: u8 = 0xfd;
+const OPCODE_SELECTDB: u8 = 0xfe;
+const OPCODE_EOF: u8 = 0xff;
+
+// Value types
+const TYPE_STRING: u8 = 0;
+const TYPE_LIST: u8 = 1;
+const TYPE_SET: u8 = 2;
+const TYPE_ZSET: u8 = 3;
+const TYPE_HASH: u8 = 4;
+const TYPE_ZSET_2: u8 = 5;
+const TYPE_MODULE_PRE_GA: u8 = 6;
+const TYPE_MODULE_2: u8 = 7;
+const TYPE_HASH_ZIPMAP: u8 = 9;
+const TYPE_LIST_ZIPLIST: u8 = 10;
+const TYPE_SET_INTSET: u8 = 11;
+const TYPE_ZSET_ZIPLIST: u8 = 12;
+const TYPE_HASH_ZIPLIST: u8 = 13;
+const TYPE_LIST_QUICKLIST: u8 = 14;
+const TYPE_STREAM_LISTPACKS: u8 = 15;
+const TYPE_HASH_LISTPACK: u8 = 16;
+const TYPE_ZSET_LISTPACK: u8 = 17;
+const TYPE_LIST_QUICKLIST_2: u8 = 18;
+const TYPE_STREAM_LISTPACKS_2: u8 = 19;
+const TYPE_SET_LISTPACK: u8 = 20;
+const TYPE_STREAM_LISTPACKS_3: u8 = 21;
+
+// Special string encodings
+const ENC_INT8: u8 = 0;
+const ENC_INT16: u8 = 1;
+const ENC_INT32: u8 = 2;
+const ENC_LZF: u8 = 3;
+
+// Module value opcodes
+const MODULE_OPCODE_EOF: u64 = 0;
+const MODULE_OPCODE_SINT: u64 = 1;
+const MODULE_OPCODE_UINT: u64 = 2;
+const MODULE_OPCODE_FLOAT: u64 = 3;
+const MODULE_OPCODE_DOUBLE: u64 = 4;
+const MODULE_OPCODE_STRING: u64 = 5;
+
+// Quicklist node containers
+const QUICKLIST_NODE_PLAIN: u64 = 1;
+const QUICKLIST_NODE_PACKED: u64 = 2;
+
+#[derive(Debug, Error)]
+pub enum RdbError {
+	#[error("RDB I/O error: {0}")]
+	Io(#[from] io::Error),
+
+	#[error("Not a Redis RDB file")]
+	BadMagic,
+
+	#[error("Unsupported RDB version {0}, versions 9 to 11 can be imported")]
+	UnsupportedVersion(u16),
+
+	#[error("RDB file is corrupt: {0}")]
+	Corrupt(String),
+
+	#[error("RDB file contains {0}, which cannot be skipped")]
+	Unskippable(&'static str),
+
+	#[error("RDB checksum mismatch (expected {expected:016x}, got {actual:016x})")]
+	ChecksumMismatch { expected: u64, actual: u64 },
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct RdbImportStats {
+	pub version: u16,
+	pub keys: u64,
+	// Keys whose expiration passed before the import
+	pub expired: u64,
+	// Keys that were not imported, counted by value type or database
+	pub skipped: BTreeMap<String, u64>,
+}
+
+// CRC-64/Jones as used by Redis, reflected, no final xor
+pub fn crc64(mut crc: u64, bytes: &[u8]) -> u64 {
+	for byte in bytes {
+		crc ^= *byte as u64;
+		for _ in 0..8 {
+			crc = if crc & 1 == 1 { (crc >> 1) ^ 0x95ac_9329_ac4b_c9b5 } else { crc >> 1 };
+		}
+	}
+	crc
+}
+
+fn corrupt(reason: &str) -> RdbError {
+	RdbError::Corrupt(reason.to_string())
+}
+
+fn truncated(e: io::Error) -> RdbError {
+	match e.kind() {
+		io::ErrorKind::UnexpectedEof => corrupt("unexpected end of file"),
+		_ => RdbError::Io(e),
+	}
+}
+
+// Binary strings become lossy utf-8, calod keys and collection members are strings
+fn to_string(bytes: Vec<u8>) -> String {
+	String::from_utf8(bytes).unwrap_or_else(|e| String::from_utf8_lossy(e.as_bytes()).to_string())
+}
+
+enum Length {
+	Len(u64),
+	// A string stored as an integer or compressed, with the encoding number
+	Encoded(u8),
+}
+
+enum Decoded {
+	Value(DataType),
+	Unsupported(&'static str),
+}
+
+// Streams the file and keeps the running checksum
+struct RdbReader<R: Read> {
+	inner: R,
+	crc: u64,
+}
+
+impl<R: Read> RdbReader<R> {
+	fn read_exact(&mut self, buf: &mut [u8]) -> Result<(), RdbError> {
+		self.inner.read_exact(buf).map_err(truncated)?;
+		self.crc = crc64(self.crc, buf);
+		Ok(())
+	}
+
+	fn u8(&mut self) -> Result<u8, RdbError> {
+		let mut byte = [0u8; 1];
+		self.read_exact(&mut byte)?;
+		Ok(byte[0])
+	}
+
+	fn array<const N: usize>(&mut self) -> Result<[u8; N], RdbError> {
+		let mut bytes = [0u8; N];
+		self.read_exact(&mut bytes)?;
+		Ok(bytes)
+	}
+
+	// Read `len` bytes without trusting `len` for the allocation size
+	fn bytes(&mut self, len: u64) -> Result<Vec<u8>, RdbError> {
+		let mut bytes = Vec::new();
+		(&mut self.inner).take(len).read_to_end(&mut bytes)?;
+		if (bytes.len() as u64) < len {
+			return Err(corrupt("unexpected end of file"));
+		}
+		self.crc = crc64(self.crc, &bytes);
+		Ok(bytes)
+	}
+
+	// 00 6 bit, 01 14 bit, 0x80 32 bit and 0x81 64 bit big endian lengths, 11 special encodings
+	fn length(&mut self) -> Result<Length, RdbError> {
+		let first = self.u8()?;
+		match first >> 6 {
+			0 => Ok(Length::Len((first & 0x3f) as u64)),
+			1 => Ok(Length::Len((((first & 0x3f) as u64) << 8) | self.u8()? as u64)),
+			2 => match first {
+				0x80 => Ok(Length::Len(u32::from_be_bytes(self.array()?) as u64)),
+				0x81 => Ok(Length::Len(u64::from_be_bytes(self.array()?))),
+				_ => Err(RdbError::Corrupt(format!("invalid length prefix {:#04x}", first))),
+			},
+			_ => Ok(Length::Encoded(first & 0x3f)),
+		}
+	}
+
+	fn len(&mut self) -> Result<u64, RdbError> {
+		match self.length()? {
+			Length::Len(len) => Ok(len),
+			Length::Encoded(_) => Err(corrupt("encoded string where a length was expected")),
+		}
+	}
+
+	fn string(&mut self) -> Result<Vec<u8>, RdbError> {
+		match self.length()? {
+			Length::Len(len) => self.bytes(len),
+			Length::Encoded(ENC_INT8) => Ok((self.u8()? as i8).to_string().into_bytes()),
+			Length::Encoded(ENC_INT16) => Ok(i16::from_le_bytes(self.array()?).to_string().into_bytes()),
+			Length::Encoded(ENC_INT32) => Ok(i32::from_le_bytes(self.array()?).to_string().into_bytes()),
+			Length::Encoded(ENC_LZF) => {
+				let compressed_len = self.len()?;
+				let len = self.len()?;
+				let compressed = self.bytes(compressed_len)?;
+				lzf_decompress(&compressed, len)
+			}
+			Length::Encoded(encoding) => Err(RdbError::Corrupt(format!("unknown string encoding {}", encoding))),
+		}
+	}
+
+	// Scores of the original sorted set type are strings, with 253-255 for nan and the infinities
+	fn string_double(&mut self) -> Result<f64, RdbError> {
+		match self.u8()? {
+			253 => Ok(f64::NAN),
+			254 => Ok(f64::INFINITY),
+			255 => Ok(f64::NEG_INFINITY),
+			len => {
+				let text = self.bytes(len as u64)?;
+				std::str::from_utf8(&text).ok().and_then(|s| s.parse().ok()).ok_or_else(|| corrupt("invalid score"))
+			}
+		}
+	}
+
+	fn strings(&mut self) -> Result<Vec<Vec<u8>>, RdbError> {
+		let count = self.len()?;
+		let mut items = Vec::new();
+		for _ in 0..count {
+			items.push(self.string()?);
+		}
+		Ok(items)
+	}
+
+	// Read one value, types calod has no equivalent for are consumed and reported
+	fn value(&mut self, kind: u8) -> Result<Decoded, RdbError> {
+		let value = match kind {
+			TYPE_STRING => DataType::String(self.string()?),
+			TYPE_LIST => list(self.strings()?),
+			TYPE_SET => set(self.strings()?),
+			TYPE_HASH => hash(self.strings_pairs()?)?,
+			TYPE_ZSET | TYPE_ZSET_2 => {
+				let mut sorted_set = SortedSet::new();
+				for _ in 0..self.len()? {
+					let member = to_string(self.string()?);
+					let score = match kind {
+						TYPE_ZSET => self.string_double()?,
+						_ => f64::from_le_bytes(self.array()?),
+					};
+					sorted_set.insert(&member, score);
+				}
+				DataType::SortedSet(sorted_set)
+			}
+			TYPE_HASH_ZIPMAP => hash(zipmap_entries(&self.string()?)?)?,
+			TYPE_LIST_ZIPLIST => list(ziplist_entries(&self.string()?)?),
+			TYPE_SET_INTSET => set(intset_entries(&self.string()?)?),
+			TYPE_SET_LISTPACK => set(listpack_entries(&self.string()?)?),
+			TYPE_HASH_ZIPLIST => hash(ziplist_entries(&self.string()?)?)?,
+			TYPE_HASH_LISTPACK => hash(listpack_entries(&self.string()?)?)?,
+			TYPE_ZSET_ZIPLIST => sorted_set(ziplist_entries(&self.string()?)?)?,
+			TYPE_ZSET_LISTPACK => sorted_set(listpack_entries(&self.string()?)?)?,
+			TYPE_LIST_QUICKLIST => {
+				let mut items = Vec::new();
+				for _ in 0..self.len()? {
+					items.extend(ziplist_entries(&self.string()?)?);
+				}
+				list(items)
+			}
+			TYPE_LIST_QUICKLIST_2 => {
+				let mut items = Vec::new();
+				for _ in 0..self.len()? {
+					let container = self.len()?;
+					let node = self.string()?;
+					match container {
+						QUICKLIST_NODE_PLAIN => items.push(node),
+						QUICKLIST_NODE_PACKED => items.extend(listpack_entries(&node)?),
+						_ => return Err(RdbError::Corrupt(format!("unknown quicklist container {}", container))),
+					}
+				}
+				list(items)
+			}
+			TYPE_STREAM_LISTPACKS | TYPE_STREAM_LISTPACKS_2 | TYPE_STREAM_LISTPACKS_3 => {
+				self.skip_stream(kind)?;
+				return Ok(Decoded::Unsupported("stream"));
+			}
+			TYPE_MODULE_2 => {
+				self.len()?;
+				self.skip_module_value()?;
+				return Ok(Decoded::Unsupported("module"));
+			}
+			TYPE_MODULE_PRE_GA => return Err(RdbError::Unskippable("a pre-release module value")),
+			_ => return Err(RdbError::Corrupt(format!("unknown value type {}", kind))),
+		};
+		Ok(Decoded::Value(value))
+	}
+
+	fn strings_pairs(&mut self) -> Result<Vec<Vec<u8>>, RdbError> {
+		let count = self.len()?;
+		let mut items = Vec::new();
+		for _ in 0..count {
+			items.push(self.string()?);
+			items.push(self.string()?);
+		}
+		Ok(items)
+	}
+
+	// Consume a stream: its listpacks, metadata and consumer groups
+	fn skip_stream(&mut self, kind: u8) -> Result<(), RdbError> {
+		for _ in 0..self.len()? {
+			self.string()?;
+			self.string()?;
+		}
+		// Length and last id, then first id, max deleted id and entries added
+		for _ in 0..3 {
+			self.len()?;
+		}
+		if kind >= TYPE_STREAM_LISTPACKS_2 {
+			for _ in 0..5 {
+				self.len()?;
+			}
+		}
+
+		for _ in 0..self.len()? {
+			self.string()?;
+			self.len()?;
+			self.len()?;
+			if kind >= TYPE_STREAM_LISTPACKS_2 {
+				self.len()?;
+			}
+			// Pending entries: raw id, delivery time and delivery count
+			for _ in 0..self.len()? {
+				self.array::<24>()?;
+				self.len()?;
+			}
+			// Consumers: name, seen time [, active time] and their pending ids
+			for _ in 0..self.len()? {
+				self.string()?;
+				self.array::<8>()?;
+				if kind >= TYPE_STREAM_LISTPACKS_3 {
+					self.array::<8>()?;
+				}
+				for _ in 0..self.len()? {
+					self.array::<16>()?;
+				}
+			}
+		}
+		Ok(())
+	}
+
+	// Module values are a sequence of typed fields closed by an EOF opcode
+	fn skip_module_value(&mut self) -> Result<(), RdbError> {
+		loop {
+			match self.len()? {
+				MODULE_OPCODE_EOF => return Ok(()),
+				MODULE_OPCODE_SINT | MODULE_OPCODE_UINT => {
+					self.len()?;
+				}
+				MODULE_OPCODE_FLOAT => {
+					self.array::<4>()?;
+				}
+				MODULE_OPCODE_DOUBLE => {
+					self.array::<8>()?;
+				}
+				MODULE_OPCODE_STRING => {
+					self.string()?;
+				}
+				opcode => return Err(RdbError::Corrupt(format!("unknown module opcode {}", opcode))),
+			}
+		}
+	}
+}
+
+fn list(items: Vec<Vec<u8>>) -> DataType {
+	DataType::List(items.into_iter().map(to_string).collect::<LinkedList<String>>())
+}
+
+fn set(members: Vec<Vec<u8>>) -> DataType {
+	let set = Set::new();
+	for member in members {
+		set.insert(to_string(member));
+	}
+	DataType::Set(set)
+}
+
+fn hash(entries: Vec<Vec<u8>>) -> Result<DataType, RdbError> {
+	if !entries.len().is_multiple_of(2) {
+		return Err(corrupt("hash with a field but no value"));
+	}
+	let hash = Hash::new();
+	let mut entries = entries.into_iter();
+	while let (Some(field), Some(value)) = (entries.next(), entries.next()) {
+		hash.insert(to_string(field), to_string(value));
+	}
+	Ok(DataType::Hash(hash))
+}
+
+fn sorted_set(entries: Vec<Vec<u8>>) -> Result<DataType, RdbError> {
+	if !entries.len().is_multiple_of(2) {
+		return Err(corrupt("sorted set member without a score"));
+	}
+	let mut sorted_set = SortedSet::new();
+	let mut entries = entries.into_iter();
+	while let (Some(member), Some(score)) = (entries.next(), entries.next()) {
+		let score = std::str::from_utf8(&score).ok().and_then(|s| s.parse::<f64>().ok()).ok_or_else(|| corrupt("invalid score"))?;
+		sorted_set.insert(&to_string(member), score);
+	}
+	Ok(DataType::SortedSet(sorted_set))
+}
+
+// Decompress an LZF block, `len` is the expected size
+// 1. A control byte below 32 starts a run of ctrl + 1 literal bytes
+// 2. Otherwise the top 3 bits are the match length - 2 (7 reads one more length byte),
+//    the low 5 bits and the next byte the distance back - 1
+pub fn lzf_decompress(input: &[u8], len: u64) -> Result<Vec<u8>, RdbError> {
+	let mut out = Vec::new();
+	let mut pos = 0;
+	let next = |pos: &mut usize| -> Result<usize, RdbError> {
+		let byte = *input.get(*pos).ok_or_else(|| corrupt("truncated lzf block"))?;
+		*pos += 1;
+		Ok(byte as usize)
+	};
+
+	while pos < input.len() {
+		let ctrl = next(&mut pos)?;
+		if ctrl < 32 {
+			let literal = input.get(pos..pos + ctrl + 1).ok_or_else(|| corrupt("truncated lzf literal"))?;
+			out.extend_from_slice(literal);
+			pos += ctrl + 1;
+		} else {
+			let mut run = ctrl >> 5;
+			if run == 7 {
+				run += next(&mut pos)?;
+			}
+			let back = ((ctrl & 0x1f) << 8) + next(&mut pos)? + 1;
+			if back > out.len() {
+				return Err(corrupt("lzf back reference before the start"));
+			}
+			let start = out.len() - back;
+			for i in 0..run + 2 {
+				out.push(out[start + i]);
+			}
+		}
+		if out.len() as u64 > len {
+			return Err(corrupt("lzf block is longer than declared"));
+		}
+	}
+
+	if out.len() as u64 != len {
+		return Err(corrupt("lzf block is shorter than declared"));
+	}
+	Ok(out)
+}
+
+// Bounds checked cursor over a ziplist, listpack, intset or zipmap blob
+struct Blob<'a> {
+	bytes: &'a [u8],
+	pos: usize,
+}
+
+impl<'a> Blob<'a> {
+	fn new(bytes: &'a [u8]) -> Self {
+		Blob { bytes, pos: 0 }
+	}
+
+	fn take(&mut self, len: usize) -> Result<&'a [u8], RdbError> {
+		if self.bytes.len() - self.pos < len {
+			return Err(corrupt("encoded value is truncated"));
+		}
+		let slice = &self.bytes[self.pos..self.pos + len];
+		self.pos += len;
+		Ok(slice)
+	}
+
+	fn u8(&mut self) -> Result<u8, RdbError> {
+		Ok(self.take(1)?[0])
+	}
+
+	fn u32_le(&mut self) -> Result<u32, RdbError> {
+		Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+	}
+
+	// Little endian two's complement integer of `len` bytes, as a decimal string
+	fn int_le(&mut self, len: usize) -> Result<Vec<u8>, RdbError> {
+		let mut value: i64 = 0;
+		for (i, byte) in self.take(len)?.iter().enumerate() {
+			value |= (*byte as i64) << (8 * i);
+		}
+		let shift = 64 - 8 * len as u32;
+		Ok(((value << shift) >> shift).to_string().into_bytes())
+	}
+
+	fn string(&mut self, len: usize) -> Result<Vec<u8>, RdbError> {
+		Ok(self.take(len)?.to_vec())
+	}
+}
+
+// Ziplist: zlbytes, zltail, zllen, then `prevlen encoding data` entries up to 0xff
+pub fn ziplist_entries(bytes: &[u8]) -> Result<Vec<Vec<u8>>, RdbError> {
+	let mut blob = Blob::new(bytes);
+	blob.take(10)?;
+	let mut entries = Vec::new();
+
+	loop {
+		match blob.u8()? {
+			0xff => return Ok(entries),
+			0xfe => {
+				blob.take(4)?;
+			}
+			_ => {}
+		}
+
+		let encoding = blob.u8()?;
+		let entry = match encoding >> 6 {
+			0 => blob.string((encoding & 0x3f) as usize)?,
+			1 => {
+				let len = (((encoding & 0x3f) as usize) << 8) | blob.u8()? as usize;
+				blob.string(len)?
+			}
+			2 => {
+				let len = u32::from_be_bytes(blob.take(4)?.try_into().unwrap()) as usize;
+				blob.string(len)?
+			}
+			_ => match encoding {
+				0xc0 => blob.int_le(2)?,
+				0xd0 => blob.int_le(4)?,
+				0xe0 => blob.int_le(8)?,
+				0xf0 => blob.int_le(3)?,
+				0xfe => blob.int_le(1)?,
+				0xf1..=0xfd => ((encoding & 0x0f) - 1).to_string().into_bytes(),
+				_ => return Err(RdbError::Corrupt(format!("unknown ziplist encoding {:#04x}", encoding))),
+			},
+		};
+		entries.push(entry);
+	}
+}
+
+// Listpack: total bytes, element count, then `encoding data backlen` entries up to 0xff
+pub fn listpack_entries(bytes: &[u8]) -> Result<Vec<Vec<u8>>, RdbError> {
+	let mut blob = Blob::new(bytes);
+	blob.take(6)?;
+	let mut entries = Vec::new();
+
+	loop {
+		let encoding = blob.u8()?;
+		if encoding == 0xff {
+			return Ok(entries);
+		}
+
+		let (entry, encoded_len) = if encoding & 0x80 == 0 {
+			((encoding & 0x7f).to_string().into_bytes(), 1)
+		} else if encoding & 0xc0 == 0x80 {
+			let len = (encoding & 0x3f) as usize;
+			(blob.string(len)?, 1 + len)
+		} else if encoding & 0xe0 == 0xc0 {
+			let value = (((encoding & 0x1f) as i64) << 8) | blob.u8()? as i64;
+			let value = if value >= 1 << 12 { value - (1 << 13) } else { value };
+			(value.to_string().into_bytes(), 2)
+		} else if encoding & 0xf0 == 0xe0 {
+			let len = (((encoding & 0x0f) as usize) << 8) | blob.u8()? as usize;
+			(blob.string(len)?, 2 + len)
+		} else {
+			match encoding {
+				0xf0 => {
+					let len = blob.u32_le()? as usize;
+					(blob.string(len)?, 5 + len)
+				}
+				0xf1 => (blob.int_le(2)?, 3),
+				0xf2 => (blob.int_le(3)?, 4),
+				0xf3 => (blob.int_le(4)?, 5),
+				0xf4 => (blob.int_le(8)?, 9),
+				_ => return Err(RdbError::Corrupt(format!("unknown listpack encoding {:#04x}", encoding))),
+			}
+		};
+
+		// The back length repeats the entry size in 7 bit groups
+		let backlen = match encoded_len {
+			0..=127 => 1,
+			128..=16382 => 2,
+			16383..=2097150 => 3,
+			2097151..=268435454 => 4,
+			_ => 5,
+		};
+		blob.take(backlen)?;
+		entries.push(entry);
+	}
+}
+
+// Intset: integer width, count, then sorted little endian integers
+pub fn intset_entries(bytes: &[u8]) -> Result<Vec<Vec<u8>>, RdbError> {
+	let mut blob = Blob::new(bytes);
+	let width = blob.u32_le()? as usize;
+	if !matches!(width, 2 | 4 | 8) {
+		return Err(RdbError::Corrupt(format!("invalid intset width {}", width)));
+	}
+	let count = blob.u32_le()?;
+	(0..count).map(|_| blob.int_le(width)).collect()
+}
+
+// Zipmap: count, then `len key len free value padding` pairs up to 0xff
+pub fn zipmap_entries(bytes: &[u8]) -> Result<Vec<Vec<u8>>, RdbError> {
+	let mut blob = Blob::new(bytes);
+	blob.u8()?;
+	let mut entries = Vec::new();
+
+	let length = |blob: &mut Blob| -> Result<Option<usize>, RdbError> {
+		match blob.u8()? {
+			0xff => Ok(None),
+			0xfe => Ok(Some(blob.u32_le()? as usize)),
+			len => Ok(Some(len as usize)),
+		}
+	};
+
+	while let Some(key_len) = length(&mut blob)? {
+		entries.push(blob.string(key_len)?);
+		let value_len = length(&mut blob)?.ok_or_else(|| corrupt("zipmap key without a value"))?;
+		let free = blob.u8()? as usize;
+		entries.push(blob.string(value_len)?);
+		blob.take(free)?;
+	}
+	Ok(entries)
+}
+
+impl CalodStore {
+	// Import a Redis RDB file
+	// 1. Keys go to the database of the same index, databases beyond ours and types calod lacks are counted as skipped
+	// 2. Keys that already expired are dropped
+	// 3. The CRC64 footer is checked unless the file was written without one
+	pub fn import_rdb(&self, path: &str) -> Result<RdbImportStats, RdbError> {
+		let file = self.storage().open(path)?;
+		let mut reader = RdbReader { inner: BufReader::with_capacity(RDB_BUFFER_SIZE, file), crc: 0 };
+		let mut stats = RdbImportStats::default();
+
+		let header: [u8; 9] = reader.array().map_err(|_| RdbError::BadMagic)?;
+		if &header[..5] != b"REDIS" {
+			return Err(RdbError::BadMagic);
+		}
+		stats.version = std::str::from_utf8(&header[5..]).ok().and_then(|v| v.parse().ok()).ok_or(RdbError::BadMagic)?;
+		if !(RDB_MIN_VERSION..=RDB_MAX_VERSION).contains(&stats.version) {
+			return Err(RdbError::UnsupportedVersion(stats.version));
+		}
+
+		let now = Utc::now().timestamp_millis();
+		let databases = self.databases();
+		let mut db = 0;
+		let mut expire_at: Option<i64> = None;
+		loop {
+			match reader.u8()? {
+				OPCODE_EOF => break,
+				OPCODE_SELECTDB => db = reader.len()?,
+				OPCODE_RESIZEDB => {
+					reader.len()?;
+					reader.len()?;
+				}
+				OPCODE_EXPIRETIME => expire_at = Some(u32::from_le_bytes(reader.array()?) as i64 * 1000),
+				OPCODE_EXPIRETIME_MS => expire_at = Some(i64::from_le_bytes(reader.array()?)),
+				OPCODE_AUX => {
+					reader.string()?;
+					reader.string()?;
+				}
+				OPCODE_FREQ => {
+					reader.u8()?;
+				}
+				OPCODE_IDLE => {
+					reader.len()?;
+				}
+				OPCODE_MODULE_AUX => {
+					// Module id, `when` opcode and `when`, then the module's own fields
+					for _ in 0..3 {
+						reader.len()?;
+					}
+					reader.skip_module_value()?;
+				}
+				OPCODE_FUNCTION2 => {
+					reader.string()?;
+				}
+				OPCODE_FUNCTION_PRE_GA => return Err(RdbError::Unskippable("a pre-release function")),
+				kind => {
+					let key = to_string(reader.string()?);
+					let value = reader.value(kind)?;
+					let expire_at = expire_at.take();
+
+					let (value, database) = match (value, databases.get(db as usize)) {
+						(Decoded::Value(value), Some(database)) => (value, database),
+						(Decoded::Value(_), None) => {
+							*stats.skipped.entry(format!("database {}", db)).or_insert(0) += 1;
+							continue;
+						}
+						(Decoded::Unsupported(kind), _) => {
+							*stats.skipped.entry(kind.to_string()).or_insert(0) += 1;
+							continue;
+						}
+					};
+					if expire_at.is_some_and(|expire_at| expire_at <= now) {
+						stats.expired += 1;
+						continue;
+					}
+
+					let mut entry = CacheEntry::new(value);
+					entry.ttl = match expire_at {
+						Some(millis) => Some(Utc.timestamp_millis_opt(millis).single().ok_or_else(|| corrupt("invalid expiry time"))?),
+						None => None,
+					};
+					database.load_entry(key, entry);
+					stats.keys += 1;
+				}
+			}
+		}
+
+		let actual = reader.crc;
+		let mut expected = [0u8; 8];
+		reader.inner.read_exact(&mut expected).map_err(truncated)?;
+		let expected = u64::from_le_bytes(expected);
+		if expected != 0 && expected != actual {
+			return Err(RdbError::ChecksumMismatch { expected, actual });
+		}
+		Ok(stats)
+	}
+}
@@ -0,0 +1,261 @@
+use std::collections::HashMap;
+use std::io::{self, Cursor, Read, Write};
+use std::sync::{Arc, Mutex};
+
+use crate::persistence::storage::{Storage, StorageFile};
+
+// What survives of the writes that were not synced when the simulated machine crashes
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CrashMode {
+	// Every unsynced byte is lost
+	DropUnsynced,
+	// The disk happened to flush everything
+	KeepUnsynced,
+	// A random prefix of the unsynced tail of every file made it to disk, as after a torn write
+	TornUnsynced(u64),
+}
+
+#[derive(Debug, Default)]
+struct SimFile {
+	data: Vec<u8>,
+	// What the file held after its last sync, the only content guaranteed to survive a crash
+	synced: Vec<u8>,
+}
+
+#[derive(Debug, Default)]
+struct SimState {
+	// Paths point at inodes so open handles keep writing to a file after it is renamed
+	paths: HashMap<String, u64>,
+	inodes: HashMap<u64, SimFile>,
+	next_inode: u64,
+	// Bytes writes may still store before they start failing, `None` for no limit
+	write_budget: Option<u64>,
+	fail_syncs: bool,
+}
+
+impl SimState {
+	fn inode(&self, path: &str) -> io::Result<u64> {
+		self.paths.get(path).copied().ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("{}: no such file", path)))
+	}
+
+	fn new_inode(&mut self, path: &str) -> u64 {
+		self.next_inode += 1;
+		self.inodes.insert(self.next_inode, SimFile::default());
+		self.paths.insert(path.to_string(), self.next_inode);
+		self.next_inode
+	}
+}
+
+// In-memory filesystem for crash recovery tests
+// 1. Writes land in the file but only become durable once the file is synced
+// 2. Renames and removals are atomic and durable, like on a journaling filesystem
+// 3. `crash` throws away unsynced data the way a power loss would, the faults below
+//    make writes and syncs fail or damage files directly
+#[derive(Debug, Clone, Default)]
+pub struct SimStorage {
+	state: Arc<Mutex<SimState>>,
+}
+
+impl SimStorage {
+	pub fn new() -> Self {
+		SimStorage::default()
+	}
+
+	// Simulate a power loss and the restart after it
+	pub fn crash(&self, mode: CrashMode) {
+		let mut state = self.state.lock().unwrap();
+		let mut rng = match mode {
+			CrashMode::TornUnsynced(seed) => seed | 1,
+			_ => 1,
+		};
+
+		// Surviving files get new inodes so handles opened before the crash fail
+		let mut paths: Vec<(String, u64)> = state.paths.drain().collect();
+		paths.sort();
+		let mut survivors = HashMap::new();
+		for (path, inode) in paths {
+			let mut file = state.inodes.remove(&inode).unwrap();
+			let appended = file.data.starts_with(&file.synced);
+			let kept = match mode {
+				CrashMode::DropUnsynced => 0,
+				CrashMode::KeepUnsynced => file.data.len(),
+				CrashMode::TornUnsynced(_) if appended => {
+					let unsynced = (file.data.len() - file.synced.len()) as u64;
+					(xorshift(&mut rng) % (unsynced + 1)) as usize
+				}
+				CrashMode::TornUnsynced(_) => 0,
+			};
+
+			if mode == CrashMode::KeepUnsynced {
+				file.synced = file.data.clone();
+			} else if appended {
+				let len = file.synced.len() + kept;
+				file.synced = file.data[..len].to_vec();
+			}
+			file.data = file.synced.clone();
+
+			state.next_inode += 1;
+			let inode = state.next_inode;
+			state.paths.insert(path, inode);
+			survivors.insert(inode, file);
+		}
+
+		state.inodes = survivors;
+		state.write_budget = None;
+		state.fail_syncs = false;
+	}
+
+	// Let writes store `bytes` more bytes in total, then fail them like a full disk
+	pub fn fail_writes_after(&self, bytes: u64) {
+		self.state.lock().unwrap().write_budget = Some(bytes);
+	}
+
+	pub fn fail_syncs(&self, fail: bool) {
+		self.state.lock().unwrap().fail_syncs = fail;
+	}
+
+	// Current content of a file, synced or not
+	pub fn read(&self, path: &str) -> Option<Vec<u8>> {
+		let state = self.state.lock().unwrap();
+		let inode = state.inode(path).ok()?;
+		Some(state.inodes[&inode].data.clone())
+	}
+
+	// Replace a file with durable content
+	pub fn write(&self, path: &str, bytes: &[u8]) {
+		let mut state = self.state.lock().unwrap();
+		let inode = state.new_inode(path);
+		let file = state.inodes.get_mut(&inode).unwrap();
+		file.data = bytes.to_vec();
+		file.synced = bytes.to_vec();
+	}
+
+	// Cut a file at `len` bytes, durably, as a damaged disk would
+	pub fn truncate_at(&self, path: &str, len: usize) {
+		self.damage(path, |data| data.truncate(len));
+	}
+
+	// Flip every bit of the byte at `offset`
+	pub fn corrupt(&self, path: &str, offset: usize) {
+		self.damage(path, |data| {
+			if let Some(byte) = data.get_mut(offset) {
+				*byte ^= 0xff;
+			}
+		});
+	}
+
+	fn damage(&self, path: &str, f: impl Fn(&mut Vec<u8>)) {
+		let mut state = self.state.lock().unwrap();
+		if let Ok(inode) = state.inode(path) {
+			let file = state.inodes.get_mut(&inode).unwrap();
+			f(&mut file.data);
+			f(&mut file.synced);
+		}
+	}
+}
+
+fn xorshift(state: &mut u64) -> u64 {
+	*state ^= *state << 13;
+	*state ^= *state >> 7;
+	*state ^= *state << 17;
+	*state
+}
+
+impl Storage for SimStorage {
+	fn create(&self, path: &str) -> io::Result<Box<dyn StorageFile>> {
+		let inode = self.state.lock().unwrap().new_inode(path);
+		Ok(Box::new(SimHandle { state: self.state.clone(), inode }))
+	}
+
+	fn append(&self, path: &str) -> io::Result<Box<dyn StorageFile>> {
+		let mut state = self.state.lock().unwrap();
+		let inode = match state.inode(path) {
+			Ok(inode) => inode,
+			Err(_) => state.new_inode(path),
+		};
+		Ok(Box::new(SimHandle { state: self.state.clone(), inode }))
+	}
+
+	fn open(&self, path: &str) -> io::Result<Box<dyn Read + Send>> {
+		let state = self.state.lock().unwrap();
+		let inode = state.inode(path)?;
+		Ok(Box::new(Cursor::new(state.inodes[&inode].data.clone())))
+	}
+
+	fn rename(&self, from: &str, to: &str) -> io::Result<()> {
+		let mut state = self.state.lock().unwrap();
+		let inode = state.inode(from)?;
+		state.paths.remove(from);
+		state.paths.insert(to.to_string(), inode);
+		Ok(())
+	}
+
+	fn remove(&self, path: &str) -> io::Result<()> {
+		let mut state = self.state.lock().unwrap();
+		state.inode(path)?;
+		state.paths.remove(path);
+		Ok(())
+	}
+
+	fn truncate(&self, path: &str, len: u64) -> io::Result<()> {
+		let mut state = self.state.lock().unwrap();
+		let inode = state.inode(path)?;
+		let file = state.inodes.get_mut(&inode).unwrap();
+		file.data.truncate(len as usize);
+		file.synced = file.data.clone();
+		Ok(())
+	}
+
+	fn len(&self, path: &str) -> io::Result<u64> {
+		let state = self.state.lock().unwrap();
+		let inode = state.inode(path)?;
+		Ok(state.inodes[&inode].data.len() as u64)
+	}
+
+	fn exists(&self, path: &str) -> bool {
+		self.state.lock().unwrap().paths.contains_key(path)
+	}
+}
+
+// An open file, writes always go to the end like the append only file and snapshots need
+#[derive(Debug)]
+struct SimHandle {
+	state: Arc<Mutex<SimState>>,
+	inode: u64,
+}
+
+impl Write for SimHandle {
+	fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+		let mut state = self.state.lock().unwrap();
+		let len = match state.write_budget {
+			Some(0) => return Err(io::Error::other("simulated write failure")),
+			Some(budget) => budget.min(buf.len() as u64) as usize,
+			None => buf.len(),
+		};
+		if let Some(budget) = state.write_budget.as_mut() {
+			*budget -= len as u64;
+		}
+
+		let file = state.inodes.get_mut(&self.inode)
+			.ok_or_else(|| io::Error::other("file lost in a crash"))?;
+		file.data.extend_from_slice(&buf[..len]);
+		Ok(len)
+	}
+
+	fn flush(&mut self) -> io::Result<()> {
+		Ok(())
+	}
+}
+
+impl StorageFile for SimHandle {
+	fn sync(&mut self) -> io::Result<()> {
+		let mut state = self.state.lock().unwrap();
+		if state.fail_syncs {
+			return Err(io::Error::other("simulated fsync failure"));
+		}
+		let file = state.inodes.get_mut(&self.inode)
+			.ok_or_else(|| io::Error::other("file lost in a crash"))?;
+		file.synced = file.data.clone();
+		Ok(())
+	}
+}
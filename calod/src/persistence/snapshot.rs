@@ -0,0 +1,281 @@
+use std::io::{self, Read, Write};
+
+use chrono::{DateTime, TimeZone, Utc};
+use crc32fast::Hasher;
+use thiserror::Error;
+
+use crate::persistence::encoding::{decode_value, encode_value, put_bytes, put_u8, put_u64, put_varint};
+use crate::store::calod_data::CacheEntry;
+
+// File layout
+// 1. Header: magic, format version (u16 LE), creation time in unix millis (i64 LE)
+// 2. Entries: type tag, expiry flag [+ unix millis], key and payload as varint length prefixed bytes
+//    Since version 2 `SNAPSHOT_SELECT_DB` and a varint index put the entries after it in another database, 0 until then
+// 3. Footer: `SNAPSHOT_EOF` followed by the CRC32 of every byte before the checksum (u32 LE)
+pub const SNAPSHOT_MAGIC: &[u8; 8] = b"CALODSNP";
+pub const SNAPSHOT_VERSION: u16 = 2;
+
+const SNAPSHOT_EOF: u8 = 0xff;
+const SNAPSHOT_SELECT_DB: u8 = 0xfe;
+const NO_EXPIRY: u8 = 0;
+const HAS_EXPIRY: u8 = 1;
+
+#[derive(Debug, Error)]
+pub enum SnapshotError {
+	#[error("Snapshot I/O error: {0}")]
+	Io(#[from] io::Error),
+
+	#[error("Not a calod snapshot")]
+	BadMagic,
+
+	#[error("Unsupported snapshot version {0}")]
+	UnsupportedVersion(u16),
+
+	#[error("Unknown value type tag {0}")]
+	UnknownType(u8),
+
+	#[error("Snapshot is corrupt: {0}")]
+	Corrupt(String),
+
+	#[error("Snapshot holds database {0} but the server has fewer databases")]
+	DatabaseOutOfRange(usize),
+
+	#[error("Background save already in progress")]
+	SaveInProgress,
+
+	#[error("Snapshot checksum mismatch (expected {expected:08x}, got {actual:08x})")]
+	ChecksumMismatch { expected: u32, actual: u32 },
+}
+
+// Pass-through writer that checksums everything written
+struct ChecksumWriter<W: Write> {
+	inner: W,
+	hasher: Hasher,
+}
+
+impl<W: Write> Write for ChecksumWriter<W> {
+	fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+		let written = self.inner.write(buf)?;
+		self.hasher.update(&buf[..written]);
+		Ok(written)
+	}
+
+	fn flush(&mut self) -> io::Result<()> {
+		self.inner.flush()
+	}
+}
+
+// Pass-through reader that checksums everything read
+struct ChecksumReader<R: Read> {
+	inner: R,
+	hasher: Hasher,
+}
+
+impl<R: Read> Read for ChecksumReader<R> {
+	fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+		let read = self.inner.read(buf)?;
+		self.hasher.update(&buf[..read]);
+		Ok(read)
+	}
+}
+
+// Streams entries into a snapshot, nothing but the current entry is buffered
+pub struct SnapshotWriter<W: Write> {
+	out: ChecksumWriter<W>,
+	scratch: Vec<u8>,
+	entries: u64,
+	db: usize,
+}
+
+impl<W: Write> SnapshotWriter<W> {
+	// Start a snapshot by writing the header
+	pub fn new(inner: W) -> Result<Self, SnapshotError> {
+		let mut out = ChecksumWriter { inner, hasher: Hasher::new() };
+		out.write_all(SNAPSHOT_MAGIC)?;
+		out.write_all(&SNAPSHOT_VERSION.to_le_bytes())?;
+		out.write_all(&Utc::now().timestamp_millis().to_le_bytes())?;
+
+		Ok(SnapshotWriter { out, scratch: Vec::new(), entries: 0, db: 0 })
+	}
+
+	// Write the following entries into database `db`, nothing is written while it stays the same
+	pub fn select_db(&mut self, db: usize) -> Result<(), SnapshotError> {
+		if db != self.db {
+			self.scratch.clear();
+			put_u8(&mut self.scratch, SNAPSHOT_SELECT_DB);
+			put_varint(&mut self.scratch, db as u64);
+			self.out.write_all(&self.scratch)?;
+			self.db = db;
+		}
+		Ok(())
+	}
+
+	// Append a single key
+	// 1. Encode the value first so its length prefix is known
+	// 2. Write tag, expiry, key and payload in one go
+	pub fn write_entry(&mut self, key: &str, entry: &CacheEntry) -> Result<(), SnapshotError> {
+		let mut payload = Vec::new();
+		let tag = encode_value(&entry.value, &mut payload);
+
+		self.scratch.clear();
+		put_u8(&mut self.scratch, tag);
+		match entry.ttl {
+			Some(expire_at) => {
+				put_u8(&mut self.scratch, HAS_EXPIRY);
+				put_u64(&mut self.scratch, expire_at.timestamp_millis() as u64);
+			}
+			None => put_u8(&mut self.scratch, NO_EXPIRY),
+		}
+		put_bytes(&mut self.scratch, key.as_bytes());
+		put_varint(&mut self.scratch, payload.len() as u64);
+
+		self.out.write_all(&self.scratch)?;
+		self.out.write_all(&payload)?;
+		self.entries += 1;
+		Ok(())
+	}
+
+	pub fn entries(&self) -> u64 {
+		self.entries
+	}
+
+	// Write the footer and hand back the underlying writer
+	pub fn finish(mut self) -> Result<W, SnapshotError> {
+		self.out.write_all(&[SNAPSHOT_EOF])?;
+		let checksum = self.out.hasher.clone().finalize();
+
+		let mut inner = self.out.inner;
+		inner.write_all(&checksum.to_le_bytes())?;
+		inner.flush()?;
+		Ok(inner)
+	}
+}
+
+// Streams entries out of a snapshot, the checksum is verified once the footer is reached
+pub struct SnapshotReader<R: Read> {
+	input: ChecksumReader<R>,
+	created_at: DateTime<Utc>,
+	finished: bool,
+	db: usize,
+}
+
+impl<R: Read> SnapshotReader<R> {
+	// Open a snapshot by validating the header
+	pub fn new(inner: R) -> Result<Self, SnapshotError> {
+		let mut input = ChecksumReader { inner, hasher: Hasher::new() };
+
+		let mut magic = [0u8; 8];
+		input.read_exact(&mut magic).map_err(|_| SnapshotError::BadMagic)?;
+		if &magic != SNAPSHOT_MAGIC {
+			return Err(SnapshotError::BadMagic);
+		}
+
+		let mut version = [0u8; 2];
+		input.read_exact(&mut version)?;
+		let version = u16::from_le_bytes(version);
+		if version == 0 || version > SNAPSHOT_VERSION {
+			return Err(SnapshotError::UnsupportedVersion(version));
+		}
+
+		let mut created_at = [0u8; 8];
+		input.read_exact(&mut created_at)?;
+		let created_at = Utc.timestamp_millis_opt(i64::from_le_bytes(created_at)).single()
+			.ok_or_else(|| SnapshotError::Corrupt(String::from("invalid creation time")))?;
+
+		Ok(SnapshotReader { input, created_at, finished: false, db: 0 })
+	}
+
+	pub fn created_at(&self) -> DateTime<Utc> {
+		self.created_at
+	}
+
+	// Database of the entry `next_entry` returned last
+	pub fn db(&self) -> usize {
+		self.db
+	}
+
+	fn read_u8(&mut self) -> Result<u8, SnapshotError> {
+		let mut byte = [0u8; 1];
+		self.input.read_exact(&mut byte).map_err(truncated)?;
+		Ok(byte[0])
+	}
+
+	fn read_varint(&mut self) -> Result<u64, SnapshotError> {
+		let mut value: u64 = 0;
+		for shift in (0..64).step_by(7) {
+			let byte = self.read_u8()?;
+			value |= ((byte & 0x7f) as u64) << shift;
+			if byte & 0x80 == 0 {
+				return Ok(value);
+			}
+		}
+		Err(SnapshotError::Corrupt(String::from("varint is too long")))
+	}
+
+	// Read a length prefixed block without trusting the prefix for the allocation size
+	fn read_block(&mut self) -> Result<Vec<u8>, SnapshotError> {
+		let len = self.read_varint()?;
+		let mut block = Vec::new();
+		(&mut self.input).take(len).read_to_end(&mut block)?;
+		if (block.len() as u64) < len {
+			return Err(SnapshotError::Corrupt(String::from("unexpected end of snapshot")));
+		}
+		Ok(block)
+	}
+
+	// Read the next entry, `None` once the footer has been read and verified
+	pub fn next_entry(&mut self) -> Result<Option<(String, CacheEntry)>, SnapshotError> {
+		if self.finished {
+			return Ok(None);
+		}
+
+		let mut tag = self.read_u8()?;
+		while tag == SNAPSHOT_SELECT_DB {
+			self.db = usize::try_from(self.read_varint()?).map_err(|_| SnapshotError::Corrupt(String::from("invalid database index")))?;
+			tag = self.read_u8()?;
+		}
+		if tag == SNAPSHOT_EOF {
+			self.verify_checksum()?;
+			self.finished = true;
+			return Ok(None);
+		}
+
+		let ttl = match self.read_u8()? {
+			NO_EXPIRY => None,
+			HAS_EXPIRY => {
+				let mut millis = [0u8; 8];
+				self.input.read_exact(&mut millis).map_err(truncated)?;
+				let millis = i64::from_le_bytes(millis);
+				Some(Utc.timestamp_millis_opt(millis).single()
+					.ok_or_else(|| SnapshotError::Corrupt(String::from("invalid expiry time")))?)
+			}
+			flag => return Err(SnapshotError::Corrupt(format!("invalid expiry flag {}", flag))),
+		};
+
+		let key = String::from_utf8(self.read_block()?).map_err(|_| SnapshotError::Corrupt(String::from("key is not valid utf-8")))?;
+		let payload = self.read_block()?;
+
+		let mut entry = CacheEntry::new(decode_value(tag, &payload)?);
+		entry.ttl = ttl;
+		Ok(Some((key, entry)))
+	}
+
+	fn verify_checksum(&mut self) -> Result<(), SnapshotError> {
+		let actual = self.input.hasher.clone().finalize();
+		let mut expected = [0u8; 4];
+		self.input.inner.read_exact(&mut expected).map_err(truncated)?;
+		let expected = u32::from_le_bytes(expected);
+
+		if expected != actual {
+			return Err(SnapshotError::ChecksumMismatch { expected, actual });
+		}
+		Ok(())
+	}
+}
+
+fn truncated(e: io::Error) -> SnapshotError {
+	match e.kind() {
+		io::ErrorKind::UnexpectedEof => SnapshotError::Corrupt(String::from("unexpected end of snapshot")),
+		_ => SnapshotError::Io(e),
+	}
+}
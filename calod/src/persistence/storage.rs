@@ -0,0 +1,78 @@
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::path::Path;
+
+// The files snapshots and the append only file are read from and written to
+// Servers use `OsStorage`, the crash recovery tests swap in `SimStorage`
+pub trait Storage: Send + Sync + fmt::Debug {
+	// Create a file for writing, an existing file is truncated
+	fn create(&self, path: &str) -> io::Result<Box<dyn StorageFile>>;
+
+	// Open a file for appending, it is created if missing
+	fn append(&self, path: &str) -> io::Result<Box<dyn StorageFile>>;
+
+	fn open(&self, path: &str) -> io::Result<Box<dyn Read + Send>>;
+
+	// Atomically replace `to` with `from`
+	fn rename(&self, from: &str, to: &str) -> io::Result<()>;
+
+	fn remove(&self, path: &str) -> io::Result<()>;
+
+	// Cut a file down to `len` bytes and make the new length durable
+	fn truncate(&self, path: &str, len: u64) -> io::Result<()>;
+
+	fn len(&self, path: &str) -> io::Result<u64>;
+
+	fn exists(&self, path: &str) -> bool;
+}
+
+pub trait StorageFile: Write + Send + fmt::Debug {
+	// Make everything written so far durable
+	fn sync(&mut self) -> io::Result<()>;
+}
+
+#[derive(Debug, Default)]
+pub struct OsStorage;
+
+impl Storage for OsStorage {
+	fn create(&self, path: &str) -> io::Result<Box<dyn StorageFile>> {
+		Ok(Box::new(File::create(path)?))
+	}
+
+	fn append(&self, path: &str) -> io::Result<Box<dyn StorageFile>> {
+		Ok(Box::new(OpenOptions::new().create(true).append(true).open(path)?))
+	}
+
+	fn open(&self, path: &str) -> io::Result<Box<dyn Read + Send>> {
+		Ok(Box::new(File::open(path)?))
+	}
+
+	fn rename(&self, from: &str, to: &str) -> io::Result<()> {
+		fs::rename(from, to)
+	}
+
+	fn remove(&self, path: &str) -> io::Result<()> {
+		fs::remove_file(path)
+	}
+
+	fn truncate(&self, path: &str, len: u64) -> io::Result<()> {
+		let file = OpenOptions::new().write(true).open(path)?;
+		file.set_len(len)?;
+		file.sync_all()
+	}
+
+	fn len(&self, path: &str) -> io::Result<u64> {
+		Ok(fs::metadata(path)?.len())
+	}
+
+	fn exists(&self, path: &str) -> bool {
+		Path::new(path).exists()
+	}
+}
+
+impl StorageFile for File {
+	fn sync(&mut self) -> io::Result<()> {
+		self.sync_all()
+	}
+}
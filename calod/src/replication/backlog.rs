@@ -0,0 +1,48 @@
+use std::collections::VecDeque;
+
+pub const DEFAULT_BACKLOG_SIZE: usize = 1024 * 1024;
+
+// The most recent bytes of the replication stream, a follower that reconnects
+// with an offset still covered here only needs the bytes it missed
+#[derive(Debug)]
+pub struct ReplicationBacklog {
+	buffer: VecDeque<u8>,
+	capacity: usize,
+	// Stream offset of the first byte in `buffer`
+	start: u64,
+}
+
+impl ReplicationBacklog {
+	pub fn new(capacity: usize, offset: u64) -> Self {
+		ReplicationBacklog { buffer: VecDeque::new(), capacity, start: offset }
+	}
+
+	// Offset one past the last byte written
+	pub fn end(&self) -> u64 {
+		self.start + self.buffer.len() as u64
+	}
+
+	// Append to the stream, the oldest bytes fall off once the backlog is full
+	pub fn append(&mut self, bytes: &[u8]) {
+		self.buffer.extend(bytes);
+		let overflow = self.buffer.len().saturating_sub(self.capacity);
+		if overflow > 0 {
+			self.buffer.drain(..overflow);
+			self.start += overflow as u64;
+		}
+	}
+
+	// Bytes from `offset` to the end, `None` once the backlog no longer covers it
+	pub fn since(&self, offset: u64) -> Option<Vec<u8>> {
+		if offset < self.start || offset > self.end() {
+			return None;
+		}
+		Some(self.buffer.range((offset - self.start) as usize..).copied().collect())
+	}
+
+	// Forget the history and continue at `offset`, after a full resync
+	pub fn reset(&mut self, offset: u64) {
+		self.buffer.clear();
+		self.start = offset;
+	}
+}
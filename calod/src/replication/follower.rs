@@ -0,0 +1,172 @@
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{Shutdown, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use thiserror::Error;
+
+use crate::persistence::aof::{command_record, read_raw_record, replay_command, selected_db, RecordError};
+use crate::persistence::snapshot::{SnapshotError, SnapshotReader};
+use crate::replication::state::ACK_INTERVAL;
+use crate::store::calod_store::CalodStore;
+
+// A link that stays silent for longer than this is considered dead, leaders ping every 10 seconds
+pub const LINK_TIMEOUT: Duration = Duration::from_secs(60);
+
+const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Error)]
+pub enum ReplicationError {
+	#[error("Replication I/O error: {0}")]
+	Io(#[from] io::Error),
+
+	#[error("Unexpected PSYNC reply `{0}`")]
+	Handshake(String),
+
+	#[error("{0}")]
+	Snapshot(#[from] SnapshotError),
+
+	#[error("Replication stream is corrupt")]
+	Corrupt,
+
+	#[error("Leader closed the connection")]
+	Closed,
+}
+
+fn read_line<R: BufRead>(reader: &mut R) -> Result<String, ReplicationError> {
+	let mut line = String::new();
+	if reader.read_line(&mut line)? == 0 {
+		return Err(ReplicationError::Closed);
+	}
+	Ok(line.trim_end_matches(['\r', '\n']).to_string())
+}
+
+impl CalodStore {
+	// REPLICAOF host port, replaces the data set with the leader's and keeps following it
+	pub fn replicate_from(self: &Arc<Self>, host: &str, port: u16) {
+		let generation = self.replication.follow(host, port);
+		let host = host.to_string();
+		let store = self.clone();
+		thread::spawn(move || store.follow_leader(&host, port, generation));
+	}
+
+	// REPLICAOF NO ONE
+	pub fn stop_replication(&self) {
+		self.replication.promote();
+	}
+
+	// Keep the link to the leader up until another REPLICAOF replaces it
+	fn follow_leader(self: &Arc<Self>, host: &str, port: u16, generation: u64) {
+		while self.replication.is_current(generation) {
+			if let Err(e) = self.sync_with_leader(host, port, generation) {
+				println!("Replication link to {}:{} is down: {}", host, port, e);
+			}
+			self.replication.set_link_up(false);
+			thread::sleep(RECONNECT_DELAY);
+		}
+	}
+
+	// One connection to the leader
+	// 1. Ask to continue from our replication ID and offset, the leader decides between a partial and a full resync
+	//    The port we listen on follows so the leader can tell where clients reach us
+	// 2. Apply the stream of write commands, feeding our own backlog and AOF like any write
+	// 3. Acknowledge our offset every `ACK_INTERVAL` and whenever the leader asks for it
+	fn sync_with_leader(self: &Arc<Self>, host: &str, port: u16, generation: u64) -> Result<(), ReplicationError> {
+		let mut stream = TcpStream::connect((host, port))?;
+		stream.set_read_timeout(Some(LINK_TIMEOUT))?;
+		let mut reader = BufReader::new(stream.try_clone()?);
+
+		let (replid, offset) = self.replication.position();
+		let mut psync = vec![String::from("PSYNC"), replid, offset.to_string()];
+		psync.extend(self.replication.listening_port().map(|port| port.to_string()));
+		stream.write_all(&command_record(psync))?;
+
+		let reply = read_line(&mut reader)?;
+		match reply.split(' ').collect::<Vec<&str>>().as_slice() {
+			["+FULLRESYNC", replid, offset] => {
+				let offset = offset.parse::<u64>().map_err(|_| ReplicationError::Handshake(reply.clone()))?;
+				self.load_leader_snapshot(&mut reader)?;
+				self.replication.adopt(replid, offset);
+				println!("Full resync from {}:{} done at offset {}", host, port, offset);
+				if self.aof().is_some() {
+					if let Err(e) = self.bgrewriteaof() {
+						println!("Failed to rewrite the AOF after a full resync: {}", e);
+					}
+				}
+			}
+			["+CONTINUE", replid] => {
+				self.replication.switch_history(replid);
+				println!("Partial resync from {}:{} at offset {}", host, port, offset);
+			}
+			_ => return Err(ReplicationError::Handshake(reply)),
+		}
+		self.replication.set_link_up(true);
+
+		let stream = Arc::new(Mutex::new(stream));
+		let (acks, store) = (stream.clone(), self.clone());
+		thread::spawn(move || {
+			while store.replication.is_current(generation) && store.send_ack(&acks).is_ok() {
+				thread::sleep(ACK_INTERVAL);
+			}
+		});
+		let result = self.apply_stream(&mut reader, &stream, generation);
+		// Also stops the acknowledging thread
+		let _ = stream.lock().unwrap().shutdown(Shutdown::Both);
+		result
+	}
+
+	fn apply_stream<R: BufRead>(self: &Arc<Self>, reader: &mut R, stream: &Mutex<TcpStream>, generation: u64) -> Result<(), ReplicationError> {
+		loop {
+			let mut consumed = 0;
+			let args = match read_raw_record(reader, &mut consumed) {
+				Ok(Some(args)) if !args.is_empty() => args,
+				Ok(Some(_)) | Err(RecordError::Corrupt) => return Err(ReplicationError::Corrupt),
+				Ok(None) | Err(RecordError::Truncated) => return Err(ReplicationError::Closed),
+				Err(RecordError::Io(e)) => return Err(e.into()),
+			};
+			if !self.replication.is_current(generation) {
+				return Ok(());
+			}
+			let getack = args.len() > 1 && args[0].eq_ignore_ascii_case(b"replconf") && args[1].eq_ignore_ascii_case(b"getack");
+			self.apply_replicated(args);
+			if getack {
+				self.send_ack(stream)?;
+			}
+		}
+	}
+
+	// REPLCONF ACK <offset>
+	fn send_ack(&self, stream: &Mutex<TcpStream>) -> io::Result<()> {
+		let (_, offset) = self.replication.position();
+		let record = command_record(vec![String::from("REPLCONF"), String::from("ACK"), offset.to_string()]);
+		stream.lock().unwrap().write_all(&record)
+	}
+
+	// The snapshot follows the FULLRESYNC line and ends with its own footer, it is read straight off the connection
+	// The data set is only replaced once the whole snapshot checked out
+	fn load_leader_snapshot<R: BufRead>(&self, reader: &mut R) -> Result<(), ReplicationError> {
+		let mut snapshot = SnapshotReader::new(reader)?;
+		self.replace_with_snapshot(&mut snapshot)?;
+		Ok(())
+	}
+
+	// Apply one command from the leader, pings and REPLCONF only move the offset
+	// A SELECT moves the following commands to another database
+	fn apply_replicated(self: &Arc<Self>, args: Vec<Vec<u8>>) {
+		let record = command_record(args.clone());
+		if args[0].eq_ignore_ascii_case(b"ping") || args[0].eq_ignore_ascii_case(b"replconf") {
+			self.replication.feed(&record);
+			return;
+		}
+		if let Some(db) = selected_db(&args) {
+			self.replication.feed_select(db, &record);
+			return;
+		}
+		match self.select(self.replication.replicated_db()) {
+			Ok(store) => store.logged_write(Some(&record), || replay_command(&store, args)),
+			// A database we do not have, the write is passed on but not applied
+			Err(_) => self.replication.feed(&record),
+		}
+	}
+}
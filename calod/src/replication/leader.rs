@@ -0,0 +1,99 @@
+use std::io::{self, BufReader, BufWriter, Write};
+use std::net::{Shutdown, TcpStream};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use chrono::Utc;
+
+use crate::persistence::aof::read_record;
+use crate::replication::state::RecordReceiver;
+use crate::store::calod_store::CalodStore;
+
+// How long a full resync waits for a running snapshot before trying again
+const SNAPSHOT_RETRY_DELAY: Duration = Duration::from_millis(100);
+
+impl CalodStore {
+	// PSYNC replid offset [listening port], the connection becomes the replication stream of one follower
+	// 1. Continue from the backlog when the follower's history is ours and still covered
+	// 2. Otherwise send a snapshot taken exactly at the current offset, then everything fed after it
+	pub fn serve_replica(self: &Arc<Self>, stream: &mut TcpStream, args: &[String]) -> io::Result<()> {
+		let mut address = stream.peer_addr().ok();
+		if let (Some(address), Some(port)) = (address.as_mut(), args.get(2).and_then(|port| port.parse::<u16>().ok())) {
+			address.set_port(port);
+		}
+		let address = address.map(|address| address.to_string()).unwrap_or_default();
+		let requested = match args {
+			[replid, offset, ..] => offset.parse::<u64>().ok().map(|offset| (replid.as_str(), offset)),
+			_ => None,
+		};
+		self.replication.start_pinger();
+
+		let continued = requested.and_then(|(replid, offset)| self.replication.add_continuing_replica(&address, replid, offset));
+		let (id, receiver) = match continued {
+			Some((missed, id, receiver)) => {
+				println!("Replica {} continues at offset {}", address, requested.unwrap().1);
+				let (replid, _) = self.replication.position();
+				let sent = stream.write_all(format!("+CONTINUE {}\r\n", replid).as_bytes()).and_then(|_| stream.write_all(&missed));
+				(id, sent.map(|_| receiver))
+			}
+			None => self.full_resync(stream, &address),
+		};
+
+		// The follower acknowledges its offset over the same connection
+		let result = receiver.and_then(|receiver| {
+			let acks = stream.try_clone()?;
+			let store = self.clone();
+			thread::spawn(move || store.read_acks(acks, id));
+			stream_to_replica(stream, receiver)
+		});
+		let _ = stream.shutdown(Shutdown::Both);
+		self.replication.remove_replica(id);
+		println!("Replica {} disconnected", address);
+		result
+	}
+
+	// Stream a snapshot of the data set right after the FULLRESYNC line, the follower is registered at the offset it was taken at
+	fn full_resync(&self, stream: &mut TcpStream, address: &str) -> (u64, io::Result<RecordReceiver>) {
+		let (epoch, started_at, (replid, offset, id, receiver)) = loop {
+			let barrier = self.replication.barrier.write().unwrap();
+			if let Ok(epoch) = self.snapshot_state().begin() {
+				break (epoch, Utc::now(), self.replication.add_full_replica(address));
+			}
+			drop(barrier);
+			thread::sleep(SNAPSHOT_RETRY_DELAY);
+		};
+		println!("Full resync of replica {} at offset {}", address, offset);
+
+		let sent = stream.write_all(format!("+FULLRESYNC {} {}\r\n", replid, offset).as_bytes()).and_then(|_| {
+			let (_, writer) = self.write_snapshot_to(BufWriter::new(&mut *stream), epoch, started_at).map_err(|e| io::Error::other(e.to_string()))?;
+			writer.into_inner().map(|_| ()).map_err(|e| e.into_error())
+		});
+		self.snapshot_state().release();
+		(id, sent.map(|_| receiver))
+	}
+
+	// Read REPLCONF ACK <offset> records until the connection closes, then stop streaming to it
+	fn read_acks(&self, stream: TcpStream, id: u64) {
+		let mut reader = BufReader::new(stream);
+		let mut consumed = 0;
+		while let Ok(Some(args)) = read_record(&mut reader, &mut consumed) {
+			if let [command, subcommand, offset] = args.as_slice() {
+				if command.eq_ignore_ascii_case("replconf") && subcommand.eq_ignore_ascii_case("ack") {
+					if let Ok(offset) = offset.parse::<u64>() {
+						self.replication.ack(id, offset);
+					}
+				}
+			}
+		}
+		self.replication.remove_replica(id);
+	}
+}
+
+// Forward the records fed to this follower until it disconnects or is dropped
+fn stream_to_replica(stream: &mut TcpStream, receiver: RecordReceiver) -> io::Result<()> {
+	for record in receiver {
+		stream.write_all(&record)?;
+	}
+	Ok(())
+}
@@ -0,0 +1,4 @@
+pub mod backlog;
+pub mod state;
+pub mod leader;
+pub mod follower;
\ No newline at end of file
@@ -0,0 +1,360 @@
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::sync::atomic::{AtomicBool, AtomicU16, AtomicU64, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, SyncSender, TrySendError};
+use std::sync::{Arc, Condvar, Mutex, Once, RwLock};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::persistence::aof::{command_record, select_record};
+use crate::replication::backlog::{ReplicationBacklog, DEFAULT_BACKLOG_SIZE};
+
+// Records a follower may fall behind by before it is dropped and has to resync
+pub const REPLICA_QUEUE_LIMIT: usize = 100_000;
+
+// A leader with followers pings them through the stream so they can detect a dead link
+pub const PING_INTERVAL: Duration = Duration::from_secs(10);
+
+// Followers acknowledge their offset this often
+pub const ACK_INTERVAL: Duration = Duration::from_secs(1);
+
+// A follower counts as good for min replicas while its last acknowledgement is at most this old
+pub const DEFAULT_MAX_LAG: Duration = Duration::from_secs(10);
+
+// The records fed to one follower
+pub(crate) type RecordReceiver = Receiver<Arc<[u8]>>;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Role {
+	Leader,
+	Follower { host: String, port: u16 },
+}
+
+#[derive(Debug)]
+struct ReplicaLink {
+	id: u64,
+	address: String,
+	sender: SyncSender<Arc<[u8]>>,
+	// Offset the follower last acknowledged with REPLCONF ACK
+	acked: u64,
+	last_ack: Option<Instant>,
+}
+
+// The history and the followers are locked together so a follower joining never misses or repeats a record
+#[derive(Debug)]
+struct ReplicationStream {
+	replid: String,
+	// The history this one went on from and the offset it ended at, its followers may still continue
+	previous: Option<(String, u64)>,
+	backlog: ReplicationBacklog,
+	replicas: Vec<ReplicaLink>,
+	// Database the writes in the stream apply to, `None` until the next SELECT
+	db: Option<usize>,
+}
+
+impl ReplicationStream {
+	// Go on under another ID, the backlog is kept so followers of the old history can continue
+	fn continue_as(&mut self, replid: String) {
+		let replid = std::mem::replace(&mut self.replid, replid);
+		self.previous = Some((replid, self.backlog.end()));
+	}
+
+	// Followers that fell too far behind are dropped, they resync when they reconnect
+	fn feed(&mut self, record: &[u8]) {
+		self.backlog.append(record);
+		if self.replicas.is_empty() {
+			return;
+		}
+
+		let record: Arc<[u8]> = Arc::from(record);
+		self.replicas.retain(|replica| match replica.sender.try_send(record.clone()) {
+			Ok(()) => true,
+			Err(TrySendError::Full(_)) => {
+				println!("Dropping replica {}, it fell behind the replication stream", replica.address);
+				false
+			}
+			Err(TrySendError::Disconnected(_)) => false,
+		});
+	}
+}
+
+// Replication state of a store, on a leader and on a follower alike
+// 1. The replication ID names a history of writes, the offset counts its bytes
+// 2. A follower copies the ID and offset of its leader so it can resume where it stopped
+#[derive(Debug)]
+pub struct Replication {
+	stream: Mutex<ReplicationStream>,
+	role: RwLock<Role>,
+	// Bumped by every REPLICAOF, the thread of a replaced link stops when it notices
+	generation: AtomicU64,
+	link_up: AtomicBool,
+	// Set once a follower attached or we followed a leader, from then on the backlog may serve a partial resync
+	streaming: AtomicBool,
+	read_only: AtomicBool,
+	// Port clients reach us on, announced to the leader so sentinels can find followers, 0 when unknown
+	listening_port: AtomicU16,
+	next_replica_id: AtomicU64,
+	// Signalled whenever a follower acknowledges, WAIT sleeps on it
+	acks: Condvar,
+	// Writes are refused unless this many followers acknowledged within `max_lag`
+	min_replicas: AtomicUsize,
+	max_lag: AtomicU64,
+	// Write commands hold it shared while they execute and feed the stream, a full resync
+	// takes it exclusively to start its snapshot exactly at the current offset
+	pub(crate) barrier: RwLock<()>,
+	pinger: Once,
+}
+
+// 40 hex characters like a Redis replication ID
+pub fn new_replid() -> String {
+	(0..3).map(|_| format!("{:016x}", RandomState::new().build_hasher().finish())).collect::<String>()[..40].to_string()
+}
+
+impl Default for Replication {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+impl Replication {
+	pub fn new() -> Self {
+		Replication {
+			stream: Mutex::new(ReplicationStream {
+				replid: new_replid(),
+				previous: None,
+				backlog: ReplicationBacklog::new(DEFAULT_BACKLOG_SIZE, 0),
+				replicas: Vec::new(),
+				db: None,
+			}),
+			role: RwLock::new(Role::Leader),
+			generation: AtomicU64::new(0),
+			link_up: AtomicBool::new(false),
+			streaming: AtomicBool::new(false),
+			read_only: AtomicBool::new(true),
+			listening_port: AtomicU16::new(0),
+			next_replica_id: AtomicU64::new(1),
+			acks: Condvar::new(),
+			min_replicas: AtomicUsize::new(0),
+			max_lag: AtomicU64::new(DEFAULT_MAX_LAG.as_secs()),
+			barrier: RwLock::new(()),
+			pinger: Once::new(),
+		}
+	}
+
+	// Replication ID and offset of the data set
+	pub fn position(&self) -> (String, u64) {
+		let stream = self.stream.lock().unwrap();
+		(stream.replid.clone(), stream.backlog.end())
+	}
+
+	pub fn role(&self) -> Role {
+		self.role.read().unwrap().clone()
+	}
+
+	// Address and acknowledged offset of every follower
+	pub fn replicas(&self) -> Vec<(String, u64)> {
+		self.stream.lock().unwrap().replicas.iter().map(|replica| (replica.address.clone(), replica.acked)).collect()
+	}
+
+	pub fn link_up(&self) -> bool {
+		self.link_up.load(Ordering::SeqCst)
+	}
+
+	// True once the stream may be replayed to a follower, its records then have to stay in execution order
+	pub(crate) fn is_streaming(&self) -> bool {
+		self.streaming.load(Ordering::SeqCst)
+	}
+
+	pub fn set_read_only(&self, read_only: bool) {
+		self.read_only.store(read_only, Ordering::SeqCst);
+	}
+
+	pub fn set_listening_port(&self, port: u16) {
+		self.listening_port.store(port, Ordering::SeqCst);
+	}
+
+	pub fn listening_port(&self) -> Option<u16> {
+		Some(self.listening_port.load(Ordering::SeqCst)).filter(|port| *port != 0)
+	}
+
+	// Clients may not write to a follower unless it was configured writable
+	pub fn is_read_only_replica(&self) -> bool {
+		self.read_only.load(Ordering::SeqCst) && self.role() != Role::Leader
+	}
+
+	// Append a record to the stream and hand it to every follower
+	pub fn feed(&self, record: &[u8]) {
+		self.stream.lock().unwrap().feed(record);
+	}
+
+	// Append a write that ran in database `db`, preceded by a SELECT when the stream is in another one
+	pub fn feed_write(&self, db: usize, record: &[u8]) {
+		let mut stream = self.stream.lock().unwrap();
+		if stream.db != Some(db) {
+			stream.feed(&select_record(db));
+			stream.db = Some(db);
+		}
+		stream.feed(record);
+	}
+
+	// A SELECT received from the leader, passed on as is so our offset stays equal to the leader's
+	pub(crate) fn feed_select(&self, db: usize, record: &[u8]) {
+		let mut stream = self.stream.lock().unwrap();
+		stream.feed(record);
+		stream.db = Some(db);
+	}
+
+	// Database the writes received from the leader apply to, 0 until its first SELECT
+	pub(crate) fn replicated_db(&self) -> usize {
+		*self.stream.lock().unwrap().db.get_or_insert(0)
+	}
+
+	// Refuse writes on a leader with fewer than `count` followers that acknowledged within `max_lag`, 0 disables the check
+	pub fn set_min_replicas(&self, count: usize, max_lag: Duration) {
+		self.min_replicas.store(count, Ordering::SeqCst);
+		self.max_lag.store(max_lag.as_secs(), Ordering::SeqCst);
+	}
+
+	// True unless a leader is short of followers that acknowledged recently
+	pub fn enough_good_replicas(&self) -> bool {
+		let min_replicas = self.min_replicas.load(Ordering::SeqCst);
+		if min_replicas == 0 || self.role() != Role::Leader {
+			return true;
+		}
+		let max_lag = Duration::from_secs(self.max_lag.load(Ordering::SeqCst));
+		let stream = self.stream.lock().unwrap();
+		stream.replicas.iter().filter(|replica| replica.last_ack.is_some_and(|at| at.elapsed() <= max_lag)).count() >= min_replicas
+	}
+
+	// WAIT, block until `count` followers acknowledged every write fed so far or the timeout passed
+	// 1. Ask the followers for an acknowledgement right away instead of waiting for their periodic one
+	// 2. Returns the number of followers that caught up, `None` waits without a timeout
+	pub fn wait(&self, count: usize, timeout: Option<Duration>) -> usize {
+		let deadline = timeout.map(|timeout| Instant::now() + timeout);
+		let mut stream = self.stream.lock().unwrap();
+		let target = stream.backlog.end();
+		let caught_up = |stream: &ReplicationStream| stream.replicas.iter().filter(|replica| replica.acked >= target).count();
+
+		if caught_up(&stream) < count && !stream.replicas.is_empty() {
+			stream.feed(&command_record(vec![String::from("REPLCONF"), String::from("GETACK"), String::from("*")]));
+		}
+		while caught_up(&stream) < count {
+			stream = match deadline {
+				Some(deadline) => {
+					let now = Instant::now();
+					if now >= deadline {
+						break;
+					}
+					self.acks.wait_timeout(stream, deadline - now).unwrap().0
+				}
+				None => self.acks.wait(stream).unwrap(),
+			};
+		}
+		caught_up(&stream)
+	}
+
+	// REPLCONF ACK from a follower
+	pub(crate) fn ack(&self, id: u64, offset: u64) {
+		let mut stream = self.stream.lock().unwrap();
+		if let Some(replica) = stream.replicas.iter_mut().find(|replica| replica.id == id) {
+			replica.acked = replica.acked.max(offset);
+			replica.last_ack = Some(Instant::now());
+		}
+		self.acks.notify_all();
+	}
+
+	fn add_replica(&self, stream: &mut ReplicationStream, address: &str) -> (u64, RecordReceiver) {
+		let id = self.next_replica_id.fetch_add(1, Ordering::SeqCst);
+		self.streaming.store(true, Ordering::SeqCst);
+		let (sender, receiver) = mpsc::sync_channel(REPLICA_QUEUE_LIMIT);
+		stream.replicas.push(ReplicaLink { id, address: address.to_string(), sender, acked: 0, last_ack: None });
+		(id, receiver)
+	}
+
+	// Register a follower that gets everything fed from the current offset on
+	pub(crate) fn add_full_replica(&self, address: &str) -> (String, u64, u64, RecordReceiver) {
+		let mut stream = self.stream.lock().unwrap();
+		let (id, receiver) = self.add_replica(&mut stream, address);
+		// The follower starts after the snapshot without knowing our database, so the next write selects it again
+		// A follower passes its leader's stream on unchanged and cannot do the same for followers of its own
+		if *self.role.read().unwrap() == Role::Leader {
+			stream.db = None;
+		}
+		(stream.replid.clone(), stream.backlog.end(), id, receiver)
+	}
+
+	// Register a follower that continues at `offset` of history `replid`, `None` if the backlog cannot serve it
+	// The previous history only counts up to where it ended, a follower past it saw writes we never had
+	pub(crate) fn add_continuing_replica(&self, address: &str, replid: &str, offset: u64) -> Option<(Vec<u8>, u64, RecordReceiver)> {
+		let mut stream = self.stream.lock().unwrap();
+		let known = stream.replid == replid
+			|| stream.previous.as_ref().is_some_and(|(previous, end)| previous == replid && offset <= *end);
+		if !known {
+			return None;
+		}
+		let missed = stream.backlog.since(offset)?;
+		let (id, receiver) = self.add_replica(&mut stream, address);
+		Some((missed, id, receiver))
+	}
+
+	pub(crate) fn remove_replica(&self, id: u64) {
+		self.stream.lock().unwrap().replicas.retain(|replica| replica.id != id);
+		self.acks.notify_all();
+	}
+
+	// Feed a PING every `PING_INTERVAL` once the first follower connected
+	pub(crate) fn start_pinger(self: &Arc<Self>) {
+		self.pinger.call_once(|| {
+			let replication = self.clone();
+			thread::spawn(move || loop {
+				thread::sleep(PING_INTERVAL);
+				if *replication.role.read().unwrap() == Role::Leader && !replication.replicas().is_empty() {
+					replication.feed(&command_record(vec![String::from("PING")]));
+				}
+			});
+		});
+	}
+
+	// Follow another server from now on, returns the generation of the new link
+	pub(crate) fn follow(&self, host: &str, port: u16) -> u64 {
+		*self.role.write().unwrap() = Role::Follower { host: host.to_string(), port };
+		self.streaming.store(true, Ordering::SeqCst);
+		self.link_up.store(false, Ordering::SeqCst);
+		self.generation.fetch_add(1, Ordering::SeqCst) + 1
+	}
+
+	// Become a leader, the data diverges from the old leader so it starts a new history
+	// The old one is kept as the previous history, so the other followers of the old leader can continue from us
+	pub(crate) fn promote(&self) {
+		*self.role.write().unwrap() = Role::Leader;
+		self.link_up.store(false, Ordering::SeqCst);
+		self.generation.fetch_add(1, Ordering::SeqCst);
+		self.stream.lock().unwrap().continue_as(new_replid());
+	}
+
+	// A leader let us continue under another ID, it was promoted since we last followed its history
+	pub(crate) fn switch_history(&self, replid: &str) {
+		let mut stream = self.stream.lock().unwrap();
+		if stream.replid != replid {
+			stream.continue_as(replid.to_string());
+		}
+	}
+
+	pub(crate) fn is_current(&self, generation: u64) -> bool {
+		self.generation.load(Ordering::SeqCst) == generation
+	}
+
+	pub(crate) fn set_link_up(&self, up: bool) {
+		self.link_up.store(up, Ordering::SeqCst);
+	}
+
+	// Take over the history of the leader after a full resync, our own followers have to resync too
+	pub(crate) fn adopt(&self, replid: &str, offset: u64) {
+		let mut stream = self.stream.lock().unwrap();
+		stream.replid = replid.to_string();
+		stream.previous = None;
+		stream.backlog.reset(offset);
+		stream.replicas.clear();
+		stream.db = None;
+	}
+}
@@ -0,0 +1,186 @@
+use std::io::Write;
+
+use crate::parser::parser::RESPOutput;
+use crate::request_response::{command::Command, response_helper};
+use crate::store::bitmap::{parse_bitfield_offset, BitOp, BitRangeUnit, BitfieldOp, BitfieldOverflow, BitfieldType, MAX_BIT_OFFSET};
+use crate::store::calod_store::{CacheError, CalodStore};
+
+const BIT_OFFSET_ERROR: &str = "ERR bit offset is not an integer or out of range";
+const BIT_VALUE_ERROR: &str = "ERR The bit argument must be 1 or 0.";
+const NOT_INTEGER_ERROR: &str = "ERR value is not an integer or out of range";
+const SYNTAX_ERROR: &str = "ERR syntax error";
+
+// Handle SETBIT, GETBIT, BITCOUNT, BITPOS, BITOP and BITFIELD
+pub fn respond<T: Write>(store: &CalodStore, stream: &mut T, command: &Command, args: &[String]) {
+	let result = match command {
+		Command::SETBIT => setbit(store, args),
+		Command::GETBIT => getbit(store, args),
+		Command::BITCOUNT => bitcount(store, args),
+		Command::BITPOS => bitpos(store, args),
+		Command::BITOP => bitop(store, args),
+		Command::BITFIELD => bitfield(store, args),
+		_ => Err(String::from("ERR unknown bitmap command")),
+	};
+
+	match result {
+		Ok(output) => response_helper::send_resp_response(stream, &output),
+		Err(e) => response_helper::send_error_response(stream, &e),
+	}
+}
+
+fn wrong_args(name: &str) -> String {
+	format!("ERR wrong number of arguments for '{}' command", name)
+}
+
+fn store_error(e: CacheError) -> String {
+	e.to_string()
+}
+
+fn parse_offset(str: &str) -> Result<u64, String> {
+	match str.parse::<u64>() {
+		Ok(offset) if offset <= MAX_BIT_OFFSET => Ok(offset),
+		_ => Err(String::from(BIT_OFFSET_ERROR)),
+	}
+}
+
+fn parse_bit(str: &str) -> Result<u8, String> {
+	match str {
+		"0" => Ok(0),
+		"1" => Ok(1),
+		_ => Err(String::from(BIT_VALUE_ERROR)),
+	}
+}
+
+fn parse_integer(str: &str) -> Result<i64, String> {
+	str.parse().map_err(|_| String::from(NOT_INTEGER_ERROR))
+}
+
+fn parse_unit(str: &str) -> Result<BitRangeUnit, String> {
+	match str.to_lowercase().as_str() {
+		"byte" => Ok(BitRangeUnit::Byte),
+		"bit" => Ok(BitRangeUnit::Bit),
+		_ => Err(String::from(SYNTAX_ERROR)),
+	}
+}
+
+// SETBIT key offset value
+fn setbit(store: &CalodStore, args: &[String]) -> Result<RESPOutput, String> {
+	if args.len() != 3 {
+		return Err(wrong_args("setbit"));
+	}
+	let offset = parse_offset(&args[1])?;
+	let bit = parse_bit(&args[2])?;
+
+	let previous = store.setbit(&args[0], offset, bit).map_err(store_error)?;
+	Ok(RESPOutput::Integer(previous as i64))
+}
+
+// GETBIT key offset
+fn getbit(store: &CalodStore, args: &[String]) -> Result<RESPOutput, String> {
+	if args.len() != 2 {
+		return Err(wrong_args("getbit"));
+	}
+	let offset = parse_offset(&args[1])?;
+
+	let bit = store.getbit(&args[0], offset).map_err(store_error)?;
+	Ok(RESPOutput::Integer(bit as i64))
+}
+
+// BITCOUNT key [start end [BYTE | BIT]]
+fn bitcount(store: &CalodStore, args: &[String]) -> Result<RESPOutput, String> {
+	let range = match args.len() {
+		1 => None,
+		3 => Some((parse_integer(&args[1])?, parse_integer(&args[2])?, BitRangeUnit::Byte)),
+		4 => Some((parse_integer(&args[1])?, parse_integer(&args[2])?, parse_unit(&args[3])?)),
+		0 => return Err(wrong_args("bitcount")),
+		_ => return Err(String::from(SYNTAX_ERROR)),
+	};
+
+	let count = store.bitcount(&args[0], range).map_err(store_error)?;
+	Ok(RESPOutput::Integer(count as i64))
+}
+
+// BITPOS key bit [start [end [BYTE | BIT]]]
+fn bitpos(store: &CalodStore, args: &[String]) -> Result<RESPOutput, String> {
+	if args.len() < 2 {
+		return Err(wrong_args("bitpos"));
+	}
+	if args.len() > 5 {
+		return Err(String::from(SYNTAX_ERROR));
+	}
+
+	let bit = parse_bit(&args[1])?;
+	let start = args.get(2).map(|s| parse_integer(s)).transpose()?;
+	let end = args.get(3).map(|s| parse_integer(s)).transpose()?;
+	let unit = args.get(4).map(|s| parse_unit(s)).transpose()?.unwrap_or(BitRangeUnit::Byte);
+
+	let position = store.bitpos(&args[0], bit, start, end, unit).map_err(store_error)?;
+	Ok(RESPOutput::Integer(position))
+}
+
+// BITOP AND | OR | XOR | NOT destkey key [key ...]
+fn bitop(store: &CalodStore, args: &[String]) -> Result<RESPOutput, String> {
+	if args.len() < 3 {
+		return Err(wrong_args("bitop"));
+	}
+	let op = BitOp::from(&args[0]).ok_or_else(|| String::from(SYNTAX_ERROR))?;
+	let keys: Vec<&str> = args[2..].iter().map(|k| k.as_str()).collect();
+
+	let len = store.bitop(op, &args[1], &keys).map_err(store_error)?;
+	Ok(RESPOutput::Integer(len as i64))
+}
+
+// BITFIELD key [GET type offset] [SET type offset value] [INCRBY type offset increment] [OVERFLOW WRAP | SAT | FAIL]
+// 1. Parse every sub-command before touching the store so a syntax error has no side effects
+// 2. Run them in order and reply with one element per GET/SET/INCRBY
+fn bitfield(store: &CalodStore, args: &[String]) -> Result<RESPOutput, String> {
+	if args.is_empty() {
+		return Err(wrong_args("bitfield"));
+	}
+
+	let mut ops: Vec<BitfieldOp> = Vec::new();
+	let mut i = 1;
+	while i < args.len() {
+		let sub_command = args[i].to_lowercase();
+
+		if sub_command == "overflow" {
+			let policy = match args.get(i + 1).map(|s| s.to_lowercase()).as_deref() {
+				Some("wrap") => BitfieldOverflow::Wrap,
+				Some("sat") => BitfieldOverflow::Sat,
+				Some("fail") => BitfieldOverflow::Fail,
+				_ => return Err(String::from("ERR Invalid OVERFLOW type specified")),
+			};
+			ops.push(BitfieldOp::Overflow(policy));
+			i += 2;
+			continue;
+		}
+
+		let arity = match sub_command.as_str() {
+			"get" => 3,
+			"set" | "incrby" => 4,
+			_ => return Err(String::from(SYNTAX_ERROR)),
+		};
+		if i + arity > args.len() {
+			return Err(String::from(SYNTAX_ERROR));
+		}
+
+		let field_type = BitfieldType::parse(&args[i + 1]).ok_or_else(|| {
+			String::from("ERR Invalid bitfield type. Use something like i16 u8. Note that u64 is not supported but i64 is.")
+		})?;
+		let offset = parse_bitfield_offset(&args[i + 2], field_type).ok_or_else(|| String::from(BIT_OFFSET_ERROR))?;
+
+		ops.push(match sub_command.as_str() {
+			"get" => BitfieldOp::Get(field_type, offset),
+			"set" => BitfieldOp::Set(field_type, offset, parse_integer(&args[i + 3])?),
+			_ => BitfieldOp::IncrBy(field_type, offset, parse_integer(&args[i + 3])?),
+		});
+		i += arity;
+	}
+
+	let results = store.bitfield(&args[0], &ops).map_err(store_error)?;
+
+	Ok(RESPOutput::Array(results.into_iter().map(|r| match r {
+		Some(value) => RESPOutput::Integer(value),
+		None => RESPOutput::Null,
+	}).collect()))
+}
@@ -1,18 +1,24 @@
 
 use std::borrow::Borrow;
-use std::io::{Error, ErrorKind, Read, Write};
+use std::io::Write;
+use std::sync::Arc;
 
-use crate::request_response::{command::Command, parsed_command::ParsedCommand, response_helper};
-use crate::store::calod_store::{CalodStore, Store};
-use crate::store::calod_ops::SetOptionalArgs;
-use crate::parser::parser::{Parser, ParserResult, ParserError, RESPOutput};
+use chrono::{Duration, Utc};
+
+use crate::request_response::{bitmap_handler, cluster_handler, command::Command, database_handler, filter_handler, geo_handler, hyperloglog_handler, json_handler, keyspace_handler, migration_handler, parsed_command::ParsedCommand, persistence_handler, replication_handler, response_helper, scan_handler};
+use crate::store::calod_store::{CalodStore, SetOptionalArgs};
+use crate::parser::parser::{Parser, ParseError, RESPOutput};
 
 pub struct ClientInput {
 	input: Vec<u8>,
+	// The store every command of the connection runs against, a handle on the database SELECT chose
+	store: Arc<CalodStore>,
+	// Set by ASKING, lets the next command use a slot that is being imported
+	asking: bool,
 }
 
 pub trait HandleClientInput {
-	fn parse_input(&mut self, buffer: &[u8]) -> Result<RESPOutput, ParserError>;
+	fn parse_input(&mut self, buffer: &[u8]) -> Result<RESPOutput, ParseError>;
 
 	fn respond<T: Write>(&self, stream: &mut T, parsed: ParsedCommand);
 
@@ -22,13 +28,13 @@ pub trait HandleClientInput {
 }
 
 impl HandleClientInput for ClientInput {
-	fn parse_input(&mut self, buffer: &[u8]) -> Result<RESPOutput, ParserError> {
-		self.append_input(&buffer);
-		let parsed = Parser::parse_resp(self.get_input());
-		match parsed {
-			Ok(res) => Ok(res.0),
-			Err(e) => Err(e),
-		}
+	fn parse_input(&mut self, buffer: &[u8]) -> Result<RESPOutput, ParseError> {
+		self.append_input(buffer);
+		// Only the parsed request is consumed, pipelined requests after it stay buffered
+		let (parsed, remaining) = Parser::parse_resp(self.get_input())?;
+		let consumed = self.input.len() - remaining.len();
+		self.input.drain(..consumed);
+		Ok(parsed)
 	}
 
 	fn respond<T: Write>(&self, stream: &mut T, parsed: ParsedCommand) {
@@ -52,38 +58,55 @@ impl HandleClientInput for ClientInput {
 			}
 			response_helper::send_bulk_string_response(stream, Some(&result));
 		} else if command_unwrapped == &Command::GET {
-			let key_expired_for_get = self.get_key_and_expiry(args);
-			if key_expired_for_get.is_none() {
-				response_helper::send_bulk_string_response(stream, None);
-				return;
+			if args.len() != 1 {
+				return response_helper::send_error_response(stream, "ERR wrong number of arguments for 'get' command");
 			}
-
-			let KeyValueExpiry {
-				key,
-				value,
-				is_expired,
-			} = key_expired_for_get.unwrap();
-
-			if is_expired {
-				self.delete_expired_keys(vec![&key]);
-				response_helper::send_bulk_string_response(stream, None);
-			} else {
-				let value = value.as_ref().map(|v| &**v);
-				response_helper::send_bulk_string_response(stream, value);
+			match self.store.get_value(&args[0]) {
+				Ok(value) => response_helper::send_bulk_bytes_response(stream, value.as_deref()),
+				Err(e) => response_helper::send_error_response(stream, &e.to_string()),
 			}
 		} else if command_unwrapped == &Command::SET {
-			let store = &mut CalodStore::get_store();
-
-			let key = args.get(0).unwrap();
-			let value = args.get(1).unwrap();
-			let optional_args = self.determine_set_optional_args(args);
+			if args.len() < 2 {
+				return response_helper::send_error_response(stream, "ERR wrong number of arguments for 'set' command");
+			}
+			let optional_args = match self.determine_set_optional_args(args) {
+				Ok(optional_args) => optional_args,
+				Err(e) => return response_helper::send_error_response(stream, &e),
+			};
 
-			store.set(key, value, &optional_args);
-			response_helper::send_bulk_string_response(stream, Some("OK"));
+			// The value is stored byte for byte, it does not have to be UTF-8
+			match self.store.set_value(&args[0], parsed.raw_args()[1].clone(), &optional_args) {
+				Ok(()) => response_helper::send_simple_string_response(stream, "OK"),
+				Err(e) => response_helper::send_error_response(stream, &e.to_string()),
+			}
+		} else if command_unwrapped.is_bitmap() {
+			bitmap_handler::respond(&self.store, stream, command_unwrapped, args);
+		} else if command_unwrapped.is_hyperloglog() {
+			hyperloglog_handler::respond(&self.store, stream, command_unwrapped, args);
+		} else if command_unwrapped.is_probabilistic_filter() {
+			filter_handler::respond(&self.store, stream, command_unwrapped, args);
+		} else if command_unwrapped.is_geo() {
+			geo_handler::respond(&self.store, stream, command_unwrapped, args);
+		} else if command_unwrapped.is_json() {
+			json_handler::respond(&self.store, stream, command_unwrapped, args);
+		} else if command_unwrapped.is_persistence() {
+			persistence_handler::respond(&self.store, stream, command_unwrapped, args);
+		} else if command_unwrapped.is_migration() {
+			migration_handler::respond(&self.store, stream, command_unwrapped, args);
+		} else if command_unwrapped.is_replication() {
+			replication_handler::respond(&self.store, stream, command_unwrapped, args);
+		} else if command_unwrapped.is_cluster() {
+			cluster_handler::respond(&self.store, stream, command_unwrapped, args);
+		} else if command_unwrapped.is_database() {
+			database_handler::respond(&self.store, stream, command_unwrapped, args);
+		} else if command_unwrapped.is_scan() {
+			scan_handler::respond(&self.store, stream, command_unwrapped, args);
+		} else if command_unwrapped.is_keyspace() {
+			keyspace_handler::respond(&self.store, stream, command_unwrapped, args);
 		}
 	}
 
-	fn response_error<T: Write>(&self, stream: &mut T, error: &str) {
+	fn respond_error<T: Write>(&self, stream: &mut T, error: &str) {
 		response_helper::send_error_response(stream, error);
 	}
 
@@ -93,8 +116,43 @@ impl HandleClientInput for ClientInput {
 }
 
 impl ClientInput {
-	pub fn new() -> ClientInput {
-		ClientInput { input: Vec::new(), }
+	pub fn new(store: Arc<CalodStore>) -> ClientInput {
+		ClientInput { input: Vec::new(), store, asking: false }
+	}
+
+	pub fn store(&self) -> &Arc<CalodStore> {
+		&self.store
+	}
+
+	// SELECT index, later commands of the connection run against that database
+	pub fn select<T: Write>(&mut self, stream: &mut T, args: &[String]) {
+		if args.len() != 1 {
+			return response_helper::send_error_response(stream, "ERR wrong number of arguments for 'select' command");
+		}
+		let index = match database_handler::db_index(&args[0]) {
+			Ok(index) => index,
+			Err(e) => return response_helper::send_error_response(stream, &e),
+		};
+		if index != 0 && self.store.cluster().is_enabled() {
+			return response_helper::send_error_response(stream, "ERR SELECT is not allowed in cluster mode");
+		}
+
+		match self.store.select(index) {
+			Ok(store) => {
+				self.store = store;
+				response_helper::send_simple_string_response(stream, "OK");
+			}
+			Err(e) => response_helper::send_error_response(stream, &e.to_string()),
+		}
+	}
+
+	pub fn set_asking(&mut self) {
+		self.asking = true;
+	}
+
+	// ASKING only applies to the command right after it
+	pub fn take_asking(&mut self) -> bool {
+		std::mem::take(&mut self.asking)
 	}
 
 	pub fn get_input(&self) -> &[u8] {
@@ -105,41 +163,33 @@ impl ClientInput {
 		self.input.extend_from_slice(input);
 	}
 
-	fn determine_set_optional_args(&self, args: &Vec<String>) -> Option<SetOptionalArgs> {
-		let mut optional_args: Option<SetOptionalArgs> = None;
-
-		if args.len() != 4 {
-			return optional_args;
+	// SET key value [EX seconds | PX milliseconds | EXAT unix-time-seconds | PXAT unix-time-milliseconds]
+	fn determine_set_optional_args(&self, args: &[String]) -> Result<Option<SetOptionalArgs>, String> {
+		if args.len() == 2 {
+			return Ok(None);
 		}
-
-		let variant = args.get(2).unwrap();
-		let duration = args.get(3).unwrap();
-		let mut duration_ms: u64 = 0;
-
-		if variant.to_lowercase() == "ex" || variant.to_lowercase() == "px" {
-			duration_ms = match duration.parse() {
-				Ok(d) => d,
-				Err(e) => {
-					println!("Error parsing duration: {}", e);
-					0
-				}
-			};
-
-			if variant.to_lowercase() == "ex" {
-				duration_ms *= 1000;
-			}
-		}
-
-		if duration_ms != 0 {
-			optional_args = Some(SetOptionalArgs {
-				expire_in_ms: Some(duration_ms),
-			});
+		if args.len() != 4 {
+			return Err(String::from("ERR syntax error"));
 		}
-		optional_args
-	}
 
-	fn delete_expired_keys(&self, keys: Vec<&str>) {
-		let store = &mut CalodStore::get_store();
-		store.delete(keys);
+		let variant = args[2].to_lowercase();
+		let duration = match args[3].parse::<i64>() {
+			Ok(duration) if duration > 0 => duration,
+			_ => return Err(String::from("ERR invalid expire time in 'set' command")),
+		};
+
+		let duration_ms = match variant.as_str() {
+			"ex" => duration.saturating_mul(1000),
+			"px" => duration,
+			// Absolute deadlines, the append only file logs every expiration this way
+			// A deadline in the past still sets the key, it expires right away
+			"exat" => (duration.saturating_mul(1000) - Utc::now().timestamp_millis()).max(1),
+			"pxat" => (duration - Utc::now().timestamp_millis()).max(1),
+			_ => return Err(String::from("ERR syntax error")),
+		};
+
+		Ok(Some(SetOptionalArgs {
+			ttl: Duration::milliseconds(duration_ms),
+		}))
 	}
-}
\ No newline at end of file
+}
@@ -0,0 +1,137 @@
+use std::io::Write;
+
+use crate::cluster::slots::{key_slot, parse_slot};
+use crate::cluster::state::{ClusterError, ClusterState, BUS_PORT_OFFSET};
+use crate::parser::parser::RESPOutput;
+use crate::request_response::{command::Command, response_helper};
+use crate::sentinel::link::split_address;
+use crate::store::calod_store::CalodStore;
+
+// Handle CLUSTER, ASKING is taken over by the connection loop
+pub fn respond<T: Write>(store: &CalodStore, stream: &mut T, command: &Command, args: &[String]) {
+	let result = match command {
+		Command::CLUSTER => cluster(store, args),
+		_ => Err(String::from("ERR unknown cluster command")),
+	};
+
+	match result {
+		Ok(output) => response_helper::send_resp_response(stream, &output),
+		Err(e) => response_helper::send_error_response(stream, &e),
+	}
+}
+
+fn wrong_args(name: &str) -> String {
+	format!("ERR wrong number of arguments for '{}' command", name)
+}
+
+fn slot_arg(slot: &str) -> Result<u16, String> {
+	parse_slot(slot).ok_or_else(|| format!("ERR Invalid or out of range slot `{}`", slot))
+}
+
+fn parse_slots(args: &[String]) -> Result<Vec<u16>, String> {
+	args.iter().map(|slot| slot_arg(slot)).collect()
+}
+
+// CLUSTER ADDSLOTSRANGE start end [start end ...]
+fn parse_slot_ranges(args: &[String]) -> Result<Vec<u16>, String> {
+	let bounds = parse_slots(args)?;
+	let mut slots = Vec::new();
+	for range in bounds.chunks(2) {
+		if range[0] > range[1] {
+			return Err(format!("ERR start slot number {} is greater than end slot number {}", range[0], range[1]));
+		}
+		slots.extend(range[0]..=range[1]);
+	}
+	Ok(slots)
+}
+
+// Apply a change of the slot assignment and persist the configuration
+fn change(store: &CalodStore, applied: Result<(), ClusterError>) -> Result<RESPOutput, String> {
+	applied.map_err(|e| format!("ERR {}", e))?;
+	store.save_cluster_config().map_err(|e| format!("ERR {}", e))?;
+	Ok(RESPOutput::SimpleString(String::from("OK")))
+}
+
+// CLUSTER SLOTS, `[start, end, [host, port, id]]` per range of slots
+fn slots(cluster: &ClusterState) -> RESPOutput {
+	let ranges = cluster.slot_table().into_iter().map(|(start, end, node)| {
+		let (host, port) = split_address(&node.address).unwrap_or_default();
+		RESPOutput::Array(vec![
+			RESPOutput::Integer(start as i64),
+			RESPOutput::Integer(end as i64),
+			RESPOutput::Array(vec![
+				RESPOutput::BulkString(host),
+				RESPOutput::Integer(port as i64),
+				RESPOutput::BulkString(node.id),
+			]),
+		])
+	});
+	RESPOutput::Array(ranges.collect())
+}
+
+// CLUSTER SETSLOT slot IMPORTING source-id | MIGRATING target-id | NODE owner-id | STABLE
+fn setslot(store: &CalodStore, slot: &str, action: &str, node: Option<&String>) -> Result<RESPOutput, String> {
+	let slot = slot_arg(slot)?;
+	let cluster = store.cluster();
+	let applied = match (action.to_lowercase().as_str(), node) {
+		("importing", Some(id)) => cluster.set_importing(slot, id),
+		("migrating", Some(id)) => cluster.set_migrating(slot, id),
+		("node", Some(id)) => cluster.set_slot_node(slot, id, store.count_keys_in_slot(slot) > 0),
+		("stable", None) => {
+			cluster.set_stable(slot);
+			Ok(())
+		}
+		_ => return Err(String::from("ERR Invalid CLUSTER SETSLOT action or number of arguments")),
+	};
+	change(store, applied)
+}
+
+// CLUSTER GETKEYSINSLOT slot count
+fn getkeysinslot(store: &CalodStore, slot: &str, count: &str) -> Result<RESPOutput, String> {
+	let slot = slot_arg(slot)?;
+	let count = count.parse::<usize>().map_err(|_| String::from("ERR Invalid number of keys"))?;
+	Ok(RESPOutput::Array(store.keys_in_slot(slot, count).into_iter().map(RESPOutput::BulkString).collect()))
+}
+
+// CLUSTER MEET ip port [cluster-bus-port], the bus port defaults to the port plus 10000
+fn meet(store: &CalodStore, host: &str, port: &str, bus_port: Option<&String>) -> Result<RESPOutput, String> {
+	let invalid = || format!("ERR Invalid node address specified: {}:{}", host, port);
+	let port = port.parse::<u16>().map_err(|_| invalid())?;
+	let bus_port = match bus_port {
+		Some(bus_port) => bus_port.parse::<u16>().map_err(|_| invalid())?,
+		None => port.checked_add(BUS_PORT_OFFSET).ok_or_else(invalid)?,
+	};
+	store.cluster_meet(host, bus_port).map_err(|e| format!("ERR {}", e))?;
+	Ok(RESPOutput::SimpleString(String::from("OK")))
+}
+
+// CLUSTER INFO | NODES | SLOTS | KEYSLOT key | MYID | ADDSLOTS slot... | ADDSLOTSRANGE start end... | DELSLOTS slot...
+// CLUSTER SETSLOT ... | GETKEYSINSLOT slot count | COUNTKEYSINSLOT slot | MEET ip port [cluster-bus-port]
+fn cluster(store: &CalodStore, args: &[String]) -> Result<RESPOutput, String> {
+	let cluster = store.cluster();
+	if !cluster.is_enabled() {
+		return Err(String::from("ERR This instance has cluster support disabled"));
+	}
+	let Some(subcommand) = args.first().map(|subcommand| subcommand.to_lowercase()) else {
+		return Err(wrong_args("cluster"));
+	};
+
+	match (subcommand.as_str(), &args[1..]) {
+		("info", []) => Ok(RESPOutput::BulkString(cluster.info_text())),
+		("nodes", []) => Ok(RESPOutput::BulkString(cluster.nodes_text())),
+		("slots", []) => Ok(slots(cluster)),
+		("keyslot", [key]) => Ok(RESPOutput::Integer(key_slot(key) as i64)),
+		("myid", []) => Ok(RESPOutput::BulkString(cluster.myself().id)),
+		("addslots", slots) if !slots.is_empty() => change(store, cluster.add_slots(&parse_slots(slots)?)),
+		("addslotsrange", ranges) if !ranges.is_empty() && ranges.len() % 2 == 0 => change(store, cluster.add_slots(&parse_slot_ranges(ranges)?)),
+		("delslots", slots) if !slots.is_empty() => change(store, cluster.del_slots(&parse_slots(slots)?)),
+		("setslot", [slot, action, node @ ..]) if node.len() <= 1 => setslot(store, slot, action, node.first()),
+		("getkeysinslot", [slot, count]) => getkeysinslot(store, slot, count),
+		("countkeysinslot", [slot]) => Ok(RESPOutput::Integer(store.count_keys_in_slot(slot_arg(slot)?) as i64)),
+		("meet", [host, port, bus_port @ ..]) if bus_port.len() <= 1 => meet(store, host, port, bus_port.first()),
+		("info" | "nodes" | "slots" | "keyslot" | "myid" | "addslots" | "addslotsrange" | "delslots" | "setslot" | "getkeysinslot" | "countkeysinslot" | "meet", _) => {
+			Err(wrong_args(&format!("cluster|{}", subcommand)))
+		}
+		_ => Err(format!("ERR unknown subcommand '{}'", args[0])),
+	}
+}
@@ -2,11 +2,72 @@
 #[derive(Debug, PartialEq)]
 pub enum Command {
 	PING,
-	ECHO(String),
-	GET(String),
-	SET(String, String),
-	DELETE(String),
-	EXIT
+	ECHO,
+	GET,
+	SET,
+	SETBIT,
+	GETBIT,
+	BITCOUNT,
+	BITPOS,
+	BITOP,
+	BITFIELD,
+	PFADD,
+	PFCOUNT,
+	PFMERGE,
+	BFRESERVE,
+	BFADD,
+	BFMADD,
+	BFEXISTS,
+	BFMEXISTS,
+	CFRESERVE,
+	CFADD,
+	CFDEL,
+	CFEXISTS,
+	GEOADD,
+	GEOPOS,
+	GEODIST,
+	GEOSEARCH,
+	GEOSEARCHSTORE,
+	JSONSET,
+	JSONGET,
+	JSONDEL,
+	JSONNUMINCRBY,
+	JSONARRAPPEND,
+	JSONOBJKEYS,
+	JSONTYPE,
+	SAVE,
+	BGSAVE,
+	LASTSAVE,
+	BGREWRITEAOF,
+	DUMP,
+	RESTORE,
+	MIGRATE,
+	REPLICAOF,
+	ROLE,
+	PSYNC,
+	WAIT,
+	CLUSTER,
+	ASKING,
+	SELECT,
+	MOVE,
+	SWAPDB,
+	FLUSHDB,
+	FLUSHALL,
+	DBSIZE,
+	SCAN,
+	HSCAN,
+	SSCAN,
+	ZSCAN,
+	KEYS,
+	DEL,
+	UNLINK,
+	EXISTS,
+	TYPE,
+	RENAME,
+	RENAMENX,
+	COPY,
+	TOUCH,
+	RANDOMKEY,
 }
 
 
@@ -22,25 +83,212 @@ impl Command {
 			command = Some(Command::GET);
 		} else if str.to_lowercase() == "set" {
 			command = Some(Command::SET);
+		} else if str.to_lowercase() == "setbit" {
+			command = Some(Command::SETBIT);
+		} else if str.to_lowercase() == "getbit" {
+			command = Some(Command::GETBIT);
+		} else if str.to_lowercase() == "bitcount" {
+			command = Some(Command::BITCOUNT);
+		} else if str.to_lowercase() == "bitpos" {
+			command = Some(Command::BITPOS);
+		} else if str.to_lowercase() == "bitop" {
+			command = Some(Command::BITOP);
+		} else if str.to_lowercase() == "bitfield" {
+			command = Some(Command::BITFIELD);
+		} else if str.to_lowercase() == "pfadd" {
+			command = Some(Command::PFADD);
+		} else if str.to_lowercase() == "pfcount" {
+			command = Some(Command::PFCOUNT);
+		} else if str.to_lowercase() == "pfmerge" {
+			command = Some(Command::PFMERGE);
+		} else if str.to_lowercase() == "bf.reserve" {
+			command = Some(Command::BFRESERVE);
+		} else if str.to_lowercase() == "bf.add" {
+			command = Some(Command::BFADD);
+		} else if str.to_lowercase() == "bf.madd" {
+			command = Some(Command::BFMADD);
+		} else if str.to_lowercase() == "bf.exists" {
+			command = Some(Command::BFEXISTS);
+		} else if str.to_lowercase() == "bf.mexists" {
+			command = Some(Command::BFMEXISTS);
+		} else if str.to_lowercase() == "cf.reserve" {
+			command = Some(Command::CFRESERVE);
+		} else if str.to_lowercase() == "cf.add" {
+			command = Some(Command::CFADD);
+		} else if str.to_lowercase() == "cf.del" {
+			command = Some(Command::CFDEL);
+		} else if str.to_lowercase() == "cf.exists" {
+			command = Some(Command::CFEXISTS);
+		} else if str.to_lowercase() == "geoadd" {
+			command = Some(Command::GEOADD);
+		} else if str.to_lowercase() == "geopos" {
+			command = Some(Command::GEOPOS);
+		} else if str.to_lowercase() == "geodist" {
+			command = Some(Command::GEODIST);
+		} else if str.to_lowercase() == "geosearch" {
+			command = Some(Command::GEOSEARCH);
+		} else if str.to_lowercase() == "geosearchstore" {
+			command = Some(Command::GEOSEARCHSTORE);
+		} else if str.to_lowercase() == "json.set" {
+			command = Some(Command::JSONSET);
+		} else if str.to_lowercase() == "json.get" {
+			command = Some(Command::JSONGET);
+		} else if str.to_lowercase() == "json.del" {
+			command = Some(Command::JSONDEL);
+		} else if str.to_lowercase() == "json.numincrby" {
+			command = Some(Command::JSONNUMINCRBY);
+		} else if str.to_lowercase() == "json.arrappend" {
+			command = Some(Command::JSONARRAPPEND);
+		} else if str.to_lowercase() == "json.objkeys" {
+			command = Some(Command::JSONOBJKEYS);
+		} else if str.to_lowercase() == "json.type" {
+			command = Some(Command::JSONTYPE);
+		} else if str.to_lowercase() == "save" {
+			command = Some(Command::SAVE);
+		} else if str.to_lowercase() == "bgsave" {
+			command = Some(Command::BGSAVE);
+		} else if str.to_lowercase() == "lastsave" {
+			command = Some(Command::LASTSAVE);
+		} else if str.to_lowercase() == "bgrewriteaof" {
+			command = Some(Command::BGREWRITEAOF);
+		} else if str.to_lowercase() == "dump" {
+			command = Some(Command::DUMP);
+		} else if str.to_lowercase() == "restore" {
+			command = Some(Command::RESTORE);
+		} else if str.to_lowercase() == "migrate" {
+			command = Some(Command::MIGRATE);
+		} else if str.to_lowercase() == "replicaof" {
+			command = Some(Command::REPLICAOF);
+		} else if str.to_lowercase() == "role" {
+			command = Some(Command::ROLE);
+		} else if str.to_lowercase() == "psync" {
+			command = Some(Command::PSYNC);
+		} else if str.to_lowercase() == "wait" {
+			command = Some(Command::WAIT);
+		} else if str.to_lowercase() == "cluster" {
+			command = Some(Command::CLUSTER);
+		} else if str.to_lowercase() == "asking" {
+			command = Some(Command::ASKING);
+		} else if str.to_lowercase() == "select" {
+			command = Some(Command::SELECT);
+		} else if str.to_lowercase() == "move" {
+			command = Some(Command::MOVE);
+		} else if str.to_lowercase() == "swapdb" {
+			command = Some(Command::SWAPDB);
+		} else if str.to_lowercase() == "flushdb" {
+			command = Some(Command::FLUSHDB);
+		} else if str.to_lowercase() == "flushall" {
+			command = Some(Command::FLUSHALL);
+		} else if str.to_lowercase() == "dbsize" {
+			command = Some(Command::DBSIZE);
+		} else if str.to_lowercase() == "scan" {
+			command = Some(Command::SCAN);
+		} else if str.to_lowercase() == "hscan" {
+			command = Some(Command::HSCAN);
+		} else if str.to_lowercase() == "sscan" {
+			command = Some(Command::SSCAN);
+		} else if str.to_lowercase() == "zscan" {
+			command = Some(Command::ZSCAN);
+		} else if str.to_lowercase() == "keys" {
+			command = Some(Command::KEYS);
+		} else if str.to_lowercase() == "del" {
+			command = Some(Command::DEL);
+		} else if str.to_lowercase() == "unlink" {
+			command = Some(Command::UNLINK);
+		} else if str.to_lowercase() == "exists" {
+			command = Some(Command::EXISTS);
+		} else if str.to_lowercase() == "type" {
+			command = Some(Command::TYPE);
+		} else if str.to_lowercase() == "rename" {
+			command = Some(Command::RENAME);
+		} else if str.to_lowercase() == "renamenx" {
+			command = Some(Command::RENAMENX);
+		} else if str.to_lowercase() == "copy" {
+			command = Some(Command::COPY);
+		} else if str.to_lowercase() == "touch" {
+			command = Some(Command::TOUCH);
+		} else if str.to_lowercase() == "randomkey" {
+			command = Some(Command::RANDOMKEY);
 		}
 
 		command
 	}
-}
 
+	pub fn is_bitmap(&self) -> bool {
+		matches!(self, Command::SETBIT | Command::GETBIT | Command::BITCOUNT | Command::BITPOS | Command::BITOP | Command::BITFIELD)
+	}
 
-fn parse_command(input: &str) -> Result<Command, &str> {
-	let parts: Vec<&str> = input.trim().split_whitespace().collect();
-	match parts.as_slice() {
-		["PING"] => Ok(Command::PING),
-		["ECHO", msg @ ..] => {
-			let message = msg.join(" ");
-			Ok(Command::ECHO(message))
-		}
-		["SET", key, value] => Ok(Command::SET(key.to_string(), value.to_string())),
-		["GET", key] => Ok(Command::GET(key.to_string())),
-		["DELETE", key] => Ok(Command::DELETE(key.to_string())),
-		["EXIT"] => Ok(Command::EXIT),
-		_ => Err("Invalid command"),
+	pub fn is_hyperloglog(&self) -> bool {
+		matches!(self, Command::PFADD | Command::PFCOUNT | Command::PFMERGE)
+	}
+
+	pub fn is_probabilistic_filter(&self) -> bool {
+		matches!(self, Command::BFRESERVE | Command::BFADD | Command::BFMADD | Command::BFEXISTS | Command::BFMEXISTS
+			| Command::CFRESERVE | Command::CFADD | Command::CFDEL | Command::CFEXISTS)
+	}
+
+	pub fn is_geo(&self) -> bool {
+		matches!(self, Command::GEOADD | Command::GEOPOS | Command::GEODIST | Command::GEOSEARCH | Command::GEOSEARCHSTORE)
+	}
+
+	pub fn is_json(&self) -> bool {
+		matches!(self, Command::JSONSET | Command::JSONGET | Command::JSONDEL | Command::JSONNUMINCRBY
+			| Command::JSONARRAPPEND | Command::JSONOBJKEYS | Command::JSONTYPE)
+	}
+
+	pub fn is_persistence(&self) -> bool {
+		matches!(self, Command::SAVE | Command::BGSAVE | Command::LASTSAVE | Command::BGREWRITEAOF)
+	}
+
+	pub fn is_migration(&self) -> bool {
+		matches!(self, Command::DUMP | Command::RESTORE | Command::MIGRATE)
+	}
+
+	pub fn is_replication(&self) -> bool {
+		matches!(self, Command::REPLICAOF | Command::ROLE | Command::PSYNC | Command::WAIT)
 	}
-}
\ No newline at end of file
+
+	pub fn is_cluster(&self) -> bool {
+		matches!(self, Command::CLUSTER)
+	}
+
+	// SELECT is taken over by the connection loop, it changes the connection and not the data
+	pub fn is_database(&self) -> bool {
+		matches!(self, Command::MOVE | Command::SWAPDB | Command::FLUSHDB | Command::FLUSHALL | Command::DBSIZE)
+	}
+
+	pub fn is_scan(&self) -> bool {
+		matches!(self, Command::SCAN | Command::HSCAN | Command::SSCAN | Command::ZSCAN | Command::KEYS)
+	}
+
+	pub fn is_keyspace(&self) -> bool {
+		matches!(self, Command::DEL | Command::UNLINK | Command::EXISTS | Command::TYPE | Command::RENAME
+			| Command::RENAMENX | Command::COPY | Command::TOUCH | Command::RANDOMKEY)
+	}
+
+	// The arguments that are keys, cluster mode routes a command by them
+	pub fn keys<'a>(&self, args: &'a [String]) -> Vec<&'a str> {
+		let keys = match self {
+			Command::DEL | Command::UNLINK | Command::EXISTS | Command::TOUCH | Command::PFCOUNT | Command::PFMERGE => args,
+			Command::RENAME | Command::RENAMENX | Command::COPY => &args[..args.len().min(2)],
+			Command::BITOP => args.get(1..).unwrap_or_default(),
+			Command::GEOSEARCHSTORE => &args[..args.len().min(2)],
+			_ if self.is_bitmap() || self.is_hyperloglog() || self.is_probabilistic_filter() || self.is_geo() || self.is_json() => &args[..args.len().min(1)],
+			Command::GET | Command::SET | Command::DUMP | Command::RESTORE | Command::MOVE
+				| Command::HSCAN | Command::SSCAN | Command::ZSCAN | Command::TYPE => &args[..args.len().min(1)],
+			_ => &[],
+		};
+		keys.iter().map(String::as_str).collect()
+	}
+
+	// Commands that change the dataset and therefore go to the append only file
+	pub fn is_write(&self) -> bool {
+		matches!(self, Command::SET | Command::DEL | Command::UNLINK | Command::SETBIT | Command::BITOP | Command::BITFIELD
+			| Command::PFADD | Command::PFMERGE | Command::BFRESERVE | Command::BFADD | Command::BFMADD
+			| Command::CFRESERVE | Command::CFADD | Command::CFDEL | Command::GEOADD | Command::GEOSEARCHSTORE
+			| Command::JSONSET | Command::JSONDEL | Command::JSONNUMINCRBY | Command::JSONARRAPPEND | Command::RESTORE
+			| Command::MOVE | Command::SWAPDB | Command::FLUSHDB | Command::FLUSHALL
+			| Command::RENAME | Command::RENAMENX | Command::COPY)
+	}
+}
+
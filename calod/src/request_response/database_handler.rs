@@ -0,0 +1,88 @@
+use std::io::Write;
+
+use crate::parser::parser::RESPOutput;
+use crate::request_response::{command::Command, response_helper};
+use crate::store::calod_store::CalodStore;
+
+// Handle MOVE, SWAPDB, FLUSHDB, FLUSHALL and DBSIZE, SELECT is taken over by the connection loop
+pub fn respond<T: Write>(store: &CalodStore, stream: &mut T, command: &Command, args: &[String]) {
+	let result = match command {
+		Command::MOVE => move_key(store, args),
+		Command::SWAPDB => swapdb(store, args),
+		Command::FLUSHDB => flush(store, args, "flushdb"),
+		Command::FLUSHALL => flush(store, args, "flushall"),
+		Command::DBSIZE => dbsize(store, args),
+		_ => Err(String::from("ERR unknown database command")),
+	};
+
+	match result {
+		Ok(output) => response_helper::send_resp_response(stream, &output),
+		Err(e) => response_helper::send_error_response(stream, &e),
+	}
+}
+
+fn wrong_args(name: &str) -> String {
+	format!("ERR wrong number of arguments for '{}' command", name)
+}
+
+pub(crate) fn db_index(arg: &str) -> Result<usize, String> {
+	arg.parse::<usize>().map_err(|_| String::from("ERR invalid DB index"))
+}
+
+// A cluster only has database 0
+fn refuse_in_cluster(store: &CalodStore, name: &str) -> Result<(), String> {
+	if store.cluster().is_enabled() {
+		return Err(format!("ERR {} is not allowed in cluster mode", name));
+	}
+	Ok(())
+}
+
+// MOVE key db
+fn move_key(store: &CalodStore, args: &[String]) -> Result<RESPOutput, String> {
+	if args.len() != 2 {
+		return Err(wrong_args("move"));
+	}
+	refuse_in_cluster(store, "MOVE")?;
+	let db = db_index(&args[1])?;
+
+	let moved = store.move_key(&args[0], db).map_err(|e| e.to_string())?;
+	Ok(RESPOutput::Integer(moved as i64))
+}
+
+// SWAPDB index1 index2
+fn swapdb(store: &CalodStore, args: &[String]) -> Result<RESPOutput, String> {
+	if args.len() != 2 {
+		return Err(wrong_args("swapdb"));
+	}
+	refuse_in_cluster(store, "SWAPDB")?;
+	let first = args[0].parse::<usize>().map_err(|_| String::from("ERR invalid first DB index"))?;
+	let second = args[1].parse::<usize>().map_err(|_| String::from("ERR invalid second DB index"))?;
+
+	store.swap_databases(first, second).map_err(|e| e.to_string())?;
+	Ok(RESPOutput::SimpleString(String::from("OK")))
+}
+
+// FLUSHDB [ASYNC|SYNC] and FLUSHALL [ASYNC|SYNC], both always flush right away
+fn flush(store: &CalodStore, args: &[String], name: &str) -> Result<RESPOutput, String> {
+	match args {
+		[] => {}
+		[mode] if mode.eq_ignore_ascii_case("async") || mode.eq_ignore_ascii_case("sync") => {}
+		[_] => return Err(String::from("ERR syntax error")),
+		_ => return Err(wrong_args(name)),
+	}
+
+	if name == "flushall" {
+		store.flush_all();
+	} else {
+		store.flush_db();
+	}
+	Ok(RESPOutput::SimpleString(String::from("OK")))
+}
+
+// DBSIZE
+fn dbsize(store: &CalodStore, args: &[String]) -> Result<RESPOutput, String> {
+	if !args.is_empty() {
+		return Err(wrong_args("dbsize"));
+	}
+	Ok(RESPOutput::Integer(store.db_size() as i64))
+}
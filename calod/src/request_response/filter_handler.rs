@@ -0,0 +1,173 @@
+use std::io::Write;
+
+use crate::parser::parser::RESPOutput;
+use crate::request_response::{command::Command, response_helper};
+use crate::store::bloom_filter::{BLOOM_DEFAULT_EXPANSION, BLOOM_MAX_CAPACITY, BLOOM_MAX_EXPANSION};
+use crate::store::calod_store::CalodStore;
+use crate::store::cuckoo_filter::{
+	CUCKOO_DEFAULT_BUCKET_SIZE, CUCKOO_DEFAULT_EXPANSION, CUCKOO_DEFAULT_MAX_ITERATIONS, CUCKOO_MAX_BUCKET_SIZE, CUCKOO_MAX_CAPACITY,
+	CUCKOO_MAX_EXPANSION, CUCKOO_MAX_ITERATIONS,
+};
+
+// Handle the BF.* and CF.* commands
+pub fn respond<T: Write>(store: &CalodStore, stream: &mut T, command: &Command, args: &[String]) {
+	let result = match command {
+		Command::BFRESERVE => bf_reserve(store, args),
+		Command::BFADD => bf_add(store, args, false),
+		Command::BFMADD => bf_add(store, args, true),
+		Command::BFEXISTS => bf_exists(store, args, false),
+		Command::BFMEXISTS => bf_exists(store, args, true),
+		Command::CFRESERVE => cf_reserve(store, args),
+		Command::CFADD => cf_add(store, args),
+		Command::CFDEL => cf_del(store, args),
+		Command::CFEXISTS => cf_exists(store, args),
+		_ => Err(String::from("ERR unknown filter command")),
+	};
+
+	match result {
+		Ok(output) => response_helper::send_resp_response(stream, &output),
+		Err(e) => response_helper::send_error_response(stream, &e),
+	}
+}
+
+fn wrong_args(name: &str) -> String {
+	format!("ERR wrong number of arguments for '{}' command", name)
+}
+
+// Parse a value in `1..=max`
+fn parse_positive<N: std::str::FromStr + PartialOrd + Default>(str: &str, max: N, error: &str) -> Result<N, String> {
+	match str.parse::<N>() {
+		Ok(value) if value > N::default() && value <= max => Ok(value),
+		_ => Err(String::from(error)),
+	}
+}
+
+fn booleans(values: Vec<bool>) -> RESPOutput {
+	RESPOutput::Array(values.into_iter().map(|v| RESPOutput::Integer(v as i64)).collect())
+}
+
+// BF.RESERVE key error_rate capacity [EXPANSION expansion] [NONSCALING]
+fn bf_reserve(store: &CalodStore, args: &[String]) -> Result<RESPOutput, String> {
+	if args.len() < 3 {
+		return Err(wrong_args("bf.reserve"));
+	}
+
+	let error_rate: f64 = match args[1].parse() {
+		Ok(rate) if rate > 0.0 && rate < 1.0 => rate,
+		_ => return Err(String::from("ERR (0 < error rate range < 1)")),
+	};
+	let capacity: u64 = parse_positive(&args[2], BLOOM_MAX_CAPACITY, "ERR (capacity should be larger than 0)")?;
+
+	let mut expansion = BLOOM_DEFAULT_EXPANSION;
+	let mut scaling = true;
+	let mut i = 3;
+	while i < args.len() {
+		match args[i].to_lowercase().as_str() {
+			"expansion" if i + 1 < args.len() => {
+				expansion = parse_positive(&args[i + 1], BLOOM_MAX_EXPANSION, "ERR expansion should be greater or equal to 1")?;
+				i += 2;
+			}
+			"nonscaling" => {
+				scaling = false;
+				i += 1;
+			}
+			_ => return Err(String::from("ERR syntax error")),
+		}
+	}
+
+	store.bf_reserve(&args[0], error_rate, capacity, expansion, scaling).map_err(|e| e.to_string())?;
+	Ok(RESPOutput::SimpleString(String::from("OK")))
+}
+
+// BF.ADD key item / BF.MADD key item [item ...]
+fn bf_add(store: &CalodStore, args: &[String], multi: bool) -> Result<RESPOutput, String> {
+	if args.len() < 2 || (!multi && args.len() != 2) {
+		return Err(wrong_args(if multi { "bf.madd" } else { "bf.add" }));
+	}
+	let items: Vec<&str> = args[1..].iter().map(|a| a.as_str()).collect();
+
+	// BF.MADD reports an item that does not fit in its own entry, the items before it stay added
+	let added = store.bf_add(&args[0], &items).map_err(|e| e.to_string())?;
+	if multi {
+		return Ok(RESPOutput::Array(added.into_iter().map(|result| match result {
+			Ok(added) => RESPOutput::Integer(added as i64),
+			Err(e) => RESPOutput::Error(e.to_string()),
+		}).collect()));
+	}
+	let added = added.into_iter().next().unwrap().map_err(|e| e.to_string())?;
+	Ok(RESPOutput::Integer(added as i64))
+}
+
+// BF.EXISTS key item / BF.MEXISTS key item [item ...]
+fn bf_exists(store: &CalodStore, args: &[String], multi: bool) -> Result<RESPOutput, String> {
+	if args.len() < 2 || (!multi && args.len() != 2) {
+		return Err(wrong_args(if multi { "bf.mexists" } else { "bf.exists" }));
+	}
+	let items: Vec<&str> = args[1..].iter().map(|a| a.as_str()).collect();
+
+	let found = store.bf_exists(&args[0], &items).map_err(|e| e.to_string())?;
+	Ok(if multi { booleans(found) } else { RESPOutput::Integer(found[0] as i64) })
+}
+
+// CF.RESERVE key capacity [BUCKETSIZE bucketsize] [MAXITERATIONS maxiterations] [EXPANSION expansion]
+fn cf_reserve(store: &CalodStore, args: &[String]) -> Result<RESPOutput, String> {
+	if args.len() < 2 {
+		return Err(wrong_args("cf.reserve"));
+	}
+	let capacity: u64 = parse_positive(&args[1], CUCKOO_MAX_CAPACITY, "ERR Bad capacity")?;
+
+	let mut bucket_size = CUCKOO_DEFAULT_BUCKET_SIZE;
+	let mut max_iterations = CUCKOO_DEFAULT_MAX_ITERATIONS;
+	let mut expansion = CUCKOO_DEFAULT_EXPANSION;
+	let mut i = 2;
+	while i + 1 < args.len() {
+		match args[i].to_lowercase().as_str() {
+			"bucketsize" => bucket_size = parse_positive(&args[i + 1], CUCKOO_MAX_BUCKET_SIZE, "ERR Bad bucket size")?,
+			"maxiterations" => max_iterations = parse_positive(&args[i + 1], CUCKOO_MAX_ITERATIONS, "ERR Bad maxiterations")?,
+			"expansion" => {
+				expansion = match args[i + 1].parse() {
+					Ok(expansion) if expansion <= CUCKOO_MAX_EXPANSION => expansion,
+					_ => return Err(String::from("ERR Bad expansion")),
+				}
+			}
+			_ => return Err(String::from("ERR syntax error")),
+		}
+		i += 2;
+	}
+	if i != args.len() {
+		return Err(String::from("ERR syntax error"));
+	}
+
+	store.cf_reserve(&args[0], capacity, bucket_size, max_iterations, expansion).map_err(|e| e.to_string())?;
+	Ok(RESPOutput::SimpleString(String::from("OK")))
+}
+
+// CF.ADD key item
+fn cf_add(store: &CalodStore, args: &[String]) -> Result<RESPOutput, String> {
+	if args.len() != 2 {
+		return Err(wrong_args("cf.add"));
+	}
+
+	store.cf_add(&args[0], &args[1]).map_err(|e| e.to_string())?;
+	Ok(RESPOutput::Integer(1))
+}
+
+// CF.DEL key item
+fn cf_del(store: &CalodStore, args: &[String]) -> Result<RESPOutput, String> {
+	if args.len() != 2 {
+		return Err(wrong_args("cf.del"));
+	}
+
+	let removed = store.cf_del(&args[0], &args[1]).map_err(|e| e.to_string())?;
+	Ok(RESPOutput::Integer(removed as i64))
+}
+
+// CF.EXISTS key item
+fn cf_exists(store: &CalodStore, args: &[String]) -> Result<RESPOutput, String> {
+	if args.len() != 2 {
+		return Err(wrong_args("cf.exists"));
+	}
+
+	let found = store.cf_exists(&args[0], &args[1]).map_err(|e| e.to_string())?;
+	Ok(RESPOutput::Integer(found as i64))
+}
@@ -0,0 +1,240 @@
+use std::cmp::Ordering;
+use std::io::Write;
+
+use crate::parser::parser::RESPOutput;
+use crate::request_response::{command::Command, response_helper};
+use crate::store::calod_store::CalodStore;
+use crate::store::geo::{is_valid_coordinate, GeoAddOptions, GeoMatch, GeoOrigin, GeoSearch, GeoShape, GeoUnit};
+
+const SYNTAX_ERROR: &str = "ERR syntax error";
+const UNIT_ERROR: &str = "ERR unsupported unit provided. please use M, KM, FT, MI";
+
+// Handle GEOADD, GEOPOS, GEODIST, GEOSEARCH and GEOSEARCHSTORE
+pub fn respond<T: Write>(store: &CalodStore, stream: &mut T, command: &Command, args: &[String]) {
+	let result = match command {
+		Command::GEOADD => geoadd(store, args),
+		Command::GEOPOS => geopos(store, args),
+		Command::GEODIST => geodist(store, args),
+		Command::GEOSEARCH => geosearch(store, args),
+		Command::GEOSEARCHSTORE => geosearchstore(store, args),
+		_ => Err(String::from("ERR unknown geo command")),
+	};
+
+	match result {
+		Ok(output) => response_helper::send_resp_response(stream, &output),
+		Err(e) => response_helper::send_error_response(stream, &e),
+	}
+}
+
+fn wrong_args(name: &str) -> String {
+	format!("ERR wrong number of arguments for '{}' command", name)
+}
+
+fn parse_float(str: &str) -> Result<f64, String> {
+	str.parse::<f64>().ok().filter(|f| f.is_finite()).ok_or_else(|| String::from("ERR value is not a valid float"))
+}
+
+fn parse_unit(str: &str) -> Result<GeoUnit, String> {
+	GeoUnit::from(str).ok_or_else(|| String::from(UNIT_ERROR))
+}
+
+fn format_distance(distance: f64) -> RESPOutput {
+	RESPOutput::BulkString(format!("{:.4}", distance))
+}
+
+fn format_coordinates(longitude: f64, latitude: f64) -> RESPOutput {
+	RESPOutput::Array(vec![
+		RESPOutput::BulkString(longitude.to_string()),
+		RESPOutput::BulkString(latitude.to_string()),
+	])
+}
+
+// GEOADD key [NX | XX] [CH] longitude latitude member [longitude latitude member ...]
+fn geoadd(store: &CalodStore, args: &[String]) -> Result<RESPOutput, String> {
+	if args.is_empty() {
+		return Err(wrong_args("geoadd"));
+	}
+
+	let mut options = GeoAddOptions::default();
+	let mut i = 1;
+	while i < args.len() {
+		match args[i].to_lowercase().as_str() {
+			"nx" => options.nx = true,
+			"xx" => options.xx = true,
+			"ch" => options.ch = true,
+			_ => break,
+		}
+		i += 1;
+	}
+
+	if options.nx && options.xx {
+		return Err(String::from("ERR XX and NX options at the same time are not compatible"));
+	}
+	if i == args.len() || !(args.len() - i).is_multiple_of(3) {
+		return Err(wrong_args("geoadd"));
+	}
+
+	let mut points: Vec<(f64, f64, String)> = Vec::new();
+	for triple in args[i..].chunks(3) {
+		points.push((parse_float(&triple[0])?, parse_float(&triple[1])?, triple[2].clone()));
+	}
+
+	let changed = store.geoadd(&args[0], &points, options).map_err(|e| e.to_string())?;
+	Ok(RESPOutput::Integer(changed as i64))
+}
+
+// GEOPOS key [member [member ...]]
+fn geopos(store: &CalodStore, args: &[String]) -> Result<RESPOutput, String> {
+	if args.is_empty() {
+		return Err(wrong_args("geopos"));
+	}
+	let members: Vec<&str> = args[1..].iter().map(|m| m.as_str()).collect();
+
+	let positions = store.geopos(&args[0], &members).map_err(|e| e.to_string())?;
+
+	Ok(RESPOutput::Array(positions.into_iter().map(|position| match position {
+		Some((longitude, latitude)) => format_coordinates(longitude, latitude),
+		None => RESPOutput::Null,
+	}).collect()))
+}
+
+// GEODIST key member1 member2 [M | KM | FT | MI]
+fn geodist(store: &CalodStore, args: &[String]) -> Result<RESPOutput, String> {
+	if args.len() != 3 && args.len() != 4 {
+		return Err(wrong_args("geodist"));
+	}
+	let unit = match args.get(3) {
+		Some(unit) => parse_unit(unit)?,
+		None => GeoUnit::Meters,
+	};
+
+	match store.geodist(&args[0], &args[1], &args[2], unit).map_err(|e| e.to_string())? {
+		Some(distance) => Ok(format_distance(distance)),
+		None => Ok(RESPOutput::Null),
+	}
+}
+
+// Reply flags of GEOSEARCH
+#[derive(Default)]
+struct GeoReplyOptions {
+	with_coord: bool,
+	with_dist: bool,
+	with_hash: bool,
+	store_dist: bool,
+}
+
+// Parse the shared arguments of GEOSEARCH and GEOSEARCHSTORE
+// 1. Exactly one of FROMMEMBER / FROMLONLAT and one of BYRADIUS / BYBOX is required
+// 2. ANY is only valid together with COUNT
+// 3. WITH* flags are only valid for GEOSEARCH, STOREDIST only for GEOSEARCHSTORE
+fn parse_search(args: &[String], store: bool) -> Result<(GeoSearch, GeoReplyOptions), String> {
+	let mut origin: Option<GeoOrigin> = None;
+	let mut shape: Option<(GeoShape, GeoUnit)> = None;
+	let mut order: Option<Ordering> = None;
+	let mut count: Option<usize> = None;
+	let mut any = false;
+	let mut reply = GeoReplyOptions::default();
+
+	let mut i = 0;
+	while i < args.len() {
+		let remaining = args.len() - i - 1;
+		match args[i].to_lowercase().as_str() {
+			"frommember" if remaining >= 1 && origin.is_none() => {
+				origin = Some(GeoOrigin::Member(args[i + 1].clone()));
+				i += 1;
+			}
+			"fromlonlat" if remaining >= 2 && origin.is_none() => {
+				let (longitude, latitude) = (parse_float(&args[i + 1])?, parse_float(&args[i + 2])?);
+				if !is_valid_coordinate(longitude, latitude) {
+					return Err(format!("ERR invalid longitude,latitude pair {:.6},{:.6}", longitude, latitude));
+				}
+				origin = Some(GeoOrigin::LonLat(longitude, latitude));
+				i += 2;
+			}
+			"byradius" if remaining >= 2 && shape.is_none() => {
+				let unit = parse_unit(&args[i + 2])?;
+				let radius = parse_float(&args[i + 1])?;
+				if radius < 0.0 {
+					return Err(String::from("ERR radius cannot be negative"));
+				}
+				shape = Some((GeoShape::Radius(radius * unit.meters()), unit));
+				i += 2;
+			}
+			"bybox" if remaining >= 3 && shape.is_none() => {
+				let unit = parse_unit(&args[i + 3])?;
+				let (width, height) = (parse_float(&args[i + 1])?, parse_float(&args[i + 2])?);
+				if width < 0.0 || height < 0.0 {
+					return Err(String::from("ERR height or width cannot be negative"));
+				}
+				shape = Some((GeoShape::Box(width * unit.meters(), height * unit.meters()), unit));
+				i += 3;
+			}
+			"asc" => order = Some(Ordering::Less),
+			"desc" => order = Some(Ordering::Greater),
+			"count" if remaining >= 1 => {
+				count = match args[i + 1].parse::<usize>() {
+					Ok(n) if n > 0 => Some(n),
+					_ => return Err(String::from("ERR COUNT must be > 0")),
+				};
+				i += 1;
+			}
+			"any" => any = true,
+			"withcoord" if !store => reply.with_coord = true,
+			"withdist" if !store => reply.with_dist = true,
+			"withhash" if !store => reply.with_hash = true,
+			"storedist" if store => reply.store_dist = true,
+			_ => return Err(String::from(SYNTAX_ERROR)),
+		}
+		i += 1;
+	}
+
+	let origin = origin.ok_or_else(|| String::from("ERR exactly one of FROMMEMBER or FROMLONLAT can be specified"))?;
+	let (shape, unit) = shape.ok_or_else(|| String::from("ERR exactly one of BYRADIUS and BYBOX can be specified"))?;
+	if any && count.is_none() {
+		return Err(String::from("ERR the ANY argument requires COUNT argument"));
+	}
+
+	Ok((GeoSearch { origin, shape, unit, order, count, any }, reply))
+}
+
+fn format_match(m: GeoMatch, reply: &GeoReplyOptions) -> RESPOutput {
+	if !reply.with_coord && !reply.with_dist && !reply.with_hash {
+		return RESPOutput::BulkString(m.member);
+	}
+
+	let mut item = vec![RESPOutput::BulkString(m.member)];
+	if reply.with_dist {
+		item.push(format_distance(m.distance));
+	}
+	if reply.with_hash {
+		item.push(RESPOutput::Integer(m.hash as i64));
+	}
+	if reply.with_coord {
+		item.push(format_coordinates(m.longitude, m.latitude));
+	}
+	RESPOutput::Array(item)
+}
+
+// GEOSEARCH key <FROMMEMBER member | FROMLONLAT longitude latitude> <BYRADIUS radius unit | BYBOX width height unit>
+//     [ASC | DESC] [COUNT count [ANY]] [WITHCOORD] [WITHDIST] [WITHHASH]
+fn geosearch(store: &CalodStore, args: &[String]) -> Result<RESPOutput, String> {
+	if args.len() < 2 {
+		return Err(wrong_args("geosearch"));
+	}
+	let (query, reply) = parse_search(&args[1..], false)?;
+
+	let matches = store.geosearch(&args[0], &query).map_err(|e| e.to_string())?;
+	Ok(RESPOutput::Array(matches.into_iter().map(|m| format_match(m, &reply)).collect()))
+}
+
+// GEOSEARCHSTORE destination source <FROMMEMBER ... | FROMLONLAT ...> <BYRADIUS ... | BYBOX ...>
+//     [ASC | DESC] [COUNT count [ANY]] [STOREDIST]
+fn geosearchstore(store: &CalodStore, args: &[String]) -> Result<RESPOutput, String> {
+	if args.len() < 3 {
+		return Err(wrong_args("geosearchstore"));
+	}
+	let (query, reply) = parse_search(&args[2..], true)?;
+
+	let stored = store.geosearchstore(&args[0], &args[1], &query, reply.store_dist).map_err(|e| e.to_string())?;
+	Ok(RESPOutput::Integer(stored as i64))
+}
@@ -0,0 +1,43 @@
+use std::io::Write;
+
+use crate::request_response::{command::Command, response_helper};
+use crate::store::calod_store::CalodStore;
+
+// Handle PFADD, PFCOUNT and PFMERGE
+pub fn respond<T: Write>(store: &CalodStore, stream: &mut T, command: &Command, args: &[String]) {
+	let keys: Vec<&str> = args.iter().map(|a| a.as_str()).collect();
+
+	match command {
+		// PFADD key [element [element ...]]
+		Command::PFADD => {
+			if keys.is_empty() {
+				return response_helper::send_error_response(stream, "ERR wrong number of arguments for 'pfadd' command");
+			}
+			match store.pfadd(keys[0], &keys[1..]) {
+				Ok(changed) => response_helper::send_integer_response(stream, changed as i64),
+				Err(e) => response_helper::send_error_response(stream, &e.to_string()),
+			}
+		}
+		// PFCOUNT key [key ...]
+		Command::PFCOUNT => {
+			if keys.is_empty() {
+				return response_helper::send_error_response(stream, "ERR wrong number of arguments for 'pfcount' command");
+			}
+			match store.pfcount(&keys) {
+				Ok(count) => response_helper::send_integer_response(stream, count as i64),
+				Err(e) => response_helper::send_error_response(stream, &e.to_string()),
+			}
+		}
+		// PFMERGE destkey [sourcekey [sourcekey ...]]
+		Command::PFMERGE => {
+			if keys.is_empty() {
+				return response_helper::send_error_response(stream, "ERR wrong number of arguments for 'pfmerge' command");
+			}
+			match store.pfmerge(keys[0], &keys[1..]) {
+				Ok(()) => response_helper::send_simple_string_response(stream, "OK"),
+				Err(e) => response_helper::send_error_response(stream, &e.to_string()),
+			}
+		}
+		_ => response_helper::send_error_response(stream, "ERR unknown hyperloglog command"),
+	}
+}
@@ -0,0 +1,187 @@
+use std::io::Write;
+
+use serde_json::{Map, Value};
+
+use crate::parser::parser::RESPOutput;
+use crate::request_response::{command::Command, response_helper};
+use crate::store::calod_store::CalodStore;
+use crate::store::json_document::{JsonPath, JsonSetCondition};
+
+// Handle the JSON.* commands
+pub fn respond<T: Write>(store: &CalodStore, stream: &mut T, command: &Command, args: &[String]) {
+	let result = match command {
+		Command::JSONSET => json_set(store, args),
+		Command::JSONGET => json_get(store, args),
+		Command::JSONDEL => json_del(store, args),
+		Command::JSONNUMINCRBY => json_numincrby(store, args),
+		Command::JSONARRAPPEND => json_arrappend(store, args),
+		Command::JSONOBJKEYS => json_objkeys(store, args),
+		Command::JSONTYPE => json_type(store, args),
+		_ => Err(String::from("ERR unknown json command")),
+	};
+
+	match result {
+		Ok(output) => response_helper::send_resp_response(stream, &output),
+		Err(e) => response_helper::send_error_response(stream, &e),
+	}
+}
+
+fn wrong_args(name: &str) -> String {
+	format!("ERR wrong number of arguments for '{}' command", name)
+}
+
+fn parse_path(path: Option<&String>) -> Result<JsonPath, String> {
+	JsonPath::parse(path.map(|p| p.as_str()).unwrap_or("$")).map_err(|e| e.to_string())
+}
+
+fn missing_path(path: &str) -> String {
+	format!("ERR Path '{}' does not exist", path)
+}
+
+// JSON.SET key path value [NX | XX]
+fn json_set(store: &CalodStore, args: &[String]) -> Result<RESPOutput, String> {
+	if args.len() != 3 && args.len() != 4 {
+		return Err(wrong_args("json.set"));
+	}
+	let condition = match args.get(3).map(|c| c.to_lowercase()).as_deref() {
+		None => None,
+		Some("nx") => Some(JsonSetCondition::Nx),
+		Some("xx") => Some(JsonSetCondition::Xx),
+		Some(_) => return Err(String::from("ERR syntax error")),
+	};
+	let path = parse_path(args.get(1))?;
+
+	match store.json_set(&args[0], &path, &args[2], condition).map_err(|e| e.to_string())? {
+		true => Ok(RESPOutput::SimpleString(String::from("OK"))),
+		false => Ok(RESPOutput::Null),
+	}
+}
+
+// JSON.GET key [path [path ...]]
+// 1. Legacy paths reply with their first match, `$` paths with an array of all matches
+// 2. Several paths reply with an object keyed by path
+fn json_get(store: &CalodStore, args: &[String]) -> Result<RESPOutput, String> {
+	if args.is_empty() {
+		return Err(wrong_args("json.get"));
+	}
+	let paths: Vec<String> = if args.len() == 1 { vec![String::from(".")] } else { args[1..].to_vec() };
+
+	let mut results: Vec<(String, Value)> = Vec::new();
+	for raw in &paths {
+		let path = parse_path(Some(raw))?;
+		let matches = match store.json_get(&args[0], &path).map_err(|e| e.to_string())? {
+			Some(matches) => matches,
+			None => return Ok(RESPOutput::Null),
+		};
+
+		let value = if path.legacy {
+			matches.into_iter().next().ok_or_else(|| missing_path(raw))?
+		} else {
+			Value::Array(matches)
+		};
+		results.push((raw.clone(), value));
+	}
+
+	let reply = if results.len() == 1 {
+		results.pop().unwrap().1
+	} else {
+		Value::Object(results.into_iter().collect::<Map<String, Value>>())
+	};
+	Ok(RESPOutput::BulkString(reply.to_string()))
+}
+
+// JSON.DEL key [path]
+fn json_del(store: &CalodStore, args: &[String]) -> Result<RESPOutput, String> {
+	if args.is_empty() || args.len() > 2 {
+		return Err(wrong_args("json.del"));
+	}
+	let path = parse_path(args.get(1))?;
+
+	let deleted = store.json_del(&args[0], &path).map_err(|e| e.to_string())?;
+	Ok(RESPOutput::Integer(deleted as i64))
+}
+
+// JSON.NUMINCRBY key path value
+fn json_numincrby(store: &CalodStore, args: &[String]) -> Result<RESPOutput, String> {
+	if args.len() != 3 {
+		return Err(wrong_args("json.numincrby"));
+	}
+	let path = parse_path(args.get(1))?;
+
+	let results = store.json_numincrby(&args[0], &path, &args[2]).map_err(|e| e.to_string())?;
+
+	if path.legacy {
+		return match results.into_iter().flatten().last() {
+			Some(value) => Ok(RESPOutput::BulkString(value.to_string())),
+			None => Err(missing_path(&args[1])),
+		};
+	}
+	let values: Vec<Value> = results.into_iter().map(|r| r.unwrap_or(Value::Null)).collect();
+	Ok(RESPOutput::BulkString(Value::Array(values).to_string()))
+}
+
+// JSON.ARRAPPEND key [path] value [value ...]
+fn json_arrappend(store: &CalodStore, args: &[String]) -> Result<RESPOutput, String> {
+	if args.len() < 2 {
+		return Err(wrong_args("json.arrappend"));
+	}
+	let (path, values) = if args.len() == 2 {
+		(parse_path(None)?, &args[1..])
+	} else {
+		(parse_path(args.get(1))?, &args[2..])
+	};
+	let values: Vec<&str> = values.iter().map(|v| v.as_str()).collect();
+
+	let lengths = store.json_arrappend(&args[0], &path, &values).map_err(|e| e.to_string())?;
+
+	if path.legacy {
+		return match lengths.into_iter().flatten().last() {
+			Some(len) => Ok(RESPOutput::Integer(len as i64)),
+			None => Err(missing_path(&args[1])),
+		};
+	}
+	Ok(RESPOutput::Array(lengths.into_iter().map(|len| match len {
+		Some(len) => RESPOutput::Integer(len as i64),
+		None => RESPOutput::Null,
+	}).collect()))
+}
+
+fn keys_reply(keys: Vec<String>) -> RESPOutput {
+	RESPOutput::Array(keys.into_iter().map(RESPOutput::BulkString).collect())
+}
+
+// JSON.OBJKEYS key [path]
+fn json_objkeys(store: &CalodStore, args: &[String]) -> Result<RESPOutput, String> {
+	if args.is_empty() || args.len() > 2 {
+		return Err(wrong_args("json.objkeys"));
+	}
+	let path = parse_path(args.get(1).or(Some(&String::from("."))))?;
+
+	let results = match store.json_objkeys(&args[0], &path).map_err(|e| e.to_string())? {
+		Some(results) => results,
+		None => return Ok(RESPOutput::Null),
+	};
+
+	if path.legacy {
+		return Ok(results.into_iter().flatten().next().map(keys_reply).unwrap_or(RESPOutput::Null));
+	}
+	Ok(RESPOutput::Array(results.into_iter().map(|keys| keys.map(keys_reply).unwrap_or(RESPOutput::Null)).collect()))
+}
+
+// JSON.TYPE key [path]
+fn json_type(store: &CalodStore, args: &[String]) -> Result<RESPOutput, String> {
+	if args.is_empty() || args.len() > 2 {
+		return Err(wrong_args("json.type"));
+	}
+	let path = parse_path(args.get(1).or(Some(&String::from("."))))?;
+
+	let types = match store.json_type(&args[0], &path).map_err(|e| e.to_string())? {
+		Some(types) => types,
+		None => return Ok(RESPOutput::Null),
+	};
+
+	if path.legacy {
+		return Ok(types.first().map(|t| RESPOutput::SimpleString(t.to_string())).unwrap_or(RESPOutput::Null));
+	}
+	Ok(RESPOutput::Array(types.into_iter().map(|t| RESPOutput::BulkString(t.to_string())).collect()))
+}
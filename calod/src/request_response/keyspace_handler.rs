@@ -0,0 +1,96 @@
+use std::io::Write;
+
+use crate::parser::parser::RESPOutput;
+use crate::request_response::{command::Command, database_handler, response_helper};
+use crate::store::calod_store::CalodStore;
+
+// Handle DEL, UNLINK, EXISTS, TYPE, RENAME, RENAMENX, COPY, TOUCH and RANDOMKEY
+pub fn respond<T: Write>(store: &CalodStore, stream: &mut T, command: &Command, args: &[String]) {
+	let result = match command {
+		Command::DEL => count_keys(args, "del", |key| store.take_entry(key).is_some()),
+		Command::UNLINK => count_keys(args, "unlink", |key| store.unlink_value(key)),
+		Command::EXISTS => count_keys(args, "exists", |key| store.contains_key(key)),
+		Command::TOUCH => count_keys(args, "touch", |key| store.touch_key(key)),
+		Command::TYPE => key_type(store, args),
+		Command::RENAME => rename(store, args, true),
+		Command::RENAMENX => rename(store, args, false),
+		Command::COPY => copy(store, args),
+		Command::RANDOMKEY => randomkey(store, args),
+		_ => Err(String::from("ERR unknown keyspace command")),
+	};
+
+	match result {
+		Ok(output) => response_helper::send_resp_response(stream, &output),
+		Err(e) => response_helper::send_error_response(stream, &e),
+	}
+}
+
+fn wrong_args(name: &str) -> String {
+	format!("ERR wrong number of arguments for '{}' command", name)
+}
+
+// DEL, UNLINK, EXISTS and TOUCH key [key ...], the number of keys `f` holds for
+// A key given twice counts twice, like in Redis
+fn count_keys(args: &[String], name: &str, mut f: impl FnMut(&str) -> bool) -> Result<RESPOutput, String> {
+	if args.is_empty() {
+		return Err(wrong_args(name));
+	}
+	Ok(RESPOutput::Integer(args.iter().filter(|key| f(key)).count() as i64))
+}
+
+// TYPE key
+fn key_type(store: &CalodStore, args: &[String]) -> Result<RESPOutput, String> {
+	if args.len() != 1 {
+		return Err(wrong_args("type"));
+	}
+	Ok(RESPOutput::SimpleString(store.type_of(&args[0]).unwrap_or("none").to_string()))
+}
+
+// RENAME key newkey and RENAMENX key newkey
+fn rename(store: &CalodStore, args: &[String], replace: bool) -> Result<RESPOutput, String> {
+	if args.len() != 2 {
+		return Err(wrong_args(if replace { "rename" } else { "renamenx" }));
+	}
+
+	let renamed = store.rename_key(&args[0], &args[1], replace).map_err(|e| e.to_string())?;
+	if replace {
+		return Ok(RESPOutput::SimpleString(String::from("OK")));
+	}
+	Ok(RESPOutput::Integer(renamed as i64))
+}
+
+// COPY source destination [DB destination-db] [REPLACE]
+fn copy(store: &CalodStore, args: &[String]) -> Result<RESPOutput, String> {
+	if args.len() < 2 {
+		return Err(wrong_args("copy"));
+	}
+
+	let mut db = store.db();
+	let mut replace = false;
+	let mut i = 2;
+	while i < args.len() {
+		match args[i].to_lowercase().as_str() {
+			"db" if i + 1 < args.len() => {
+				db = database_handler::db_index(&args[i + 1])?;
+				i += 1;
+			}
+			"replace" => replace = true,
+			_ => return Err(String::from("ERR syntax error")),
+		}
+		i += 1;
+	}
+	if db != store.db() && store.cluster().is_enabled() {
+		return Err(String::from("ERR Copying to another database is not allowed in cluster mode"));
+	}
+
+	let copied = store.copy_key(&args[0], &args[1], db, replace).map_err(|e| e.to_string())?;
+	Ok(RESPOutput::Integer(copied as i64))
+}
+
+// RANDOMKEY
+fn randomkey(store: &CalodStore, args: &[String]) -> Result<RESPOutput, String> {
+	if !args.is_empty() {
+		return Err(wrong_args("randomkey"));
+	}
+	Ok(store.random_key().map_or(RESPOutput::Null, RESPOutput::BulkString))
+}
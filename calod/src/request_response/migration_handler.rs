@@ -0,0 +1,106 @@
+use std::io::Write;
+use std::time::Duration;
+
+use crate::parser::parser::RESPOutput;
+use crate::persistence::dump::{restore_deadline, restore_payload, DumpError, MigrateOptions};
+use crate::request_response::{command::Command, response_helper};
+use crate::store::calod_store::CalodStore;
+
+// Handle DUMP, RESTORE and MIGRATE
+pub fn respond<T: Write>(store: &CalodStore, stream: &mut T, command: &Command, args: &[String]) {
+	let result = match command {
+		Command::DUMP => dump(store, args),
+		Command::RESTORE => restore(store, args),
+		Command::MIGRATE => migrate(store, args),
+		_ => Err(String::from("ERR unknown migration command")),
+	};
+
+	match result {
+		Ok(output) => response_helper::send_resp_response(stream, &output),
+		Err(e) => response_helper::send_error_response(stream, &e),
+	}
+}
+
+fn wrong_args(name: &str) -> String {
+	format!("ERR wrong number of arguments for '{}' command", name)
+}
+
+// DUMP key
+fn dump(store: &CalodStore, args: &[String]) -> Result<RESPOutput, String> {
+	if args.len() != 1 {
+		return Err(wrong_args("dump"));
+	}
+
+	Ok(store.dump(&args[0]).map_or(RESPOutput::Null, RESPOutput::BulkString))
+}
+
+// RESTORE key ttl payload [REPLACE] [ABSTTL]
+// 1. `ttl` is in milliseconds, 0 for no expiry, with ABSTTL it is a unix time in milliseconds
+fn restore(store: &CalodStore, args: &[String]) -> Result<RESPOutput, String> {
+	if args.len() < 3 {
+		return Err(wrong_args("restore"));
+	}
+	let (mut replace, mut absolute) = (false, false);
+	for option in &args[3..] {
+		match option.to_lowercase().as_str() {
+			"replace" => replace = true,
+			"absttl" => absolute = true,
+			_ => return Err(String::from("ERR syntax error")),
+		}
+	}
+	let ttl = match args[1].parse::<i64>() {
+		Ok(ttl) if ttl >= 0 => ttl,
+		_ => return Err(String::from("ERR Invalid TTL value, must be >= 0")),
+	};
+	let value = restore_payload(&args[2]).map_err(|e| format!("ERR {}", e))?;
+
+	store.restore_value(&args[0], value, restore_deadline(ttl, absolute), replace).map_err(|e| e.to_string())?;
+	Ok(RESPOutput::SimpleString(String::from("OK")))
+}
+
+// MIGRATE host port key|"" destination-db timeout [COPY] [REPLACE] [KEYS key [key ...]]
+// 1. Keys are restored into `destination-db` of the target
+// 2. Moved keys are logged as a DEL so a replay does not bring them back
+// 3. Replies NOKEY when none of the keys exist
+fn migrate(store: &CalodStore, args: &[String]) -> Result<RESPOutput, String> {
+	if args.len() < 5 {
+		return Err(wrong_args("migrate"));
+	}
+	let port = args[1].parse::<u16>().map_err(|_| String::from("ERR value is not an integer or out of range"))?;
+	let db = args[3].parse::<usize>().map_err(|_| String::from("ERR value is not an integer or out of range"))?;
+	let timeout = args[4].parse::<u64>().map_err(|_| String::from("ERR timeout is not an integer or out of range"))?;
+
+	let mut options = MigrateOptions {
+		timeout: (timeout > 0).then(|| Duration::from_millis(timeout)),
+		db,
+		..MigrateOptions::default()
+	};
+	let mut keys = Vec::new();
+	let mut rest = args[5..].iter();
+	while let Some(option) = rest.next() {
+		match option.to_lowercase().as_str() {
+			"copy" => options.copy = true,
+			"replace" => options.replace = true,
+			"keys" if args[2].is_empty() => keys.extend(rest.by_ref().cloned()),
+			"keys" => return Err(String::from("ERR When using MIGRATE KEYS option, the key argument must be set to the empty string")),
+			_ => return Err(String::from("ERR syntax error")),
+		}
+	}
+	if !args[2].is_empty() {
+		keys.push(args[2].clone());
+	}
+	if keys.is_empty() {
+		return Err(String::from("ERR syntax error"));
+	}
+
+	let (outcome, result) = store.migrate((args[0].as_str(), port), &keys, &options);
+	result.map_err(|e| match e {
+		DumpError::Io(_) => e.to_string(),
+		_ => format!("ERR {}", e),
+	})?;
+
+	if outcome.moved.is_empty() {
+		return Ok(RESPOutput::SimpleString(String::from("NOKEY")));
+	}
+	Ok(RESPOutput::SimpleString(String::from("OK")))
+}
@@ -1,4 +1,16 @@
 pub mod command;
 pub mod client_input;
 pub mod parsed_command;
-pub mod response_helper;
\ No newline at end of file
+pub mod response_helper;
+pub mod bitmap_handler;
+pub mod hyperloglog_handler;
+pub mod filter_handler;
+pub mod geo_handler;
+pub mod json_handler;
+pub mod persistence_handler;
+pub mod migration_handler;
+pub mod replication_handler;
+pub mod cluster_handler;
+pub mod database_handler;
+pub mod scan_handler;
+pub mod keyspace_handler;
\ No newline at end of file
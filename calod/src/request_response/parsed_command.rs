@@ -6,11 +6,19 @@ use crate::request_response::{command::Command};
 pub struct ParsedCommand {
 	pub command: Option<Command>,
 	pub args: Vec<String>,
+	// The arguments byte for byte, `args` holds them decoded as UTF-8 for option and key parsing
+	pub raw_args: Vec<Vec<u8>>,
+}
+
+impl Default for ParsedCommand {
+	fn default() -> Self {
+		Self::new()
+	}
 }
 
 impl ParsedCommand {
 	pub fn new() -> ParsedCommand {
-		ParsedCommand { command: None, args: Vec::new(), }
+		ParsedCommand { command: None, args: Vec::new(), raw_args: Vec::new() }
 	}
 
 	pub fn command(&self) -> &Option<Command> {
@@ -25,11 +33,17 @@ impl ParsedCommand {
 		self.command = command;
 	}
 
+	pub fn raw_args(&self) -> &Vec<Vec<u8>> {
+		&self.raw_args
+	}
+
 	pub fn set_args(&mut self, args: Vec<String>) {
+		self.raw_args = args.iter().map(|arg| arg.as_bytes().to_vec()).collect();
 		self.args = args;
 	}
 
-	pub fn append_args(&mut self, arg: String) {
-		self.args.push(arg);
+	pub fn append_arg(&mut self, arg: Vec<u8>) {
+		self.args.push(String::from_utf8_lossy(&arg).into_owned());
+		self.raw_args.push(arg);
 	}
 }
\ No newline at end of file
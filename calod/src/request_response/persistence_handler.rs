@@ -0,0 +1,65 @@
+use std::io::Write;
+use std::sync::Arc;
+
+use crate::parser::parser::RESPOutput;
+use crate::request_response::{command::Command, response_helper};
+use crate::store::calod_store::CalodStore;
+
+// Handle SAVE, BGSAVE, LASTSAVE and BGREWRITEAOF
+pub fn respond<T: Write>(store: &Arc<CalodStore>, stream: &mut T, command: &Command, args: &[String]) {
+	let result = match command {
+		Command::SAVE => save(store, args),
+		Command::BGSAVE => bgsave(store, args),
+		Command::LASTSAVE => lastsave(store, args),
+		Command::BGREWRITEAOF => bgrewriteaof(store, args),
+		_ => Err(String::from("ERR unknown persistence command")),
+	};
+
+	match result {
+		Ok(output) => response_helper::send_resp_response(stream, &output),
+		Err(e) => response_helper::send_error_response(stream, &e),
+	}
+}
+
+fn wrong_args(name: &str) -> String {
+	format!("ERR wrong number of arguments for '{}' command", name)
+}
+
+// SAVE
+fn save(store: &CalodStore, args: &[String]) -> Result<RESPOutput, String> {
+	if !args.is_empty() {
+		return Err(wrong_args("save"));
+	}
+
+	store.save().map_err(|e| format!("ERR {}", e))?;
+	Ok(RESPOutput::SimpleString(String::from("OK")))
+}
+
+// BGSAVE
+fn bgsave(store: &Arc<CalodStore>, args: &[String]) -> Result<RESPOutput, String> {
+	if !args.is_empty() {
+		return Err(wrong_args("bgsave"));
+	}
+
+	store.bgsave().map_err(|e| format!("ERR {}", e))?;
+	Ok(RESPOutput::SimpleString(String::from("Background saving started")))
+}
+
+// LASTSAVE
+fn lastsave(store: &CalodStore, args: &[String]) -> Result<RESPOutput, String> {
+	if !args.is_empty() {
+		return Err(wrong_args("lastsave"));
+	}
+
+	Ok(RESPOutput::Integer(store.lastsave()))
+}
+
+// BGREWRITEAOF
+fn bgrewriteaof(store: &Arc<CalodStore>, args: &[String]) -> Result<RESPOutput, String> {
+	if !args.is_empty() {
+		return Err(wrong_args("bgrewriteaof"));
+	}
+
+	store.bgrewriteaof().map_err(|e| format!("ERR {}", e))?;
+	Ok(RESPOutput::SimpleString(String::from("Background append only file rewriting started")))
+}
@@ -0,0 +1,95 @@
+use std::io::Write;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::parser::parser::RESPOutput;
+use crate::replication::state::Role;
+use crate::request_response::{command::Command, response_helper};
+use crate::store::calod_store::CalodStore;
+
+// Handle REPLICAOF, ROLE and WAIT, PSYNC is taken over by the connection loop
+pub fn respond<T: Write>(store: &Arc<CalodStore>, stream: &mut T, command: &Command, args: &[String]) {
+	let result = match command {
+		Command::REPLICAOF => replicaof(store, args),
+		Command::ROLE => role(store, args),
+		Command::WAIT => wait(store, args),
+		Command::PSYNC => Err(String::from("ERR PSYNC is only valid as the first command of a replication connection")),
+		_ => Err(String::from("ERR unknown replication command")),
+	};
+
+	match result {
+		Ok(output) => response_helper::send_resp_response(stream, &output),
+		Err(e) => response_helper::send_error_response(stream, &e),
+	}
+}
+
+fn wrong_args(name: &str) -> String {
+	format!("ERR wrong number of arguments for '{}' command", name)
+}
+
+// REPLICAOF host port | NO ONE
+// 1. Following the leader we already follow keeps the current link
+fn replicaof(store: &Arc<CalodStore>, args: &[String]) -> Result<RESPOutput, String> {
+	if args.len() != 2 {
+		return Err(wrong_args("replicaof"));
+	}
+
+	if args[0].eq_ignore_ascii_case("no") && args[1].eq_ignore_ascii_case("one") {
+		store.stop_replication();
+		return Ok(RESPOutput::SimpleString(String::from("OK")));
+	}
+
+	let port = args[1].parse::<u16>().map_err(|_| String::from("ERR Invalid master port"))?;
+	if store.replication().role() == (Role::Follower { host: args[0].clone(), port }) {
+		return Ok(RESPOutput::SimpleString(String::from("OK Already connected to specified master")));
+	}
+	store.replicate_from(&args[0], port);
+	Ok(RESPOutput::SimpleString(String::from("OK")))
+}
+
+// ROLE
+// 1. Leaders reply `master`, their offset and the address and acknowledged offset of every follower
+// 2. Followers reply `slave`, the leader, the link state and their offset
+fn role(store: &CalodStore, args: &[String]) -> Result<RESPOutput, String> {
+	if !args.is_empty() {
+		return Err(wrong_args("role"));
+	}
+
+	let replication = store.replication();
+	let (_, offset) = replication.position();
+	let reply = match replication.role() {
+		Role::Leader => vec![
+			RESPOutput::BulkString(String::from("master")),
+			RESPOutput::Integer(offset as i64),
+			RESPOutput::Array(
+				replication
+					.replicas()
+					.into_iter()
+					.map(|(address, acked)| RESPOutput::Array(vec![RESPOutput::BulkString(address), RESPOutput::Integer(acked as i64)]))
+					.collect(),
+			),
+		],
+		Role::Follower { host, port } => vec![
+			RESPOutput::BulkString(String::from("slave")),
+			RESPOutput::BulkString(host),
+			RESPOutput::Integer(port as i64),
+			RESPOutput::BulkString(String::from(if replication.link_up() { "connected" } else { "connect" })),
+			RESPOutput::Integer(offset as i64),
+		],
+	};
+	Ok(RESPOutput::Array(reply))
+}
+
+// WAIT numreplicas timeout
+// 1. Blocks until `numreplicas` followers acknowledged every write so far, a timeout of 0 blocks forever
+// 2. Replies the number of followers that caught up
+fn wait(store: &CalodStore, args: &[String]) -> Result<RESPOutput, String> {
+	if args.len() != 2 {
+		return Err(wrong_args("wait"));
+	}
+
+	let count = args[0].parse::<usize>().map_err(|_| String::from("ERR value is not an integer or out of range"))?;
+	let timeout = args[1].parse::<u64>().map_err(|_| String::from("ERR timeout is not an integer or out of range"))?;
+	let timeout = if timeout == 0 { None } else { Some(Duration::from_millis(timeout)) };
+	Ok(RESPOutput::Integer(store.replication().wait(count, timeout) as i64))
+}
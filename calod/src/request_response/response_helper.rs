@@ -1,48 +1,21 @@
-
-
 use std::io::Write;
-use crate store::calod_store::{CalodStore};
+use crate::parser::parser::RESPOutput;
 
 
 pub fn send_bulk_string_response<T: Write>(stream: &mut T, data: Option<&str>) {
-	let response: String;
-	if data.is_some() {
-		let str = data.unwrap();
-		response = format!("${}\r\n{}\r\n", str.len(), str);
-	} else {
-		response = String::from("$-1\r\n");
-	}
-
-	match stream.write(response.as_bytes()) {
-		Ok(t) => {
-			println!("Wrote {} bytes to output", t);
-		},
-		Err(e) => {
-			println!("unable to write to response: {}", e);
-		}
-	}
+	send_bulk_bytes_response(stream, data.map(str::as_bytes));
 }
 
 pub fn send_pong_response<T: Write>(stream: &mut T) {
-	let store = &mut CalodStore::get_store();
-	let response = store.get_stats();
-
-	let full_response = format!("PONG\nStats: {}", response);
-	send_simple_string_response(stream, full_response);
+	send_simple_string_response(stream, "PONG");
 }
 
 pub fn send_simple_string_response<T: Write>(stream: &mut T, str: &str) {
-	match stream.write(format_simple_string_response(str).as_bytes()) {
-		Ok(t) => { println!("Wrote {} bytes to output", t); },
-		Err(e) => { println!("unable to write to response: {}", e); },
-	}
+	write_response(stream, format_simple_string_response(str).as_bytes());
 }
 
 pub fn send_error_response<T: Write>(stream: &mut T, str: &str) {
-	match stream.write(format_error_response(str).as_bytes()) {
-		Ok(t) => { println!("Wrote {} bytes to output", t); },
-		Err(e) => { println!("unable to write to response: {}", e); }
-	}
+	write_response(stream, format_error_response(str).as_bytes());
 }
 
 pub fn format_simple_string_response(res: &str) -> String {
@@ -50,7 +23,89 @@ pub fn format_simple_string_response(res: &str) -> String {
 }
 
 pub fn format_error_response(res: &str) -> String {
-	format!("+{}\r\n", res)
+	format!("-{}\r\n", res)
+}
+
+pub fn send_integer_response<T: Write>(stream: &mut T, value: i64) {
+	write_response(stream, format!(":{}\r\n", value).as_bytes());
+}
+
+// Binary safe variant of `send_bulk_string_response`
+pub fn send_bulk_bytes_response<T: Write>(stream: &mut T, data: Option<&[u8]>) {
+	match data {
+		Some(bytes) => {
+			let mut response = Vec::with_capacity(bytes.len() + 16);
+			encode_bulk(bytes, &mut response);
+			write_response(stream, &response);
+		}
+		None => write_response(stream, b"$-1\r\n"),
+	}
+}
+
+// A reply encoded ahead, such as one held back until its command is logged
+pub fn send_encoded_response<T: Write>(stream: &mut T, bytes: &[u8]) {
+	write_response(stream, bytes);
 }
 
-pub fn format_error_response(res: &str) -> String { format!("-{}\r\n", res) }
\ No newline at end of file
+pub fn send_resp_response<T: Write>(stream: &mut T, output: &RESPOutput) {
+	write_response(stream, &encode_resp_output(output));
+}
+
+// Serialize a `RESPOutput` tree as text, binary bulk strings are decoded lossily
+pub fn format_resp_output(output: &RESPOutput) -> String {
+	String::from_utf8_lossy(&encode_resp_output(output)).into_owned()
+}
+
+// Serialize a `RESPOutput` tree byte for byte, used for replies and log records
+pub fn encode_resp_output(output: &RESPOutput) -> Vec<u8> {
+	let mut out = Vec::new();
+	encode_into(output, &mut out);
+	out
+}
+
+fn encode_into(output: &RESPOutput, out: &mut Vec<u8>) {
+	match output {
+		RESPOutput::SimpleString(str) => out.extend_from_slice(format_simple_string_response(str).as_bytes()),
+		RESPOutput::Error(str) => out.extend_from_slice(format_error_response(str).as_bytes()),
+		RESPOutput::BulkString(str) => encode_bulk(str.as_bytes(), out),
+		RESPOutput::BulkBytes(bytes) => encode_bulk(bytes, out),
+		RESPOutput::Integer(num) => out.extend_from_slice(format!(":{}\r\n", num).as_bytes()),
+		RESPOutput::Null => out.extend_from_slice(b"$-1\r\n"),
+		RESPOutput::Array(items) => encode_aggregate('*', items, out),
+		RESPOutput::Double(num) if num.is_nan() => out.extend_from_slice(b",nan\r\n"),
+		RESPOutput::Double(num) if num.is_infinite() => out.extend_from_slice(format!(",{}inf\r\n", if *num < 0.0 { "-" } else { "" }).as_bytes()),
+		RESPOutput::Double(num) => out.extend_from_slice(format!(",{}\r\n", num).as_bytes()),
+		RESPOutput::Boolean(value) => out.extend_from_slice(format!("#{}\r\n", if *value { 't' } else { 'f' }).as_bytes()),
+		RESPOutput::BigNumber(num) => out.extend_from_slice(format!("({}\r\n", num).as_bytes()),
+		RESPOutput::Verbatim(format, text) => out.extend_from_slice(format!("={}\r\n{}:{}\r\n", text.len() + 4, format, text).as_bytes()),
+		RESPOutput::Map(entries) => {
+			out.extend_from_slice(format!("%{}\r\n", entries.len()).as_bytes());
+			for (key, value) in entries {
+				encode_into(key, out);
+				encode_into(value, out);
+			}
+		}
+		RESPOutput::Set(items) => encode_aggregate('~', items, out),
+		RESPOutput::Push(items) => encode_aggregate('>', items, out),
+	}
+}
+
+fn encode_bulk(bytes: &[u8], out: &mut Vec<u8>) {
+	out.extend_from_slice(format!("${}\r\n", bytes.len()).as_bytes());
+	out.extend_from_slice(bytes);
+	out.extend_from_slice(b"\r\n");
+}
+
+fn encode_aggregate(kind: char, items: &[RESPOutput], out: &mut Vec<u8>) {
+	out.extend_from_slice(format!("{}{}\r\n", kind, items.len()).as_bytes());
+	for item in items {
+		encode_into(item, out);
+	}
+}
+
+// A reply that cannot be written means the client is gone, the connection loop notices on its next read
+fn write_response<T: Write>(stream: &mut T, bytes: &[u8]) {
+	if let Err(e) = stream.write_all(bytes) {
+		eprintln!("unable to write to response: {}", e);
+	}
+}
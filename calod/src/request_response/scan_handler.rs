@@ -0,0 +1,134 @@
+use std::io::Write;
+
+use crate::parser::parser::RESPOutput;
+use crate::request_response::{command::Command, response_helper};
+use crate::store::calod_store::CalodStore;
+use crate::store::scan::DEFAULT_SCAN_COUNT;
+
+const SYNTAX_ERROR: &str = "ERR syntax error";
+
+// Handle SCAN, HSCAN, SSCAN, ZSCAN and KEYS
+pub fn respond<T: Write>(store: &CalodStore, stream: &mut T, command: &Command, args: &[String]) {
+	let result = match command {
+		Command::SCAN => scan(store, args),
+		Command::HSCAN => hscan(store, args),
+		Command::SSCAN => sscan(store, args),
+		Command::ZSCAN => zscan(store, args),
+		Command::KEYS => keys(store, args),
+		_ => Err(String::from("ERR unknown scan command")),
+	};
+
+	match result {
+		Ok(output) => response_helper::send_resp_response(stream, &output),
+		Err(e) => response_helper::send_error_response(stream, &e),
+	}
+}
+
+fn wrong_args(name: &str) -> String {
+	format!("ERR wrong number of arguments for '{}' command", name)
+}
+
+// The options shared by the SCAN family, TYPE only applies to SCAN and NOVALUES only to HSCAN
+#[derive(Default)]
+struct ScanArgs {
+	cursor: u64,
+	count: usize,
+	pattern: Option<String>,
+	kind: Option<String>,
+	no_values: bool,
+}
+
+// cursor [MATCH pattern] [COUNT count] [TYPE type] [NOVALUES]
+fn parse_scan_args(args: &[String], with_type: bool, with_no_values: bool) -> Result<ScanArgs, String> {
+	let cursor = args[0].parse::<u64>().map_err(|_| String::from("ERR invalid cursor"))?;
+	let mut scan = ScanArgs { cursor, count: DEFAULT_SCAN_COUNT, ..Default::default() };
+
+	let mut i = 1;
+	while i < args.len() {
+		let has_value = i + 1 < args.len();
+		match args[i].to_lowercase().as_str() {
+			"match" if has_value => {
+				scan.pattern = Some(args[i + 1].clone());
+				i += 1;
+			}
+			"count" if has_value => {
+				scan.count = match args[i + 1].parse::<usize>() {
+					Ok(n) if n > 0 => n,
+					Ok(_) => return Err(String::from(SYNTAX_ERROR)),
+					Err(_) => return Err(String::from("ERR value is not an integer or out of range")),
+				};
+				i += 1;
+			}
+			"type" if has_value && with_type => {
+				scan.kind = Some(args[i + 1].clone());
+				i += 1;
+			}
+			"novalues" if with_no_values => scan.no_values = true,
+			_ => return Err(String::from(SYNTAX_ERROR)),
+		}
+		i += 1;
+	}
+	Ok(scan)
+}
+
+// The reply of every SCAN variant, the next cursor and the items of this page
+fn scan_reply(next: u64, items: Vec<String>) -> RESPOutput {
+	RESPOutput::Array(vec![
+		RESPOutput::BulkString(next.to_string()),
+		RESPOutput::Array(items.into_iter().map(RESPOutput::BulkString).collect()),
+	])
+}
+
+// SCAN cursor [MATCH pattern] [COUNT count] [TYPE type]
+fn scan(store: &CalodStore, args: &[String]) -> Result<RESPOutput, String> {
+	if args.is_empty() {
+		return Err(wrong_args("scan"));
+	}
+	let scan = parse_scan_args(args, true, false)?;
+
+	let (next, keys) = store.scan_keys(scan.cursor, scan.count, scan.pattern.as_deref(), scan.kind.as_deref());
+	Ok(scan_reply(next, keys))
+}
+
+// HSCAN key cursor [MATCH pattern] [COUNT count] [NOVALUES]
+fn hscan(store: &CalodStore, args: &[String]) -> Result<RESPOutput, String> {
+	if args.len() < 2 {
+		return Err(wrong_args("hscan"));
+	}
+	let scan = parse_scan_args(&args[1..], false, true)?;
+
+	let (next, entries) = store.scan_hash(&args[0], scan.cursor, scan.count, scan.pattern.as_deref()).map_err(|e| e.to_string())?;
+	let items = entries.into_iter().flat_map(|(field, value)| if scan.no_values { vec![field] } else { vec![field, value] }).collect();
+	Ok(scan_reply(next, items))
+}
+
+// SSCAN key cursor [MATCH pattern] [COUNT count]
+fn sscan(store: &CalodStore, args: &[String]) -> Result<RESPOutput, String> {
+	if args.len() < 2 {
+		return Err(wrong_args("sscan"));
+	}
+	let scan = parse_scan_args(&args[1..], false, false)?;
+
+	let (next, members) = store.scan_set(&args[0], scan.cursor, scan.count, scan.pattern.as_deref()).map_err(|e| e.to_string())?;
+	Ok(scan_reply(next, members))
+}
+
+// ZSCAN key cursor [MATCH pattern] [COUNT count]
+fn zscan(store: &CalodStore, args: &[String]) -> Result<RESPOutput, String> {
+	if args.len() < 2 {
+		return Err(wrong_args("zscan"));
+	}
+	let scan = parse_scan_args(&args[1..], false, false)?;
+
+	let (next, members) = store.scan_sorted_set(&args[0], scan.cursor, scan.count, scan.pattern.as_deref()).map_err(|e| e.to_string())?;
+	let items = members.into_iter().flat_map(|(member, score)| [member, score.to_string()]).collect();
+	Ok(scan_reply(next, items))
+}
+
+// KEYS pattern, the whole keyspace in one reply
+fn keys(store: &CalodStore, args: &[String]) -> Result<RESPOutput, String> {
+	if args.len() != 1 {
+		return Err(wrong_args("keys"));
+	}
+	Ok(RESPOutput::Array(store.keys_matching(&args[0]).into_iter().map(RESPOutput::BulkString).collect()))
+}
@@ -0,0 +1,67 @@
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+use crate::parser::parser::RESPOutput;
+use crate::persistence::aof::command_record;
+
+// Split `host:port`, IPv6 hosts come in brackets
+pub fn split_address(address: &str) -> Option<(String, u16)> {
+	let (host, port) = address.rsplit_once(':')?;
+	let host = host.strip_prefix('[').and_then(|host| host.strip_suffix(']')).unwrap_or(host);
+	Some((host.to_string(), port.parse().ok()?))
+}
+
+pub fn join_address(host: &str, port: u16) -> String {
+	if host.contains(':') {
+		format!("[{}]:{}", host, port)
+	} else {
+		format!("{}:{}", host, port)
+	}
+}
+
+// Send one command to a server or sentinel and wait for the reply, every step is bounded by `timeout`
+pub fn query(address: &str, args: &[&str], timeout: Duration) -> io::Result<RESPOutput> {
+	let target = address.to_socket_addrs()?.next().ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no address to connect to"))?;
+	let mut stream = TcpStream::connect_timeout(&target, timeout)?;
+	stream.set_read_timeout(Some(timeout))?;
+	stream.set_write_timeout(Some(timeout))?;
+	stream.write_all(&command_record(args.iter().map(|arg| arg.to_string()).collect()))?;
+	read_reply(&mut BufReader::new(stream))
+}
+
+// One RESP reply, an error reply becomes an `io::Error`
+pub fn read_reply<R: BufRead>(reader: &mut R) -> io::Result<RESPOutput> {
+	let mut line = String::new();
+	if reader.read_line(&mut line)? == 0 {
+		return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "connection closed"));
+	}
+	let line = line.trim_end_matches(['\r', '\n']);
+	let invalid = || io::Error::new(io::ErrorKind::InvalidData, format!("unexpected reply `{}`", line));
+	if line.is_empty() || !line.is_char_boundary(1) {
+		return Err(invalid());
+	}
+
+	let (kind, rest) = line.split_at(1);
+	match kind {
+		"+" => Ok(RESPOutput::SimpleString(rest.to_string())),
+		"-" => Err(io::Error::other(rest.to_string())),
+		":" => rest.parse().map(RESPOutput::Integer).map_err(|_| invalid()),
+		"$" => match rest.parse::<i64>().map_err(|_| invalid())? {
+			-1 => Ok(RESPOutput::Null),
+			len if len >= 0 => {
+				let mut bytes = vec![0; len as usize + 2];
+				reader.read_exact(&mut bytes)?;
+				bytes.truncate(len as usize);
+				Ok(RESPOutput::BulkString(String::from_utf8_lossy(&bytes).into_owned()))
+			}
+			_ => Err(invalid()),
+		},
+		"*" => match rest.parse::<i64>().map_err(|_| invalid())? {
+			-1 => Ok(RESPOutput::Null),
+			len if len >= 0 => (0..len).map(|_| read_reply(reader)).collect::<io::Result<Vec<RESPOutput>>>().map(RESPOutput::Array),
+			_ => Err(invalid()),
+		},
+		_ => Err(invalid()),
+	}
+}
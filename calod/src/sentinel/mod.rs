@@ -0,0 +1,4 @@
+pub mod link;
+pub mod state;
+pub mod monitor;
+pub mod server;
\ No newline at end of file
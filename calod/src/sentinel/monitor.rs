@@ -0,0 +1,231 @@
+use std::net::TcpListener;
+use std::thread;
+use std::time::Instant;
+
+use crate::parser::parser::RESPOutput;
+use crate::sentinel::link::{join_address, query, split_address};
+use crate::sentinel::state::{jitter, ReplicaInfo, Sentinel};
+
+// What a server answers to ROLE: the leader it follows (`None` for a leader) and its offset
+fn parse_role(reply: &RESPOutput) -> Option<(Option<String>, u64)> {
+	let RESPOutput::Array(fields) = reply else {
+		return None;
+	};
+	match fields.as_slice() {
+		[RESPOutput::BulkString(role), RESPOutput::Integer(offset), ..] if role == "master" => Some((None, *offset as u64)),
+		[RESPOutput::BulkString(role), RESPOutput::BulkString(host), RESPOutput::Integer(port), _, RESPOutput::Integer(offset)] if role == "slave" => {
+			Some((Some(join_address(host, *port as u16)), *offset as u64))
+		}
+		_ => None,
+	}
+}
+
+// Addresses of the followers in the ROLE reply of a leader
+fn role_replicas(reply: &RESPOutput) -> Vec<String> {
+	let RESPOutput::Array(fields) = reply else {
+		return Vec::new();
+	};
+	match fields.get(2) {
+		Some(RESPOutput::Array(replicas)) => replicas
+			.iter()
+			.filter_map(|replica| match replica {
+				RESPOutput::Array(replica) => match replica.first() {
+					Some(RESPOutput::BulkString(address)) => Some(address.clone()),
+					_ => None,
+				},
+				_ => None,
+			})
+			.collect(),
+		_ => Vec::new(),
+	}
+}
+
+impl Sentinel {
+	// Serve sentinel commands on `listener` and watch the leader until the process exits
+	pub fn start(&'static self, listener: TcpListener) {
+		thread::spawn(move || self.serve(listener));
+		thread::spawn(move || loop {
+			self.tick();
+			thread::sleep(self.config.ping_interval);
+		});
+	}
+
+	// One round of monitoring
+	// 1. Ask the leader and every follower for their role, followers of the wrong leader are pointed at the right one
+	// 2. Share the current leader and its epoch with the other sentinels
+	// 3. Once a quorum agrees the leader is down, try to win an election and fail over
+	pub fn tick(&self) {
+		self.check_leader();
+		self.check_replicas();
+		self.send_hellos();
+		if self.subjectively_down() && self.objectively_down() && self.failover_due() {
+			self.try_failover();
+		}
+	}
+
+	fn check_leader(&self) {
+		let leader = self.leader();
+		let reply = match query(&leader, &["ROLE"], self.config.ping_interval) {
+			Ok(reply) => reply,
+			Err(_) => return,
+		};
+		if !matches!(parse_role(&reply), Some((None, _))) {
+			return;
+		}
+
+		let mut state = self.state.lock().unwrap();
+		if state.leader != leader {
+			return;
+		}
+		state.leader_last_ok = Instant::now();
+		state.next_failover = None;
+		for address in role_replicas(&reply) {
+			state.replicas.entry(address).or_default();
+		}
+	}
+
+	fn check_replicas(&self) {
+		let (leader, addresses) = {
+			let state = self.state.lock().unwrap();
+			(state.leader.clone(), state.replicas.keys().cloned().collect::<Vec<String>>())
+		};
+		let leader_up = !self.subjectively_down();
+
+		for address in addresses {
+			let role = query(&address, &["ROLE"], self.config.ping_interval).ok().as_ref().and_then(parse_role);
+			let Some((following, offset)) = role else {
+				continue;
+			};
+			let misconfigured = following.as_deref() != Some(leader.as_str());
+			self.state.lock().unwrap().replicas.insert(address.clone(), ReplicaInfo { last_ok: Some(Instant::now()), offset });
+
+			// A returning old leader, or a follower a failover did not reach, follows the current leader from now on
+			if leader_up && misconfigured {
+				println!("Pointing {} at leader {}", address, leader);
+				self.reconfigure(&address, &leader);
+			}
+		}
+	}
+
+	// REPLICAOF host port
+	fn reconfigure(&self, address: &str, leader: &str) -> bool {
+		match split_address(leader) {
+			Some((host, port)) => query(address, &["REPLICAOF", &host, &port.to_string()], self.config.ping_interval).is_ok(),
+			None => false,
+		}
+	}
+
+	pub(crate) fn send_hellos(&self) {
+		let (epoch, leader) = {
+			let state = self.state.lock().unwrap();
+			(state.config_epoch.to_string(), state.leader.clone())
+		};
+		for peer in &self.config.peers {
+			let _ = query(peer, &["SENTINEL", "HELLO", &self.config.name, &epoch, &leader], self.config.ping_interval);
+		}
+	}
+
+	// Enough sentinels, us included, see the leader as down
+	fn objectively_down(&self) -> bool {
+		let (leader, epoch) = {
+			let state = self.state.lock().unwrap();
+			(state.leader.clone(), state.current_epoch.to_string())
+		};
+		let Some((host, port)) = split_address(&leader) else {
+			return false;
+		};
+		let port = port.to_string();
+
+		let agreeing = self
+			.config
+			.peers
+			.iter()
+			.filter(|peer| {
+				let reply = query(peer, &["SENTINEL", "IS-MASTER-DOWN-BY-ADDR", &host, &port, &epoch, "*"], self.config.ping_interval);
+				matches!(reply, Ok(RESPOutput::Array(reply)) if reply.first() == Some(&RESPOutput::Integer(1)))
+			})
+			.count();
+		agreeing + 1 >= self.config.quorum
+	}
+
+	// The first attempt waits a random part of a ping interval, later ones the failover timeout
+	fn failover_due(&self) -> bool {
+		let mut state = self.state.lock().unwrap();
+		match state.next_failover {
+			Some(at) => Instant::now() >= at,
+			None => {
+				state.next_failover = Some(Instant::now() + jitter(self.config.ping_interval));
+				false
+			}
+		}
+	}
+
+	// Ask for the votes of the other sentinels in a new epoch, a majority of all sentinels and at least the quorum has to agree
+	fn try_failover(&self) {
+		let (leader, epoch) = {
+			let mut state = self.state.lock().unwrap();
+			state.next_failover = Some(Instant::now() + self.failover_backoff());
+			(state.leader.clone(), state.current_epoch + 1)
+		};
+		if self.vote(epoch, &self.runid) != Some((epoch, self.runid.clone())) {
+			return;
+		}
+		let Some((host, port)) = split_address(&leader) else {
+			return;
+		};
+		let (port, epoch_arg) = (port.to_string(), epoch.to_string());
+
+		let votes = 1 + self
+			.config
+			.peers
+			.iter()
+			.filter(|peer| {
+				let reply = query(peer, &["SENTINEL", "IS-MASTER-DOWN-BY-ADDR", &host, &port, &epoch_arg, &self.runid], self.config.ping_interval);
+				match reply {
+					Ok(RESPOutput::Array(reply)) => matches!(reply.as_slice(), [_, RESPOutput::BulkString(runid), RESPOutput::Integer(voted)] if *runid == self.runid && *voted as u64 == epoch),
+					_ => false,
+				}
+			})
+			.count();
+		let sentinels = self.config.peers.len() + 1;
+		let needed = self.config.quorum.max(sentinels / 2 + 1);
+		if votes < needed {
+			println!("Lost the election for {} in epoch {} with {} of {} votes", self.config.name, epoch, votes, needed);
+			return;
+		}
+		self.failover(&leader, epoch);
+	}
+
+	// Promote the reachable follower with the highest offset and point the others at it
+	fn failover(&self, leader: &str, epoch: u64) {
+		let candidate = {
+			let state = self.state.lock().unwrap();
+			if state.leader != leader || state.config_epoch >= epoch {
+				return;
+			}
+			state
+				.replicas
+				.iter()
+				.filter(|(_, info)| info.last_ok.is_some_and(|at| at.elapsed() <= self.config.down_after))
+				.max_by(|(a, a_info), (b, b_info)| a_info.offset.cmp(&b_info.offset).then_with(|| b.cmp(a)))
+				.map(|(address, _)| address.clone())
+		};
+		let Some(candidate) = candidate else {
+			println!("No follower of {} can be promoted", self.config.name);
+			return;
+		};
+
+		if let Err(e) = query(&candidate, &["REPLICAOF", "NO", "ONE"], self.config.ping_interval) {
+			println!("Failed to promote {}: {}", candidate, e);
+			return;
+		}
+		println!("Promoted {} to leader of {} in epoch {}", candidate, self.config.name, epoch);
+		self.adopt(epoch, &candidate);
+
+		// Followers we cannot reach now are reconfigured once they answer again
+		for replica in self.replicas() {
+			self.reconfigure(&replica, &candidate);
+		}
+		self.send_hellos();
+	}
+}
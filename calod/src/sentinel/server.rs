@@ -0,0 +1,93 @@
+use std::io::BufReader;
+use std::net::{TcpListener, TcpStream};
+use std::thread;
+
+use crate::parser::parser::RESPOutput;
+use crate::persistence::aof::read_record;
+use crate::request_response::response_helper;
+use crate::sentinel::link::split_address;
+use crate::sentinel::state::Sentinel;
+
+fn wrong_args(name: &str) -> String {
+	format!("ERR wrong number of arguments for '{}' command", name)
+}
+
+fn parse_epoch(epoch: &str) -> Result<u64, String> {
+	epoch.parse::<u64>().map_err(|_| String::from("ERR invalid epoch"))
+}
+
+impl Sentinel {
+	pub(crate) fn serve(&'static self, listener: TcpListener) {
+		for stream in listener.incoming().flatten() {
+			thread::spawn(move || self.serve_client(stream));
+		}
+	}
+
+	fn serve_client(&self, mut stream: TcpStream) {
+		let mut reader = match stream.try_clone() {
+			Ok(stream) => BufReader::new(stream),
+			Err(_) => return,
+		};
+		let mut consumed = 0;
+		while let Ok(Some(args)) = read_record(&mut reader, &mut consumed) {
+			match self.respond(&args) {
+				Ok(output) => response_helper::send_resp_response(&mut stream, &output),
+				Err(e) => response_helper::send_error_response(&mut stream, &e),
+			}
+		}
+	}
+
+	// PING and the SENTINEL subcommands clients and other sentinels use
+	// 1. GET-MASTER-ADDR-BY-NAME and REPLICAS publish the current configuration to clients
+	// 2. IS-MASTER-DOWN-BY-ADDR reports whether we see the leader as down, with a run ID it also asks for our vote
+	// 3. HELLO carries the configuration of another sentinel, a newer epoch replaces ours
+	pub fn respond(&self, args: &[String]) -> Result<RESPOutput, String> {
+		let Some(command) = args.first() else {
+			return Err(String::from("ERR empty command"));
+		};
+		if command.eq_ignore_ascii_case("ping") {
+			return Ok(RESPOutput::SimpleString(String::from("PONG")));
+		}
+		if !command.eq_ignore_ascii_case("sentinel") {
+			return Err(format!("ERR unknown command '{}'", command));
+		}
+
+		let subcommand = args.get(1).map(|subcommand| subcommand.to_lowercase()).unwrap_or_default();
+		match (subcommand.as_str(), &args[2.min(args.len())..]) {
+			("get-master-addr-by-name", [name]) => Ok(self.leader_address(name)),
+			("replicas", [name]) if *name == self.config.name => Ok(RESPOutput::Array(self.replicas().into_iter().map(RESPOutput::BulkString).collect())),
+			("replicas", [_]) => Err(String::from("ERR No such master with that name")),
+			("is-master-down-by-addr", [host, port, epoch, runid]) => self.is_leader_down(host, port, parse_epoch(epoch)?, runid),
+			("hello", [name, epoch, leader]) => {
+				if *name == self.config.name && split_address(leader).is_some() {
+					self.adopt(parse_epoch(epoch)?, leader);
+				}
+				Ok(RESPOutput::SimpleString(String::from("OK")))
+			}
+			("get-master-addr-by-name" | "replicas" | "is-master-down-by-addr" | "hello", _) => Err(wrong_args(&format!("sentinel {}", subcommand))),
+			_ => Err(String::from("ERR unknown sentinel subcommand")),
+		}
+	}
+
+	// `[host, port]` of the current leader, null for a name we do not monitor
+	fn leader_address(&self, name: &str) -> RESPOutput {
+		if name != self.config.name {
+			return RESPOutput::Null;
+		}
+		match split_address(&self.leader()) {
+			Some((host, port)) => RESPOutput::Array(vec![RESPOutput::BulkString(host), RESPOutput::BulkString(port.to_string())]),
+			None => RESPOutput::Null,
+		}
+	}
+
+	// `[down, voted run ID, voted epoch]`, a run ID of `*` only asks for our opinion
+	// Votes are only given for the leader we monitor
+	fn is_leader_down(&self, host: &str, port: &str, epoch: u64, runid: &str) -> Result<RESPOutput, String> {
+		let port = port.parse::<u16>().map_err(|_| String::from("ERR invalid port"))?;
+		let ours = split_address(&self.leader()) == Some((host.to_string(), port));
+		let down = ours && self.subjectively_down();
+		let vote = if ours && runid != "*" { self.vote(epoch, runid) } else { None };
+		let (voted_epoch, voted) = vote.unwrap_or((0, String::from("*")));
+		Ok(RESPOutput::Array(vec![RESPOutput::Integer(down as i64), RESPOutput::BulkString(voted), RESPOutput::Integer(voted_epoch as i64)]))
+	}
+}
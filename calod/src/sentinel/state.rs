@@ -0,0 +1,159 @@
+use std::collections::hash_map::RandomState;
+use std::collections::BTreeMap;
+use std::hash::{BuildHasher, Hasher};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::replication::state::new_replid;
+
+// The leader is considered down by one sentinel once it failed to answer for this long
+pub const DEFAULT_DOWN_AFTER: Duration = Duration::from_secs(30);
+
+// A sentinel waits this long, plus a random delay, before trying another failover of the same leader
+pub const DEFAULT_FAILOVER_TIMEOUT: Duration = Duration::from_secs(60);
+
+// The leader, its followers and the other sentinels are contacted this often
+pub const DEFAULT_PING_INTERVAL: Duration = Duration::from_secs(1);
+
+// One monitored leader, identified by `name` among the sentinels watching it
+#[derive(Debug, Clone)]
+pub struct SentinelConfig {
+	pub name: String,
+	// `host:port` of the leader when the sentinel starts
+	pub leader: String,
+	// Sentinels that have to agree the leader is down before a failover
+	pub quorum: usize,
+	// `host:port` of every other sentinel watching the same leader
+	pub peers: Vec<String>,
+	pub down_after: Duration,
+	pub failover_timeout: Duration,
+	pub ping_interval: Duration,
+}
+
+impl SentinelConfig {
+	pub fn new(name: &str, leader: &str, quorum: usize) -> Self {
+		SentinelConfig {
+			name: name.to_string(),
+			leader: leader.to_string(),
+			quorum,
+			peers: Vec::new(),
+			down_after: DEFAULT_DOWN_AFTER,
+			failover_timeout: DEFAULT_FAILOVER_TIMEOUT,
+			ping_interval: DEFAULT_PING_INTERVAL,
+		}
+	}
+}
+
+// What the last ROLE of a follower told us
+#[derive(Debug, Clone, Default)]
+pub(crate) struct ReplicaInfo {
+	pub(crate) last_ok: Option<Instant>,
+	pub(crate) offset: u64,
+}
+
+#[derive(Debug)]
+pub(crate) struct MonitorState {
+	pub(crate) leader: String,
+	// Epoch of the failover that made `leader` the leader, sentinels adopt the configuration with the highest one
+	pub(crate) config_epoch: u64,
+	pub(crate) leader_last_ok: Instant,
+	pub(crate) replicas: BTreeMap<String, ReplicaInfo>,
+	// Every election runs in a new epoch, a sentinel votes once per epoch
+	pub(crate) current_epoch: u64,
+	pub(crate) vote: Option<(u64, String)>,
+	// No failover is attempted before this
+	pub(crate) next_failover: Option<Instant>,
+}
+
+// A sentinel watching one leader
+// 1. A leader that does not answer for `down_after` is subjectively down, with a quorum of sentinels agreeing it is objectively down
+// 2. The sentinel that first wins a majority of votes in an epoch promotes the follower with the highest offset
+// 3. The new leader and its epoch are shared with the other sentinels and published to clients
+#[derive(Debug)]
+pub struct Sentinel {
+	pub(crate) config: SentinelConfig,
+	pub(crate) runid: String,
+	pub(crate) state: Mutex<MonitorState>,
+}
+
+// A random delay up to `max`, so sentinels that noticed a failure together do not keep splitting the votes
+pub(crate) fn jitter(max: Duration) -> Duration {
+	let max = max.as_millis().max(1) as u64;
+	Duration::from_millis(RandomState::new().build_hasher().finish() % max)
+}
+
+impl Sentinel {
+	pub fn new(config: SentinelConfig) -> Self {
+		let state = MonitorState {
+			leader: config.leader.clone(),
+			config_epoch: 0,
+			leader_last_ok: Instant::now(),
+			replicas: BTreeMap::new(),
+			current_epoch: 0,
+			vote: None,
+			next_failover: None,
+		};
+		Sentinel { config, runid: new_replid(), state: Mutex::new(state) }
+	}
+
+	pub fn runid(&self) -> &str {
+		&self.runid
+	}
+
+	// `host:port` of the current leader
+	pub fn leader(&self) -> String {
+		self.state.lock().unwrap().leader.clone()
+	}
+
+	pub fn config_epoch(&self) -> u64 {
+		self.state.lock().unwrap().config_epoch
+	}
+
+	// `host:port` of every known follower
+	pub fn replicas(&self) -> Vec<String> {
+		self.state.lock().unwrap().replicas.keys().cloned().collect()
+	}
+
+	pub fn subjectively_down(&self) -> bool {
+		self.state.lock().unwrap().leader_last_ok.elapsed() > self.config.down_after
+	}
+
+	// How long a sentinel stays out of elections after it tried one or voted for another sentinel
+	pub(crate) fn failover_backoff(&self) -> Duration {
+		self.config.failover_timeout + jitter(self.config.failover_timeout)
+	}
+
+	// A vote request for `epoch`, the first sentinel asking in an epoch gets our vote
+	// Returns the epoch and sentinel we voted for last, if any
+	pub(crate) fn vote(&self, epoch: u64, runid: &str) -> Option<(u64, String)> {
+		let mut state = self.state.lock().unwrap();
+		state.current_epoch = state.current_epoch.max(epoch);
+		let voted = state.vote.as_ref().is_some_and(|(voted_epoch, _)| *voted_epoch >= epoch);
+		if !voted && epoch == state.current_epoch {
+			state.vote = Some((epoch, runid.to_string()));
+			if runid != self.runid {
+				state.next_failover = Some(Instant::now() + self.failover_backoff());
+			}
+		}
+		state.vote.clone()
+	}
+
+	// Take over a configuration with a newer epoch, the old leader is watched as a follower from now on
+	pub(crate) fn adopt(&self, epoch: u64, leader: &str) -> bool {
+		let mut state = self.state.lock().unwrap();
+		if epoch <= state.config_epoch {
+			return false;
+		}
+
+		let old = std::mem::replace(&mut state.leader, leader.to_string());
+		if old != leader {
+			state.replicas.remove(leader);
+			state.replicas.insert(old.clone(), ReplicaInfo::default());
+			state.leader_last_ok = Instant::now();
+			println!("{} switched from {} to {} in epoch {}", self.config.name, old, leader, epoch);
+		}
+		state.config_epoch = epoch;
+		state.current_epoch = state.current_epoch.max(epoch);
+		true
+	}
+}
@@ -0,0 +1,410 @@
+use crate::store::calod_data::DataType;
+use crate::store::calod_store::{CacheError, CalodStore};
+
+// Redis caps string values at 512MB, so the highest addressable bit is 2^32 - 1
+pub const MAX_BIT_OFFSET: u64 = (512 * 1024 * 1024 * 8) - 1;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BitRangeUnit {
+	Byte,
+	Bit,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BitOp {
+	And,
+	Or,
+	Xor,
+	Not,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BitfieldOverflow {
+	Wrap,
+	Sat,
+	Fail,
+}
+
+// Integer type of a BITFIELD operation, e.g. `i8` or `u16`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BitfieldType {
+	pub signed: bool,
+	pub bits: u8,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BitfieldOp {
+	Get(BitfieldType, u64),
+	Set(BitfieldType, u64, i64),
+	IncrBy(BitfieldType, u64, i64),
+	Overflow(BitfieldOverflow),
+}
+
+impl BitOp {
+	pub fn from(str: &str) -> Option<BitOp> {
+		match str.to_lowercase().as_str() {
+			"and" => Some(BitOp::And),
+			"or" => Some(BitOp::Or),
+			"xor" => Some(BitOp::Xor),
+			"not" => Some(BitOp::Not),
+			_ => None,
+		}
+	}
+}
+
+impl BitfieldType {
+	// Parse `i1..i64` and `u1..u63`, unsigned 64 bit values are not representable in a reply
+	pub fn parse(str: &str) -> Option<BitfieldType> {
+		let signed = match str.chars().next()? {
+			'i' | 'I' => true,
+			'u' | 'U' => false,
+			_ => return None,
+		};
+		let bits: u8 = str[1..].parse().ok()?;
+		let max_bits = if signed { 64 } else { 63 };
+
+		if bits == 0 || bits > max_bits {
+			return None;
+		}
+		Some(BitfieldType { signed, bits })
+	}
+
+	fn min(&self) -> i128 {
+		if self.signed { -(1i128 << (self.bits - 1)) } else { 0 }
+	}
+
+	fn max(&self) -> i128 {
+		if self.signed { (1i128 << (self.bits - 1)) - 1 } else { (1i128 << self.bits) - 1 }
+	}
+
+	// Fit a value into the type according to the overflow policy, `None` means FAIL
+	fn fit(&self, value: i128, overflow: BitfieldOverflow) -> Option<i64> {
+		if value >= self.min() && value <= self.max() {
+			return Some(value as i64);
+		}
+
+		match overflow {
+			BitfieldOverflow::Fail => None,
+			BitfieldOverflow::Sat => Some(value.clamp(self.min(), self.max()) as i64),
+			BitfieldOverflow::Wrap => {
+				let modulus = 1i128 << self.bits;
+				let mut wrapped = value.rem_euclid(modulus);
+				if self.signed && wrapped > self.max() {
+					wrapped -= modulus;
+				}
+				Some(wrapped as i64)
+			}
+		}
+	}
+}
+
+// Parse a BITFIELD offset, `#N` addresses the N-th field of the given type
+pub fn parse_bitfield_offset(str: &str, field_type: BitfieldType) -> Option<u64> {
+	let (multiplier, digits) = match str.strip_prefix('#') {
+		Some(rest) => (field_type.bits as u64, rest),
+		None => (1, str),
+	};
+	let offset = digits.parse::<u64>().ok()?.checked_mul(multiplier)?;
+
+	if offset > MAX_BIT_OFFSET - (field_type.bits as u64 - 1) {
+		return None;
+	}
+	Some(offset)
+}
+
+// Bit 0 is the most significant bit of the first byte, like in Redis
+pub fn get_bit(bytes: &[u8], offset: u64) -> u8 {
+	let byte = (offset >> 3) as usize;
+	if byte >= bytes.len() {
+		return 0;
+	}
+	(bytes[byte] >> (7 - (offset & 7))) & 1
+}
+
+// Set a bit and return its previous value
+// 1. Grow the value with zero bytes so the offset is addressable
+// 2. Flip the bit in place
+pub fn set_bit(bytes: &mut Vec<u8>, offset: u64, bit: u8) -> u8 {
+	let byte = (offset >> 3) as usize;
+	if byte >= bytes.len() {
+		bytes.resize(byte + 1, 0);
+	}
+
+	let mask = 1u8 << (7 - (offset & 7));
+	let previous = (bytes[byte] & mask != 0) as u8;
+	if bit == 1 {
+		bytes[byte] |= mask;
+	} else {
+		bytes[byte] &= !mask;
+	}
+	previous
+}
+
+// Count set bits eight bytes at a time
+pub fn popcount(bytes: &[u8]) -> u64 {
+	let mut chunks = bytes.chunks_exact(8);
+	let mut count: u64 = 0;
+
+	for chunk in &mut chunks {
+		count += u64::from_ne_bytes(chunk.try_into().unwrap()).count_ones() as u64;
+	}
+	for byte in chunks.remainder() {
+		count += byte.count_ones() as u64;
+	}
+	count
+}
+
+// Resolve a Redis style inclusive range with negative indexes against `total` units
+// Returns `None` when the range is empty
+pub fn normalize_range(start: i64, end: i64, total: i64) -> Option<(i64, i64)> {
+	let mut start = if start < 0 { total + start } else { start };
+	let mut end = if end < 0 { total + end } else { end };
+
+	start = start.max(0);
+	end = end.max(0).min(total - 1);
+
+	if total == 0 || start > end {
+		return None;
+	}
+	Some((start, end))
+}
+
+// Mask of the bits of a byte that fall in the inclusive bit range
+fn byte_mask(byte: u64, first_bit: u64, last_bit: u64) -> u8 {
+	let byte_start = byte * 8;
+	let from = first_bit.saturating_sub(byte_start).min(8);
+	let to = (last_bit + 1).saturating_sub(byte_start).min(8);
+	((0xffu16 >> from) & !(0xffu16 >> to)) as u8
+}
+
+// Count the set bits in an optional range
+pub fn bit_count(bytes: &[u8], range: Option<(i64, i64, BitRangeUnit)>) -> u64 {
+	let (start, end, unit) = match range {
+		None => return popcount(bytes),
+		Some(range) => range,
+	};
+
+	match unit {
+		BitRangeUnit::Byte => match normalize_range(start, end, bytes.len() as i64) {
+			Some((start, end)) => popcount(&bytes[start as usize..=end as usize]),
+			None => 0,
+		},
+		BitRangeUnit::Bit => match normalize_range(start, end, bytes.len() as i64 * 8) {
+			Some((first, last)) => {
+				let (first, last) = (first as u64, last as u64);
+				let (first_byte, last_byte) = (first / 8, last / 8);
+
+				if first_byte == last_byte {
+					return (bytes[first_byte as usize] & byte_mask(first_byte, first, last)).count_ones() as u64;
+				}
+
+				let head = (bytes[first_byte as usize] & byte_mask(first_byte, first, last)).count_ones() as u64;
+				let tail = (bytes[last_byte as usize] & byte_mask(last_byte, first, last)).count_ones() as u64;
+				head + tail + popcount(&bytes[first_byte as usize + 1..last_byte as usize])
+			}
+			None => 0,
+		},
+	}
+}
+
+// Find the first bit set to `bit` in the range
+// 1. Resolve the range in bits, the end defaults to the end of the value
+// 2. Scan byte by byte, masking the partial bytes on both edges
+// 3. When looking for a clear bit without an explicit end, the value is treated as zero padded
+pub fn bit_pos(bytes: &[u8], bit: u8, start: Option<i64>, end: Option<i64>, unit: BitRangeUnit) -> i64 {
+	let scale: i64 = if unit == BitRangeUnit::Byte { 8 } else { 1 };
+	let total = bytes.len() as i64 * 8 / scale;
+	let end_given = end.is_some();
+
+	let (start, end) = match normalize_range(start.unwrap_or(0), end.unwrap_or(-1), total) {
+		Some(range) => range,
+		None => return -1,
+	};
+	let first = (start * scale) as u64;
+	let last = (end * scale + scale - 1) as u64;
+
+	for byte in first / 8..=last / 8 {
+		let value = if bit == 1 { bytes[byte as usize] } else { !bytes[byte as usize] };
+		let candidates = value & byte_mask(byte, first, last);
+		if candidates != 0 {
+			return (byte * 8 + candidates.leading_zeros() as u64) as i64;
+		}
+	}
+
+	if bit == 0 && !end_given {
+		return (last + 1) as i64;
+	}
+	-1
+}
+
+// Combine the sources byte by byte, shorter sources are zero padded
+pub fn bit_op(op: BitOp, sources: &[&[u8]]) -> Vec<u8> {
+	let len = sources.iter().map(|s| s.len()).max().unwrap_or(0);
+
+	if op == BitOp::Not {
+		return sources.first().map(|s| s.iter().map(|b| !b).collect()).unwrap_or_default();
+	}
+
+	let mut result = vec![0u8; len];
+	for (i, out) in result.iter_mut().enumerate() {
+		let mut iter = sources.iter().map(|s| s.get(i).copied().unwrap_or(0));
+		let first = iter.next().unwrap_or(0);
+		*out = iter.fold(first, |acc, b| match op {
+			BitOp::And => acc & b,
+			BitOp::Or => acc | b,
+			BitOp::Xor => acc ^ b,
+			BitOp::Not => unreachable!(),
+		});
+	}
+	result
+}
+
+// Read a big endian field of `bits` bits starting at `offset`
+pub fn bitfield_get(bytes: &[u8], field_type: BitfieldType, offset: u64) -> i64 {
+	let mut value: u64 = 0;
+	for i in 0..field_type.bits as u64 {
+		value = (value << 1) | get_bit(bytes, offset + i) as u64;
+	}
+
+	if field_type.signed && field_type.bits < 64 && value >> (field_type.bits - 1) & 1 == 1 {
+		value |= u64::MAX << field_type.bits;
+	}
+	value as i64
+}
+
+fn bitfield_write(bytes: &mut Vec<u8>, field_type: BitfieldType, offset: u64, value: i64) {
+	let bits = field_type.bits as u64;
+	for i in 0..bits {
+		let bit = ((value as u64) >> (bits - 1 - i)) & 1;
+		set_bit(bytes, offset + i, bit as u8);
+	}
+}
+
+// Run BITFIELD sub-commands in order, OVERFLOW only affects the operations after it
+pub fn bitfield(bytes: &mut Vec<u8>, ops: &[BitfieldOp]) -> Vec<Option<i64>> {
+	let mut overflow = BitfieldOverflow::Wrap;
+	let mut results = Vec::new();
+
+	for op in ops {
+		match *op {
+			BitfieldOp::Overflow(policy) => overflow = policy,
+			BitfieldOp::Get(field_type, offset) => {
+				results.push(Some(bitfield_get(bytes, field_type, offset)));
+			}
+			BitfieldOp::Set(field_type, offset, value) => {
+				let previous = bitfield_get(bytes, field_type, offset);
+				match field_type.fit(value as i128, overflow) {
+					Some(fitted) => {
+						bitfield_write(bytes, field_type, offset, fitted);
+						results.push(Some(previous));
+					}
+					None => results.push(None),
+				}
+			}
+			BitfieldOp::IncrBy(field_type, offset, increment) => {
+				let current = bitfield_get(bytes, field_type, offset) as i128;
+				match field_type.fit(current + increment as i128, overflow) {
+					Some(fitted) => {
+						bitfield_write(bytes, field_type, offset, fitted);
+						results.push(Some(fitted));
+					}
+					None => results.push(None),
+				}
+			}
+		}
+	}
+	results
+}
+
+// BITFIELD with only GET and OVERFLOW, nothing is written so the value is only borrowed
+fn bitfield_read(bytes: &[u8], ops: &[BitfieldOp]) -> Vec<Option<i64>> {
+	ops.iter().filter_map(|op| match *op {
+		BitfieldOp::Get(field_type, offset) => Some(Some(bitfield_get(bytes, field_type, offset))),
+		_ => None,
+	}).collect()
+}
+
+fn is_read_only(ops: &[BitfieldOp]) -> bool {
+	ops.iter().all(|op| matches!(op, BitfieldOp::Get(..) | BitfieldOp::Overflow(_)))
+}
+
+impl CalodStore {
+	pub fn setbit(&self, key: &str, offset: u64, bit: u8) -> Result<u8, CacheError> {
+		self.update_value(key, || DataType::String(Vec::new()), |value| match value.string_bytes_mut() {
+			Some(bytes) => Ok(set_bit(bytes, offset, bit)),
+			None => Err(CacheError::WrongType),
+		})
+	}
+
+	pub fn getbit(&self, key: &str, offset: u64) -> Result<u8, CacheError> {
+		self.read_value(key, |value| match value.map(DataType::string_bytes) {
+			Some(Some(bytes)) => Ok(get_bit(&bytes, offset)),
+			Some(None) => Err(CacheError::WrongType),
+			None => Ok(0),
+		})
+	}
+
+	pub fn bitcount(&self, key: &str, range: Option<(i64, i64, BitRangeUnit)>) -> Result<u64, CacheError> {
+		self.read_value(key, |value| match value.map(DataType::string_bytes) {
+			Some(Some(bytes)) => Ok(bit_count(&bytes, range)),
+			Some(None) => Err(CacheError::WrongType),
+			None => Ok(0),
+		})
+	}
+
+	// A missing key is an empty string, so clear bits are found at 0 and set bits nowhere
+	pub fn bitpos(&self, key: &str, bit: u8, start: Option<i64>, end: Option<i64>, unit: BitRangeUnit) -> Result<i64, CacheError> {
+		self.read_value(key, |value| match value.map(DataType::string_bytes) {
+			Some(Some(bytes)) => Ok(bit_pos(&bytes, bit, start, end, unit)),
+			Some(None) => Err(CacheError::WrongType),
+			None => Ok(if bit == 1 { -1 } else { 0 }),
+		})
+	}
+
+	// Store the result of a BITOP in `destkey` and return its length
+	// 1. Collect the sources, missing keys are empty strings
+	// 2. Combine them and overwrite the destination
+	// 3. An empty result deletes the destination like Redis does
+	pub fn bitop(&self, op: BitOp, destkey: &str, keys: &[&str]) -> Result<usize, CacheError> {
+		if op == BitOp::Not && keys.len() != 1 {
+			return Err(CacheError::InvalidArgument(String::from("BITOP NOT must be called with a single source key.")));
+		}
+
+		let mut sources: Vec<Vec<u8>> = Vec::with_capacity(keys.len());
+		for key in keys {
+			let bytes = self.read_value(key, |value| match value.map(DataType::string_bytes) {
+				Some(Some(bytes)) => Ok(bytes.into_owned()),
+				Some(None) => Err(CacheError::WrongType),
+				None => Ok(Vec::new()),
+			})?;
+			sources.push(bytes);
+		}
+
+		let borrowed: Vec<&[u8]> = sources.iter().map(|s| s.as_slice()).collect();
+		let result = bit_op(op, &borrowed);
+		let len = result.len();
+
+		if len == 0 {
+			self.remove_value(destkey);
+		} else {
+			self.replace_value(destkey, DataType::String(result));
+		}
+		Ok(len)
+	}
+
+	// Only create the key when at least one operation writes
+	pub fn bitfield(&self, key: &str, ops: &[BitfieldOp]) -> Result<Vec<Option<i64>>, CacheError> {
+		if is_read_only(ops) {
+			return self.read_value(key, |value| match value.map(DataType::string_bytes) {
+				Some(Some(bytes)) => Ok(bitfield_read(&bytes, ops)),
+				Some(None) => Err(CacheError::WrongType),
+				None => Ok(bitfield_read(&[], ops)),
+			});
+		}
+
+		self.update_value(key, || DataType::String(Vec::new()), |value| match value.string_bytes_mut() {
+			Some(bytes) => Ok(bitfield(bytes, ops)),
+			None => Err(CacheError::WrongType),
+		})
+	}
+}
@@ -0,0 +1,214 @@
+use crate::persistence::encoding::{put_f64, put_u64, put_u8, put_varint, Decoder};
+use crate::persistence::snapshot::SnapshotError;
+use crate::store::calod_data::DataType;
+use crate::store::calod_store::{CacheError, CalodStore};
+use crate::store::hyperloglog::murmurhash64a;
+
+// Defaults used when BF.ADD creates the filter implicitly, same as RedisBloom
+pub const BLOOM_DEFAULT_ERROR_RATE: f64 = 0.01;
+pub const BLOOM_DEFAULT_CAPACITY: u64 = 100;
+pub const BLOOM_DEFAULT_EXPANSION: u32 = 2;
+
+// Upper bounds for BF.RESERVE, a sub-filter never grows past `BLOOM_MAX_CAPACITY` items
+pub const BLOOM_MAX_CAPACITY: u64 = 1 << 30;
+pub const BLOOM_MAX_EXPANSION: u32 = 32768;
+
+// Every new sub-filter gets a tighter error rate so the compound rate stays bounded
+const BLOOM_ERROR_TIGHTENING_RATIO: f64 = 0.5;
+
+#[derive(Debug, Clone, PartialEq)]
+struct BloomLayer {
+	bits: Vec<u64>,
+	num_bits: u64,
+	num_hashes: u32,
+	capacity: u64,
+	count: u64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BloomFilter {
+	layers: Vec<BloomLayer>,
+	error_rate: f64,
+	expansion: u32,
+	scaling: bool,
+}
+
+// Two independent hashes for Kirsch-Mitzenmacher double hashing
+fn item_hashes(item: &[u8]) -> (u64, u64) {
+	let h1 = murmurhash64a(item);
+	let h2 = murmurhash64a(&h1.to_le_bytes()) | 1;
+	(h1, h2)
+}
+
+impl BloomLayer {
+	// Size the layer for `capacity` items at `error_rate`
+	// m = -n ln(p) / ln(2)^2, k = m / n ln(2)
+	fn new(capacity: u64, error_rate: f64) -> Self {
+		let ln2 = std::f64::consts::LN_2;
+		let num_bits = ((-(capacity as f64) * error_rate.ln()) / (ln2 * ln2)).ceil().max(64.0) as u64;
+		let num_hashes = ((num_bits as f64 / capacity as f64) * ln2).ceil().max(1.0) as u32;
+
+		BloomLayer {
+			bits: vec![0; num_bits.div_ceil(64) as usize],
+			num_bits,
+			num_hashes,
+			capacity,
+			count: 0,
+		}
+	}
+
+	fn positions(&self, (h1, h2): (u64, u64)) -> impl Iterator<Item = u64> + '_ {
+		(0..self.num_hashes as u64).map(move |i| h1.wrapping_add(i.wrapping_mul(h2)) % self.num_bits)
+	}
+
+	fn contains(&self, hashes: (u64, u64)) -> bool {
+		self.positions(hashes).all(|bit| self.bits[(bit / 64) as usize] & (1 << (bit % 64)) != 0)
+	}
+
+	fn insert(&mut self, hashes: (u64, u64)) {
+		let positions: Vec<u64> = self.positions(hashes).collect();
+		for bit in positions {
+			self.bits[(bit / 64) as usize] |= 1 << (bit % 64);
+		}
+		self.count += 1;
+	}
+
+	fn is_full(&self) -> bool {
+		self.count >= self.capacity
+	}
+}
+
+impl BloomFilter {
+	pub fn new(error_rate: f64, capacity: u64, expansion: u32, scaling: bool) -> Self {
+		BloomFilter {
+			layers: vec![BloomLayer::new(capacity, error_rate)],
+			error_rate,
+			expansion,
+			scaling,
+		}
+	}
+
+	pub fn capacity(&self) -> u64 {
+		self.layers.iter().map(|l| l.capacity).sum()
+	}
+
+	pub fn len(&self) -> u64 {
+		self.layers.iter().map(|l| l.count).sum()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.len() == 0
+	}
+
+	pub fn num_layers(&self) -> usize {
+		self.layers.len()
+	}
+
+	pub fn contains(&self, item: &[u8]) -> bool {
+		let hashes = item_hashes(item);
+		self.layers.iter().any(|l| l.contains(hashes))
+	}
+
+	// Add an item, returns false if it may already have been added
+	// 1. Probe all layers, a hit in any of them means "maybe present"
+	// 2. Grow a new layer with `expansion` times the capacity once the last one is full
+	// 3. Set the bits in the newest layer
+	pub fn add(&mut self, item: &[u8]) -> Result<bool, CacheError> {
+		let hashes = item_hashes(item);
+		if self.layers.iter().any(|l| l.contains(hashes)) {
+			return Ok(false);
+		}
+
+		if self.layers.last().unwrap().is_full() {
+			if !self.scaling {
+				return Err(CacheError::InvalidArgument(String::from("non scaling filter is full")));
+			}
+
+			let capacity = match self.layers.last().unwrap().capacity.checked_mul(self.expansion as u64) {
+				Some(capacity) if capacity <= BLOOM_MAX_CAPACITY => capacity,
+				_ => return Err(CacheError::InvalidArgument(String::from("Maximum filter size reached"))),
+			};
+			let error_rate = self.error_rate * BLOOM_ERROR_TIGHTENING_RATIO.powi(self.layers.len() as i32);
+			self.layers.push(BloomLayer::new(capacity, error_rate));
+		}
+
+		self.layers.last_mut().unwrap().insert(hashes);
+		Ok(true)
+	}
+
+	// Snapshot encoding: filter parameters followed by every layer and its bit words
+	pub fn encode(&self, out: &mut Vec<u8>) {
+		put_f64(out, self.error_rate);
+		put_varint(out, self.expansion as u64);
+		put_u8(out, self.scaling as u8);
+		put_varint(out, self.layers.len() as u64);
+		for layer in &self.layers {
+			put_varint(out, layer.num_bits);
+			put_varint(out, layer.num_hashes as u64);
+			put_varint(out, layer.capacity);
+			put_varint(out, layer.count);
+			for word in &layer.bits {
+				put_u64(out, *word);
+			}
+		}
+	}
+
+	pub fn decode(decoder: &mut Decoder) -> Result<Self, SnapshotError> {
+		let invalid = || SnapshotError::Corrupt(String::from("invalid bloom filter"));
+		let error_rate = decoder.f64()?;
+		let expansion = u32::try_from(decoder.varint()?).map_err(|_| invalid())?;
+		let scaling = decoder.u8()? != 0;
+
+		let num_layers = decoder.varint()?;
+		let mut layers = Vec::new();
+		for _ in 0..num_layers {
+			let num_bits = decoder.varint()?;
+			let num_hashes = u32::try_from(decoder.varint()?).map_err(|_| invalid())?;
+			let capacity = decoder.varint()?;
+			let count = decoder.varint()?;
+			if num_bits == 0 || num_hashes == 0 {
+				return Err(invalid());
+			}
+
+			let mut bits = Vec::new();
+			for _ in 0..num_bits.div_ceil(64) {
+				bits.push(decoder.u64()?);
+			}
+			layers.push(BloomLayer { bits, num_bits, num_hashes, capacity, count });
+		}
+
+		if layers.is_empty() || !(error_rate > 0.0 && error_rate < 1.0) {
+			return Err(invalid());
+		}
+		Ok(BloomFilter { layers, error_rate, expansion, scaling })
+	}
+}
+
+impl CalodStore {
+	// BF.RESERVE refuses to overwrite an existing key
+	pub fn bf_reserve(&self, key: &str, error_rate: f64, capacity: u64, expansion: u32, scaling: bool) -> Result<(), CacheError> {
+		if self.read_value(key, |value| value.is_some()) {
+			return Err(CacheError::InvalidArgument(String::from("item exists")));
+		}
+		self.replace_value(key, DataType::BloomFilter(BloomFilter::new(error_rate, capacity, expansion, scaling)));
+		Ok(())
+	}
+
+	// Every item gets its own result, items that do not fit leave the ones before them added
+	pub fn bf_add(&self, key: &str, items: &[&str]) -> Result<Vec<Result<bool, CacheError>>, CacheError> {
+		self.update_value(key, || {
+			DataType::BloomFilter(BloomFilter::new(BLOOM_DEFAULT_ERROR_RATE, BLOOM_DEFAULT_CAPACITY, BLOOM_DEFAULT_EXPANSION, true))
+		}, |value| match value {
+			DataType::BloomFilter(filter) => Ok(items.iter().map(|item| filter.add(item.as_bytes())).collect()),
+			_ => Err(CacheError::WrongType),
+		})
+	}
+
+	pub fn bf_exists(&self, key: &str, items: &[&str]) -> Result<Vec<bool>, CacheError> {
+		self.read_value(key, |value| match value {
+			Some(DataType::BloomFilter(filter)) => Ok(items.iter().map(|item| filter.contains(item.as_bytes())).collect()),
+			Some(_) => Err(CacheError::WrongType),
+			None => Ok(vec![false; items.len()]),
+		})
+	}
+}
@@ -0,0 +1,484 @@
+use std::borrow::Borrow;
+use std::hash::Hash;
+use std::marker::PhantomData;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use chrono::{DateTime, Duration, Utc};
+use dashmap::mapref::entry::{Entry as MapEntry, OccupiedEntry, VacantEntry};
+use dashmap::mapref::one::{MappedRefMut, RefMut};
+use dashmap::DashMap;
+
+use crate::store::scan::KeyIndex;
+
+// Keys an eviction draws to pick its victim from, like maxmemory-samples in Redis
+pub const EVICTION_SAMPLES: usize = 5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum EvictionPolicy {
+	// Least recently used first
+	Lru,
+	// Least frequently used first
+	Lfu,
+	// Recency, frequency and closeness to expiry added up
+	#[default]
+	Weighted,
+}
+
+impl EvictionPolicy {
+	pub fn from_name(name: &str) -> Option<Self> {
+		match name.to_lowercase().as_str() {
+			"lru" => Some(EvictionPolicy::Lru),
+			"lfu" => Some(EvictionPolicy::Lfu),
+			"weighted" => Some(EvictionPolicy::Weighted),
+			_ => None,
+		}
+	}
+
+	// How good a victim an entry is, the entry with the highest score is evicted first
+	// 1. Recency is the time since the last access in milliseconds
+	// 2. Frequency weighs the inverse of the accesses, plus one to avoid dividing by zero
+	// 3. An entry about to expire weighs the inverse of its time left, an expired one is always evicted first
+	pub fn score(&self, last_accessed: DateTime<Utc>, frequency: u32, ttl: Option<DateTime<Utc>>, now: DateTime<Utc>) -> f64 {
+		let recency = (now - last_accessed).num_milliseconds() as f64;
+		let rarity = 1.0 / (frequency as f64 + 1.0);
+		match self {
+			EvictionPolicy::Lru => recency,
+			EvictionPolicy::Lfu => rarity,
+			EvictionPolicy::Weighted => {
+				let expiry = match ttl.map(|ttl| (ttl - now).num_milliseconds()) {
+					Some(left) if left <= 0 => f64::MAX,
+					Some(left) => 1.0 / left as f64,
+					None => 0.0,
+				};
+				recency + rarity + expiry
+			}
+		}
+	}
+
+	// The key of the best victim among `entries` of `(key, last accessed, frequency, ttl)`
+	pub fn victim<K>(&self, entries: impl Iterator<Item = (K, DateTime<Utc>, u32, Option<DateTime<Utc>>)>, now: DateTime<Utc>) -> Option<K> {
+		entries
+			.map(|(key, last_accessed, frequency, ttl)| (self.score(last_accessed, frequency, ttl, now), key))
+			.max_by(|(a, _), (b, _)| a.total_cmp(b))
+			.map(|(_, key)| key)
+	}
+}
+
+pub fn is_expired(ttl: Option<DateTime<Utc>>, now: DateTime<Utc>) -> bool {
+	ttl.is_some_and(|ttl| ttl < now)
+}
+
+// What eviction and expiry read from an entry
+pub(crate) trait Evictable {
+	// `(last accessed, frequency, ttl)`, the policies score entries by these
+	fn meta(&self) -> (DateTime<Utc>, u32, Option<DateTime<Utc>>);
+}
+
+// Entries indexed for sampling and scanning, counted against a capacity
+// `Cache` and every database of the server keep their entries in one, the server passes hooks
+// where a running snapshot has to see an entry before it goes
+#[derive(Debug)]
+pub(crate) struct Keyspace<K: Eq + Hash, E> {
+	pub(crate) data: DashMap<K, E>,
+	// The keys in scan order for SCAN, RANDOMKEY and eviction sampling
+	pub(crate) index: KeyIndex<K>,
+	len: Occupancy,
+}
+
+impl<K: Eq + Hash + Clone, E: Evictable> Keyspace<K, E> {
+	pub(crate) fn new() -> Self {
+		let data = DashMap::new();
+		let index = KeyIndex::for_map(&data);
+		Keyspace { data, index, len: Occupancy::default() }
+	}
+
+	// Room for `key` if it is new, evicting sampled victims with `evict` while `capacity` keys are held
+	// No shard may be locked by the caller, the key goes over the capacity when there is nothing left to evict
+	pub(crate) fn make_room<Q: Eq + Hash + ?Sized>(&self, key: &Q, capacity: usize, policy: EvictionPolicy, mut evict: impl FnMut(K)) -> Reservation<'_>
+	where
+		K: Borrow<Q>,
+	{
+		self.len.reserve(capacity, self.data.contains_key(key), || match self.sample_victim(policy) {
+			Some(victim) => {
+				evict(victim);
+				true
+			}
+			None => false,
+		})
+	}
+
+	// The victim among a few keys sampled at random, ranking every key would make each write O(n)
+	// The shard of a sampled key is only locked while its meta is read
+	fn sample_victim(&self, policy: EvictionPolicy) -> Option<K> {
+		let sampled = self.index.sample(EVICTION_SAMPLES).into_iter().filter_map(|key| {
+			let (last_accessed, frequency, ttl) = self.data.get(&key)?.meta();
+			Some((key, last_accessed, frequency, ttl))
+		});
+		policy.victim(sampled, Utc::now())
+	}
+
+	// Add the entry of a vacant key into the room reserved for it
+	// The key is indexed while its shard is locked, so SCAN never sees the index and the map disagree
+	pub(crate) fn fill<'a>(&'a self, vacant: VacantEntry<'a, K, E>, entry: E, room: Reservation<'a>) -> RefMut<'a, K, E> {
+		self.index.insert(self.data.determine_map(vacant.key()), vacant.key().clone());
+		let entry = vacant.insert(entry);
+		room.fill();
+		entry
+	}
+
+	// Add the entry of a vacant key whatever the capacity, such as one loaded from a file
+	pub(crate) fn fill_over_capacity<'a>(&'a self, vacant: VacantEntry<'a, K, E>, entry: E) -> RefMut<'a, K, E> {
+		let room = self.len.force();
+		self.fill(vacant, entry, room)
+	}
+
+	// Remove a key if `f` accepts its entry, `f` runs with the shard locked
+	pub(crate) fn remove_if<Q: Eq + Hash + ?Sized>(&self, key: &Q, f: impl FnOnce(&K, &E) -> bool) -> Option<E>
+	where
+		K: Borrow<Q>,
+	{
+		let (_, entry) = self.data.remove_if(key, |key, entry| f(key, entry) && self.unindex(key))?;
+		self.len.release(1);
+		Some(entry)
+	}
+
+	// Drop `key` if it expired by `now`, `before` sees the entry first with the shard locked, returns true if it did
+	pub(crate) fn remove_expired<Q: Eq + Hash + ?Sized>(&self, key: &Q, now: DateTime<Utc>, before: impl FnOnce(&K, &E)) -> bool
+	where
+		K: Borrow<Q>,
+	{
+		self.remove_if(key, |key, entry| {
+			let expired = is_expired(entry.meta().2, now);
+			if expired {
+				before(key, entry);
+			}
+			expired
+		}).is_some()
+	}
+
+	pub(crate) fn remove_occupied(&self, occupied: OccupiedEntry<'_, K, E>) -> E {
+		self.unindex(occupied.key());
+		let (_, entry) = occupied.remove_entry();
+		self.len.release(1);
+		entry
+	}
+
+	// Remove every entry `f` accepts, returns how many there were
+	pub(crate) fn remove_where(&self, f: impl Fn(&E) -> bool) -> usize {
+		let mut removed = 0;
+		self.data.retain(|key, entry| {
+			let remove = f(entry) && self.unindex(key);
+			removed += remove as usize;
+			!remove
+		});
+		self.len.release(removed);
+		removed
+	}
+
+	// Take a key being removed out of the index, called with its shard locked, always true
+	fn unindex(&self, key: &K) -> bool {
+		self.index.remove(self.data.determine_map(key), key);
+		true
+	}
+}
+
+// Keys of a keyspace with a capacity, counted ahead of the insert so concurrent writers cannot overshoot it
+#[derive(Debug, Default)]
+struct Occupancy(AtomicUsize);
+
+impl Occupancy {
+	// Room for one more key unless `exists`, evicting with `evict` while the keyspace is full
+	// `evict` returns false when there is nothing left to evict, the key goes over the capacity then
+	fn reserve(&self, capacity: usize, exists: bool, mut evict: impl FnMut() -> bool) -> Reservation<'_> {
+		if exists {
+			return Reservation { occupancy: self, held: false };
+		}
+		while self.0.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |len| (len < capacity).then_some(len + 1)).is_err() {
+			if !evict() {
+				self.0.fetch_add(1, Ordering::SeqCst);
+				break;
+			}
+		}
+		Reservation { occupancy: self, held: true }
+	}
+
+	// Room for a key that goes in whatever the capacity, such as one loaded from a file
+	fn force(&self) -> Reservation<'_> {
+		self.0.fetch_add(1, Ordering::SeqCst);
+		Reservation { occupancy: self, held: true }
+	}
+
+	// Keys were removed, called once they are out of the map so the count never falls below the keys held
+	fn release(&self, keys: usize) {
+		self.0.fetch_sub(keys, Ordering::SeqCst);
+	}
+}
+
+// Room taken for a new key, given back when dropped unless `fill` says the key went in
+#[must_use]
+pub(crate) struct Reservation<'a> {
+	occupancy: &'a Occupancy,
+	// False when the key existed as the room was asked for, none was taken then
+	held: bool,
+}
+
+impl Reservation<'_> {
+	// The key was added, one that was removed since the room was asked for is counted now
+	pub(crate) fn fill(mut self) {
+		if !self.held {
+			self.occupancy.0.fetch_add(1, Ordering::SeqCst);
+		}
+		self.held = false;
+	}
+}
+
+impl Drop for Reservation<'_> {
+	fn drop(&mut self) {
+		if self.held {
+			self.occupancy.release(1);
+		}
+	}
+}
+
+// A value with the access meta its eviction is decided by
+#[derive(Debug, Clone)]
+pub struct Cached<V> {
+	value: V,
+	frequency: u32,
+	last_accessed: DateTime<Utc>,
+	ttl: Option<DateTime<Utc>>,
+}
+
+impl<V> Evictable for Cached<V> {
+	fn meta(&self) -> (DateTime<Utc>, u32, Option<DateTime<Utc>>) {
+		(self.last_accessed, self.frequency, self.ttl)
+	}
+}
+
+impl<V> Cached<V> {
+	fn new(value: V, ttl: Option<DateTime<Utc>>, now: DateTime<Utc>) -> Self {
+		Cached { value, frequency: 1, last_accessed: now, ttl }
+	}
+
+	fn touch(&mut self, now: DateTime<Utc>) -> &mut V {
+		self.frequency = self.frequency.saturating_add(1);
+		self.last_accessed = now;
+		&mut self.value
+	}
+}
+
+// A mutable borrow of a value in the cache, its shard stays locked until it is dropped
+pub type ValueMut<'a, K, V> = MappedRefMut<'a, K, Cached<V>, V>;
+
+pub struct CacheBuilder<K, V> {
+	capacity: usize,
+	time_to_live: Option<Duration>,
+	policy: EvictionPolicy,
+	cache: PhantomData<fn() -> (K, V)>,
+}
+
+impl<K: Eq + Hash + Clone, V: Clone> CacheBuilder<K, V> {
+	// Entries the cache holds before it evicts
+	pub fn capacity(mut self, capacity: usize) -> Self {
+		self.capacity = capacity;
+		self
+	}
+
+	// Lifetime of the entries inserted without their own
+	pub fn time_to_live(mut self, time_to_live: std::time::Duration) -> Self {
+		self.time_to_live = Duration::from_std(time_to_live).ok();
+		self
+	}
+
+	pub fn policy(mut self, policy: EvictionPolicy) -> Self {
+		self.policy = policy;
+		self
+	}
+
+	pub fn build(self) -> Cache<K, V> {
+		Cache { keyspace: Keyspace::new(), capacity: self.capacity.max(1), time_to_live: self.time_to_live, policy: self.policy }
+	}
+}
+
+// In-process cache with the eviction and expiry of the server, safe to share between threads
+// 1. Expired entries count as missing and are dropped when they are next touched or by `purge_expired`
+// 2. Inserting a new key into a full cache evicts first, the entry with the highest score of the policy among a few sampled ones
+// 3. Reads and writes update the access meta the policy scores by
+pub struct Cache<K: Eq + Hash, V> {
+	keyspace: Keyspace<K, Cached<V>>,
+	capacity: usize,
+	time_to_live: Option<Duration>,
+	policy: EvictionPolicy,
+}
+
+impl<K: Eq + Hash + Clone, V: Clone> Cache<K, V> {
+	pub fn new(capacity: usize) -> Self {
+		Self::builder().capacity(capacity).build()
+	}
+
+	pub fn builder() -> CacheBuilder<K, V> {
+		CacheBuilder { capacity: usize::MAX, time_to_live: None, policy: EvictionPolicy::default(), cache: PhantomData }
+	}
+
+	pub fn capacity(&self) -> usize {
+		self.capacity
+	}
+
+	pub fn policy(&self) -> EvictionPolicy {
+		self.policy
+	}
+
+	// Entries held, expired ones not purged yet included
+	pub fn len(&self) -> usize {
+		self.keyspace.data.len()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.keyspace.data.is_empty()
+	}
+
+	pub fn get<Q: Eq + Hash + ?Sized>(&self, key: &Q) -> Option<V>
+	where
+		K: Borrow<Q>,
+	{
+		self.get_mut(key).map(|value| value.clone())
+	}
+
+	pub fn get_mut<Q: Eq + Hash + ?Sized>(&self, key: &Q) -> Option<ValueMut<'_, K, V>>
+	where
+		K: Borrow<Q>,
+	{
+		let now = Utc::now();
+		self.drop_expired(key, now);
+		self.keyspace.data.get_mut(key).map(|entry| entry.map(|cached| cached.touch(now)))
+	}
+
+	pub fn contains_key<Q: Eq + Hash + ?Sized>(&self, key: &Q) -> bool
+	where
+		K: Borrow<Q>,
+	{
+		self.keyspace.data.get(key).is_some_and(|cached| !is_expired(cached.ttl, Utc::now()))
+	}
+
+	// Time left before the entry expires, `None` for a missing entry or one that never does
+	pub fn time_to_live<Q: Eq + Hash + ?Sized>(&self, key: &Q) -> Option<std::time::Duration>
+	where
+		K: Borrow<Q>,
+	{
+		let now = Utc::now();
+		let ttl = self.keyspace.data.get(key)?.ttl?;
+		(ttl - now).to_std().ok()
+	}
+
+	// Insert or replace a value with the default lifetime, returns the live value it replaced
+	pub fn insert(&self, key: K, value: V) -> Option<V> {
+		let ttl = self.time_to_live.map(|time_to_live| Utc::now() + time_to_live);
+		self.insert_until(key, value, ttl)
+	}
+
+	pub fn insert_with_ttl(&self, key: K, value: V, time_to_live: std::time::Duration) -> Option<V> {
+		let ttl = Duration::from_std(time_to_live).ok().map(|time_to_live| Utc::now() + time_to_live);
+		self.insert_until(key, value, ttl)
+	}
+
+	fn insert_until(&self, key: K, value: V, ttl: Option<DateTime<Utc>>) -> Option<V> {
+		let now = Utc::now();
+		let room = self.make_room(&key);
+		match self.keyspace.data.entry(key) {
+			MapEntry::Occupied(mut occupied) => {
+				let old = occupied.insert(Cached::new(value, ttl, now));
+				(!is_expired(old.ttl, now)).then_some(old.value)
+			}
+			MapEntry::Vacant(vacant) => {
+				self.keyspace.fill(vacant, Cached::new(value, ttl, now), room);
+				None
+			}
+		}
+	}
+
+	pub fn remove<Q: Eq + Hash + ?Sized>(&self, key: &Q) -> Option<V>
+	where
+		K: Borrow<Q>,
+	{
+		let old = self.keyspace.remove_if(key, |_, _| true)?;
+		(!is_expired(old.ttl, Utc::now())).then_some(old.value)
+	}
+
+	// The entry of a key for reading and updating it in place
+	// A full cache makes room for a missing key first, the room is given back if the entry is not filled
+	pub fn entry(&self, key: K) -> Entry<'_, K, V> {
+		let now = Utc::now();
+		self.drop_expired(&key, now);
+		let room = self.make_room(&key);
+		let ttl = self.time_to_live.map(|time_to_live| now + time_to_live);
+		Entry { entry: self.keyspace.data.entry(key), ttl, now, keyspace: &self.keyspace, room }
+	}
+
+	// Drop every expired entry, returns how many there were
+	pub fn purge_expired(&self) -> usize {
+		let now = Utc::now();
+		self.keyspace.remove_where(|cached| is_expired(cached.ttl, now))
+	}
+
+	pub fn clear(&self) {
+		self.keyspace.remove_where(|_| true);
+	}
+
+	fn drop_expired<Q: Eq + Hash + ?Sized>(&self, key: &Q, now: DateTime<Utc>)
+	where
+		K: Borrow<Q>,
+	{
+		self.keyspace.remove_expired(key, now, |_, _| ());
+	}
+
+	// Room for `key` if it is new, evicting sampled victims while the cache is full
+	// No shard may be locked by the caller
+	fn make_room(&self, key: &K) -> Reservation<'_> {
+		self.keyspace.make_room(key, self.capacity, self.policy, |victim| {
+			self.remove(&victim);
+		})
+	}
+}
+
+pub struct Entry<'a, K: Eq + Hash, V> {
+	entry: MapEntry<'a, K, Cached<V>>,
+	// Deadline of a value inserted through the entry
+	ttl: Option<DateTime<Utc>>,
+	now: DateTime<Utc>,
+	keyspace: &'a Keyspace<K, Cached<V>>,
+	room: Reservation<'a>,
+}
+
+impl<'a, K: Eq + Hash + Clone, V> Entry<'a, K, V> {
+	pub fn key(&self) -> &K {
+		self.entry.key()
+	}
+
+	pub fn is_occupied(&self) -> bool {
+		matches!(self.entry, MapEntry::Occupied(_))
+	}
+
+	pub fn and_modify(self, f: impl FnOnce(&mut V)) -> Self {
+		let now = self.now;
+		Entry { entry: self.entry.and_modify(|cached| f(cached.touch(now))), ..self }
+	}
+
+	pub fn or_insert(self, value: V) -> ValueMut<'a, K, V> {
+		self.or_insert_with(|| value)
+	}
+
+	pub fn or_insert_with(self, f: impl FnOnce() -> V) -> ValueMut<'a, K, V> {
+		let Entry { entry, ttl, now, keyspace, room } = self;
+		match entry {
+			MapEntry::Occupied(occupied) => occupied.into_ref().map(|cached| cached.touch(now)),
+			MapEntry::Vacant(vacant) => keyspace.fill(vacant, Cached::new(f(), ttl, now), room).map(|cached| &mut cached.value),
+		}
+	}
+
+	pub fn or_default(self) -> ValueMut<'a, K, V>
+	where
+		V: Default,
+	{
+		self.or_insert_with(V::default)
+	}
+}
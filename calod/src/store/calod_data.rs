@@ -1,102 +1,237 @@
-use dashmap::DashMap;
+use std::borrow::Cow;
+use std::collections::LinkedList;
+use std::sync::RwLock;
+use std::sync::atomic::{AtomicU64, Ordering};
+
 use chrono::{DateTime, Utc};
+use serde_json::Value;
+
+use crate::store::bloom_filter::BloomFilter;
+use crate::store::cache::Evictable;
+use crate::store::cuckoo_filter::CuckooFilter;
+use crate::store::hyperloglog::HyperLogLog;
+use crate::store::scan::ScanMap;
+use crate::store::sorted_set::SortedSet;
 
 // CacheEntry struct
-#[derive(Debug, Clone)]
+#[derive(Debug)]
 pub struct CacheEntry {
-	value: DataType,
-	frequency: u32,
-	last_accessed: DateTime<Utc>,
-	ttl: Option<DateTime<Utc>>,
+	pub value: DataType,
+	pub frequency: u32,
+	pub last_accessed: DateTime<Utc>,
+	pub ttl: Option<DateTime<Utc>>,
+	// Last snapshot epoch that has written or preserved this entry, see `SnapshotState`
+	pub snapshot_epoch: AtomicU64,
 }
 
-struct CacheEntryWithScore {
-	key: String,
-	score: f64,
-}
+impl CacheEntry {
+	pub fn new(value: DataType) -> Self {
+		CacheEntry {
+			value,
+			frequency: 1,
+			last_accessed: Utc::now(),
+			ttl: None,
+			snapshot_epoch: AtomicU64::new(0),
+		}
+	}
 
-impl Ord for CacheEntryWithScore {
-	fn cmp(&self, other: &Self) -> std::cmp::Ordering {
-		self.score.partial_cmp(&other.score).unwrap()
+	// Count an access, the eviction policies score entries by these
+	pub fn touch(&mut self, now: DateTime<Utc>) {
+		self.frequency = self.frequency.saturating_add(1);
+		self.last_accessed = now;
 	}
 }
 
-impl PartialOrd for CacheEntryWithScore {
-	fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
-		Some(self.cmp(other))
+impl Evictable for CacheEntry {
+	fn meta(&self) -> (DateTime<Utc>, u32, Option<DateTime<Utc>>) {
+		(self.last_accessed, self.frequency, self.ttl)
 	}
 }
 
-impl PartialEq for CacheEntryWithScore {
-	fn eq(&self, other: &Self) -> bool {
-		self.score == other.score
+impl Clone for CacheEntry {
+	fn clone(&self) -> Self {
+		CacheEntry {
+			value: self.value.clone(),
+			frequency: self.frequency,
+			last_accessed: self.last_accessed,
+			ttl: self.ttl,
+			snapshot_epoch: AtomicU64::new(self.snapshot_epoch.load(Ordering::SeqCst)),
+		}
 	}
 }
 
-impl Eq for CacheEntryWithScore {}
-
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum DataType {
-	String(String),
+	// Raw bytes so bitmaps and other binary payloads survive untouched
+	String(Vec<u8>),
 	List(LinkedList<String>),
 	Set(Set),
 	Hash(Hash),
+	SortedSet(SortedSet),
+	HyperLogLog(HyperLogLog),
+	BloomFilter(BloomFilter),
+	CuckooFilter(CuckooFilter),
+	// Parsed document so JSON.* commands can read and update parts of it in place
+	Json(Value),
 }
 
-#[derive(Debug)]
-pub struct LinkedList {
-	head: LinkedListNode,
-}
+impl DataType {
+	// The bytes of a string value, a HyperLogLog is a string to clients and reads as its encoding
+	pub fn string_bytes(&self) -> Option<Cow<'_, [u8]>> {
+		match self {
+			DataType::String(bytes) => Some(Cow::Borrowed(bytes)),
+			DataType::HyperLogLog(hll) => Some(Cow::Owned(hll.to_string_bytes())),
+			_ => None,
+		}
+	}
 
-#[derive(Debug)]
-pub struct LinkedListNode {
-	data: String,
-	next: Option<Box<LinkedList>>,
+	// The bytes of a string value to change them, a HyperLogLog turns into the plain string of its encoding first
+	pub fn string_bytes_mut(&mut self) -> Option<&mut Vec<u8>> {
+		if let DataType::HyperLogLog(hll) = self {
+			*self = DataType::String(hll.to_string_bytes());
+		}
+		match self {
+			DataType::String(bytes) => Some(bytes),
+			_ => None,
+		}
+	}
+
+	pub fn type_name(&self) -> &'static str {
+		match self {
+			DataType::String(_) => "string",
+			DataType::List(_) => "list",
+			DataType::Set(_) => "set",
+			DataType::Hash(_) => "hash",
+			DataType::SortedSet(_) => "zset",
+			// Like Redis, a HyperLogLog is a string, see `string_bytes`
+			DataType::HyperLogLog(_) => "string",
+			DataType::BloomFilter(_) => "bloom",
+			DataType::CuckooFilter(_) => "cuckoo",
+			DataType::Json(_) => "json",
+		}
+	}
+
+	// Roughly how many allocations dropping the value frees, UNLINK leaves costly ones to the lazy free thread
+	pub fn free_effort(&self) -> usize {
+		match self {
+			DataType::List(list) => list.len(),
+			DataType::Set(set) => set.len(),
+			DataType::Hash(hash) => hash.len(),
+			DataType::SortedSet(sorted_set) => sorted_set.len(),
+			DataType::Json(Value::Array(items)) => items.len(),
+			DataType::Json(Value::Object(fields)) => fields.len(),
+			_ => 1,
+		}
+	}
 }
 
+// Members in scan order, SSCAN resumes from a cursor without sorting the set
 #[derive(Debug)]
 pub struct Set {
-	data: DashMap<String, ()>,
+	data: RwLock<ScanMap<String, ()>>,
+}
+
+impl Default for Set {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+impl Clone for Set {
+	fn clone(&self) -> Self {
+		Set { data: RwLock::new(self.data.read().unwrap().clone()) }
+	}
 }
 
 impl Set {
 	pub fn new() -> Self {
-		Set { data: DashMap::new(), }
+		Set { data: RwLock::new(ScanMap::new()), }
 	}
 
 	pub fn insert(&self, value: String) {
-		self.data.insert(value, ());
+		self.data.write().unwrap().insert(value, ());
 	}
 
 	pub fn contains(&self, value: &str) -> bool {
-		self.data.contains_key(value)
+		self.data.read().unwrap().contains_key(value)
 	}
 
 	pub fn remove(&self, value: &str) {
-		self.data.remove(value);
+		self.data.write().unwrap().remove(value);
+	}
+
+	pub fn len(&self) -> usize {
+		self.data.read().unwrap().len()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.data.read().unwrap().is_empty()
+	}
+
+	pub fn members(&self) -> Vec<String> {
+		self.data.read().unwrap().iter().map(|(member, _)| member.clone()).collect()
+	}
+
+	// SSCAN, one page of members and the cursor of the next
+	pub fn scan(&self, cursor: u64, count: usize) -> (u64, Vec<String>) {
+		let data = self.data.read().unwrap();
+		let (next, page) = data.page(cursor, count);
+		(next, page.into_iter().map(|(member, _)| member.clone()).collect())
 	}
 }
 
+// Fields in scan order, HSCAN resumes from a cursor without sorting the hash
 #[derive(Debug)]
 pub struct Hash {
-	data: DashMap<String, String>,
+	data: RwLock<ScanMap<String, String>>,
+}
+
+impl Default for Hash {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+impl Clone for Hash {
+	fn clone(&self) -> Self {
+		Hash { data: RwLock::new(self.data.read().unwrap().clone()) }
+	}
 }
 
 impl Hash {
 	pub fn new() -> Self {
-		Hash { data: DashMap::new(), }
+		Hash { data: RwLock::new(ScanMap::new()), }
 	}
 
 	pub fn insert(&self, key: String, value: String) {
-		self.data.insert(key, value);
+		self.data.write().unwrap().insert(key, value);
 	}
 
 	pub fn get(&self, key: &str) -> Option<String> {
-		self.data.get(key).map(|entry| entry.clone())
+		self.data.read().unwrap().get(key).cloned()
 	}
 
 	pub fn remove(&self, key: &str) {
-		self.data.remove(key);
+		self.data.write().unwrap().remove(key);
+	}
+
+	pub fn len(&self) -> usize {
+		self.data.read().unwrap().len()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.data.read().unwrap().is_empty()
+	}
+
+	pub fn entries(&self) -> Vec<(String, String)> {
+		self.data.read().unwrap().iter().map(|(key, value)| (key.clone(), value.clone())).collect()
+	}
+
+	// HSCAN, one page of fields with their values and the cursor of the next
+	pub fn scan(&self, cursor: u64, count: usize) -> (u64, Vec<(String, String)>) {
+		let data = self.data.read().unwrap();
+		let (next, page) = data.page(cursor, count);
+		(next, page.into_iter().map(|(key, value)| (key.clone(), value.clone())).collect())
 	}
 }
 
@@ -1,20 +1,36 @@
 
-use once_cell::sync::Lazy;
 use std::sync::RwLock;
-use chrono::{Duration, Utc, Instant};
-use std::collections::{VecDeque, BinaryHeap};
-use dashmap::DashMap;
-use std::sync::Once;
-use std::sync::{Arc, Once, Mutex};
+use chrono::{DateTime, Duration, Utc};
+use dashmap::mapref::entry::Entry;
+use std::sync::{Arc, Mutex};
 use std::sync::atomic::{AtomicUsize, Ordering};
+use std::io::{BufReader, BufWriter, Read, Write};
 use thiserror::Error;
 
-use serial_test::serial;
 
-use crate::store::calod_data::{CacheEntry, DateTimeMeta, DateTimeMetaBuilder};
+use crate::persistence::aof::AppendOnlyFile;
+use crate::persistence::background_save::SnapshotState;
+use crate::persistence::snapshot::{SnapshotError, SnapshotReader, SnapshotWriter};
+use crate::persistence::storage::{OsStorage, Storage};
+use crate::replication::state::Replication;
+use crate::cluster::slots::key_slot;
+use crate::cluster::state::ClusterState;
+use crate::store::cache::{is_expired, EvictionPolicy, Keyspace, Reservation};
+use crate::store::calod_data::{CacheEntry, DataType};
+use crate::store::glob::glob_match;
+use crate::store::lazy_free;
 
-static STORE: Lazy<RwLock<Option<CalodStore>>> = Lazy::new(|| RwLock::new(None));
-static INIT: Once = Once::new();
+// Large buffers keep snapshot I/O sequential for multi gigabyte stores
+const SNAPSHOT_BUFFER_SIZE: usize = 8 * 1024 * 1024;
+
+// Logical databases a server starts with, like Redis
+pub const DEFAULT_DATABASES: usize = 16;
+
+// RANDOMKEY gives up after drawing this many expired keys in a row
+const RANDOM_KEY_ATTEMPTS: usize = 100;
+
+// Identities of databases, they stay with a keyspace when SWAPDB moves it to another index
+static NEXT_DATABASE_ID: AtomicUsize = AtomicUsize::new(0);
 
 
 #[derive(Debug)]
@@ -27,277 +43,736 @@ pub enum CacheError {
 	#[error("Key `{0}` not found")]
 	KeyNotFound(String),
 
-	#[error("Store is not initialized")]
-	StoreNotInitialized,
-
 	#[error("Key `{0}` has expired")]
 	KeyExpired(String),
 
 	#[error("Invalid TTL value provided")]
 	InvalidTtl,
+
+	#[error("WRONGTYPE Operation against a key holding the wrong kind of value")]
+	WrongType,
+
+	#[error("ERR {0}")]
+	InvalidArgument(String),
+
+	#[error("BUSYKEY Target key name already exists.")]
+	BusyKey,
+
+	#[error("ERR DB index is out of range")]
+	DbIndexOutOfRange,
+
+	#[error("ERR no such key")]
+	NoSuchKey,
+
+	#[error("TRYAGAIN a snapshot of the databases is being written")]
+	SnapshotInProgress,
+}
+
+// One logical database, a keyspace with its own entries, deadlines and key index
+// The index shares the bytes of the keys with the map
+#[derive(Debug)]
+pub(crate) struct Database {
+	pub(crate) id: usize,
+	keyspace: Keyspace<Arc<str>, CacheEntry>,
+}
+
+impl Database {
+	fn new() -> Self {
+		Database {
+			id: NEXT_DATABASE_ID.fetch_add(1, Ordering::SeqCst),
+			keyspace: Keyspace::new(),
+		}
+	}
+
+	// Add an entry read from a file, loading does not count as a write and never evicts
+	pub(crate) fn load_entry(&self, key: String, entry: CacheEntry) {
+		match self.keyspace.data.entry(Arc::from(key)) {
+			Entry::Occupied(mut occupied) => {
+				occupied.insert(entry);
+			}
+			Entry::Vacant(vacant) => {
+				self.keyspace.fill_over_capacity(vacant, entry);
+			}
+		}
+	}
+
+	// Clone the entries of one shard that `keep` accepts, the shard is read locked only while they are copied
+	fn copy_shard(&self, shard: usize, mut keep: impl FnMut(&CacheEntry) -> bool) -> Vec<(Arc<str>, CacheEntry)> {
+		let entries = self.keyspace.data.shards()[shard].read();
+		self.keyspace.index.keys(shard).into_iter()
+			.filter_map(|key| {
+				let hash = self.keyspace.data.hash_usize(&key) as u64;
+				let (_, entry) = entries.get(hash, |(stored, _)| *stored == key)?;
+				keep(entry.get()).then(|| (key, entry.get().clone()))
+			})
+			.collect()
+	}
 }
 
 
+// A handle on one logical database of a server, `select` hands out handles on the others
+// Everything but the selected index is shared by the handles of a server
 #[derive(Debug)]
 pub struct CalodStore {
-	start_time: Instant,
-	request_count: u64,
-	data: DashMap<String, CacheEntry>,
-	lru_queue: Arc<Mutex<VecDeque<String>>>,
-	capacity: AtomicUsize,
+	db: usize,
+	// Indexed by database number, SWAPDB swaps two slots
+	databases: Arc<RwLock<Vec<Arc<Database>>>>,
+	// Keys per database
+	capacity: Arc<AtomicUsize>,
+	policy: Arc<RwLock<EvictionPolicy>>,
+	snapshot: Arc<SnapshotState>,
+	// Held by a write command from executing it until it is logged, see `logged_write`
+	pub(crate) writes: Arc<Mutex<()>>,
+	pub(crate) aof: Arc<RwLock<Option<Arc<AppendOnlyFile>>>>,
+	storage: Arc<RwLock<Arc<dyn Storage>>>,
+	pub(crate) replication: Arc<Replication>,
+	pub(crate) cluster: Arc<ClusterState>,
 }
 
 
-pub trait Store {
-	fn initialize(capacity: usize);
+impl CalodStore {
+	pub fn new(capacity: usize) -> Self {
+		CalodStore::with_storage(capacity, Arc::new(OsStorage))
+	}
 
-	fn get_store() -> &'static mut CalodStore;
+	// A store whose snapshots and append only file go through `storage`
+	pub fn with_storage(capacity: usize, storage: Arc<dyn Storage>) -> Self {
+		CalodStore {
+			db: 0,
+			databases: Arc::new(RwLock::new((0..DEFAULT_DATABASES).map(|_| Arc::new(Database::new())).collect())),
+			capacity: Arc::new(AtomicUsize::new(capacity)),
+			policy: Arc::new(RwLock::new(EvictionPolicy::default())),
+			snapshot: Arc::new(SnapshotState::new()),
+			writes: Arc::new(Mutex::new(())),
+			aof: Arc::new(RwLock::new(None)),
+			storage: Arc::new(RwLock::new(storage)),
+			replication: Arc::new(Replication::new()),
+			cluster: Arc::new(ClusterState::new()),
+		}
+	}
 
-	fn get(&self, key: &str) -> Option<&str>;
+	// SELECT, a handle on database `index` of the same server
+	pub fn select(&self, index: usize) -> Result<Arc<CalodStore>, CacheError> {
+		if index >= self.database_count() {
+			return Err(CacheError::DbIndexOutOfRange);
+		}
+		Ok(Arc::new(CalodStore {
+			db: index,
+			databases: self.databases.clone(),
+			capacity: self.capacity.clone(),
+			policy: self.policy.clone(),
+			snapshot: self.snapshot.clone(),
+			writes: self.writes.clone(),
+			aof: self.aof.clone(),
+			storage: self.storage.clone(),
+			replication: self.replication.clone(),
+			cluster: self.cluster.clone(),
+		}))
+	}
 
-	fn set(&mut self, key: &str, opt: &Option<SetOptionalArgs>) -> Option<DataType>;
+	// Index of the database this handle works on
+	pub fn db(&self) -> usize {
+		self.db
+	}
 
-	fn is_key_expired(&self, key: &str) -> bool;
+	pub fn database_count(&self) -> usize {
+		self.databases.read().unwrap().len()
+	}
 
-	fn delete(&mut self, keys: Vec<&str>) -> u64;
+	// Grow or shrink the number of databases, meant for startup before any handle selects one
+	// Dropped databases lose their keys, there is always at least one
+	pub fn set_database_count(&self, count: usize) {
+		let mut databases = self.databases.write().unwrap();
+		databases.truncate(count.max(1));
+		while databases.len() < count {
+			databases.push(Arc::new(Database::new()));
+		}
+	}
 
-	fn invalidate(&self);
-}
+	fn database(&self) -> Arc<Database> {
+		self.databases.read().unwrap()[self.db].clone()
+	}
 
-impl Store for CalodStore {
-	
-	// Initialize the store globally using call_once (happens only once)
-	// 1. Load the config from the env or json config file
-	// 2. Acquire write lock on store
-	// 3. Initialize the store with new structs
-	fn initialize() {
-		INIT.call_once(|| unsafe {
-			let config = Config::from_env_or_file().expect("Failed to load config");
-
-			let mut store = STORE.write().unwrap();
-			*store = Some(CalodStore {
-				data: DashMap::new(),
-				lru_queue: Arc::new(Mutex::new(VecDeque::new())),
-				capacity: AtomicUsize::new(config.cache_capacity),
-			});
-			println!("Store is initialized with capacity: {}", config.cache_capacity);
-		});
+	pub(crate) fn database_at(&self, index: usize) -> Option<Arc<Database>> {
+		self.databases.read().unwrap().get(index).cloned()
 	}
 
+	// Every database in index order, a copy of the table so no lock is held while they are visited
+	pub(crate) fn databases(&self) -> Vec<Arc<Database>> {
+		self.databases.read().unwrap().clone()
+	}
 
-	// Retreive the store instance from the global variable
-	// 1. Acquire read lock on the `STORE`
-	// 2. If `STORE` is not None (Some), return a clone of Arc (Reference)
-	// 3. Return an error is `STORE` is none
-	fn get_store() -> Result<&'static mut CalodStore, CacheError> {
-		let store = STORE.read().unwrap();
-		if let Some(store) = store.as_ref() {
-			Ok(Arc::clone(store))
-		} else {
-			Err(CacheError::StoreNotInitialized)
-		}
+	pub fn storage(&self) -> Arc<dyn Storage> {
+		self.storage.read().unwrap().clone()
 	}
 
+	// Point later loads and saves at another storage, an enabled AOF keeps the storage it was opened on
+	pub fn set_storage(&self, storage: Arc<dyn Storage>) {
+		*self.storage.write().unwrap() = storage;
+	}
 
-	// Retreive a value from the Calod Cache
-	// 1. Check if the key exists in the `DashMap` -> Return KeyNotFound Error
-	// 2. Check if the key has expired -> Return KeyExpired Error
-	// 3. Get the `CacheEntry` from the cache
-	// 4. Update the access meta `frequency` and `last_accessed`
-	// 5. Move the key in the front of the LRU eviction queue
-	// 6. Return the value store in cache entry
-	fn get(&self, key: &str) -> Result<Option<&str>, CacheError> {
-		if !self.data.contains_key(key) {
-			return Err(CacheError::KeyNotFound(key.to_string()));
-		}
+	pub fn set_eviction_policy(&self, policy: EvictionPolicy) {
+		*self.policy.write().unwrap() = policy;
+	}
 
-		if self.is_key_expired(key?) {
-			return Err(CacheError::KeyExpired(key.to_string()));
-		}
+	pub fn snapshot_state(&self) -> &SnapshotState {
+		&self.snapshot
+	}
 
-		if let Some(mut entry) = self.data.get_mut(key) {
-			entry.frequency += 1;
-			entry.last_accessed = Utc::now();
+	pub fn replication(&self) -> &Replication {
+		&self.replication
+	}
 
-			// Move the key to the front of the LRU queue
-			let mut lru_queue = self.lru_queue.lock().unwrap();
-			self.lru_queue.retain(|k| k != key);
-			self.lru_queue.push_front(key.to_string());
+	pub fn cluster(&self) -> &ClusterState {
+		&self.cluster
+	}
 
-			Ok(Some(entry.value.clone()))
-		} else {
-			Ok(None)
+	// Insert or overwrite an entry, preserving the old one for a running snapshot
+	fn insert_entry(&self, database: &Database, key: &str, entry: CacheEntry) -> Option<CacheEntry> {
+		let room = self.make_room(database, key);
+		match database.keyspace.data.entry(Arc::from(key)) {
+			Entry::Occupied(mut occupied) => {
+				self.snapshot.before_replace(database.id, key, occupied.get(), &entry);
+				Some(occupied.insert(entry))
+			}
+			Entry::Vacant(vacant) => {
+				self.snapshot.before_insert(&entry);
+				database.keyspace.fill(vacant, entry, room);
+				None
+			}
 		}
 	}
 
-	// Insert/Update a value in the Calod cache
-	// 1. Check if the cache capacity is full, if yes evict
-	// 2. Calculate TTL of the `CacheEntry`
-	// 3. Insert the cache entry into the `DashMap`
-	// 4. Move the key to front of LRU eviction queue
-	// 6. Return the old value of existed (in case update)
-	fn set(&mut self, key: &str, value: &DataType, opt: &Option<SetOptionalArgs>) -> Option<DataType> {
-		if self.data.len() >= self.capacity {
-			self.evict();
-		}
+	// Room for `key` if it is new, evicting sampled victims while the database is full
+	// 1. No shard may be locked by the caller, victims go through `remove_from` so a running snapshot keeps them
+	// 2. Victims are logged and fed to the followers as a DEL, a read only follower never evicts on its own
+	fn make_room<'a>(&self, database: &'a Database, key: &str) -> Reservation<'a> {
+		let capacity = if self.replication.is_read_only_replica() { usize::MAX } else { self.capacity.load(Ordering::SeqCst) };
+		let policy = *self.policy.read().unwrap();
+		database.keyspace.make_room(key, capacity, policy, |victim| {
+			if self.remove_from(database, &victim) {
+				self.log_eviction(database.id, &victim);
+			}
+		})
+	}
 
-		let ttl_datetime = opt.map(|t| Utc::now() + t);
-		let entry = CacheEntry {
-			value: value.clone(),
-			frequency: 1,
-			last_accessed: Utc::now(),
-			ttl: ttl_datetime,
-		};
+	// Index a database is at right now, `None` for a database that was dropped
+	pub(crate) fn index_of(&self, id: usize) -> Option<usize> {
+		self.databases.read().unwrap().iter().position(|database| database.id == id)
+	}
 
-		// Insert and handle the previous entry properly
-		let old_entry = self.data.insert(key.to_string(), entry);
+	// Remove an entry, preserving it for a running snapshot
+	fn remove_entry(&self, database: &Database, key: &str) -> Option<CacheEntry> {
+		database.keyspace.remove_if(key, |key, entry| {
+			self.snapshot.before_write(database.id, key, entry);
+			true
+		})
+	}
 
-		// Move the key to the front of the LRU queue
-		self.lru_queue.push_front(key.to_string());
+	// Remove a single key of `database`, returns true if it existed
+	fn remove_from(&self, database: &Database, key: &str) -> bool {
+		self.remove_entry(database, key).is_some()
+	}
 
-		Ok(old_entry.map(|e| e.value))
+	// Drop `key` if it has expired, returns true if it did
+	fn remove_expired(&self, database: &Database, key: &str, now: DateTime<Utc>) -> bool {
+		database.keyspace.remove_expired(key, now, |key, entry| self.snapshot.before_write(database.id, key, entry))
 	}
 
-	// Evict and entry from the cache LRU/LFU/Predictive Weights
-	// 1. Iterate through the LRU queue to calculate priority scores
-	// 2. Calcaulate Weights LRU + LFU + Predictive
-	// 3. Push the entry onto the min heap
-	// 4. Pop the lowest priority entry from the heap and evict it
-	fn evict(&mut self) {
-		let mut heap = BinaryHeap::new();
+	// Add a taken entry to `database` unless a live key is in the way, the entry is handed back then
+	fn insert_vacant(&self, database: &Database, key: &str, entry: CacheEntry) -> Result<(), CacheEntry> {
+		self.remove_expired(database, key, Utc::now());
 
-		for key in self.lru_queue.iter() {
-			if let Some(entry) = self.data.get(key) {
-				let lru_weight = self.calculate_lru_weight(entry);
-				let lfu_weight = self.calculate_lfu_weight(entry);
-				let predictive_weight = self.calculate_predictive_weight(entry);
+		let room = self.make_room(database, key);
+		match database.keyspace.data.entry(Arc::from(key)) {
+			Entry::Occupied(_) => return Err(entry),
+			Entry::Vacant(vacant) => {
+				self.snapshot.before_insert(&entry);
+				database.keyspace.fill(vacant, entry, room);
+			}
+		}
+		Ok(())
+	}
 
-				// Combine the weights into a single priority score
-				let total_priority_score = lru_weight + lfu_weight + predictive_weight;
+	// Read a value without touching its access meta
+	// 1. Expired entries are reported as missing
+	// 2. Hand a borrow of the value to the closure while the shard lock is held
+	pub fn read_value<R>(&self, key: &str, f: impl FnOnce(Option<&DataType>) -> R) -> R {
+		let now = Utc::now();
+		match self.database().keyspace.data.get(key) {
+			Some(entry) if entry.ttl.is_none_or(|ttl| ttl >= now) => f(Some(&entry.value)),
+			_ => f(None),
+		}
+	}
 
-				heap.push(CacheEntryWithScore { key: key.clone(), score: total_priority_score });
+	// GET, the value of a string key as a client access
+	// 1. An expired entry is removed on the way and reported as missing
+	// 2. A live entry counts as accessed for eviction
+	pub fn get_value(&self, key: &str) -> Result<Option<Vec<u8>>, CacheError> {
+		let now = Utc::now();
+		let database = self.database();
+		{
+			let Some(mut entry) = database.keyspace.data.get_mut(key) else {
+				return Ok(None);
+			};
+			if !is_expired(entry.ttl, now) {
+				entry.touch(now);
+				return match entry.value.string_bytes() {
+					Some(bytes) => Ok(Some(bytes.into_owned())),
+					None => Err(CacheError::WrongType),
+				};
 			}
 		}
+		self.remove_expired(&database, key, now);
+		Ok(None)
+	}
 
-		if let Some(lowest) = heap.pop() {
-			self.data.remove(&lowest.key);
-			self.lru_queue.retain(|k| k != &lowest.key);
-		}
+	// SET, a string value with an optional time to live, whatever the key held before is dropped
+	pub fn set_value(&self, key: &str, value: Vec<u8>, opt: &Option<SetOptionalArgs>) -> Result<(), CacheError> {
+		let ttl = match opt {
+			Some(opt) => Some(Utc::now().checked_add_signed(opt.ttl).ok_or(CacheError::InvalidTtl)?),
+			None => None,
+		};
+		self.restore_value(key, DataType::String(value), ttl, true)
 	}
 
-	// Calculate the LRU weight
-	// 1. Calculate the time since last accessed
-	fn calculate_lru_weight(&self, entry: &CacheEntry) -> f64 {
+	pub fn contains_key(&self, key: &str) -> bool {
+		self.read_value(key, |value| value.is_some())
+	}
+
+	// CLUSTER GETKEYSINSLOT, at most `count` live keys that hash to `slot`
+	pub fn keys_in_slot(&self, slot: u16, count: usize) -> Vec<String> {
 		let now = Utc::now();
-		let duration_since_last_access = now - entry.last_accessed;
-		duration_since_last_access.num_milliseconds() as f64;
+		self.database().keyspace.data
+			.iter()
+			.filter(|entry| entry.ttl.is_none_or(|ttl| ttl >= now) && key_slot(entry.key()) == slot)
+			.map(|entry| entry.key().to_string())
+			.take(count)
+			.collect()
 	}
 
-	// Calculate the LFU weight using inverse of frequency
-	// Add 1.0 to avoid Divide by Zero
-	fn calculate_lfu_weight(&self, entry: &CacheEntry) -> f64 {
-		1.0 / (entry.frequency as f64 + 1.0)
+	// SCAN, about `count` keys from `cursor` on and the cursor of the next page, 0 once the keyspace was walked
+	// 1. Keys are taken in scan order, so a key present for the whole scan is returned exactly once
+	// 2. `pattern` and `kind` filter the page afterwards, a page may come back short or even empty
+	// 3. Expired keys are skipped
+	pub fn scan_keys(&self, cursor: u64, count: usize, pattern: Option<&str>, kind: Option<&str>) -> (u64, Vec<String>) {
+		let database = self.database();
+		let (next, page) = database.keyspace.index.scan(cursor, count);
+
+		let now = Utc::now();
+		let keys = page.into_iter()
+			.filter(|key| pattern.is_none_or(|pattern| glob_match(pattern, key)))
+			.filter(|key| match database.keyspace.data.get(key) {
+				Some(entry) => !is_expired(entry.ttl, now) && kind.is_none_or(|kind| entry.value.type_name().eq_ignore_ascii_case(kind)),
+				None => false,
+			})
+			.map(|key| key.to_string())
+			.collect();
+		(next, keys)
 	}
 
-	// Calculate the Predictive weight based on TTL
-	fn calculate_predictive_weight(&self, entry: &CacheEntry) -> f64 {
-		if let Some(ttl) = entry.ttl {
-			let now = Utc::now();
-			let time_until_expiry = ttl - now;
+	// KEYS, every live key matching `pattern` in one pass
+	pub fn keys_matching(&self, pattern: &str) -> Vec<String> {
+		let now = Utc::now();
+		self.database().keyspace.data
+			.iter()
+			.filter(|entry| !is_expired(entry.ttl, now) && glob_match(pattern, entry.key()))
+			.map(|entry| entry.key().to_string())
+			.collect()
+	}
 
-			if time_until_expiry.num_seconds() <= 0 {
-				return f64::MAX;
-			} else {
-				return 1.0 / (time_until_expiry.num_milliseconds() as f64);
+	// CLUSTER COUNTKEYSINSLOT
+	pub fn count_keys_in_slot(&self, slot: u16) -> usize {
+		let now = Utc::now();
+		self.database().keyspace.data.iter().filter(|entry| entry.ttl.is_none_or(|ttl| ttl >= now) && key_slot(entry.key()) == slot).count()
+	}
+
+	// Mutate a value in place, creating it when missing
+	// 1. Drop the entry first if it has already expired
+	// 2. Insert the `default` value if the key does not exist
+	// 3. Update the access meta and hand the value to the closure
+	pub fn update_value<R>(&self, key: &str, default: impl FnOnce() -> DataType, f: impl FnOnce(&mut DataType) -> R) -> R {
+		let now = Utc::now();
+		let database = self.database();
+		self.remove_expired(&database, key, now);
+
+		let room = self.make_room(&database, key);
+		let mut entry = match database.keyspace.data.entry(Arc::from(key)) {
+			Entry::Occupied(occupied) => {
+				self.snapshot.before_write(database.id, key, occupied.get());
+				occupied.into_ref()
 			}
-		}
-		// if there's no TTL, we assume it's not expiring soon
-		0.0
-	}  
+			Entry::Vacant(vacant) => {
+				let entry = CacheEntry::new(default());
+				self.snapshot.before_insert(&entry);
+				database.keyspace.fill(vacant, entry, room)
+			}
+		};
+		entry.touch(now);
+		f(&mut entry.value)
+	}
 
-	// Check if key is expired based on ttl
-	fn is_key_expired(&self, key: &str) -> Result<bool, CacheError> {
+	// Mutate a live value in place, None if the key does not exist
+	// The entry stays locked across the closure, so checks and writes inside it can't race
+	pub fn update_existing<R>(&self, key: &str, f: impl FnOnce(&mut DataType) -> R) -> Option<R> {
 		let now = Utc::now();
+		let database = self.database();
+		self.remove_expired(&database, key, now);
 
-		if let Some(entry) = self.data.get(key) {
-			if let Some(ttl) = entry.ttl {
-				return Ok(ttl < now);
+		let mut entry = database.keyspace.data.get_mut(key)?;
+		self.snapshot.before_write(database.id, key, &entry);
+		entry.touch(now);
+		Some(f(&mut entry.value))
+	}
+
+	// Overwrite a key with a fresh entry without TTL (like a plain SET)
+	pub fn replace_value(&self, key: &str, value: DataType) {
+		self.insert_entry(&self.database(), key, CacheEntry::new(value));
+	}
+
+	// Remove a single key, returns true if it existed
+	pub fn remove_value(&self, key: &str) -> bool {
+		self.remove_from(&self.database(), key)
+	}
+
+	// Remove a single key if `f` accepts its value, returns true if it was removed
+	pub fn remove_value_if(&self, key: &str, f: impl FnOnce(&DataType) -> bool) -> bool {
+		let database = self.database();
+		self.remove_expired(&database, key, Utc::now());
+
+		let removed = database.keyspace.remove_if(key, |key, entry| {
+			if !f(&entry.value) {
+				return false;
+			}
+			self.snapshot.before_write(database.id, key, entry);
+			true
+		});
+		removed.is_some()
+	}
+
+	// Create a key with an optional deadline (RESTORE)
+	// 1. A live key is only overwritten with `replace`, an expired one counts as missing
+	// 2. A deadline that has already passed deletes the key instead
+	pub fn restore_value(&self, key: &str, value: DataType, ttl: Option<DateTime<Utc>>, replace: bool) -> Result<(), CacheError> {
+		let now = Utc::now();
+		let database = self.database();
+		let expired = is_expired(ttl, now);
+		let mut entry = CacheEntry::new(value);
+		entry.ttl = ttl;
+		let room = (!expired).then(|| self.make_room(&database, key));
+
+		match database.keyspace.data.entry(Arc::from(key)) {
+			Entry::Occupied(mut occupied) => {
+				if !replace && occupied.get().ttl.is_none_or(|ttl| ttl >= now) {
+					return Err(CacheError::BusyKey);
+				}
+				if expired {
+					self.snapshot.before_write(database.id, key, occupied.get());
+					database.keyspace.remove_occupied(occupied);
+				} else {
+					self.snapshot.before_replace(database.id, key, occupied.get(), &entry);
+					occupied.insert(entry);
+				}
+			}
+			Entry::Vacant(vacant) => {
+				if let Some(room) = room {
+					self.snapshot.before_insert(&entry);
+					database.keyspace.fill(vacant, entry, room);
+				}
 			}
-			return Ok(false);
 		}
+		Ok(())
+	}
 
-		Err(CacheError::KeyNotFound(key.to_string()))
+	// DBSIZE, keys that expired but were not removed yet still count
+	pub fn db_size(&self) -> usize {
+		self.database().keyspace.data.len()
 	}
 
-	// Delete Cache Entries from CalodStore and LRU -> Iterate and delete
-	fn delete(&mut self, keys: Vec<&str>) -> Result<u64, CacheError> {
-		let mut delete_count = 0;
-		
+	// FLUSHDB, remove every key of the selected database, preserving them for a running snapshot
+	pub fn flush_db(&self) {
+		self.clear_database(&self.database());
+	}
+
+	// FLUSHALL
+	pub fn flush_all(&self) {
+		for database in self.databases() {
+			self.clear_database(&database);
+		}
+	}
+
+	fn clear_database(&self, database: &Database) {
+		let keys: Vec<Arc<str>> = database.keyspace.data.iter().map(|entry| entry.key().clone()).collect();
 		for key in keys {
-			if self.data.remove(key).is_some() {
-				self.lru_queue.retain(|k| k != key);
-				delete_count += 1;
+			self.remove_entry(database, &key);
+		}
+	}
+
+	// MOVE, hand a live key over to database `index`, false if it is missing here or exists there
+	pub fn move_key(&self, key: &str, index: usize) -> Result<bool, CacheError> {
+		if index == self.db {
+			return Err(CacheError::InvalidArgument(String::from("source and destination objects are the same")));
+		}
+		let target = self.database_at(index).ok_or(CacheError::DbIndexOutOfRange)?;
+
+		let Some(entry) = self.take_entry(key) else {
+			return Ok(false);
+		};
+		match self.insert_vacant(&target, key, entry) {
+			Ok(()) => Ok(true),
+			Err(entry) => {
+				self.return_entry(key, entry);
+				Ok(false)
 			}
 		}
+	}
 
-		Ok(delete_count)
+	// UNLINK, remove a live key right away and leave freeing a large value to the lazy free thread
+	pub fn unlink_value(&self, key: &str) -> bool {
+		let Some(entry) = self.take_entry(key) else {
+			return false;
+		};
+		lazy_free::free(entry.value);
+		true
 	}
 
-	// Invalidate keys form the cache
-	// 1. Iterate through the cache entries, checking the TTL and add them to the vec
-	// 2. Delete them for fuck sake!
-	fn invalidate(&self) {
-		let now = Utc::now();
-		let mut keys_to_remove = Vec::new();
+	// TYPE, `None` for a missing key
+	pub fn type_of(&self, key: &str) -> Option<&'static str> {
+		self.read_value(key, |value| value.map(DataType::type_name))
+	}
 
-		for entry in self.data.iter() {
-			if let Some(expire_at) = entry.ttl {
-				if expire_at < now {
-					keys_to_remove.push(entry.key().clone());
-				}
+	// TOUCH, count a live key as accessed, returns false if it does not exist
+	pub fn touch_key(&self, key: &str) -> bool {
+		let now = Utc::now();
+		let database = self.database();
+		let touched = match database.keyspace.data.get_mut(key) {
+			Some(mut entry) if !is_expired(entry.ttl, now) => {
+				entry.touch(now);
+				true
 			}
+			_ => false,
+		};
+		touched
+	}
+
+	// RENAME and RENAMENX, the entry keeps its deadline
+	// 1. Without `replace` nothing changes if `to` exists, false is returned then
+	// 2. With `replace` the old value of `to` is dropped
+	pub fn rename_key(&self, from: &str, to: &str, replace: bool) -> Result<bool, CacheError> {
+		if from == to {
+			return if self.contains_key(from) { Ok(replace) } else { Err(CacheError::NoSuchKey) };
 		}
+		let database = self.database();
+		let entry = self.take_entry(from).ok_or(CacheError::NoSuchKey)?;
 
-		for key in keys_to_remove {
-			self.data.remove(&key);
-			println!("Invalidation: Key {} has been removed.", key);
+		if replace {
+			self.insert_entry(&database, to, entry);
+			return Ok(true);
+		}
+		match self.insert_vacant(&database, to, entry) {
+			Ok(()) => Ok(true),
+			Err(entry) => {
+				self.return_entry(from, entry);
+				Ok(false)
+			}
 		}
 	}
 
-	pub fn increment_request_count(&mut self) {
-		self.request_count += 1;
-	}
+	// COPY to `to` in the database at `index`, the copy keeps the deadline of the source
+	// Returns false if the source is missing, or `to` exists and `replace` is off
+	pub fn copy_key(&self, from: &str, to: &str, index: usize, replace: bool) -> Result<bool, CacheError> {
+		if from == to && index == self.db {
+			return Err(CacheError::InvalidArgument(String::from("source and destination objects are the same")));
+		}
+		let target = self.database_at(index).ok_or(CacheError::DbIndexOutOfRange)?;
 
-	pub fn get_stats(&self) -> String {
-		let uptime = self.start_time.elapsed();
-		let response_time_avg = self.calculate_avg_response_time();
+		// The source shard is unlocked again before the target is written, both may be the same
+		let now = Utc::now();
+		let database = self.database();
+		let source = database.keyspace.data.get(from).filter(|entry| !is_expired(entry.ttl, now)).map(|entry| (entry.value.clone(), entry.ttl));
+		let Some((value, ttl)) = source else {
+			return Ok(false);
+		};
+		let mut entry = CacheEntry::new(value);
+		entry.ttl = ttl;
 
-		format!("Uptime: {:?}\nRequests Handled: {}\nAverage Response Time: {:?}", uptime, self.request_count, response_time_avg)
+		if replace {
+			self.insert_entry(&target, to, entry);
+			return Ok(true);
+		}
+		Ok(self.insert_vacant(&target, to, entry).is_ok())
 	}
-}
 
-impl CalodStore {
-	pub fn reset() {
-		unsafe {
-			let mut store = STORE.write().unwrap();
-			if let Some(mut s) = store.take() {
-				s.data.clear();
-				println!("Store is reset.");
-			} else {
-				println!("Store is already reset.");
+	// RANDOMKEY, a live key of the selected database or `None` if there is none
+	// Draws a random point of the key index and takes the key at or after it, wrapping around
+	pub fn random_key(&self) -> Option<String> {
+		let database = self.database();
+		let now = Utc::now();
+		for _ in 0..RANDOM_KEY_ATTEMPTS {
+			let candidate = database.keyspace.index.random()?;
+			// The index lock is released before a shard is locked, writers take them the other way around
+			if database.keyspace.data.get(&candidate).is_some_and(|entry| !is_expired(entry.ttl, now)) {
+				return Some(candidate.to_string());
 			}
 		}
+		None
+	}
+
+	// SWAPDB, clients on either index see the other database from now on
+	// A snapshot maps its databases to indexes once, so a swap is refused while one is running
+	// Clients wait for it to finish before their SWAPDB runs, see `handle_request`
+	pub fn swap_databases(&self, first: usize, second: usize) -> Result<(), CacheError> {
+		if first.max(second) >= self.database_count() {
+			return Err(CacheError::DbIndexOutOfRange);
+		}
+		let Some(_swap) = self.snapshot.lock_databases() else {
+			return Err(CacheError::SnapshotInProgress);
+		};
+		self.databases.write().unwrap().swap(first, second);
+		Ok(())
+	}
+
+	// Copy of a live entry, used to send it elsewhere
+	pub(crate) fn clone_entry(&self, key: &str) -> Option<CacheEntry> {
+		let now = Utc::now();
+		self.database().keyspace.data.get(key).filter(|entry| entry.ttl.is_none_or(|ttl| ttl >= now)).map(|entry| entry.clone())
+	}
+
+	// Remove a live entry and hand it over, see `return_entry` to undo
+	pub(crate) fn take_entry(&self, key: &str) -> Option<CacheEntry> {
+		let database = self.database();
+		let entry = self.remove_entry(&database, key)?;
+		entry.ttl.is_none_or(|ttl| ttl >= Utc::now()).then_some(entry)
+	}
+
+	// Put back an entry from `take_entry`, unless the key was written in the meantime
+	pub(crate) fn return_entry(&self, key: &str, entry: CacheEntry) {
+		let database = self.database();
+		let Entry::Vacant(vacant) = database.keyspace.data.entry(Arc::from(key)) else {
+			return;
+		};
+		self.snapshot.before_insert(&entry);
+		database.keyspace.fill_over_capacity(vacant, entry);
+	}
+
+	// Load a binary snapshot into the store, returns the number of keys loaded
+	// 1. Stream the entries one by one instead of reading the whole file
+	// 2. Keys that expired while the store was down are skipped
+	// 3. A checksum or format error aborts the load
+	pub fn load_from_file(&self, file_path: &str) -> Result<u64, SnapshotError> {
+		let file = self.storage().open(file_path)?;
+		let mut reader = SnapshotReader::new(BufReader::with_capacity(SNAPSHOT_BUFFER_SIZE, file))?;
+		self.load_snapshot_entries(&mut reader)
 	}
 
-	pub fn load_from_file(file_path: &str) -> Result<Self> {
-		let file_content = fs::read_to_string(file_path)?;
-		let store: CalodStore = serde_json::from_str(&file_content)?;
-		Ok(store)
+	// Entries go to the database the snapshot lists them under
+	pub(crate) fn load_snapshot_entries<R: Read>(&self, reader: &mut SnapshotReader<R>) -> Result<u64, SnapshotError> {
+		load_entries(reader, &self.databases())
 	}
 
-	pub fn save_to_file(&self, file_path: &str) -> Result<()> {
-		let json_data = serde_json::to_string(self)?;
-		fs::write(file_path, json_data)?;
+	// Load a snapshot into new databases that take the place of the current ones once it checked out
+	// Returns the number of keys loaded, the swap waits for the running snapshot like SWAPDB
+	pub(crate) fn replace_with_snapshot<R: Read>(&self, reader: &mut SnapshotReader<R>) -> Result<u64, SnapshotError> {
+		let databases: Vec<Arc<Database>> = (0..self.database_count()).map(|_| Arc::new(Database::new())).collect();
+		let loaded = load_entries(reader, &databases)?;
+
+		// The old databases are dropped once no lock is held
+		let _old = {
+			let _swap = self.snapshot.lock_databases();
+			std::mem::replace(&mut *self.databases.write().unwrap(), databases)
+		};
+		Ok(loaded)
+	}
+
+	// Visit every entry of the selected database until `f` fails, the shard of the current entry stays read locked during `f`
+	pub(crate) fn try_for_each_entry<E>(&self, mut f: impl FnMut(&str, &CacheEntry) -> Result<(), E>) -> Result<(), E> {
+		for entry in self.database().keyspace.data.iter() {
+			f(entry.key(), entry.value())?;
+		}
 		Ok(())
 	}
-}
\ No newline at end of file
+
+	// Add an entry read from a file to the selected database, loading does not count as a write
+	pub(crate) fn load_entry(&self, key: String, entry: CacheEntry) {
+		self.database().load_entry(key, entry);
+	}
+
+	// Write a point-in-time snapshot of the store, returns the number of keys saved
+	pub fn save_to_file(&self, file_path: &str) -> Result<u64, SnapshotError> {
+		let epoch = self.snapshot.begin()?;
+		self.write_snapshot(file_path, epoch)
+	}
+
+	// Write the snapshot of an epoch opened with `SnapshotState::begin` and close the epoch
+	pub(crate) fn write_snapshot(&self, file_path: &str, epoch: u64) -> Result<u64, SnapshotError> {
+		let started_at = Utc::now();
+		let temp_path = format!("{}.tmp-{}", file_path, std::process::id());
+
+		let result = self.write_snapshot_file(&temp_path, epoch, started_at)
+			.and_then(|saved| self.storage().rename(&temp_path, file_path).map(|_| saved).map_err(SnapshotError::from));
+		if result.is_err() {
+			let _ = self.storage().remove(&temp_path);
+		}
+
+		self.snapshot.end(result.is_ok());
+		result
+	}
+
+	// Stream the snapshot into `temp_path` and fsync it before the caller renames the file into place
+	pub(crate) fn write_snapshot_file(&self, temp_path: &str, epoch: u64, started_at: DateTime<Utc>) -> Result<u64, SnapshotError> {
+		let file = self.storage().create(temp_path)?;
+		let (saved, writer) = self.write_snapshot_to(BufWriter::with_capacity(SNAPSHOT_BUFFER_SIZE, file), epoch, started_at)?;
+		let mut file = writer.into_inner().map_err(|e| e.into_error())?;
+		file.sync()?;
+		Ok(saved)
+	}
+
+	// Write the snapshot of an epoch into any writer, returns the number of keys and the writer
+	// 1. Write every live entry the epoch can still claim, database by database, concurrent writes preserve theirs first
+	// 2. Append the pre-images preserved by writes during the pass under the index of their database
+	pub(crate) fn write_snapshot_to<W: Write>(&self, out: W, epoch: u64, started_at: DateTime<Utc>) -> Result<(u64, W), SnapshotError> {
+		let mut writer = SnapshotWriter::new(out)?;
+		let is_live = |entry: &CacheEntry| entry.ttl.is_none_or(|ttl| ttl >= started_at);
+		let databases = self.databases();
+
+		// Entries are claimed and copied a shard at a time, so writers never wait on the file
+		for (index, database) in databases.iter().enumerate() {
+			for shard in 0..database.keyspace.data.shards().len() {
+				let entries = database.copy_shard(shard, |entry| self.snapshot.claim(entry, epoch) && is_live(entry));
+				for (key, entry) in entries {
+					writer.select_db(index)?;
+					writer.write_entry(&key, &entry)?;
+				}
+			}
+		}
+		let mut preserved: Vec<(usize, String, CacheEntry)> = self.snapshot.take_preserved().into_iter()
+			.filter_map(|(id, key, entry)| databases.iter().position(|database| database.id == id).map(|index| (index, key, entry)))
+			.collect();
+		preserved.sort_by_key(|(index, _, _)| *index);
+		for (index, key, entry) in preserved {
+			if is_live(&entry) {
+				writer.select_db(index)?;
+				writer.write_entry(&key, &entry)?;
+			}
+		}
+
+		let saved = writer.entries();
+		Ok((saved, writer.finish()?))
+	}
+}
+
+// Skip the keys that expired since the snapshot was taken
+fn load_entries<R: Read>(reader: &mut SnapshotReader<R>, databases: &[Arc<Database>]) -> Result<u64, SnapshotError> {
+	let now = Utc::now();
+	let mut loaded = 0;
+
+	while let Some((key, entry)) = reader.next_entry()? {
+		if is_expired(entry.ttl, now) {
+			continue;
+		}
+		let database = databases.get(reader.db()).ok_or(SnapshotError::DatabaseOutOfRange(reader.db()))?;
+		database.load_entry(key, entry);
+		loaded += 1;
+	}
+	Ok(loaded)
+}
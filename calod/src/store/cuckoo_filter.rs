@@ -0,0 +1,309 @@
+use crate::persistence::encoding::{put_bytes, put_u64, put_varint, Decoder};
+use crate::persistence::snapshot::SnapshotError;
+use crate::store::calod_data::DataType;
+use crate::store::calod_store::{CacheError, CalodStore};
+use crate::store::hyperloglog::murmurhash64a;
+
+// Defaults used when CF.ADD creates the filter implicitly, same as RedisBloom
+pub const CUCKOO_DEFAULT_CAPACITY: u64 = 1024;
+pub const CUCKOO_DEFAULT_BUCKET_SIZE: usize = 2;
+pub const CUCKOO_DEFAULT_MAX_ITERATIONS: u32 = 20;
+pub const CUCKOO_DEFAULT_EXPANSION: u32 = 1;
+
+// Upper bounds for CF.RESERVE, a layer never grows past `CUCKOO_MAX_CAPACITY` fingerprints
+pub const CUCKOO_MAX_CAPACITY: u64 = 1 << 30;
+pub const CUCKOO_MAX_BUCKET_SIZE: usize = 255;
+pub const CUCKOO_MAX_ITERATIONS: u32 = 65535;
+pub const CUCKOO_MAX_EXPANSION: u32 = 32768;
+
+// An empty slot, fingerprints are never zero
+const EMPTY_SLOT: u8 = 0;
+
+#[derive(Debug, Clone, PartialEq)]
+struct CuckooLayer {
+	// `num_buckets * bucket_size` fingerprints, bucket after bucket
+	slots: Vec<u8>,
+	num_buckets: u64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CuckooFilter {
+	layers: Vec<CuckooLayer>,
+	capacity: u64,
+	bucket_size: usize,
+	max_iterations: u32,
+	expansion: u32,
+	count: u64,
+	// xorshift state used to pick the victim slot when kicking out fingerprints
+	kick_state: u64,
+}
+
+// Derive the 8 bit fingerprint and the primary bucket hash of an item
+fn fingerprint_and_hash(item: &[u8]) -> (u8, u64) {
+	let hash = murmurhash64a(item);
+	let fingerprint = (hash >> 56) as u8 % 255 + 1;
+	(fingerprint, hash)
+}
+
+impl CuckooLayer {
+	fn new(capacity: u64, bucket_size: usize) -> Self {
+		// A power of two keeps the alternate bucket computation an involution
+		let num_buckets = (capacity / bucket_size as u64).max(1).next_power_of_two();
+		CuckooLayer {
+			slots: vec![EMPTY_SLOT; num_buckets as usize * bucket_size],
+			num_buckets,
+		}
+	}
+
+	fn primary_bucket(&self, hash: u64) -> u64 {
+		hash & (self.num_buckets - 1)
+	}
+
+	// The alternate bucket only depends on the current bucket and the fingerprint,
+	// so a kicked out fingerprint can always find its other home
+	fn alternate_bucket(&self, bucket: u64, fingerprint: u8) -> u64 {
+		(bucket ^ murmurhash64a(&[fingerprint])) & (self.num_buckets - 1)
+	}
+
+	fn bucket(&self, bucket: u64, bucket_size: usize) -> &[u8] {
+		let start = bucket as usize * bucket_size;
+		&self.slots[start..start + bucket_size]
+	}
+
+	fn bucket_mut(&mut self, bucket: u64, bucket_size: usize) -> &mut [u8] {
+		let start = bucket as usize * bucket_size;
+		&mut self.slots[start..start + bucket_size]
+	}
+
+	fn contains(&self, fingerprint: u8, hash: u64, bucket_size: usize) -> bool {
+		let first = self.primary_bucket(hash);
+		let second = self.alternate_bucket(first, fingerprint);
+		self.bucket(first, bucket_size).contains(&fingerprint) || self.bucket(second, bucket_size).contains(&fingerprint)
+	}
+
+	fn try_insert(&mut self, bucket: u64, fingerprint: u8, bucket_size: usize) -> bool {
+		match self.bucket_mut(bucket, bucket_size).iter_mut().find(|slot| **slot == EMPTY_SLOT) {
+			Some(slot) => {
+				*slot = fingerprint;
+				true
+			}
+			None => false,
+		}
+	}
+
+	fn remove(&mut self, fingerprint: u8, hash: u64, bucket_size: usize) -> bool {
+		let first = self.primary_bucket(hash);
+		let second = self.alternate_bucket(first, fingerprint);
+
+		for bucket in [first, second] {
+			if let Some(slot) = self.bucket_mut(bucket, bucket_size).iter_mut().find(|slot| **slot == fingerprint) {
+				*slot = EMPTY_SLOT;
+				return true;
+			}
+		}
+		false
+	}
+}
+
+impl CuckooFilter {
+	pub fn new(capacity: u64, bucket_size: usize, max_iterations: u32, expansion: u32) -> Self {
+		CuckooFilter {
+			layers: vec![CuckooLayer::new(capacity, bucket_size)],
+			capacity,
+			bucket_size,
+			max_iterations,
+			expansion,
+			count: 0,
+			kick_state: 0x9e3779b97f4a7c15,
+		}
+	}
+
+	pub fn len(&self) -> u64 {
+		self.count
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.count == 0
+	}
+
+	pub fn num_layers(&self) -> usize {
+		self.layers.len()
+	}
+
+	pub fn contains(&self, item: &[u8]) -> bool {
+		let (fingerprint, hash) = fingerprint_and_hash(item);
+		self.layers.iter().any(|l| l.contains(fingerprint, hash, self.bucket_size))
+	}
+
+	fn next_victim(&mut self) -> usize {
+		self.kick_state ^= self.kick_state << 13;
+		self.kick_state ^= self.kick_state >> 7;
+		self.kick_state ^= self.kick_state << 17;
+		(self.kick_state % self.bucket_size as u64) as usize
+	}
+
+	// Insert an item, duplicates are stored again so they can be deleted independently
+	// 1. Look for a free slot in the candidate buckets of every layer
+	// 2. Otherwise kick random fingerprints of the newest layer to their alternate bucket,
+	//    up to `max_iterations` times
+	// 3. If that fails, undo the kicks, grow a new layer and store the item there
+	pub fn add(&mut self, item: &[u8]) -> Result<(), CacheError> {
+		let (fingerprint, hash) = fingerprint_and_hash(item);
+		let bucket_size = self.bucket_size;
+
+		for layer in self.layers.iter_mut() {
+			let first = layer.primary_bucket(hash);
+			let second = layer.alternate_bucket(first, fingerprint);
+			if layer.try_insert(first, fingerprint, bucket_size) || layer.try_insert(second, fingerprint, bucket_size) {
+				self.count += 1;
+				return Ok(());
+			}
+		}
+
+		if self.kick_insert(fingerprint, hash) {
+			self.count += 1;
+			return Ok(());
+		}
+
+		if self.expansion == 0 {
+			return Err(CacheError::InvalidArgument(String::from("Filter is full")));
+		}
+
+		let capacity = match (self.expansion as u64).checked_pow(self.layers.len() as u32).and_then(|factor| self.capacity.checked_mul(factor)) {
+			Some(capacity) if capacity <= CUCKOO_MAX_CAPACITY => capacity,
+			_ => return Err(CacheError::InvalidArgument(String::from("Maximum filter size reached"))),
+		};
+		let mut layer = CuckooLayer::new(capacity, bucket_size);
+		let home = layer.primary_bucket(hash);
+		layer.try_insert(home, fingerprint, bucket_size);
+		self.layers.push(layer);
+
+		self.count += 1;
+		Ok(())
+	}
+
+	// Make room in the newest layer by relocating fingerprints, restoring it on failure
+	fn kick_insert(&mut self, mut fingerprint: u8, hash: u64) -> bool {
+		let bucket_size = self.bucket_size;
+		let mut path: Vec<(u64, usize)> = Vec::new();
+		let mut bucket = self.layers.last().unwrap().primary_bucket(hash);
+
+		for _ in 0..self.max_iterations {
+			let victim = self.next_victim();
+			let layer = self.layers.last_mut().unwrap();
+			std::mem::swap(&mut layer.bucket_mut(bucket, bucket_size)[victim], &mut fingerprint);
+			path.push((bucket, victim));
+
+			bucket = layer.alternate_bucket(bucket, fingerprint);
+			if layer.try_insert(bucket, fingerprint, bucket_size) {
+				return true;
+			}
+		}
+
+		let layer = self.layers.last_mut().unwrap();
+		for (bucket, victim) in path.into_iter().rev() {
+			std::mem::swap(&mut layer.bucket_mut(bucket, bucket_size)[victim], &mut fingerprint);
+		}
+		false
+	}
+
+	// Remove one copy of an item, newest layers first
+	pub fn remove(&mut self, item: &[u8]) -> bool {
+		let (fingerprint, hash) = fingerprint_and_hash(item);
+		let bucket_size = self.bucket_size;
+
+		for layer in self.layers.iter_mut().rev() {
+			if layer.remove(fingerprint, hash, bucket_size) {
+				self.count -= 1;
+				return true;
+			}
+		}
+		false
+	}
+
+	// Snapshot encoding: filter parameters followed by the fingerprint slots of every layer
+	pub fn encode(&self, out: &mut Vec<u8>) {
+		put_varint(out, self.capacity);
+		put_varint(out, self.bucket_size as u64);
+		put_varint(out, self.max_iterations as u64);
+		put_varint(out, self.expansion as u64);
+		put_varint(out, self.count);
+		put_u64(out, self.kick_state);
+		put_varint(out, self.layers.len() as u64);
+		for layer in &self.layers {
+			put_varint(out, layer.num_buckets);
+			put_bytes(out, &layer.slots);
+		}
+	}
+
+	pub fn decode(decoder: &mut Decoder) -> Result<Self, SnapshotError> {
+		let invalid = || SnapshotError::Corrupt(String::from("invalid cuckoo filter"));
+		let capacity = decoder.varint()?;
+		let bucket_size = usize::try_from(decoder.varint()?).map_err(|_| invalid())?;
+		let max_iterations = u32::try_from(decoder.varint()?).map_err(|_| invalid())?;
+		let expansion = u32::try_from(decoder.varint()?).map_err(|_| invalid())?;
+		let count = decoder.varint()?;
+		let kick_state = decoder.u64()?;
+
+		let num_layers = decoder.varint()?;
+		let mut layers = Vec::new();
+		for _ in 0..num_layers {
+			let num_buckets = decoder.varint()?;
+			let slots = decoder.bytes()?.to_vec();
+			if !num_buckets.is_power_of_two() || num_buckets.checked_mul(bucket_size as u64) != Some(slots.len() as u64) {
+				return Err(invalid());
+			}
+			layers.push(CuckooLayer { slots, num_buckets });
+		}
+
+		if layers.is_empty() || bucket_size == 0 || bucket_size > CUCKOO_MAX_BUCKET_SIZE || kick_state == 0 {
+			return Err(invalid());
+		}
+		Ok(CuckooFilter { layers, capacity, bucket_size, max_iterations, expansion, count, kick_state })
+	}
+}
+
+fn default_cuckoo_filter() -> DataType {
+	DataType::CuckooFilter(CuckooFilter::new(CUCKOO_DEFAULT_CAPACITY, CUCKOO_DEFAULT_BUCKET_SIZE, CUCKOO_DEFAULT_MAX_ITERATIONS, CUCKOO_DEFAULT_EXPANSION))
+}
+
+impl CalodStore {
+	pub fn cf_reserve(&self, key: &str, capacity: u64, bucket_size: usize, max_iterations: u32, expansion: u32) -> Result<(), CacheError> {
+		if self.read_value(key, |value| value.is_some()) {
+			return Err(CacheError::InvalidArgument(String::from("item exists")));
+		}
+		self.replace_value(key, DataType::CuckooFilter(CuckooFilter::new(capacity, bucket_size, max_iterations, expansion)));
+		Ok(())
+	}
+
+	pub fn cf_add(&self, key: &str, item: &str) -> Result<(), CacheError> {
+		self.update_value(key, default_cuckoo_filter, |value| match value {
+			DataType::CuckooFilter(filter) => filter.add(item.as_bytes()),
+			_ => Err(CacheError::WrongType),
+		})
+	}
+
+	pub fn cf_del(&self, key: &str, item: &str) -> Result<bool, CacheError> {
+		let exists = self.read_value(key, |value| match value {
+			Some(DataType::CuckooFilter(_)) => Ok(true),
+			Some(_) => Err(CacheError::WrongType),
+			None => Ok(false),
+		})?;
+		if !exists {
+			return Err(CacheError::InvalidArgument(String::from("Not found")));
+		}
+
+		self.update_value(key, default_cuckoo_filter, |value| match value {
+			DataType::CuckooFilter(filter) => Ok(filter.remove(item.as_bytes())),
+			_ => Err(CacheError::WrongType),
+		})
+	}
+
+	pub fn cf_exists(&self, key: &str, item: &str) -> Result<bool, CacheError> {
+		self.read_value(key, |value| match value {
+			Some(DataType::CuckooFilter(filter)) => Ok(filter.contains(item.as_bytes())),
+			Some(_) => Err(CacheError::WrongType),
+			None => Ok(false),
+		})
+	}
+}
@@ -0,0 +1,406 @@
+use std::cmp::Ordering;
+
+use crate::store::calod_data::DataType;
+use crate::store::calod_store::{CacheError, CalodStore};
+use crate::store::sorted_set::SortedSet;
+
+// Limits of the EPSG:900913 / Web Mercator projection used by Redis
+pub const GEO_LAT_MIN: f64 = -85.05112878;
+pub const GEO_LAT_MAX: f64 = 85.05112878;
+pub const GEO_LONG_MIN: f64 = -180.0;
+pub const GEO_LONG_MAX: f64 = 180.0;
+
+// 26 bits per coordinate, the interleaved 52 bit hash is exact in a f64 score
+pub const GEO_STEP_MAX: u32 = 26;
+
+const EARTH_RADIUS_IN_METERS: f64 = 6372797.560856;
+const MERCATOR_MAX: f64 = 20037726.37;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GeoUnit {
+	Meters,
+	Kilometers,
+	Miles,
+	Feet,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum GeoOrigin {
+	Member(String),
+	LonLat(f64, f64),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GeoShape {
+	// Radius in meters
+	Radius(f64),
+	// Width and height in meters
+	Box(f64, f64),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct GeoSearch {
+	pub origin: GeoOrigin,
+	pub shape: GeoShape,
+	pub unit: GeoUnit,
+	pub order: Option<Ordering>,
+	pub count: Option<usize>,
+	pub any: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct GeoMatch {
+	pub member: String,
+	// Distance from the origin, in the unit of the search
+	pub distance: f64,
+	pub hash: u64,
+	pub longitude: f64,
+	pub latitude: f64,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct GeoAddOptions {
+	pub nx: bool,
+	pub xx: bool,
+	pub ch: bool,
+}
+
+impl GeoUnit {
+	pub fn from(str: &str) -> Option<GeoUnit> {
+		match str.to_lowercase().as_str() {
+			"m" => Some(GeoUnit::Meters),
+			"km" => Some(GeoUnit::Kilometers),
+			"mi" => Some(GeoUnit::Miles),
+			"ft" => Some(GeoUnit::Feet),
+			_ => None,
+		}
+	}
+
+	pub fn meters(&self) -> f64 {
+		match self {
+			GeoUnit::Meters => 1.0,
+			GeoUnit::Kilometers => 1000.0,
+			GeoUnit::Miles => 1609.34,
+			GeoUnit::Feet => 0.3048,
+		}
+	}
+}
+
+pub fn is_valid_coordinate(longitude: f64, latitude: f64) -> bool {
+	(GEO_LONG_MIN..=GEO_LONG_MAX).contains(&longitude) && (GEO_LAT_MIN..=GEO_LAT_MAX).contains(&latitude)
+}
+
+// Spread the low 32 bits of `x` to the even bit positions
+fn spread_bits(x: u32) -> u64 {
+	let mut x = x as u64;
+	x = (x | (x << 16)) & 0x0000ffff0000ffff;
+	x = (x | (x << 8)) & 0x00ff00ff00ff00ff;
+	x = (x | (x << 4)) & 0x0f0f0f0f0f0f0f0f;
+	x = (x | (x << 2)) & 0x3333333333333333;
+	(x | (x << 1)) & 0x5555555555555555
+}
+
+fn squash_bits(x: u64) -> u32 {
+	let mut x = x & 0x5555555555555555;
+	x = (x | (x >> 1)) & 0x3333333333333333;
+	x = (x | (x >> 2)) & 0x0f0f0f0f0f0f0f0f;
+	x = (x | (x >> 4)) & 0x00ff00ff00ff00ff;
+	x = (x | (x >> 8)) & 0x0000ffff0000ffff;
+	((x | (x >> 16)) & 0x00000000ffffffff) as u32
+}
+
+// Latitude bits go to the even positions, longitude bits to the odd ones
+fn interleave(lat_index: u32, lon_index: u32) -> u64 {
+	spread_bits(lat_index) | (spread_bits(lon_index) << 1)
+}
+
+fn deinterleave(hash: u64) -> (u32, u32) {
+	(squash_bits(hash), squash_bits(hash >> 1))
+}
+
+// Grid cell of a coordinate at `step` bits of precision per axis
+fn cell_indexes(longitude: f64, latitude: f64, step: u32) -> (u32, u32) {
+	let cells = (1u64 << step) as f64;
+	let lat = ((latitude - GEO_LAT_MIN) / (GEO_LAT_MAX - GEO_LAT_MIN) * cells) as u64;
+	let lon = ((longitude - GEO_LONG_MIN) / (GEO_LONG_MAX - GEO_LONG_MIN) * cells) as u64;
+	(lat.min((1u64 << step) - 1) as u32, lon.min((1u64 << step) - 1) as u32)
+}
+
+// Encode a coordinate into the 52 bit geohash used as sorted set score
+pub fn geohash_encode(longitude: f64, latitude: f64) -> u64 {
+	let (lat, lon) = cell_indexes(longitude, latitude, GEO_STEP_MAX);
+	interleave(lat, lon)
+}
+
+// Decode a 52 bit geohash into the center of its cell, as (longitude, latitude)
+pub fn geohash_decode(hash: u64) -> (f64, f64) {
+	let (lat, lon) = deinterleave(hash);
+	let cells = (1u64 << GEO_STEP_MAX) as f64;
+	let lat_unit = (GEO_LAT_MAX - GEO_LAT_MIN) / cells;
+	let lon_unit = (GEO_LONG_MAX - GEO_LONG_MIN) / cells;
+
+	let latitude = GEO_LAT_MIN + (lat as f64 + 0.5) * lat_unit;
+	let longitude = GEO_LONG_MIN + (lon as f64 + 0.5) * lon_unit;
+	(longitude.clamp(GEO_LONG_MIN, GEO_LONG_MAX), latitude.clamp(GEO_LAT_MIN, GEO_LAT_MAX))
+}
+
+// Great-circle distance in meters (haversine)
+pub fn geo_distance(lon1: f64, lat1: f64, lon2: f64, lat2: f64) -> f64 {
+	let (lat1r, lat2r) = (lat1.to_radians(), lat2.to_radians());
+	let u = ((lat2r - lat1r) / 2.0).sin();
+	let v = ((lon2 - lon1).to_radians() / 2.0).sin();
+	2.0 * EARTH_RADIUS_IN_METERS * (u * u + lat1r.cos() * lat2r.cos() * v * v).sqrt().asin()
+}
+
+// Pick a precision whose cells are about as large as the search radius
+fn estimate_step_by_radius(mut range: f64, latitude: f64) -> u32 {
+	if range == 0.0 {
+		return GEO_STEP_MAX;
+	}
+	let mut step: i32 = 1;
+	while range < MERCATOR_MAX {
+		range *= 2.0;
+		step += 1;
+	}
+	step -= 2;
+
+	// Cells shrink towards the poles, compensate with a coarser precision
+	if !(-66.0..=66.0).contains(&latitude) {
+		step -= 1;
+		if !(-80.0..=80.0).contains(&latitude) {
+			step -= 1;
+		}
+	}
+	step.clamp(1, GEO_STEP_MAX as i32) as u32
+}
+
+// Half extents of the search shape in degrees around the origin
+// 1. The latitude extent is the half height along a meridian
+// 2. A radius covers at most asin(sin(r / R) / cos(lat)) of longitude unless it contains a pole
+// 3. A box is widest on its parallel closest to a pole
+fn bounding_deltas(shape: GeoShape, latitude: f64) -> (f64, f64) {
+	let half_height = match shape {
+		GeoShape::Radius(radius) => radius,
+		GeoShape::Box(_, height) => height / 2.0,
+	};
+	let lat_delta = (half_height / EARTH_RADIUS_IN_METERS).to_degrees();
+	let furthest_latitude = latitude.abs() + lat_delta;
+	if furthest_latitude >= 90.0 {
+		return (180.0, lat_delta);
+	}
+
+	let ratio = match shape {
+		GeoShape::Radius(radius) => (radius / EARTH_RADIUS_IN_METERS).sin() / latitude.to_radians().cos(),
+		GeoShape::Box(width, _) => (width / 4.0 / EARTH_RADIUS_IN_METERS).sin() / furthest_latitude.to_radians().cos(),
+	};
+	if ratio >= 1.0 {
+		return (180.0, lat_delta);
+	}
+
+	let lon_delta = match shape {
+		GeoShape::Radius(_) => ratio.asin().to_degrees(),
+		GeoShape::Box(..) => 2.0 * ratio.asin().to_degrees(),
+	};
+	(lon_delta, lat_delta)
+}
+
+// Score ranges `[min, max)` of the 3x3 block of cells around the origin
+// 1. Estimate the precision from the radius
+// 2. Lower the precision until the bounding box of the shape fits in the block
+// 3. At precision 0 a single range covers the whole world
+pub fn search_ranges(longitude: f64, latitude: f64, shape: GeoShape) -> Vec<(u64, u64)> {
+	let radius = match shape {
+		GeoShape::Radius(radius) => radius,
+		GeoShape::Box(width, height) => ((width / 2.0).powi(2) + (height / 2.0).powi(2)).sqrt(),
+	};
+	let (lon_delta, lat_delta) = bounding_deltas(shape, latitude);
+	let mut step = estimate_step_by_radius(radius, latitude);
+
+	while step > 0 {
+		let cells = 1u64 << step;
+		let lat_unit = (GEO_LAT_MAX - GEO_LAT_MIN) / cells as f64;
+		let lon_unit = (GEO_LONG_MAX - GEO_LONG_MIN) / cells as f64;
+		let (lat, lon) = cell_indexes(longitude, latitude, step);
+
+		let cell_lat_min = GEO_LAT_MIN + lat as f64 * lat_unit;
+		let cell_lon_min = GEO_LONG_MIN + lon as f64 * lon_unit;
+		let lat_fits = (latitude - lat_delta).max(GEO_LAT_MIN) >= cell_lat_min - lat_unit
+			&& (latitude + lat_delta).min(GEO_LAT_MAX) <= cell_lat_min + 2.0 * lat_unit;
+		let lon_fits = lon_unit * 3.0 >= 360.0
+			|| (longitude - lon_delta >= cell_lon_min - lon_unit && longitude + lon_delta <= cell_lon_min + 2.0 * lon_unit);
+
+		if lat_fits && lon_fits {
+			return neighbour_ranges(lat, lon, step);
+		}
+		step -= 1;
+	}
+	vec![(0, 1u64 << (2 * GEO_STEP_MAX))]
+}
+
+fn neighbour_ranges(lat: u32, lon: u32, step: u32) -> Vec<(u64, u64)> {
+	let cells = 1i64 << step;
+	let shift = 2 * (GEO_STEP_MAX - step);
+	let mut ranges: Vec<(u64, u64)> = Vec::with_capacity(9);
+
+	for lat_offset in -1i64..=1 {
+		let neighbour_lat = lat as i64 + lat_offset;
+		if neighbour_lat < 0 || neighbour_lat >= cells {
+			continue;
+		}
+		for lon_offset in -1i64..=1 {
+			// Longitude wraps around the antimeridian
+			let neighbour_lon = (lon as i64 + lon_offset).rem_euclid(cells);
+			let hash = interleave(neighbour_lat as u32, neighbour_lon as u32);
+			let range = (hash << shift, (hash + 1) << shift);
+			if !ranges.contains(&range) {
+				ranges.push(range);
+			}
+		}
+	}
+	ranges
+}
+
+// Distance of a point from the origin if it lies within the shape
+fn distance_if_in_shape(shape: GeoShape, origin: (f64, f64), point: (f64, f64)) -> Option<f64> {
+	let distance = geo_distance(origin.0, origin.1, point.0, point.1);
+	match shape {
+		GeoShape::Radius(radius) => (distance <= radius).then_some(distance),
+		GeoShape::Box(width, height) => {
+			let lat_distance = EARTH_RADIUS_IN_METERS * (point.1.to_radians() - origin.1.to_radians()).abs();
+			let lon_distance = geo_distance(origin.0, point.1, point.0, point.1);
+			(lat_distance <= height / 2.0 && lon_distance <= width / 2.0).then_some(distance)
+		}
+	}
+}
+
+// Run a search over the members of a sorted set
+// 1. Query the score ranges of the cells around the origin
+// 2. Keep the members whose decoded position lies within the shape
+// 3. Sort and truncate as requested, COUNT without ANY implies ascending order
+pub fn search_sorted_set(set: &SortedSet, origin: (f64, f64), query: &GeoSearch) -> Vec<GeoMatch> {
+	let mut matches: Vec<GeoMatch> = Vec::new();
+
+	'ranges: for (min, max) in search_ranges(origin.0, origin.1, query.shape) {
+		for (member, score) in set.range_by_score(min as f64, max as f64) {
+			let hash = score as u64;
+			let (longitude, latitude) = geohash_decode(hash);
+
+			if let Some(distance) = distance_if_in_shape(query.shape, origin, (longitude, latitude)) {
+				matches.push(GeoMatch {
+					member: member.to_string(),
+					distance: distance / query.unit.meters(),
+					hash,
+					longitude,
+					latitude,
+				});
+
+				if query.any && query.count.is_some_and(|count| matches.len() >= count) {
+					break 'ranges;
+				}
+			}
+		}
+	}
+
+	let order = match (query.order, query.count, query.any) {
+		(Some(order), _, _) => Some(order),
+		(None, Some(_), false) => Some(Ordering::Less),
+		_ => None,
+	};
+	match order {
+		Some(Ordering::Greater) => matches.sort_by(|a, b| b.distance.total_cmp(&a.distance)),
+		Some(_) => matches.sort_by(|a, b| a.distance.total_cmp(&b.distance)),
+		None => {}
+	}
+
+	if let Some(count) = query.count {
+		matches.truncate(count);
+	}
+	matches
+}
+
+impl CalodStore {
+	// Add members to a geo index, returns the number of added (or changed with CH) members
+	pub fn geoadd(&self, key: &str, points: &[(f64, f64, String)], options: GeoAddOptions) -> Result<u64, CacheError> {
+		for (longitude, latitude, _) in points {
+			if !is_valid_coordinate(*longitude, *latitude) {
+				return Err(CacheError::InvalidArgument(format!("invalid longitude,latitude pair {:.6},{:.6}", longitude, latitude)));
+			}
+		}
+
+		let add = |value: &mut DataType| match value {
+			DataType::SortedSet(set) => {
+				let mut changed: u64 = 0;
+				for (longitude, latitude, member) in points {
+					let score = geohash_encode(*longitude, *latitude) as f64;
+					let existing = set.score(member);
+
+					if (options.nx && existing.is_some()) || (options.xx && existing.is_none()) {
+						continue;
+					}
+					set.insert(member, score);
+
+					if existing.is_none() || (options.ch && existing != Some(score)) {
+						changed += 1;
+					}
+				}
+				Ok(changed)
+			}
+			_ => Err(CacheError::WrongType),
+		};
+
+		// XX only updates members, so a missing key is left missing
+		if options.xx {
+			return self.update_existing(key, add).unwrap_or(Ok(0));
+		}
+		self.update_value(key, || DataType::SortedSet(SortedSet::new()), add)
+	}
+
+	pub fn geopos(&self, key: &str, members: &[&str]) -> Result<Vec<Option<(f64, f64)>>, CacheError> {
+		self.read_value(key, |value| match value {
+			Some(DataType::SortedSet(set)) => Ok(members.iter().map(|m| set.score(m).map(|s| geohash_decode(s as u64))).collect()),
+			Some(_) => Err(CacheError::WrongType),
+			None => Ok(vec![None; members.len()]),
+		})
+	}
+
+	pub fn geodist(&self, key: &str, first: &str, second: &str, unit: GeoUnit) -> Result<Option<f64>, CacheError> {
+		let positions = self.geopos(key, &[first, second])?;
+		match (positions[0], positions[1]) {
+			(Some((lon1, lat1)), Some((lon2, lat2))) => Ok(Some(geo_distance(lon1, lat1, lon2, lat2) / unit.meters())),
+			_ => Ok(None),
+		}
+	}
+
+	pub fn geosearch(&self, key: &str, query: &GeoSearch) -> Result<Vec<GeoMatch>, CacheError> {
+		self.read_value(key, |value| match value {
+			Some(DataType::SortedSet(set)) => {
+				let origin = match &query.origin {
+					GeoOrigin::LonLat(longitude, latitude) => (*longitude, *latitude),
+					GeoOrigin::Member(member) => match set.score(member) {
+						Some(score) => geohash_decode(score as u64),
+						None => return Err(CacheError::InvalidArgument(String::from("could not decode requested zset member"))),
+					},
+				};
+				Ok(search_sorted_set(set, origin, query))
+			}
+			Some(_) => Err(CacheError::WrongType),
+			None => Ok(Vec::new()),
+		})
+	}
+
+	// Store the matches in `destkey`, scored by geohash or by distance with STOREDIST
+	pub fn geosearchstore(&self, destkey: &str, key: &str, query: &GeoSearch, store_distance: bool) -> Result<usize, CacheError> {
+		let matches = self.geosearch(key, query)?;
+		if matches.is_empty() {
+			self.remove_value(destkey);
+			return Ok(0);
+		}
+
+		let mut set = SortedSet::new();
+		for m in &matches {
+			set.insert(&m.member, if store_distance { m.distance } else { m.hash as f64 });
+		}
+		self.replace_value(destkey, DataType::SortedSet(set));
+		Ok(matches.len())
+	}
+}
@@ -0,0 +1,69 @@
+// Redis style glob matching on keys
+// 1. `*` matches any run of characters, `?` any single one
+// 2. `[abc]`, `[a-z]` and `[^...]` match a character class, `\` escapes the next character
+pub fn glob_match(pattern: &str, key: &str) -> bool {
+	let pattern = pattern.as_bytes();
+	let key = key.as_bytes();
+	let (mut p, mut k) = (0, 0);
+	// Position of the last `*` and the key position it is currently matched up to
+	let mut backtrack: Option<(usize, usize)> = None;
+
+	while k < key.len() {
+		if p < pattern.len() && pattern[p] == b'*' {
+			backtrack = Some((p, k));
+			p += 1;
+			continue;
+		}
+		if let Some(next) = match_one(pattern, p, key[k]) {
+			p = next;
+			k += 1;
+			continue;
+		}
+		match backtrack {
+			Some((star, matched)) => {
+				p = star + 1;
+				k = matched + 1;
+				backtrack = Some((star, matched + 1));
+			}
+			None => return false,
+		}
+	}
+
+	pattern[p..].iter().all(|c| *c == b'*')
+}
+
+// Match the single character token at `p`, returns the position after it
+fn match_one(pattern: &[u8], p: usize, c: u8) -> Option<usize> {
+	match *pattern.get(p)? {
+		b'?' => Some(p + 1),
+		b'[' => match_class(pattern, p + 1, c),
+		b'\\' if p + 1 < pattern.len() => (pattern[p + 1] == c).then_some(p + 2),
+		literal => (literal == c).then_some(p + 1),
+	}
+}
+
+// `p` is just past the `[`, an unterminated class runs to the end of the pattern
+fn match_class(pattern: &[u8], mut p: usize, c: u8) -> Option<usize> {
+	let negate = pattern.get(p) == Some(&b'^');
+	if negate {
+		p += 1;
+	}
+
+	let mut matched = false;
+	while p < pattern.len() && pattern[p] != b']' {
+		if pattern[p] == b'\\' && p + 1 < pattern.len() {
+			matched |= pattern[p + 1] == c;
+			p += 2;
+		} else if p + 2 < pattern.len() && pattern[p + 1] == b'-' && pattern[p + 2] != b']' {
+			let (low, high) = (pattern[p].min(pattern[p + 2]), pattern[p].max(pattern[p + 2]));
+			matched |= (low..=high).contains(&c);
+			p += 3;
+		} else {
+			matched |= pattern[p] == c;
+			p += 1;
+		}
+	}
+
+	let next = (p + 1).min(pattern.len());
+	(matched != negate).then_some(next)
+}
@@ -0,0 +1,386 @@
+use std::borrow::Cow;
+
+use crate::persistence::encoding::{put_u8, put_varint, Decoder};
+use crate::persistence::snapshot::SnapshotError;
+use crate::store::calod_data::DataType;
+use crate::store::calod_store::{CacheError, CalodStore};
+
+// Same layout as Redis: 2^14 registers and the remaining 50 hash bits for the rank
+pub const HLL_P: u32 = 14;
+pub const HLL_REGISTERS: usize = 1 << HLL_P;
+const HLL_Q: u32 = 64 - HLL_P;
+const HLL_ALPHA_INF: f64 = 0.721_347_520_444_481_7;
+
+// A sparse entry costs 3 bytes, switch to dense once it would outgrow Redis' default budget
+pub const HLL_SPARSE_MAX_ENTRIES: usize = 3000 / 3;
+
+// A HyperLogLog read as a string starts with this, like in Redis
+pub const HLL_STRING_HEADER: &[u8] = b"HYLL";
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum HllEncoding {
+	// Non-zero registers as (index, rank), sorted by index
+	Sparse(Vec<(u16, u8)>),
+	// One byte per register
+	Dense(Vec<u8>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct HyperLogLog {
+	encoding: HllEncoding,
+	cached_cardinality: Option<u64>,
+}
+
+// MurmurHash64A with the Redis seed so estimates match a Redis server
+pub fn murmurhash64a(key: &[u8]) -> u64 {
+	const M: u64 = 0xc6a4a7935bd1e995;
+	const R: u32 = 47;
+	let seed: u64 = 0xadc83b19;
+	let mut h: u64 = seed ^ (key.len() as u64).wrapping_mul(M);
+
+	let mut chunks = key.chunks_exact(8);
+	for chunk in &mut chunks {
+		let mut k = u64::from_le_bytes(chunk.try_into().unwrap());
+		k = k.wrapping_mul(M);
+		k ^= k >> R;
+		k = k.wrapping_mul(M);
+		h ^= k;
+		h = h.wrapping_mul(M);
+	}
+
+	let tail = chunks.remainder();
+	if !tail.is_empty() {
+		for (i, byte) in tail.iter().enumerate() {
+			h ^= (*byte as u64) << (8 * i);
+		}
+		h = h.wrapping_mul(M);
+	}
+
+	h ^= h >> R;
+	h = h.wrapping_mul(M);
+	h ^= h >> R;
+	h
+}
+
+// Split the hash of an element into its register index and rank
+// 1. The low 14 bits select the register
+// 2. The rank is the position of the first set bit in the remaining 50 bits
+fn hash_element(element: &[u8]) -> (u16, u8) {
+	let hash = murmurhash64a(element);
+	let index = (hash & (HLL_REGISTERS as u64 - 1)) as u16;
+	let remaining = (hash >> HLL_P) | (1u64 << HLL_Q);
+	(index, remaining.trailing_zeros() as u8 + 1)
+}
+
+fn hll_sigma(mut x: f64) -> f64 {
+	if x == 1.0 {
+		return f64::INFINITY;
+	}
+	let mut y = 1.0;
+	let mut z = x;
+	loop {
+		x *= x;
+		let z_prime = z;
+		z += x * y;
+		y += y;
+		if z_prime == z {
+			return z;
+		}
+	}
+}
+
+fn hll_tau(mut x: f64) -> f64 {
+	if x == 0.0 || x == 1.0 {
+		return 0.0;
+	}
+	let mut y = 1.0;
+	let mut z = 1.0 - x;
+	loop {
+		x = x.sqrt();
+		let z_prime = z;
+		y *= 0.5;
+		z -= (1.0 - x).powi(2) * y;
+		if z_prime == z {
+			return z / 3.0;
+		}
+	}
+}
+
+impl Default for HyperLogLog {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+impl HyperLogLog {
+	pub fn new() -> Self {
+		HyperLogLog {
+			encoding: HllEncoding::Sparse(Vec::new()),
+			cached_cardinality: None,
+		}
+	}
+
+	pub fn encoding(&self) -> &HllEncoding {
+		&self.encoding
+	}
+
+	pub fn is_sparse(&self) -> bool {
+		matches!(self.encoding, HllEncoding::Sparse(_))
+	}
+
+	// Add an element, returns true if any register changed
+	pub fn add(&mut self, element: &[u8]) -> bool {
+		let (index, rank) = hash_element(element);
+		self.set_register_max(index, rank)
+	}
+
+	// Raise a register to `rank` if it is lower
+	// 1. Dense: update the byte in place
+	// 2. Sparse: binary search the entry, insert or raise it
+	// 3. Promote to dense once the sparse form grows past its budget
+	fn set_register_max(&mut self, index: u16, rank: u8) -> bool {
+		let changed = match &mut self.encoding {
+			HllEncoding::Dense(registers) => {
+				let register = &mut registers[index as usize];
+				if *register < rank {
+					*register = rank;
+					true
+				} else {
+					false
+				}
+			}
+			HllEncoding::Sparse(entries) => match entries.binary_search_by_key(&index, |(i, _)| *i) {
+				Ok(pos) if entries[pos].1 < rank => {
+					entries[pos].1 = rank;
+					true
+				}
+				Ok(_) => false,
+				Err(pos) => {
+					entries.insert(pos, (index, rank));
+					true
+				}
+			},
+		};
+
+		if changed {
+			self.cached_cardinality = None;
+			if let HllEncoding::Sparse(entries) = &self.encoding {
+				if entries.len() > HLL_SPARSE_MAX_ENTRIES {
+					self.promote_to_dense();
+				}
+			}
+		}
+		changed
+	}
+
+	fn promote_to_dense(&mut self) {
+		if let HllEncoding::Sparse(entries) = &self.encoding {
+			let mut registers = vec![0u8; HLL_REGISTERS];
+			for (index, rank) in entries {
+				registers[*index as usize] = *rank;
+			}
+			self.encoding = HllEncoding::Dense(registers);
+		}
+	}
+
+	fn register_histogram(&self, histogram: &mut [u32; HLL_Q as usize + 2]) {
+		match &self.encoding {
+			HllEncoding::Dense(registers) => {
+				for rank in registers {
+					histogram[*rank as usize] += 1;
+				}
+			}
+			HllEncoding::Sparse(entries) => {
+				histogram[0] += (HLL_REGISTERS - entries.len()) as u32;
+				for (_, rank) in entries {
+					histogram[*rank as usize] += 1;
+				}
+			}
+		}
+	}
+
+	// Estimate the cardinality with the estimator by Otmar Ertl, as Redis does
+	pub fn count(&mut self) -> u64 {
+		if let Some(cardinality) = self.cached_cardinality {
+			return cardinality;
+		}
+		let cardinality = self.estimate();
+		self.cached_cardinality = Some(cardinality);
+		cardinality
+	}
+
+	fn estimate(&self) -> u64 {
+		let mut histogram = [0u32; HLL_Q as usize + 2];
+		self.register_histogram(&mut histogram);
+		estimate_from_histogram(&histogram)
+	}
+
+	// Merge `other` into self by taking the max of every register
+	pub fn merge(&mut self, other: &HyperLogLog) {
+		match &other.encoding {
+			HllEncoding::Sparse(entries) => {
+				for (index, rank) in entries {
+					self.set_register_max(*index, *rank);
+				}
+			}
+			HllEncoding::Dense(registers) => {
+				self.promote_to_dense();
+				if let HllEncoding::Dense(own) = &mut self.encoding {
+					for (mine, theirs) in own.iter_mut().zip(registers.iter()) {
+						*mine = (*mine).max(*theirs);
+					}
+				}
+				self.cached_cardinality = None;
+			}
+		}
+	}
+
+	// The string GET returns for a HyperLogLog, a SET of it reads back as the same HyperLogLog
+	pub fn to_string_bytes(&self) -> Vec<u8> {
+		let mut out = HLL_STRING_HEADER.to_vec();
+		self.encode(&mut out);
+		out
+	}
+
+	// Parse a string written by `to_string_bytes`, `None` for any other string
+	pub fn from_string_bytes(bytes: &[u8]) -> Option<Self> {
+		let mut decoder = Decoder::new(bytes.strip_prefix(HLL_STRING_HEADER)?);
+		let hll = HyperLogLog::decode(&mut decoder).ok()?;
+		decoder.is_empty().then_some(hll)
+	}
+
+	// Snapshot encoding: encoding byte, then (index, rank) pairs or the raw registers
+	pub fn encode(&self, out: &mut Vec<u8>) {
+		match &self.encoding {
+			HllEncoding::Sparse(entries) => {
+				put_u8(out, 0);
+				put_varint(out, entries.len() as u64);
+				for (index, rank) in entries {
+					put_varint(out, *index as u64);
+					put_u8(out, *rank);
+				}
+			}
+			HllEncoding::Dense(registers) => {
+				put_u8(out, 1);
+				out.extend_from_slice(registers);
+			}
+		}
+	}
+
+	pub fn decode(decoder: &mut Decoder) -> Result<Self, SnapshotError> {
+		let invalid = || SnapshotError::Corrupt(String::from("invalid hyperloglog"));
+		let encoding = match decoder.u8()? {
+			0 => {
+				let mut entries: Vec<(u16, u8)> = Vec::new();
+				for _ in 0..decoder.varint()? {
+					let index = decoder.varint()?;
+					let rank = decoder.u8()?;
+					if index >= HLL_REGISTERS as u64 || rank as u32 > HLL_Q + 1 || entries.last().is_some_and(|(i, _)| *i as u64 >= index) {
+						return Err(invalid());
+					}
+					entries.push((index as u16, rank));
+				}
+				HllEncoding::Sparse(entries)
+			}
+			1 => {
+				let mut registers = Vec::with_capacity(HLL_REGISTERS);
+				for _ in 0..HLL_REGISTERS {
+					let rank = decoder.u8()?;
+					if rank as u32 > HLL_Q + 1 {
+						return Err(invalid());
+					}
+					registers.push(rank);
+				}
+				HllEncoding::Dense(registers)
+			}
+			_ => return Err(invalid()),
+		};
+		Ok(HyperLogLog { encoding, cached_cardinality: None })
+	}
+}
+
+fn estimate_from_histogram(histogram: &[u32; HLL_Q as usize + 2]) -> u64 {
+	let m = HLL_REGISTERS as f64;
+	let mut z = m * hll_tau((m - histogram[HLL_Q as usize + 1] as f64) / m);
+
+	for j in (1..=HLL_Q as usize).rev() {
+		z += histogram[j] as f64;
+		z *= 0.5;
+	}
+	z += m * hll_sigma(histogram[0] as f64 / m);
+
+	(HLL_ALPHA_INF * m * m / z).round() as u64
+}
+
+// The HyperLogLog of a value, a string is one when it holds the encoding of one
+fn read_hll(value: &DataType) -> Result<Cow<'_, HyperLogLog>, CacheError> {
+	match value {
+		DataType::HyperLogLog(hll) => Ok(Cow::Borrowed(hll)),
+		DataType::String(bytes) => HyperLogLog::from_string_bytes(bytes).map(Cow::Owned).ok_or(CacheError::WrongType),
+		_ => Err(CacheError::WrongType),
+	}
+}
+
+// Like `read_hll` to change it, a string holding a HyperLogLog stays one from then on
+fn hll_mut(value: &mut DataType) -> Result<&mut HyperLogLog, CacheError> {
+	if let DataType::String(bytes) = value {
+		*value = DataType::HyperLogLog(HyperLogLog::from_string_bytes(bytes).ok_or(CacheError::WrongType)?);
+	}
+	match value {
+		DataType::HyperLogLog(hll) => Ok(hll),
+		_ => Err(CacheError::WrongType),
+	}
+}
+
+impl CalodStore {
+	// Returns true when a register was altered or the key was created
+	pub fn pfadd(&self, key: &str, elements: &[&str]) -> Result<bool, CacheError> {
+		let mut created = false;
+		let changed = self.update_value(key, || {
+			created = true;
+			DataType::HyperLogLog(HyperLogLog::new())
+		}, |value| {
+			let hll = hll_mut(value)?;
+			let mut changed = false;
+			for element in elements {
+				changed |= hll.add(element.as_bytes());
+			}
+			Ok(changed)
+		})?;
+
+		Ok(changed || created)
+	}
+
+	// Count a single key using its cached cardinality, or the union of several keys
+	pub fn pfcount(&self, keys: &[&str]) -> Result<u64, CacheError> {
+		if keys.len() == 1 {
+			return self.update_existing(keys[0], |value| hll_mut(value).map(|hll| hll.count())).unwrap_or(Ok(0));
+		}
+
+		let mut union = self.collect_union(keys)?;
+		Ok(union.count())
+	}
+
+	// Merge the sources into `destkey`, which is itself part of the union if it exists
+	pub fn pfmerge(&self, destkey: &str, sources: &[&str]) -> Result<(), CacheError> {
+		let mut keys = vec![destkey];
+		keys.extend_from_slice(sources);
+		let union = self.collect_union(&keys)?;
+
+		self.update_value(destkey, || DataType::HyperLogLog(HyperLogLog::new()), |value| {
+			*hll_mut(value)? = union;
+			Ok(())
+		})
+	}
+
+	fn collect_union(&self, keys: &[&str]) -> Result<HyperLogLog, CacheError> {
+		let mut union = HyperLogLog::new();
+		for key in keys {
+			self.read_value(key, |value| match value {
+				Some(value) => read_hll(value).map(|hll| union.merge(&hll)),
+				None => Ok(()),
+			})?;
+		}
+		Ok(union)
+	}
+}
@@ -0,0 +1,440 @@
+use std::cell::Cell;
+
+use serde_json::{Number, Value};
+
+use crate::store::calod_data::DataType;
+use crate::store::calod_store::{CacheError, CalodStore};
+
+// One segment of the supported JSONPath subset
+#[derive(Debug, Clone, PartialEq)]
+pub enum PathSegment {
+	// `.name` or `['name']`
+	Key(String),
+	// `[3]` or `[-1]`
+	Index(i64),
+	// `.*` or `[*]`
+	Wildcard,
+	// `..name`
+	RecursiveKey(String),
+	// `..*`
+	RecursiveWildcard,
+}
+
+// A parsed path, legacy paths (not starting with `$`) reply with a single value instead of an array
+#[derive(Debug, Clone, PartialEq)]
+pub struct JsonPath {
+	pub segments: Vec<PathSegment>,
+	pub legacy: bool,
+}
+
+// A concrete location inside a document
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum PathStep {
+	Key(String),
+	Index(usize),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum JsonSetCondition {
+	Nx,
+	Xx,
+}
+
+fn missing_key() -> CacheError {
+	CacheError::InvalidArgument(String::from("could not perform this operation on a key that doesn't exist"))
+}
+
+fn path_error(path: &str) -> CacheError {
+	CacheError::InvalidArgument(format!("Path '{}' does not exist or is not a valid JSONPath", path))
+}
+
+fn parse_json(text: &str) -> Result<Value, CacheError> {
+	serde_json::from_str(text).map_err(|e| CacheError::InvalidArgument(e.to_string()))
+}
+
+impl JsonPath {
+	// Parse `$`-rooted JSONPath or the legacy `.a.b` / `a.b` syntax
+	// 1. Strip the root and normalise legacy paths to start with a `.`
+	// 2. Read dot segments, recursive descent and bracket selectors in order
+	pub fn parse(path: &str) -> Result<JsonPath, CacheError> {
+		let (legacy, rest) = match path.strip_prefix('$') {
+			Some(rest) => (false, rest.to_string()),
+			None if path == "." => (true, String::new()),
+			None if path.starts_with('.') || path.starts_with('[') => (true, path.to_string()),
+			None => (true, format!(".{}", path)),
+		};
+
+		let chars: Vec<char> = rest.chars().collect();
+		let mut segments = Vec::new();
+		let mut i = 0;
+
+		while i < chars.len() {
+			match chars[i] {
+				'.' if chars.get(i + 1) == Some(&'.') => {
+					i += 2;
+					let name = read_name(&chars, &mut i);
+					segments.push(match name.as_str() {
+						"" => return Err(path_error(path)),
+						"*" => PathSegment::RecursiveWildcard,
+						_ => PathSegment::RecursiveKey(name),
+					});
+				}
+				'.' => {
+					i += 1;
+					let name = read_name(&chars, &mut i);
+					segments.push(match name.as_str() {
+						"" => return Err(path_error(path)),
+						"*" => PathSegment::Wildcard,
+						_ => PathSegment::Key(name),
+					});
+				}
+				'[' => {
+					let end = chars[i..].iter().position(|c| *c == ']').ok_or_else(|| path_error(path))? + i;
+					let inner: String = chars[i + 1..end].iter().collect::<String>().trim().to_string();
+					i = end + 1;
+
+					let quoted = inner.len() >= 2
+						&& ((inner.starts_with('\'') && inner.ends_with('\'')) || (inner.starts_with('"') && inner.ends_with('"')));
+					segments.push(if inner == "*" {
+						PathSegment::Wildcard
+					} else if quoted {
+						PathSegment::Key(inner[1..inner.len() - 1].to_string())
+					} else {
+						PathSegment::Index(inner.parse().map_err(|_| path_error(path))?)
+					});
+				}
+				_ => return Err(path_error(path)),
+			}
+		}
+
+		Ok(JsonPath { segments, legacy })
+	}
+
+	pub fn is_root(&self) -> bool {
+		self.segments.is_empty()
+	}
+
+	// Resolve the path to the concrete locations it matches, in document order
+	pub fn resolve(&self, root: &Value) -> Vec<Vec<PathStep>> {
+		let mut current: Vec<Vec<PathStep>> = vec![Vec::new()];
+
+		for segment in &self.segments {
+			let mut next = Vec::new();
+			for location in current {
+				let node = match get_at(root, &location) {
+					Some(node) => node,
+					None => continue,
+				};
+
+				match segment {
+					PathSegment::Key(name) => {
+						if node.as_object().is_some_and(|map| map.contains_key(name.as_str())) {
+							next.push(child(&location, PathStep::Key(name.clone())));
+						}
+					}
+					PathSegment::Index(index) => {
+						if let Value::Array(items) = node {
+							let resolved = if *index < 0 { items.len() as i64 + index } else { *index };
+							if resolved >= 0 && (resolved as usize) < items.len() {
+								next.push(child(&location, PathStep::Index(resolved as usize)));
+							}
+						}
+					}
+					PathSegment::Wildcard => next.extend(children(node, &location)),
+					PathSegment::RecursiveKey(name) => {
+						for descendant in descendants(node, &location, true) {
+							if let Some(Value::Object(map)) = get_at(root, &descendant) {
+								if map.contains_key(name.as_str()) {
+									next.push(child(&descendant, PathStep::Key(name.clone())));
+								}
+							}
+						}
+					}
+					PathSegment::RecursiveWildcard => next.extend(descendants(node, &location, false)),
+				}
+			}
+			current = next;
+		}
+		current
+	}
+}
+
+fn read_name(chars: &[char], i: &mut usize) -> String {
+	let start = *i;
+	while *i < chars.len() && chars[*i] != '.' && chars[*i] != '[' {
+		*i += 1;
+	}
+	chars[start..*i].iter().collect()
+}
+
+fn child(location: &[PathStep], step: PathStep) -> Vec<PathStep> {
+	let mut path = location.to_vec();
+	path.push(step);
+	path
+}
+
+fn children(node: &Value, location: &[PathStep]) -> Vec<Vec<PathStep>> {
+	match node {
+		Value::Object(map) => map.keys().map(|k| child(location, PathStep::Key(k.clone()))).collect(),
+		Value::Array(items) => (0..items.len()).map(|i| child(location, PathStep::Index(i))).collect(),
+		_ => Vec::new(),
+	}
+}
+
+// All nodes below `node` in pre-order, optionally including `node` itself
+fn descendants(node: &Value, location: &[PathStep], include_self: bool) -> Vec<Vec<PathStep>> {
+	let mut result = Vec::new();
+	if include_self {
+		result.push(location.to_vec());
+	}
+	for child_location in children(node, location) {
+		let child_node = match child_location.last().unwrap() {
+			PathStep::Key(k) => &node[k.as_str()],
+			PathStep::Index(i) => &node[*i],
+		};
+		result.extend(descendants(child_node, &child_location, true));
+	}
+	result
+}
+
+pub fn get_at<'a>(root: &'a Value, location: &[PathStep]) -> Option<&'a Value> {
+	location.iter().try_fold(root, |node, step| match step {
+		PathStep::Key(k) => node.as_object()?.get(k),
+		PathStep::Index(i) => node.as_array()?.get(*i),
+	})
+}
+
+pub fn get_at_mut<'a>(root: &'a mut Value, location: &[PathStep]) -> Option<&'a mut Value> {
+	location.iter().try_fold(root, |node, step| match step {
+		PathStep::Key(k) => node.as_object_mut()?.get_mut(k),
+		PathStep::Index(i) => node.as_array_mut()?.get_mut(*i),
+	})
+}
+
+// RedisJSON type names
+pub fn json_type(value: &Value) -> &'static str {
+	match value {
+		Value::Null => "null",
+		Value::Bool(_) => "boolean",
+		Value::Number(n) if n.is_i64() || n.is_u64() => "integer",
+		Value::Number(_) => "number",
+		Value::String(_) => "string",
+		Value::Array(_) => "array",
+		Value::Object(_) => "object",
+	}
+}
+
+// Add two numbers, staying integral when both sides are integers and the sum fits
+fn add_numbers(current: &Number, increment: &Number) -> Option<Number> {
+	if let (Some(a), Some(b)) = (current.as_i64(), increment.as_i64()) {
+		if let Some(sum) = a.checked_add(b) {
+			return Some(Number::from(sum));
+		}
+	}
+	Number::from_f64(current.as_f64()? + increment.as_f64()?)
+}
+
+// Set `value` at every match of `path`
+// 1. Existing matches are replaced (skipped with NX)
+// 2. If nothing matches and the last segment is a key, it is created in every matching parent object (skipped with XX)
+// Returns false when nothing was written
+pub fn set_path(root: &mut Value, path: &JsonPath, value: Value, condition: Option<JsonSetCondition>) -> bool {
+	let matches = path.resolve(root);
+
+	if !matches.is_empty() {
+		if condition == Some(JsonSetCondition::Nx) {
+			return false;
+		}
+		for location in &matches {
+			if let Some(node) = get_at_mut(root, location) {
+				*node = value.clone();
+			}
+		}
+		return true;
+	}
+
+	if condition == Some(JsonSetCondition::Xx) {
+		return false;
+	}
+	let (last, parent_segments) = match path.segments.split_last() {
+		Some((PathSegment::Key(name), parent)) => (name, parent),
+		_ => return false,
+	};
+
+	let parent_path = JsonPath { segments: parent_segments.to_vec(), legacy: path.legacy };
+	let mut created = false;
+	for location in parent_path.resolve(root) {
+		if let Some(Value::Object(map)) = get_at_mut(root, &location) {
+			map.insert(last.clone(), value.clone());
+			created = true;
+		}
+	}
+	created
+}
+
+// Delete every match of `path`, returns the number of removed values
+// 1. Drop matches nested in another match, they go away with their parent
+// 2. Delete from the back so array indexes stay valid
+pub fn delete_path(root: &mut Value, path: &JsonPath) -> u64 {
+	let mut matches = path.resolve(root);
+	matches.sort();
+	matches.dedup();
+
+	let mut outermost: Vec<Vec<PathStep>> = Vec::new();
+	for location in matches {
+		if !outermost.iter().any(|parent| location.starts_with(parent)) {
+			outermost.push(location);
+		}
+	}
+
+	let mut deleted = 0;
+	for location in outermost.into_iter().rev() {
+		let (last, parent) = location.split_last().unwrap();
+		match (get_at_mut(root, parent), last) {
+			(Some(Value::Object(map)), PathStep::Key(k)) => {
+				deleted += u64::from(map.shift_remove(k).is_some());
+			}
+			(Some(Value::Array(items)), PathStep::Index(i)) if *i < items.len() => {
+				items.remove(*i);
+				deleted += 1;
+			}
+			_ => {}
+		}
+	}
+	deleted
+}
+
+impl CalodStore {
+	fn read_json<R>(&self, key: &str, f: impl FnOnce(Option<&Value>) -> Result<R, CacheError>) -> Result<R, CacheError> {
+		self.read_value(key, |value| match value {
+			Some(DataType::Json(document)) => f(Some(document)),
+			Some(_) => Err(CacheError::WrongType),
+			None => f(None),
+		})
+	}
+
+	// Run `f` on the document of an existing key with its entry locked, `None` when the key does not exist
+	fn update_json<R>(&self, key: &str, f: impl FnOnce(&mut Value) -> Result<R, CacheError>) -> Option<Result<R, CacheError>> {
+		self.update_existing(key, |value| match value {
+			DataType::Json(document) => f(document),
+			_ => Err(CacheError::WrongType),
+		})
+	}
+
+	// JSON.SET, returns false for the nil reply (NX/XX not met or no parent to create the key in)
+	// 1. The root path creates or replaces the whole document
+	// 2. Any other path requires the key to exist
+	pub fn json_set(&self, key: &str, path: &JsonPath, json: &str, condition: Option<JsonSetCondition>) -> Result<bool, CacheError> {
+		let value = parse_json(json)?;
+
+		if !path.is_root() {
+			return self.update_json(key, |document| Ok(set_path(document, path, value, condition)))
+				.unwrap_or_else(|| Err(CacheError::InvalidArgument(String::from("new objects must be created at the root"))));
+		}
+		if condition == Some(JsonSetCondition::Xx) {
+			return self.update_json(key, |document| {
+				*document = value;
+				Ok(true)
+			}).unwrap_or(Ok(false));
+		}
+
+		// The existence check for NX happens with the entry locked, `created` tells a new key from an old one
+		let created = Cell::new(false);
+		self.update_value(key, || {
+			created.set(true);
+			DataType::Json(Value::Null)
+		}, |data| match data {
+			DataType::Json(_) if !created.get() && condition == Some(JsonSetCondition::Nx) => Ok(false),
+			DataType::Json(document) => {
+				*document = value;
+				Ok(true)
+			}
+			_ => Err(CacheError::WrongType),
+		})
+	}
+
+	// Values matching a path, `None` when the key does not exist
+	pub fn json_get(&self, key: &str, path: &JsonPath) -> Result<Option<Vec<Value>>, CacheError> {
+		self.read_json(key, |document| match document {
+			Some(document) => Ok(Some(path.resolve(document).iter().filter_map(|l| get_at(document, l).cloned()).collect())),
+			None => Ok(None),
+		})
+	}
+
+	// JSON.DEL, deleting the root removes the key
+	pub fn json_del(&self, key: &str, path: &JsonPath) -> Result<u64, CacheError> {
+		if path.is_root() {
+			let mut wrong_type = false;
+			let removed = self.remove_value_if(key, |value| {
+				wrong_type = !matches!(value, DataType::Json(_));
+				!wrong_type
+			});
+			return match wrong_type {
+				true => Err(CacheError::WrongType),
+				false => Ok(removed as u64),
+			};
+		}
+		self.update_json(key, |document| Ok(delete_path(document, path))).unwrap_or(Ok(0))
+	}
+
+	// JSON.NUMINCRBY, one new value per match, `None` for matches that are not numbers
+	// Every sum is worked out before any is stored, so a failing match leaves the document as it was
+	pub fn json_numincrby(&self, key: &str, path: &JsonPath, increment: &str) -> Result<Vec<Option<Value>>, CacheError> {
+		let increment = match parse_json(increment)? {
+			Value::Number(n) => n,
+			_ => return Err(CacheError::InvalidArgument(String::from("expected a number"))),
+		};
+
+		self.update_json(key, |document| {
+			let mut sums = Vec::new();
+			for location in path.resolve(document) {
+				let sum = match get_at(document, &location) {
+					Some(Value::Number(current)) => Some(add_numbers(current, &increment)
+						.ok_or_else(|| CacheError::InvalidArgument(String::from("result is not a finite number")))?),
+					_ => None,
+				};
+				sums.push((location, sum));
+			}
+
+			Ok(sums.into_iter().map(|(location, sum)| {
+				let sum = Value::Number(sum?);
+				*get_at_mut(document, &location)? = sum.clone();
+				Some(sum)
+			}).collect())
+		}).unwrap_or_else(|| Err(missing_key()))
+	}
+
+	// JSON.ARRAPPEND, the new length of every matched array, `None` for matches that are not arrays
+	pub fn json_arrappend(&self, key: &str, path: &JsonPath, values: &[&str]) -> Result<Vec<Option<usize>>, CacheError> {
+		let values: Vec<Value> = values.iter().map(|v| parse_json(v)).collect::<Result<_, _>>()?;
+
+		self.update_json(key, |document| {
+			Ok(path.resolve(document).iter().map(|location| match get_at_mut(document, location) {
+				Some(Value::Array(items)) => {
+					items.extend(values.iter().cloned());
+					Some(items.len())
+				}
+				_ => None,
+			}).collect())
+		}).unwrap_or_else(|| Err(missing_key()))
+	}
+
+	// JSON.OBJKEYS, the keys of every matched object, `None` for matches that are not objects
+	pub fn json_objkeys(&self, key: &str, path: &JsonPath) -> Result<Option<Vec<Option<Vec<String>>>>, CacheError> {
+		self.read_json(key, |document| match document {
+			Some(document) => Ok(Some(path.resolve(document).iter().map(|location| match get_at(document, location) {
+				Some(Value::Object(map)) => Some(map.keys().cloned().collect()),
+				_ => None,
+			}).collect())),
+			None => Ok(None),
+		})
+	}
+
+	// JSON.TYPE, the type name of every match
+	pub fn json_type(&self, key: &str, path: &JsonPath) -> Result<Option<Vec<&'static str>>, CacheError> {
+		self.read_json(key, |document| match document {
+			Some(document) => Ok(Some(path.resolve(document).iter().filter_map(|l| get_at(document, l)).map(json_type).collect())),
+			None => Ok(None),
+		})
+	}
+}
@@ -0,0 +1,30 @@
+use std::sync::mpsc::{self, Sender};
+use std::sync::OnceLock;
+use std::thread;
+
+use crate::store::calod_data::DataType;
+
+// Values that take more than this many allocations to drop are freed in the background
+pub const LAZY_FREE_THRESHOLD: usize = 64;
+
+static LAZY_FREE: OnceLock<Sender<DataType>> = OnceLock::new();
+
+// Drop a value, on the lazy free thread if freeing it right here could stall the caller
+// Returns true if the value was handed to the thread
+pub fn free(value: DataType) -> bool {
+	if value.free_effort() <= LAZY_FREE_THRESHOLD {
+		return false;
+	}
+	// The thread only ever stops with the process, so the send cannot fail
+	let _ = lazy_free_thread().send(value);
+	true
+}
+
+// Started by the first value that needs it
+fn lazy_free_thread() -> &'static Sender<DataType> {
+	LAZY_FREE.get_or_init(|| {
+		let (sender, receiver) = mpsc::channel::<DataType>();
+		thread::spawn(move || receiver.into_iter().for_each(drop));
+		sender
+	})
+}
@@ -1,2 +1,14 @@
 pub mod calod_store;
-pub mod calod_data;
\ No newline at end of file
+pub mod calod_data;
+pub mod cache;
+pub mod sorted_set;
+pub mod bitmap;
+pub mod hyperloglog;
+pub mod bloom_filter;
+pub mod cuckoo_filter;
+pub mod geo;
+pub mod json_document;
+pub mod glob;
+pub mod scan;
+pub mod lazy_free;
+pub mod random;
\ No newline at end of file
@@ -0,0 +1,25 @@
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::OnceLock;
+
+// SplitMix64 increment, every draw moves the shared state by it
+const GOLDEN_GAMMA: u64 = 0x9E37_79B9_7F4A_7C15;
+
+// One generator for the whole process, RANDOMKEY and eviction sampling draw from it
+// Seeded once from the hasher keys the standard library picks at random for each process
+static STATE: OnceLock<AtomicU64> = OnceLock::new();
+
+// A uniformly distributed number, SplitMix64 so concurrent draws only contend on one atomic add
+pub fn next_u64() -> u64 {
+	let state = STATE.get_or_init(|| AtomicU64::new(RandomState::new().build_hasher().finish()));
+	let mut z = state.fetch_add(GOLDEN_GAMMA, Ordering::Relaxed).wrapping_add(GOLDEN_GAMMA);
+	z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+	z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+	z ^ (z >> 31)
+}
+
+// A number below `bound`, which must not be 0
+pub fn below(bound: usize) -> usize {
+	(next_u64() % bound as u64) as usize
+}
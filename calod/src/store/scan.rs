@@ -0,0 +1,295 @@
+use std::borrow::Borrow;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::BTreeMap;
+use std::hash::{BuildHasher, Hash, Hasher};
+use std::ops::RangeBounds;
+use std::sync::Mutex;
+
+use dashmap::DashMap;
+
+use crate::store::calod_data::DataType;
+use crate::store::calod_store::{CacheError, CalodStore};
+use crate::store::glob::glob_match;
+use crate::store::random;
+
+// Items a SCAN call looks at when no COUNT is given
+pub const DEFAULT_SCAN_COUNT: usize = 10;
+
+// Where an item comes in a scan, its hash with the bits reversed
+// Redis walks a table of 2^n buckets by incrementing the bucket index from the top bit down, so every
+// bucket of a grown or shrunk table lies entirely before or after the cursor. Ordering items by their
+// reversed hash is that walk for a table of 2^64 buckets: the cursor is the position of the next item
+// and a resize cannot move an item across it, an item present for the whole scan is returned exactly once
+pub fn scan_position<T: Hash + ?Sized>(item: &T) -> u64 {
+	let mut hasher = DefaultHasher::new();
+	item.hash(&mut hasher);
+	hasher.finish().reverse_bits()
+}
+
+// Take about `count` items of a sequence sorted by position, returns them and the cursor of the next page
+// 1. Items sharing the position of the last one taken join the page, a cursor cannot point between them
+// 2. The cursor is 0 once the sequence is exhausted
+pub fn take_page<T>(sorted: impl Iterator<Item = (u64, T)>, count: usize) -> (u64, Vec<T>) {
+	let mut page = Vec::new();
+	let mut last = None;
+	for (position, item) in sorted {
+		if page.len() >= count.max(1) && last != Some(position) {
+			return (position, page);
+		}
+		last = Some(position);
+		page.push(item);
+	}
+	(0, page)
+}
+
+// Items kept in scan order, so a page is a range lookup instead of a sort
+// 1. Items sharing a position stay together in one bucket
+// 2. `shift` drops low bits of the positions, the key index of a shard keeps the top bits of the cursor for the shard number
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScanMap<K, V> {
+	buckets: BTreeMap<u64, Vec<(K, V)>>,
+	len: usize,
+	shift: u32,
+}
+
+impl<K, V> Default for ScanMap<K, V> {
+	fn default() -> Self {
+		ScanMap { buckets: BTreeMap::new(), len: 0, shift: 0 }
+	}
+}
+
+impl<K: Hash + Eq, V> ScanMap<K, V> {
+	pub fn new() -> Self {
+		ScanMap::default()
+	}
+
+	pub fn with_shift(shift: u32) -> Self {
+		ScanMap { buckets: BTreeMap::new(), len: 0, shift }
+	}
+
+	pub fn len(&self) -> usize {
+		self.len
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.len == 0
+	}
+
+	// Where `key` comes in this map, the cursor of a page starting at it
+	pub fn position<Q: Hash + ?Sized>(&self, key: &Q) -> u64 {
+		scan_position(key) >> self.shift
+	}
+
+	pub fn get<Q: Hash + Eq + ?Sized>(&self, key: &Q) -> Option<&V>
+	where
+		K: Borrow<Q>,
+	{
+		let bucket = self.buckets.get(&self.position(key))?;
+		bucket.iter().find(|(k, _)| k.borrow() == key).map(|(_, value)| value)
+	}
+
+	pub fn get_mut<Q: Hash + Eq + ?Sized>(&mut self, key: &Q) -> Option<&mut V>
+	where
+		K: Borrow<Q>,
+	{
+		let position = self.position(key);
+		let bucket = self.buckets.get_mut(&position)?;
+		bucket.iter_mut().find(|(k, _)| k.borrow() == key).map(|(_, value)| value)
+	}
+
+	pub fn contains_key<Q: Hash + Eq + ?Sized>(&self, key: &Q) -> bool
+	where
+		K: Borrow<Q>,
+	{
+		self.get(key).is_some()
+	}
+
+	// Insert or replace, returns the value it replaced
+	pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+		let position = self.position(&key);
+		let bucket = self.buckets.entry(position).or_default();
+		if let Some((_, old)) = bucket.iter_mut().find(|(k, _)| *k == key) {
+			return Some(std::mem::replace(old, value));
+		}
+		bucket.push((key, value));
+		self.len += 1;
+		None
+	}
+
+	pub fn remove<Q: Hash + Eq + ?Sized>(&mut self, key: &Q) -> Option<V>
+	where
+		K: Borrow<Q>,
+	{
+		let position = self.position(key);
+		let bucket = self.buckets.get_mut(&position)?;
+		let index = bucket.iter().position(|(k, _)| k.borrow() == key)?;
+		let (_, value) = bucket.swap_remove(index);
+		if bucket.is_empty() {
+			self.buckets.remove(&position);
+		}
+		self.len -= 1;
+		Some(value)
+	}
+
+	pub fn clear(&mut self) {
+		self.buckets.clear();
+		self.len = 0;
+	}
+
+	// Every item in scan order
+	pub fn iter(&self) -> impl Iterator<Item = (&K, &V)> {
+		self.buckets.values().flatten().map(|(key, value)| (key, value))
+	}
+
+	// About `count` items from `cursor` on and the cursor of the next page, 0 once the map was walked
+	pub fn page(&self, cursor: u64, count: usize) -> (u64, Vec<(&K, &V)>) {
+		let items = self.buckets.range(cursor..).flat_map(|(position, bucket)| bucket.iter().map(move |(key, value)| (*position, (key, value))));
+		take_page(items, count)
+	}
+
+	// Items with a position in `range`, in scan order
+	pub fn range(&self, range: impl RangeBounds<u64>) -> impl Iterator<Item = (&K, &V)> {
+		self.buckets.range(range).flat_map(|(_, bucket)| bucket.iter().map(|(key, value)| (key, value)))
+	}
+
+	// The first item at or after `position`, wrapping around, used to draw items at random
+	pub fn at_or_after(&self, position: u64) -> Option<(&K, &V)> {
+		let mut buckets = self.buckets.range(position..).chain(self.buckets.iter());
+		buckets.next().and_then(|(_, bucket)| bucket.first()).map(|(key, value)| (key, value))
+	}
+}
+
+// The keys of a DashMap in scan order, one `ScanMap` per shard of the map
+// 1. A key is added and removed while its shard of the map is write locked, so the index never disagrees with the map
+// 2. Writers on different shards never wait for each other, a reader locks one index shard at a time and never a map shard with it
+// 3. The top bits of a cursor pick the shard, the rest is the position within it
+#[derive(Debug)]
+pub(crate) struct KeyIndex<K> {
+	shards: Vec<Mutex<ScanMap<K, ()>>>,
+	shard_bits: u32,
+}
+
+impl<K: Hash + Eq + Clone> KeyIndex<K> {
+	// An index lined up with the shards of `map`, their number is a power of two
+	pub(crate) fn for_map<V, S: BuildHasher + Clone>(map: &DashMap<K, V, S>) -> Self {
+		let shards = map.shards().len();
+		let shard_bits = shards.trailing_zeros();
+		KeyIndex { shards: (0..shards).map(|_| Mutex::new(ScanMap::with_shift(shard_bits))).collect(), shard_bits }
+	}
+
+	// `shard` is the one of the key in the map, see `DashMap::determine_map`
+	pub(crate) fn insert(&self, shard: usize, key: K) {
+		self.shards[shard].lock().unwrap().insert(key, ());
+	}
+
+	pub(crate) fn remove<Q: Hash + Eq + ?Sized>(&self, shard: usize, key: &Q)
+	where
+		K: Borrow<Q>,
+	{
+		self.shards[shard].lock().unwrap().remove(key);
+	}
+
+	// Every key of one shard, in scan order
+	pub(crate) fn keys(&self, shard: usize) -> Vec<K> {
+		self.shards[shard].lock().unwrap().iter().map(|(key, _)| key.clone()).collect()
+	}
+
+	fn cursor(&self, shard: usize, position: u64) -> u64 {
+		match self.shard_bits {
+			0 => position,
+			bits => ((shard as u64) << (64 - bits)) | position,
+		}
+	}
+
+	// About `count` keys from `cursor` on and the cursor of the next page, shard after shard
+	pub(crate) fn scan(&self, cursor: u64, count: usize) -> (u64, Vec<K>) {
+		let (mut shard, mut position) = match self.shard_bits {
+			0 => (0, cursor),
+			bits => ((cursor >> (64 - bits)) as usize, cursor & (u64::MAX >> bits)),
+		};
+		let mut page = Vec::new();
+		while shard < self.shards.len() {
+			if !page.is_empty() && page.len() >= count {
+				return (self.cursor(shard, position), page);
+			}
+			let index = self.shards[shard].lock().unwrap();
+			let (next, keys) = index.page(position, count - page.len());
+			page.extend(keys.into_iter().map(|(key, _)| key.clone()));
+			if next != 0 {
+				return (self.cursor(shard, next), page);
+			}
+			shard += 1;
+			position = 0;
+		}
+		(0, page)
+	}
+
+	// The key at or after a random point of a random shard, the next shards are tried when it is empty
+	pub(crate) fn random(&self) -> Option<K> {
+		let draw = random::next_u64();
+		let first = random::below(self.shards.len());
+		(0..self.shards.len()).find_map(|i| {
+			let index = self.shards[(first + i) % self.shards.len()].lock().unwrap();
+			index.at_or_after(draw >> self.shard_bits).map(|(key, _)| key.clone())
+		})
+	}
+
+	// Up to `count` keys in a row from a random point, wrapping around, like `dictGetSomeKeys` in Redis
+	// Fewer only come back when the index holds fewer, so a small keyspace is seen whole
+	pub(crate) fn sample(&self, count: usize) -> Vec<K> {
+		let first = random::below(self.shards.len());
+		let position = random::next_u64() >> self.shard_bits;
+		let mut keys = Vec::with_capacity(count);
+		// The first shard is visited twice, from the point on and again before it once the others were
+		for i in 0..=self.shards.len() {
+			if keys.len() >= count {
+				break;
+			}
+			let index = self.shards[(first + i) % self.shards.len()].lock().unwrap();
+			let left = count - keys.len();
+			match i {
+				0 => keys.extend(index.range(position..).take(left).map(|(key, _)| key.clone())),
+				i if i == self.shards.len() => keys.extend(index.range(..position).take(left).map(|(key, _)| key.clone())),
+				_ => keys.extend(index.iter().take(left).map(|(key, _)| key.clone())),
+			}
+		}
+		keys
+	}
+}
+
+// Drop the members of a page that do not match `pattern`, MATCH filters after the page was taken
+fn matching<T>(page: Vec<(String, T)>, pattern: Option<&str>) -> Vec<(String, T)> {
+	page.into_iter().filter(|(member, _)| pattern.is_none_or(|pattern| glob_match(pattern, member))).collect()
+}
+
+impl CalodStore {
+	// HSCAN, fields with their values
+	pub fn scan_hash(&self, key: &str, cursor: u64, count: usize, pattern: Option<&str>) -> Result<(u64, Vec<(String, String)>), CacheError> {
+		let (next, page) = self.read_value(key, |value| match value {
+			None => Ok((0, Vec::new())),
+			Some(DataType::Hash(hash)) => Ok(hash.scan(cursor, count)),
+			Some(_) => Err(CacheError::WrongType),
+		})?;
+		Ok((next, matching(page, pattern)))
+	}
+
+	// SSCAN
+	pub fn scan_set(&self, key: &str, cursor: u64, count: usize, pattern: Option<&str>) -> Result<(u64, Vec<String>), CacheError> {
+		let (next, page) = self.read_value(key, |value| match value {
+			None => Ok((0, Vec::new())),
+			Some(DataType::Set(set)) => Ok(set.scan(cursor, count)),
+			Some(_) => Err(CacheError::WrongType),
+		})?;
+		Ok((next, page.into_iter().filter(|member| pattern.is_none_or(|pattern| glob_match(pattern, member))).collect()))
+	}
+
+	// ZSCAN, members with their scores
+	pub fn scan_sorted_set(&self, key: &str, cursor: u64, count: usize, pattern: Option<&str>) -> Result<(u64, Vec<(String, f64)>), CacheError> {
+		let (next, page) = self.read_value(key, |value| match value {
+			None => Ok((0, Vec::new())),
+			Some(DataType::SortedSet(sorted_set)) => Ok(sorted_set.scan(cursor, count)),
+			Some(_) => Err(CacheError::WrongType),
+		})?;
+		Ok((next, matching(page, pattern)))
+	}
+}
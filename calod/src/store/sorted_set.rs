@@ -0,0 +1,97 @@
+use std::cmp::Ordering;
+use std::collections::BTreeSet;
+use std::ops::Bound;
+
+use crate::store::scan::ScanMap;
+
+// f64 wrapper with a total order so scores can live in a BTreeSet
+#[derive(Debug, Clone, Copy)]
+pub struct Score(pub f64);
+
+impl Ord for Score {
+	fn cmp(&self, other: &Self) -> Ordering {
+		self.0.total_cmp(&other.0)
+	}
+}
+
+impl PartialOrd for Score {
+	fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+		Some(self.cmp(other))
+	}
+}
+
+impl PartialEq for Score {
+	fn eq(&self, other: &Self) -> bool {
+		self.cmp(other) == Ordering::Equal
+	}
+}
+
+impl Eq for Score {}
+
+// Members ordered by (score, member), their scores are kept in scan order for ZSCAN
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SortedSet {
+	scores: ScanMap<String, f64>,
+	ordered: BTreeSet<(Score, String)>,
+}
+
+impl SortedSet {
+	pub fn new() -> Self {
+		SortedSet { scores: ScanMap::new(), ordered: BTreeSet::new() }
+	}
+
+	pub fn len(&self) -> usize {
+		self.scores.len()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.scores.is_empty()
+	}
+
+	pub fn score(&self, member: &str) -> Option<f64> {
+		self.scores.get(member).copied()
+	}
+
+	// Insert or update a member, returns true if it was newly added
+	pub fn insert(&mut self, member: &str, score: f64) -> bool {
+		match self.scores.insert(member.to_string(), score) {
+			Some(previous) => {
+				self.ordered.remove(&(Score(previous), member.to_string()));
+				self.ordered.insert((Score(score), member.to_string()));
+				false
+			}
+			None => {
+				self.ordered.insert((Score(score), member.to_string()));
+				true
+			}
+		}
+	}
+
+	pub fn remove(&mut self, member: &str) -> bool {
+		match self.scores.remove(member) {
+			Some(score) => {
+				self.ordered.remove(&(Score(score), member.to_string()));
+				true
+			}
+			None => false,
+		}
+	}
+
+	// Iterate members in ascending score order
+	pub fn iter(&self) -> impl Iterator<Item = (&str, f64)> {
+		self.ordered.iter().map(|(score, member)| (member.as_str(), score.0))
+	}
+
+	// Members with `min <= score < max` in ascending order
+	pub fn range_by_score(&self, min: f64, max: f64) -> impl Iterator<Item = (&str, f64)> {
+		let lower = Bound::Included((Score(min), String::new()));
+		let upper = Bound::Excluded((Score(max), String::new()));
+		self.ordered.range((lower, upper)).map(|(score, member)| (member.as_str(), score.0))
+	}
+
+	// ZSCAN, one page of members with their scores and the cursor of the next
+	pub fn scan(&self, cursor: u64, count: usize) -> (u64, Vec<(String, f64)>) {
+		let (next, page) = self.scores.page(cursor, count);
+		(next, page.into_iter().map(|(member, score)| (member.clone(), *score)).collect())
+	}
+}
@@ -0,0 +1,129 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+	use std::fs;
+	use std::sync::Arc;
+	use std::thread;
+	use std::time::Duration;
+
+	use calod::parser::parser::RESPOutput;
+	use calod::persistence::aof::{aof_record, command_record, AppendOnlyFile, FsyncPolicy};
+	use calod::request_response::command::Command;
+	use calod::store::calod_data::DataType;
+	use calod::store::calod_store::CalodStore;
+
+	use crate::common::{set, string};
+
+	fn request(args: &[&str]) -> RESPOutput {
+		RESPOutput::Array(args.iter().map(|a| RESPOutput::BulkString(a.to_string())).collect())
+	}
+
+	fn aof_path(name: &str) -> String {
+		std::env::temp_dir().join(format!("calod-{}-{}.aof", name, std::process::id())).to_string_lossy().to_string()
+	}
+
+	#[test]
+	fn records_only_writes_with_absolute_expiry() {
+		assert_eq!(aof_record(&request(&["GET", "key"]), &Some(Command::GET)), None);
+
+		let record = String::from_utf8(aof_record(&request(&["SET", "key", "value", "EX", "10"]), &Some(Command::SET)).unwrap()).unwrap();
+		assert!(record.starts_with("*5\r\n$3\r\nSET\r\n$3\r\nkey\r\n$5\r\nvalue\r\n$4\r\nPXAT\r\n$13\r\n"));
+	}
+
+	#[test]
+	fn torn_last_record_is_truncated() {
+		let path = aof_path("torn");
+		let complete = "*2\r\n$4\r\nECHO\r\n$1\r\na\r\n*2\r\n$4\r\nECHO\r\n$1\r\nb\r\n";
+		fs::write(&path, format!("{}*2\r\n$4\r\nECHO\r\n$3\r\nab", complete)).unwrap();
+
+		let stats = Arc::new(CalodStore::new(10)).load_aof(&path).unwrap();
+		assert_eq!(stats.commands, 2);
+		assert_eq!(stats.truncated, 20);
+		assert_eq!(fs::read_to_string(&path).unwrap(), complete);
+		fs::remove_file(&path).unwrap();
+	}
+
+	#[test]
+	fn corruption_before_the_end_is_an_error() {
+		let path = aof_path("corrupt");
+		fs::write(&path, "*2\r\n$4\r\nECHO\r\n$1\r\na\r\nGARBAGE\r\n*1\r\n$4\r\nECHO\r\n").unwrap();
+		assert!(Arc::new(CalodStore::new(10)).load_aof(&path).is_err());
+		fs::remove_file(&path).unwrap();
+	}
+
+	#[test]
+	fn rewrite_writes_a_snapshot_preamble() {
+		let path = aof_path("rewrite");
+		let store = Arc::new(CalodStore::new(1000));
+		for i in 0..100 {
+			store.replace_value(&format!("key:{}", i), DataType::String(b"value".to_vec()));
+		}
+		store.enable_aof(AppendOnlyFile::open(store.storage(), &path, FsyncPolicy::Always).unwrap());
+
+		store.bgrewriteaof().unwrap();
+		while store.aof().unwrap().is_rewriting() {
+			thread::sleep(Duration::from_millis(5));
+		}
+		let echo = "*2\r\n$4\r\nECHO\r\n$2\r\nhi\r\n";
+		store.logged_write(Some(echo.as_bytes()), || ());
+
+		let bytes = fs::read(&path).unwrap();
+		assert!(bytes.starts_with(b"CALODSNP"));
+		assert!(bytes.ends_with(echo.as_bytes()));
+
+		let stats = Arc::new(CalodStore::new(1000)).load_aof(&path).unwrap();
+		assert_eq!((stats.keys, stats.commands, stats.truncated), (100, 1, 0));
+		fs::remove_file(&path).unwrap();
+	}
+
+	#[test]
+	fn concurrent_writers_log_in_execution_order() {
+		let path = aof_path("concurrent");
+		let store = Arc::new(CalodStore::new(1000));
+		store.enable_aof(AppendOnlyFile::open(store.storage(), &path, FsyncPolicy::No).unwrap());
+
+		// Writers race on the same keys of three databases, so replaying out of order ends with other values
+		let writers: Vec<_> = (0..8).map(|writer| {
+			let store = store.select(writer % 3).unwrap();
+			thread::spawn(move || {
+				for i in 0..500 {
+					let (key, value) = (format!("key:{}", i % 10), format!("{}:{}", writer, i));
+					let record = command_record(vec![String::from("SET"), key.clone(), value.clone()]);
+					store.logged_write(Some(&record), || set(&store, &key, &value));
+				}
+			})
+		}).collect();
+		writers.into_iter().for_each(|writer| writer.join().unwrap());
+
+		let replayed = Arc::new(CalodStore::new(1000));
+		assert_eq!(replayed.load_aof(&path).unwrap().commands, 8 * 500);
+		for db in 0..3 {
+			let (live, replayed) = (store.select(db).unwrap(), replayed.select(db).unwrap());
+			for i in 0..10 {
+				let key = format!("key:{}", i);
+				assert_eq!(string(&replayed, &key), string(&live, &key));
+			}
+		}
+		fs::remove_file(&path).unwrap();
+	}
+
+	#[test]
+	fn evictions_are_logged_as_a_del() {
+		let path = aof_path("eviction");
+		let store = Arc::new(CalodStore::new(2));
+		store.enable_aof(AppendOnlyFile::open(store.storage(), &path, FsyncPolicy::Always).unwrap());
+		for key in ["a", "b", "c"] {
+			let record = command_record(vec!["SET", key, "1"]);
+			store.logged_write(Some(&record), || set(&store, key, "1"));
+		}
+
+		let replayed = Arc::new(CalodStore::new(100));
+		assert_eq!(replayed.load_aof(&path).unwrap().commands, 4);
+		let (mut kept, mut loaded) = (store.keys_matching("*"), replayed.keys_matching("*"));
+		kept.sort();
+		loaded.sort();
+		assert_eq!(kept, loaded);
+		fs::remove_file(&path).unwrap();
+	}
+}
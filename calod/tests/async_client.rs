@@ -0,0 +1,182 @@
+#[cfg(test)]
+mod tests {
+	use std::collections::{BTreeMap, HashMap};
+	use std::sync::atomic::{AtomicUsize, Ordering};
+	use std::sync::{Arc, Mutex};
+	use std::time::Duration;
+
+	use tokio::io::{AsyncWriteExt, BufReader};
+	use tokio::net::TcpListener;
+
+	use calod::client::async_connection::{ClientOptions, Pipeline};
+	use calod::client::commands::{self, GeoBy, GeoFrom, GeoUnit, SetSlot};
+	use calod::client::pool::Client;
+	use calod::client::reply::{ClientError, FromResp};
+	use calod::client::resp3::read_value;
+	use calod::parser::parser::{RESPOutput, PROTO_MAX_BULK_LEN};
+	use calod::request_response::response_helper::format_resp_output;
+
+	// A server keeping strings in a map, it counts the connections it accepted
+	// `SLEEP` never answers and `QUIT` closes the connection without answering
+	async fn start_server() -> (String, Arc<AtomicUsize>) {
+		let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+		let address = listener.local_addr().unwrap().to_string();
+		let (accepted, keys) = (Arc::new(AtomicUsize::new(0)), Arc::new(Mutex::new(HashMap::new())));
+		let counter = accepted.clone();
+		tokio::spawn(async move {
+			loop {
+				let (stream, _) = listener.accept().await.unwrap();
+				counter.fetch_add(1, Ordering::SeqCst);
+				let keys = keys.clone();
+				tokio::spawn(async move {
+					let (reader, mut writer) = stream.into_split();
+					let mut reader = BufReader::new(reader);
+					while let Ok(request) = read_value(&mut reader).await {
+						let args = Vec::<String>::from_resp(request).unwrap();
+						let reply = match args.iter().map(String::as_str).collect::<Vec<&str>>().as_slice() {
+							["PING"] => RESPOutput::SimpleString(String::from("PONG")),
+							["GET", key] => keys.lock().unwrap().get(*key).cloned().map_or(RESPOutput::Null, RESPOutput::BulkString),
+							["SET", key, value] => {
+								keys.lock().unwrap().insert(key.to_string(), value.to_string());
+								RESPOutput::SimpleString(String::from("OK"))
+							}
+							["SLEEP"] => std::future::pending().await,
+							["QUIT"] => return,
+							_ => RESPOutput::Error(format!("ERR unknown command '{}'", args[0])),
+						};
+						writer.write_all(format_resp_output(&reply).as_bytes()).await.unwrap();
+					}
+				});
+			}
+		});
+		(address, accepted)
+	}
+
+	async fn decode(bytes: &[u8]) -> RESPOutput {
+		let mut reader = bytes;
+		read_value(&mut reader).await.unwrap()
+	}
+
+	#[tokio::test]
+	async fn resp3_replies_decode_into_rust_types() {
+		let reply = RESPOutput::Map(vec![
+			(RESPOutput::SimpleString(String::from("ratio")), RESPOutput::Double(0.5)),
+			(RESPOutput::SimpleString(String::from("flags")), RESPOutput::Set(vec![RESPOutput::Boolean(true), RESPOutput::Boolean(false)])),
+			(RESPOutput::SimpleString(String::from("big")), RESPOutput::BigNumber(String::from("3492890328409238509324850943850943825024385"))),
+			(RESPOutput::SimpleString(String::from("text")), RESPOutput::Verbatim(String::from("txt"), String::from("Some string"))),
+			(RESPOutput::SimpleString(String::from("none")), RESPOutput::Null),
+		]);
+		let encoded = format_resp_output(&reply);
+		assert!(encoded.starts_with("%5\r\n+ratio\r\n,0.5\r\n+flags\r\n~2\r\n#t\r\n#f\r\n"));
+		assert_eq!(decode(encoded.as_bytes()).await, reply);
+
+		// Attributes are skipped, blob errors and RESP3 nulls decode like their RESP2 counterparts
+		assert_eq!(decode(b"|1\r\n+ttl\r\n:3600\r\n:42\r\n").await, RESPOutput::Integer(42));
+		assert_eq!(decode(b"!21\r\nSYNTAX invalid syntax\r\n").await, RESPOutput::Error(String::from("SYNTAX invalid syntax")));
+		assert_eq!(decode(b"_\r\n").await, RESPOutput::Null);
+		assert!(matches!(decode(b",-inf\r\n").await, RESPOutput::Double(num) if num == f64::NEG_INFINITY));
+		assert_eq!(decode(b">2\r\n$7\r\nmessage\r\n$2\r\nhi\r\n").await, RESPOutput::Push(vec![RESPOutput::BulkString(String::from("message")), RESPOutput::BulkString(String::from("hi"))]));
+
+		// Blobs are limited like the server limits bulk strings, before anything is allocated
+		let oversized = format!("${}\r\n", PROTO_MAX_BULK_LEN + 1);
+		let error = read_value(&mut oversized.as_bytes()).await.unwrap_err();
+		assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+		assert!(read_value(&mut &b"!9223372036854775807\r\n"[..]).await.is_err());
+
+		// A map decodes from RESP3 and from a flat RESP2 array alike
+		let flat = decode(b"*4\r\n$1\r\na\r\n:1\r\n$1\r\nb\r\n:2\r\n").await;
+		let expected = BTreeMap::from([(String::from("a"), 1), (String::from("b"), 2)]);
+		assert_eq!(BTreeMap::<String, i64>::from_resp(flat).unwrap(), expected);
+		let map = decode(b"%2\r\n+a\r\n:1\r\n+b\r\n:2\r\n").await;
+		assert_eq!(HashMap::<String, u16>::from_resp(map).unwrap(), expected.iter().map(|(key, value)| (key.clone(), *value as u16)).collect());
+		let coordinates = decode(b"*2\r\n*2\r\n$3\r\n1.5\r\n$4\r\n-2.5\r\n*-1\r\n").await;
+		assert_eq!(Vec::<Option<(f64, f64)>>::from_resp(coordinates).unwrap(), vec![Some((1.5, -2.5)), None]);
+		assert!(matches!(i64::from_resp(RESPOutput::Error(String::from("ERR boom"))), Err(ClientError::Server(e)) if e == "ERR boom"));
+		assert!(matches!(bool::from_resp(RESPOutput::Array(Vec::new())), Err(ClientError::UnexpectedReply(_))));
+	}
+
+	#[test]
+	fn commands_build_their_arguments() {
+		let args = |cmd: &[String]| cmd.join(" ");
+		assert_eq!(args(commands::set_px("key", "value", Duration::from_secs(2)).arguments()), "SET key value PX 2000");
+		assert_eq!(args(commands::geoadd("places", &[(13.361389, 38.115556, "Palermo")]).arguments()), "GEOADD places 13.361389 38.115556 Palermo");
+		let search = commands::geosearch("places", GeoFrom::LonLat(15.0, 37.0), GeoBy::Box(400.0, 400.0, GeoUnit::Kilometers)).arg("ASC").arg("COUNT").arg(1);
+		assert_eq!(args(search.arguments()), "GEOSEARCH places FROMLONLAT 15 37 BYBOX 400 400 km ASC COUNT 1");
+		assert_eq!(args(commands::cluster_setslot(7, SetSlot::Importing("abc")).arguments()), "CLUSTER SETSLOT 7 IMPORTING abc");
+		assert_eq!(args(commands::cluster_addslotsrange(&[(0, 99), (200, 299)]).arguments()), "CLUSTER ADDSLOTSRANGE 0 99 200 299");
+		assert_eq!(args(commands::migrate("10.0.0.2", 6379, &["a", "b"], Duration::from_millis(500)).arguments()), "MIGRATE 10.0.0.2 6379  0 500 KEYS a b");
+		assert_eq!(args(commands::cmd("OBJECT").arg("ENCODING").arg("key").arguments()), "OBJECT ENCODING key");
+	}
+
+	#[tokio::test]
+	async fn clients_pipeline_and_reuse_connections() {
+		let (address, accepted) = start_server().await;
+		let client = Client::with_options(&address, ClientOptions { pool_size: 2, ..ClientOptions::default() });
+
+		assert_eq!(client.query(commands::ping()).await.unwrap(), "PONG");
+		client.query(commands::set("greeting", "hello")).await.unwrap();
+		assert_eq!(client.query(commands::get("greeting")).await.unwrap(), Some(String::from("hello")));
+		assert_eq!(client.query(commands::get("missing")).await.unwrap(), None);
+		assert!(matches!(client.query(commands::lastsave()).await, Err(ClientError::Server(e)) if e == "ERR unknown command 'LASTSAVE'"));
+		assert_eq!(accepted.load(Ordering::SeqCst), 1);
+
+		// Replies come back in order, an error reply does not hide the others
+		let mut pipeline = Pipeline::new();
+		pipeline.add(commands::set("a", "1")).add(commands::get("a")).add(commands::bgsave()).add(commands::get("greeting"));
+		let (set, a, failed, greeting): ((), Option<String>, RESPOutput, String) = client.pipeline(&pipeline).await.unwrap();
+		assert_eq!((set, a, greeting), ((), Some(String::from("1")), String::from("hello")));
+		assert_eq!(failed, RESPOutput::Error(String::from("ERR unknown command 'BGSAVE'")));
+
+		// Concurrent commands share at most `pool_size` connections
+		let tasks: Vec<_> = (0..20)
+			.map(|i| {
+				let client = client.clone();
+				tokio::spawn(async move { client.query(commands::set(&format!("key{}", i), "value")).await })
+			})
+			.collect();
+		for task in tasks {
+			task.await.unwrap().unwrap();
+		}
+		assert!(accepted.load(Ordering::SeqCst) <= 2);
+		assert_eq!(client.idle_connections(), accepted.load(Ordering::SeqCst));
+
+		client.close();
+		assert!(matches!(client.query(commands::ping()).await, Err(ClientError::PoolClosed)));
+		assert_eq!(client.idle_connections(), 0);
+	}
+
+	#[tokio::test]
+	async fn broken_connections_are_replaced() {
+		let (address, accepted) = start_server().await;
+		let options = ClientOptions { response_timeout: Duration::from_millis(200), reconnect_delay: Duration::from_millis(10), ..ClientOptions::default() };
+		let client = Client::with_options(&address, options.clone());
+
+		// A connection the server closed is not given back, the next command opens another
+		assert!(matches!(client.query(commands::cmd("QUIT")).await, Err(ClientError::Io(_))));
+		assert_eq!(client.idle_connections(), 0);
+		assert_eq!(client.query(commands::ping()).await.unwrap(), "PONG");
+		assert_eq!(accepted.load(Ordering::SeqCst), 2);
+
+		// Neither is one whose reply did not come in time
+		let started = std::time::Instant::now();
+		assert!(matches!(client.query(commands::cmd("SLEEP")).await, Err(ClientError::Timeout(_))));
+		assert!(started.elapsed() < Duration::from_secs(2));
+		assert_eq!(client.query(commands::ping()).await.unwrap(), "PONG");
+		assert_eq!(accepted.load(Ordering::SeqCst), 3);
+
+		// Nor one of a command given up on before its reply
+		let mut connection = client.get().await.unwrap();
+		assert!(tokio::time::timeout(Duration::from_millis(50), connection.query(&commands::cmd("SLEEP"))).await.is_err());
+		assert!(!connection.is_connected());
+		drop(connection);
+		assert_eq!(client.query(commands::ping()).await.unwrap(), "PONG");
+		assert_eq!(accepted.load(Ordering::SeqCst), 4);
+
+		// Connecting gives up after the configured attempts
+		let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+		let closed = listener.local_addr().unwrap().to_string();
+		drop(listener);
+		let unreachable = Client::with_options(&closed, ClientOptions { reconnect_attempts: 2, ..options });
+		assert!(matches!(unreachable.query(commands::ping()).await, Err(ClientError::Io(_))));
+	}
+}
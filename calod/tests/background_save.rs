@@ -0,0 +1,102 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+	use std::sync::Arc;
+	use std::thread;
+	use std::time::Duration;
+
+	use calod::persistence::background_save::{parse_save_rules, SaveRule};
+	use calod::persistence::snapshot::SnapshotError;
+	use calod::store::calod_store::{CacheError, CalodStore};
+
+	use crate::common::{set, string};
+
+	fn snapshot_path(name: &str) -> String {
+		std::env::temp_dir().join(format!("calod-{}-{}.snapshot", name, std::process::id())).to_string_lossy().to_string()
+	}
+
+	#[test]
+	fn parses_save_rules() {
+		assert_eq!(parse_save_rules("900 1 60 10000").unwrap(), vec![
+			SaveRule { seconds: 900, changes: 1 },
+			SaveRule { seconds: 60, changes: 10000 },
+		]);
+		assert!(parse_save_rules("900").is_err());
+		assert!(parse_save_rules("").unwrap().is_empty());
+	}
+
+	#[test]
+	fn bgsave_is_point_in_time_while_writes_continue() {
+		let path = snapshot_path("bgsave");
+		let store = Arc::new(CalodStore::new(10_000));
+		store.snapshot_state().set_path(&path);
+		for i in 0..5_000 {
+			set(&store, &format!("key:{}", i), "before");
+		}
+
+		store.bgsave().unwrap();
+		assert!(matches!(store.bgsave(), Err(SnapshotError::SaveInProgress)));
+		for i in 0..5_000 {
+			match i % 3 {
+				0 => set(&store, &format!("key:{}", i), "after"),
+				1 => { store.remove_value(&format!("key:{}", i)); }
+				_ => set(&store, &format!("new:{}", i), "after"),
+			}
+		}
+		while store.snapshot_state().in_progress() {
+			thread::sleep(Duration::from_millis(5));
+		}
+		assert!(store.snapshot_state().last_save_ok());
+
+		let restored = CalodStore::new(10_000);
+		assert_eq!(restored.load_from_file(&path).unwrap(), 5_000);
+		for i in 0..5_000 {
+			assert_eq!(string(&restored, &format!("key:{}", i)).as_deref(), Some("before"));
+			assert_eq!(string(&restored, &format!("new:{}", i)), None);
+		}
+		std::fs::remove_file(&path).unwrap();
+	}
+
+	#[test]
+	fn save_resets_dirty_counter() {
+		let path = snapshot_path("save");
+		let store = CalodStore::new(100);
+		store.snapshot_state().set_path(&path);
+		set(&store, "a", "1");
+		set(&store, "b", "2");
+		assert_eq!(store.snapshot_state().dirty(), 2);
+		assert!(store.snapshot_state().is_due(&[SaveRule { seconds: 0, changes: 2 }]));
+
+		assert_eq!(store.save().unwrap(), 2);
+		assert_eq!(store.snapshot_state().dirty(), 0);
+		assert!(!store.snapshot_state().is_due(&[SaveRule { seconds: 0, changes: 1 }]));
+		std::fs::remove_file(&path).unwrap();
+	}
+
+	#[test]
+	fn swapdb_is_refused_during_a_running_bgsave() {
+		let path = snapshot_path("swapdb");
+		let store = Arc::new(CalodStore::new(10_000));
+		store.snapshot_state().set_path(&path);
+		let other = store.select(1).unwrap();
+		for i in 0..5_000 {
+			set(&store, &format!("zero:{}", i), "0");
+			set(&other, &format!("one:{}", i), "1");
+		}
+
+		// The snapshot keeps the layout it started with, the swap only happens once it is written
+		store.bgsave().unwrap();
+		assert!(matches!(store.swap_databases(0, 1), Err(CacheError::SnapshotInProgress)));
+		store.snapshot_state().wait_until_idle();
+		store.swap_databases(0, 1).unwrap();
+		assert!(store.snapshot_state().last_save_ok());
+		assert_eq!(string(&store, "one:0").as_deref(), Some("1"));
+
+		let restored = CalodStore::new(10_000);
+		assert_eq!(restored.load_from_file(&path).unwrap(), 10_000);
+		assert_eq!(string(&restored, "zero:0").as_deref(), Some("0"));
+		assert_eq!(string(&restored.select(1).unwrap(), "one:0").as_deref(), Some("1"));
+		std::fs::remove_file(&path).unwrap();
+	}
+}
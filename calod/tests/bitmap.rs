@@ -0,0 +1,65 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+	use calod::request_response::bitmap_handler;
+	use calod::request_response::command::Command;
+	use calod::store::bitmap::*;
+	use calod::store::calod_store::CalodStore;
+
+	use crate::common::command;
+
+	#[test]
+	fn set_bit_grows_value_and_returns_previous_bit() {
+		let mut bytes = Vec::new();
+		assert_eq!(set_bit(&mut bytes, 7, 1), 0);
+		assert_eq!(set_bit(&mut bytes, 7, 1), 1);
+		assert_eq!(bytes, vec![1]);
+		assert_eq!(get_bit(&bytes, 100), 0);
+	}
+
+	#[test]
+	fn bit_count_with_byte_and_bit_ranges() {
+		let bytes = b"foobar";
+		assert_eq!(bit_count(bytes, None), 26);
+		assert_eq!(bit_count(bytes, Some((1, 1, BitRangeUnit::Byte))), 6);
+		assert_eq!(bit_count(bytes, Some((5, 30, BitRangeUnit::Bit))), 17);
+	}
+
+	#[test]
+	fn bit_pos_treats_value_as_zero_padded_without_end() {
+		assert_eq!(bit_pos(&[0xff, 0xf0, 0x00], 0, None, None, BitRangeUnit::Byte), 12);
+		assert_eq!(bit_pos(&[0xff, 0xff], 0, None, None, BitRangeUnit::Byte), 16);
+		assert_eq!(bit_pos(&[0xff, 0xff], 0, Some(0), Some(-1), BitRangeUnit::Byte), -1);
+		assert_eq!(bit_pos(&[0x00, 0xff, 0xf0], 1, Some(7), Some(15), BitRangeUnit::Bit), 8);
+	}
+
+	#[test]
+	fn bitfield_overflow_policies() {
+		let u2 = BitfieldType::parse("u2").unwrap();
+		let ops = [
+			BitfieldOp::IncrBy(u2, 100, 1),
+			BitfieldOp::Overflow(BitfieldOverflow::Sat),
+			BitfieldOp::IncrBy(u2, 102, 1),
+		];
+		let mut bytes = Vec::new();
+
+		for _ in 0..3 {
+			bitfield(&mut bytes, &ops);
+		}
+		assert_eq!(bitfield(&mut bytes, &ops), vec![Some(0), Some(3)]);
+		assert_eq!(bitfield(&mut bytes, &[BitfieldOp::Overflow(BitfieldOverflow::Fail), BitfieldOp::IncrBy(u2, 102, 1)]), vec![None]);
+	}
+
+	#[test]
+	fn bitfield_rejects_offsets_past_the_last_bit() {
+		let store = CalodStore::new(100);
+		let reply = command(bitmap_handler::respond, &store, Command::BITFIELD, &["k", "SET", "u8", "18446744073709551615", "1"]);
+		assert_eq!(reply, "-ERR bit offset is not an integer or out of range\r\n");
+		assert!(!store.contains_key("k"));
+
+		let u8 = BitfieldType::parse("u8").unwrap();
+		assert_eq!(parse_bitfield_offset(&(MAX_BIT_OFFSET - 7).to_string(), u8), Some(MAX_BIT_OFFSET - 7));
+		assert_eq!(parse_bitfield_offset(&(MAX_BIT_OFFSET - 6).to_string(), u8), None);
+	}
+}
@@ -0,0 +1,146 @@
+#[cfg(test)]
+mod tests {
+	use std::sync::Arc;
+	use std::thread;
+	use std::time::Duration;
+
+	use calod::store::cache::{Cache, EvictionPolicy};
+	use calod::store::calod_data::DataType;
+	use calod::store::calod_store::CalodStore;
+
+	// Accesses a millisecond apart, so recency tells them apart
+	fn pause() {
+		thread::sleep(Duration::from_millis(5));
+	}
+
+	#[test]
+	fn full_caches_evict_by_policy() {
+		let lru: Cache<String, u32> = Cache::builder().capacity(2).policy(EvictionPolicy::Lru).build();
+		lru.insert(String::from("a"), 1);
+		pause();
+		lru.insert(String::from("b"), 2);
+		pause();
+		assert_eq!(lru.get("a"), Some(1));
+		pause();
+		assert_eq!(lru.insert(String::from("c"), 3), None);
+		assert_eq!((lru.get("a"), lru.get("b"), lru.get("c")), (Some(1), None, Some(3)));
+		assert_eq!(lru.len(), 2);
+
+		// Replacing a key never evicts
+		assert_eq!(lru.insert(String::from("c"), 4), Some(3));
+		assert!(lru.contains_key("a"));
+
+		let lfu: Cache<&str, u32> = Cache::builder().capacity(2).policy(EvictionPolicy::Lfu).build();
+		lfu.insert("a", 1);
+		lfu.insert("b", 2);
+		for _ in 0..3 {
+			lfu.get("b");
+		}
+		lfu.get("a");
+		lfu.insert("c", 3);
+		assert_eq!((lfu.get("a"), lfu.get("b")), (None, Some(2)));
+		assert_eq!(lfu.remove("b"), Some(2));
+		assert_eq!(lfu.remove("b"), None);
+	}
+
+	#[test]
+	fn entries_expire() {
+		let cache: Cache<String, String> = Cache::builder().time_to_live(Duration::from_millis(50)).build();
+		cache.insert(String::from("short"), String::from("lived"));
+		cache.insert_with_ttl(String::from("long"), String::from("lived"), Duration::from_secs(60));
+		assert!(cache.time_to_live("short").unwrap() <= Duration::from_millis(50));
+		assert!(cache.time_to_live("long").unwrap() > Duration::from_secs(59));
+
+		thread::sleep(Duration::from_millis(80));
+		assert!(!cache.contains_key("short"));
+		assert_eq!(cache.get("short"), None);
+		assert_eq!(cache.get("long"), Some(String::from("lived")));
+		cache.insert_with_ttl(String::from("gone"), String::from("soon"), Duration::from_millis(1));
+		thread::sleep(Duration::from_millis(5));
+		assert_eq!(cache.purge_expired(), 1);
+		assert_eq!(cache.len(), 1);
+
+		// The weighted policy evicts an expired entry before a cold one
+		let weighted: Cache<&str, u8> = Cache::builder().capacity(2).policy(EvictionPolicy::Weighted).build();
+		weighted.insert("cold", 0);
+		pause();
+		weighted.insert_with_ttl("expired", 1, Duration::from_millis(1));
+		pause();
+		weighted.insert("new", 2);
+		assert_eq!((weighted.get("cold"), weighted.get("new")), (Some(0), Some(2)));
+	}
+
+	#[test]
+	fn entries_update_in_place_across_threads() {
+		let cache: Arc<Cache<String, u64>> = Arc::new(Cache::new(100));
+		let workers: Vec<_> = (0..8)
+			.map(|_| {
+				let cache = cache.clone();
+				thread::spawn(move || {
+					for i in 0..1000 {
+						*cache.entry(format!("counter{}", i % 10)).or_insert(0) += 1;
+					}
+				})
+			})
+			.collect();
+		for worker in workers {
+			worker.join().unwrap();
+		}
+		assert!((0..10).all(|i| cache.get(&format!("counter{}", i)) == Some(800)));
+
+		let entry = cache.entry(String::from("counter0")).and_modify(|count| *count = 0);
+		assert!(entry.is_occupied());
+		assert_eq!(*entry.or_default(), 0);
+		assert_eq!(*cache.entry(String::from("fresh")).or_insert_with(|| 7), 7);
+		*cache.get_mut("fresh").unwrap() *= 2;
+		assert_eq!(cache.get("fresh"), Some(14));
+	}
+
+	#[test]
+	fn the_server_store_evicts_like_the_cache() {
+		let store = CalodStore::new(2);
+		store.set_eviction_policy(EvictionPolicy::Lru);
+		store.replace_value("a", DataType::String(b"1".to_vec()));
+		pause();
+		store.replace_value("b", DataType::String(b"2".to_vec()));
+		pause();
+		store.update_value("a", || DataType::String(Vec::new()), |_| ());
+		pause();
+		store.replace_value("c", DataType::String(b"3".to_vec()));
+		assert!(store.contains_key("a") && !store.contains_key("b") && store.contains_key("c"));
+
+		// Overwriting keeps every key
+		store.replace_value("c", DataType::String(b"4".to_vec()));
+		assert!(store.contains_key("a") && store.contains_key("c"));
+	}
+
+	#[test]
+	fn concurrent_inserts_never_overshoot_the_capacity() {
+		let cache: Arc<Cache<String, usize>> = Arc::new(Cache::builder().capacity(64).policy(EvictionPolicy::Lru).build());
+		let store = Arc::new(CalodStore::new(64));
+		let workers: Vec<_> = (0..8)
+			.map(|worker| {
+				let (cache, store) = (cache.clone(), store.clone());
+				thread::spawn(move || {
+					for i in 0..2000 {
+						let key = format!("{}:{}", worker, i);
+						cache.insert(key.clone(), i);
+						store.replace_value(&key, DataType::String(Vec::new()));
+					}
+				})
+			})
+			.collect();
+		for worker in workers {
+			worker.join().unwrap();
+		}
+		assert_eq!((cache.len(), store.db_size()), (64, 64));
+
+		// Evicted keys left the index too, and cleared ones give their room back
+		assert_eq!(store.scan_keys(0, 1000, None, None).1.len(), 64);
+		cache.clear();
+		for i in 0..64 {
+			cache.insert(i.to_string(), i);
+		}
+		assert!((0..64).all(|i| cache.contains_key(&i.to_string())));
+	}
+}
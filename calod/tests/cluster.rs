@@ -0,0 +1,119 @@
+#[cfg(test)]
+mod tests {
+	use std::ops::Range;
+
+	use calod::cluster::slots::{crc16, key_slot, slot_ranges};
+	use calod::cluster::state::{ClusterState, RouteError};
+	use calod::request_response::cluster_handler;
+	use calod::request_response::command::Command;
+	use calod::store::calod_data::DataType;
+	use calod::store::calod_store::CalodStore;
+
+	const OTHER: &str = "0000000000000000000000000000000000000001";
+
+	// We serve the lower half of the slots at 127.0.0.1:7000, OTHER the upper half
+	fn two_nodes() -> ClusterState {
+		let cluster = ClusterState::new();
+		cluster.enable("127.0.0.1:7000");
+		cluster.add_node(OTHER, "127.0.0.1:7001");
+		cluster.add_slots(&(0..8192).collect::<Vec<u16>>()).unwrap();
+		for slot in 8192..16384 {
+			cluster.assign_slot(slot, OTHER).unwrap();
+		}
+		cluster
+	}
+
+	fn key_in(slots: Range<u16>) -> String {
+		(0..).map(|i| format!("key{}", i)).find(|key| slots.contains(&key_slot(key))).unwrap()
+	}
+
+	fn cluster_command(store: &CalodStore, args: &[&str]) -> String {
+		let mut out = Vec::new();
+		cluster_handler::respond(store, &mut out, &Command::CLUSTER, &args.iter().map(|arg| arg.to_string()).collect::<Vec<_>>());
+		String::from_utf8(out).unwrap()
+	}
+
+	#[test]
+	fn key_slots_follow_hash_tags() {
+		assert_eq!(crc16(b"123456789"), 0x31c3);
+		assert_eq!(key_slot("foo"), 12182);
+		assert_eq!(key_slot("{user1000}.following"), key_slot("{user1000}.followers"));
+		assert_eq!(key_slot("{user1000}.following"), key_slot("user1000"));
+
+		// Empty tags do not count, only the first pair of braces does
+		assert_eq!(key_slot("foo{}{bar}"), crc16(b"foo{}{bar}") % 16384);
+		assert_eq!(key_slot("foo{{bar}}zap"), key_slot("{bar"));
+		assert_eq!(slot_ranges([1, 2, 3, 7, 9, 10]), vec![(1, 3), (7, 7), (9, 10)]);
+	}
+
+	#[test]
+	fn keys_of_other_nodes_are_redirected() {
+		let cluster = two_nodes();
+		let (mine, theirs) = (key_in(0..8192), key_in(8192..16384));
+		let absent = |_: &str| false;
+		assert_eq!(cluster.route(&[&mine], false, absent), Ok(()));
+		assert_eq!(cluster.route(&[&theirs], false, absent), Err(RouteError::Moved(key_slot(&theirs), String::from("127.0.0.1:7001"))));
+		assert_eq!(cluster.route(&[&mine, &theirs], false, absent), Err(RouteError::CrossSlot));
+		assert_eq!(cluster.route(&[], false, absent), Ok(()));
+
+		// Keys that already left a migrating slot are looked up on the target
+		let slot = key_slot(&mine);
+		let sibling = format!("{{{}}}.other", mine);
+		cluster.set_migrating(slot, OTHER).unwrap();
+		assert_eq!(cluster.route(&[&mine], false, |_| true), Ok(()));
+		assert_eq!(cluster.route(&[&mine], false, absent), Err(RouteError::Ask(slot, String::from("127.0.0.1:7001"))));
+		assert_eq!(cluster.route(&[&mine, &sibling], false, |key| key == mine), Err(RouteError::TryAgain));
+
+		// A slot being imported is only served after ASKING
+		cluster.set_importing(key_slot(&theirs), OTHER).unwrap();
+		assert!(matches!(cluster.route(&[&theirs], false, absent), Err(RouteError::Moved(..))));
+		assert_eq!(cluster.route(&[&theirs], true, absent), Ok(()));
+
+		// Outside cluster mode every key is served, in it unassigned slots are refused
+		assert_eq!(ClusterState::new().route(&[&theirs], false, absent), Ok(()));
+		cluster.del_slots(&[slot]).unwrap();
+		assert_eq!(cluster.route(&[&mine], false, |_| true), Err(RouteError::Unassigned));
+	}
+
+	#[test]
+	fn config_round_trips_through_the_nodes_format() {
+		let cluster = two_nodes();
+		cluster.set_migrating(5, OTHER).unwrap();
+		cluster.set_importing(9000, OTHER).unwrap();
+		let text = cluster.config_text();
+
+		let loaded = ClusterState::new();
+		loaded.load_config(&text).unwrap();
+		assert_eq!(loaded.config_text(), text);
+		assert_eq!(loaded.myself(), cluster.myself());
+		let table: Vec<(u16, u16, String)> = loaded.slot_table().into_iter().map(|(start, end, node)| (start, end, node.address)).collect();
+		assert_eq!(table, vec![(0, 8191, String::from("127.0.0.1:7000")), (8192, 16383, String::from("127.0.0.1:7001"))]);
+		assert!(loaded.info_text().contains("cluster_state:ok\r\n"));
+		assert!(loaded.info_text().contains("cluster_known_nodes:2\r\n"));
+		assert!(loaded.load_config("garbage").is_err());
+	}
+
+	#[test]
+	fn cluster_commands() {
+		let store = CalodStore::new(usize::MAX);
+		assert!(cluster_command(&store, &["INFO"]).starts_with("-ERR This instance has cluster support disabled"));
+
+		store.cluster().enable("127.0.0.1:7000");
+		assert_eq!(cluster_command(&store, &["KEYSLOT", "foo"]), ":12182\r\n");
+		assert_eq!(cluster_command(&store, &["ADDSLOTSRANGE", "0", "99"]), "+OK\r\n");
+		assert!(cluster_command(&store, &["ADDSLOTS", "99"]).starts_with("-ERR Slot 99 is already busy"));
+		assert_eq!(cluster_command(&store, &["DELSLOTS", "99"]), "+OK\r\n");
+
+		let id = store.cluster().myself().id;
+		assert_eq!(cluster_command(&store, &["SLOTS"]), format!("*1\r\n*3\r\n:0\r\n:98\r\n*3\r\n$9\r\n127.0.0.1\r\n:7000\r\n$40\r\n{}\r\n", id));
+		assert!(cluster_command(&store, &["NODES"]).contains(&format!("{} 127.0.0.1:7000@17000 myself,master - 0 0 0 connected 0-98\n", id)));
+		assert!(cluster_command(&store, &["ADDSLOTS", "16384"]).starts_with("-ERR Invalid or out of range slot"));
+		assert!(cluster_command(&store, &["KEYSLOT"]).starts_with("-ERR wrong number of arguments"));
+
+		store.replace_value("foo", DataType::String(b"bar".to_vec()));
+		assert_eq!(cluster_command(&store, &["COUNTKEYSINSLOT", "12182"]), ":1\r\n");
+		assert_eq!(cluster_command(&store, &["GETKEYSINSLOT", "12182", "10"]), "*1\r\n$3\r\nfoo\r\n");
+		assert!(cluster_command(&store, &["SETSLOT", "5", "MIGRATING", "unknown"]).starts_with("-ERR Unknown node unknown"));
+		assert!(cluster_command(&store, &["SETSLOT", "5", "STABLE", "extra"]).starts_with("-ERR Invalid CLUSTER SETSLOT action"));
+	}
+}
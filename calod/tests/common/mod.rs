@@ -0,0 +1,55 @@
+// Helpers shared by the integration tests, every test file uses only some of them
+#![allow(dead_code)]
+
+use std::net::TcpListener;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use calod::handle_connection;
+use calod::request_response::command::Command;
+use calod::store::calod_data::DataType;
+use calod::store::calod_store::CalodStore;
+
+// The signature every command handler shares
+pub type Respond = fn(&CalodStore, &mut Vec<u8>, &Command, &[String]);
+
+pub fn set(store: &CalodStore, key: &str, value: &str) {
+	store.replace_value(key, DataType::String(value.as_bytes().to_vec()));
+}
+
+// `None` for a missing key and for other types
+pub fn string(store: &CalodStore, key: &str) -> Option<String> {
+	store.read_value(key, |value| match value {
+		Some(DataType::String(bytes)) => Some(String::from_utf8(bytes.clone()).unwrap()),
+		_ => None,
+	})
+}
+
+// The reply of a handler such as `keyspace_handler::respond`
+pub fn command(respond: Respond, store: &CalodStore, command: Command, args: &[&str]) -> String {
+	let mut out = Vec::new();
+	respond(store, &mut out, &command, &args.iter().map(|arg| arg.to_string()).collect::<Vec<_>>());
+	String::from_utf8(out).unwrap()
+}
+
+// Serve `store` on a local port the way the server does, a thread per connection, returns the address
+pub fn serve(store: Arc<CalodStore>) -> String {
+	let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+	let address = listener.local_addr().unwrap().to_string();
+	thread::spawn(move || {
+		for stream in listener.incoming() {
+			let store = store.clone();
+			thread::spawn(move || handle_connection(stream.unwrap(), store));
+		}
+	});
+	address
+}
+
+pub fn wait_for(mut condition: impl FnMut() -> bool) {
+	let deadline = Instant::now() + Duration::from_secs(5);
+	while !condition() {
+		assert!(Instant::now() < deadline, "timed out");
+		thread::sleep(Duration::from_millis(10));
+	}
+}
@@ -0,0 +1,210 @@
+#[cfg(test)]
+mod tests {
+	use std::collections::HashMap;
+	use std::sync::Arc;
+	use std::thread;
+	use std::time::Duration;
+
+	use calod::parser::parser::RESPOutput;
+	use calod::persistence::aof::{aof_record, AofLoadStats, AppendOnlyFile, FsyncPolicy};
+	use calod::persistence::sim_storage::{CrashMode, SimStorage};
+	use calod::request_response::command::Command;
+	use calod::store::calod_data::DataType;
+	use calod::store::calod_store::CalodStore;
+
+	const SNAPSHOT: &str = "calod.snapshot";
+	const AOF: &str = "calod.aof";
+	const KEYS: u64 = 16;
+
+	// The writes the store acknowledged, in order, over the keys `<prefix>:0` .. `<prefix>:15`
+	struct Model {
+		prefix: String,
+		writes: Vec<(String, String)>,
+		rng: u64,
+	}
+
+	impl Model {
+		fn new(prefix: &str, seed: u64) -> Self {
+			Model { prefix: prefix.to_string(), writes: Vec::new(), rng: seed | 1 }
+		}
+
+		fn keys(&self) -> Vec<String> {
+			(0..KEYS).map(|i| format!("{}:{}", self.prefix, i)).collect()
+		}
+
+		// The dataset after the first `n` acknowledged writes
+		fn state_after(&self, n: usize) -> HashMap<String, String> {
+			self.writes[..n].iter().cloned().collect()
+		}
+
+		fn state(&self) -> HashMap<String, String> {
+			self.state_after(self.writes.len())
+		}
+
+		// SET a random key like a client would and record it once the store returns
+		fn write(&mut self, store: &CalodStore) {
+			self.rng ^= self.rng << 13;
+			self.rng ^= self.rng >> 7;
+			self.rng ^= self.rng << 17;
+			let key = format!("{}:{}", self.prefix, self.rng % KEYS);
+			let value = format!("value-{}", self.writes.len());
+
+			let request = RESPOutput::Array(vec![
+				RESPOutput::BulkString(String::from("SET")),
+				RESPOutput::BulkString(key.clone()),
+				RESPOutput::BulkString(value.clone()),
+			]);
+			let record = aof_record(&request, &Some(Command::SET));
+			store.logged_write(record.as_deref(), || store.replace_value(&key, DataType::String(value.clone().into_bytes())));
+			self.writes.push((key, value));
+		}
+	}
+
+	fn dataset(store: &CalodStore, model: &Model) -> HashMap<String, String> {
+		model.keys().into_iter().filter_map(|key| {
+			let value = store.read_value(&key, |value| match value {
+				Some(DataType::String(bytes)) => Some(String::from_utf8_lossy(bytes).to_string()),
+				_ => None,
+			})?;
+			Some((key, value))
+		}).collect()
+	}
+
+	fn writer(sim: &SimStorage) -> Arc<CalodStore> {
+		Arc::new(CalodStore::with_storage(1000, Arc::new(sim.clone())))
+	}
+
+	// Logged commands replay through the command handlers into a fresh store
+	fn recover_aof(sim: &SimStorage, model: &Model) -> (AofLoadStats, HashMap<String, String>) {
+		let server = writer(sim);
+		let stats = server.load_aof(AOF).unwrap();
+		(stats, dataset(&server, model))
+	}
+
+	#[test]
+	fn snapshot_save_is_all_or_nothing() {
+		for budget in (0..).step_by(97) {
+			let sim = SimStorage::new();
+			let store = writer(&sim);
+			let mut model = Model::new("snapshot", budget + 1);
+
+			for _ in 0..50 {
+				model.write(&store);
+			}
+			store.save_to_file(SNAPSHOT).unwrap();
+			let saved = model.state();
+			for _ in 0..50 {
+				model.write(&store);
+			}
+
+			// The disk fills up somewhere during the second save, or syncs fail on the last try
+			sim.fail_writes_after(budget);
+			sim.fail_syncs(budget > 2000);
+			let result = store.save_to_file(SNAPSHOT);
+			sim.crash(CrashMode::TornUnsynced(budget));
+
+			let recovered = CalodStore::with_storage(1000, Arc::new(sim.clone()));
+			recovered.load_from_file(SNAPSHOT).unwrap();
+			let expected = if result.is_ok() { model.state() } else { saved };
+			assert_eq!(dataset(&recovered, &model), expected, "write budget {}", budget);
+
+			if budget > 2000 {
+				assert!(result.is_err());
+				break;
+			}
+		}
+	}
+
+	#[test]
+	fn damaged_snapshot_never_loads() {
+		let sim = SimStorage::new();
+		let store = writer(&sim);
+		let mut model = Model::new("damaged", 7);
+		for _ in 0..30 {
+			model.write(&store);
+		}
+		store.save_to_file(SNAPSHOT).unwrap();
+		let original = sim.read(SNAPSHOT).unwrap();
+
+		for offset in 0..original.len() {
+			sim.write(SNAPSHOT, &original);
+			sim.corrupt(SNAPSHOT, offset);
+			assert!(CalodStore::with_storage(1000, Arc::new(sim.clone())).load_from_file(SNAPSHOT).is_err(), "corrupt byte {}", offset);
+
+			sim.write(SNAPSHOT, &original);
+			sim.truncate_at(SNAPSHOT, offset);
+			assert!(CalodStore::with_storage(1000, Arc::new(sim.clone())).load_from_file(SNAPSHOT).is_err(), "truncated at {}", offset);
+		}
+	}
+
+	#[test]
+	fn aof_always_keeps_every_acknowledged_write() {
+		for seed in 1..=20 {
+			let sim = SimStorage::new();
+			let store = writer(&sim);
+			store.enable_aof(AppendOnlyFile::open(store.storage(), AOF, FsyncPolicy::Always).unwrap());
+			let mut model = Model::new("always", seed);
+
+			for i in 0..40 {
+				model.write(&store);
+				// Rewrite half way so recovery also goes through the snapshot preamble
+				if i == 20 && seed % 2 == 0 {
+					store.bgrewriteaof().unwrap();
+				}
+			}
+			while store.aof().unwrap().is_rewriting() {
+				thread::sleep(Duration::from_millis(1));
+			}
+
+			sim.crash(CrashMode::TornUnsynced(seed));
+			let (_, recovered) = recover_aof(&sim, &model);
+			assert_eq!(recovered, model.state(), "seed {}", seed);
+		}
+	}
+
+	#[test]
+	fn aof_without_fsync_recovers_a_prefix() {
+		for seed in 1..=20 {
+			let sim = SimStorage::new();
+			let store = writer(&sim);
+			store.enable_aof(AppendOnlyFile::open(store.storage(), AOF, FsyncPolicy::No).unwrap());
+			let mut model = Model::new("no-fsync", seed);
+
+			for _ in 0..30 {
+				model.write(&store);
+			}
+			store.aof().unwrap().fsync().unwrap();
+			for _ in 0..30 {
+				model.write(&store);
+			}
+
+			sim.crash(CrashMode::TornUnsynced(seed));
+			let (stats, recovered) = recover_aof(&sim, &model);
+			let replayed = stats.commands as usize;
+			assert!((30..=60).contains(&replayed), "seed {} replayed {}", seed, replayed);
+			assert_eq!(recovered, model.state_after(replayed), "seed {}", seed);
+
+			// The torn tail was cut off, the next start finds a clean log
+			assert_eq!(recover_aof(&sim, &model).0.truncated, 0);
+		}
+	}
+
+	#[test]
+	fn aof_cut_at_any_offset_recovers_a_prefix() {
+		let sim = SimStorage::new();
+		let store = writer(&sim);
+		store.enable_aof(AppendOnlyFile::open(store.storage(), AOF, FsyncPolicy::Always).unwrap());
+		let mut model = Model::new("cut", 3);
+		for _ in 0..10 {
+			model.write(&store);
+		}
+		let original = sim.read(AOF).unwrap();
+
+		for cut in 0..=original.len() {
+			sim.write(AOF, &original[..cut]);
+			let (stats, recovered) = recover_aof(&sim, &model);
+			assert_eq!(recovered, model.state_after(stats.commands as usize), "cut at {}", cut);
+			assert_eq!(sim.read(AOF).unwrap().len() as u64, cut as u64 - stats.truncated);
+		}
+	}
+}
@@ -0,0 +1,200 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+	use std::fs;
+	use std::io::{BufRead, BufReader, Write};
+	use std::net::{TcpListener, TcpStream};
+	use std::sync::atomic::{AtomicBool, Ordering};
+	use std::sync::Arc;
+	use std::thread;
+	use std::time::Duration;
+
+	use calod::client::commands;
+	use calod::client::pool::Client;
+	use calod::persistence::aof::{command_record, select_record, AppendOnlyFile, FsyncPolicy};
+	use calod::store::calod_store::{CacheError, CalodStore, DEFAULT_DATABASES};
+
+	use crate::common::{serve, set, string, wait_for};
+
+	// A client write, logged and fed to the followers with the database of `store`
+	fn write(store: &CalodStore, key: &str, value: &str) {
+		let record = command_record(vec![String::from("SET"), key.to_string(), value.to_string()]);
+		store.logged_write(Some(&record), || set(store, key, value));
+	}
+
+	fn temp_path(name: &str) -> String {
+		std::env::temp_dir().join(format!("calod-databases-{}-{}", name, std::process::id())).to_string_lossy().to_string()
+	}
+
+	#[test]
+	fn databases_are_separate_keyspaces() {
+		let store = Arc::new(CalodStore::new(100));
+		let other = store.select(1).unwrap();
+		assert_eq!(store.database_count(), DEFAULT_DATABASES);
+		assert!(matches!(store.select(DEFAULT_DATABASES), Err(CacheError::DbIndexOutOfRange)));
+
+		set(&store, "key", "zero");
+		set(&other, "key", "one");
+		set(&other, "extra", "one");
+		assert_eq!((string(&store, "key").as_deref(), string(&other, "key").as_deref()), (Some("zero"), Some("one")));
+		assert_eq!((store.db_size(), other.db_size()), (1, 2));
+
+		other.flush_db();
+		assert_eq!((store.db_size(), other.db_size()), (1, 0));
+		set(&other, "key", "one");
+		store.flush_all();
+		assert_eq!((store.db_size(), other.db_size()), (0, 0));
+
+		// SELECT only changes the database of its own connection
+		let mut client = TcpStream::connect(serve(store.clone())).unwrap();
+		let mut reader = BufReader::new(client.try_clone().unwrap());
+		for request in [select_record(1), command_record(vec![String::from("SET"), String::from("key"), String::from("selected")])] {
+			client.write_all(&request).unwrap();
+			let mut reply = String::new();
+			reader.read_line(&mut reply).unwrap();
+			assert_eq!(reply, "+OK\r\n");
+		}
+		assert_eq!((string(&store, "key"), string(&other, "key").as_deref()), (None, Some("selected")));
+	}
+
+	#[test]
+	fn move_and_swapdb() {
+		let store = CalodStore::new(100);
+		let other = store.select(2).unwrap();
+		set(&store, "moved", "a");
+		set(&store, "taken", "b");
+		set(&other, "taken", "c");
+
+		assert!(store.move_key("moved", 2).unwrap());
+		assert!(!store.move_key("taken", 2).unwrap());
+		assert!(!store.move_key("missing", 2).unwrap());
+		assert!(store.move_key("taken", 0).is_err());
+		assert_eq!((string(&store, "moved"), string(&other, "moved").as_deref()), (None, Some("a")));
+		assert_eq!((string(&store, "taken").as_deref(), string(&other, "taken").as_deref()), (Some("b"), Some("c")));
+
+		// Handles keep their index, so they see the swapped keyspace
+		store.swap_databases(0, 2).unwrap();
+		assert_eq!((string(&store, "moved").as_deref(), string(&other, "taken").as_deref()), (Some("a"), Some("b")));
+
+		// A swap is refused while a snapshot is running
+		store.snapshot_state().begin().unwrap();
+		assert!(matches!(store.swap_databases(0, 2), Err(CacheError::SnapshotInProgress)));
+		store.snapshot_state().release();
+		store.swap_databases(0, 2).unwrap();
+		assert_eq!(string(&store, "taken").as_deref(), Some("b"));
+		assert!(store.swap_databases(0, DEFAULT_DATABASES).is_err());
+	}
+
+	#[tokio::test]
+	async fn swapdb_waits_for_a_snapshot_without_blocking_writes() {
+		let store = Arc::new(CalodStore::new(100));
+		let client = Client::new(&serve(store.clone()));
+		client.query(commands::set("key", "zero")).await.unwrap();
+
+		store.snapshot_state().begin().unwrap();
+		let swapped = Arc::new(AtomicBool::new(false));
+		let swap = tokio::spawn({
+			let (client, swapped) = (client.clone(), swapped.clone());
+			async move {
+				client.query(commands::swapdb(0, 1)).await.unwrap();
+				swapped.store(true, Ordering::SeqCst);
+			}
+		});
+		tokio::time::sleep(Duration::from_millis(50)).await;
+		client.query(commands::set("other", "zero")).await.unwrap();
+		assert!(!swapped.load(Ordering::SeqCst));
+
+		store.snapshot_state().release();
+		swap.await.unwrap();
+		assert_eq!(string(&store.select(1).unwrap(), "other").as_deref(), Some("zero"));
+	}
+
+	#[test]
+	fn snapshots_and_the_aof_keep_keys_in_their_database() {
+		let path = temp_path("snapshot");
+		let store = Arc::new(CalodStore::new(100));
+		set(&store, "key", "zero");
+		set(&store.select(3).unwrap(), "key", "three");
+		assert_eq!(store.save_to_file(&path).unwrap(), 2);
+
+		let loaded = CalodStore::new(100);
+		assert_eq!(loaded.load_from_file(&path).unwrap(), 2);
+		assert_eq!((string(&loaded, "key").as_deref(), string(&loaded.select(3).unwrap(), "key").as_deref()), (Some("zero"), Some("three")));
+		fs::remove_file(&path).unwrap();
+
+		// Writes are logged with a SELECT whenever the database changes
+		let path = temp_path("aof");
+		store.enable_aof(AppendOnlyFile::open(store.storage(), &path, FsyncPolicy::Always).unwrap());
+		write(&store.select(5).unwrap(), "logged", "five");
+		write(&store.select(5).unwrap(), "again", "five");
+		write(&store, "logged", "zero");
+		let log = fs::read(&path).unwrap();
+		assert_eq!(log.windows(select_record(5).len()).filter(|window| *window == select_record(5).as_slice()).count(), 1);
+
+		let replayed = Arc::new(CalodStore::new(100));
+		assert_eq!(replayed.load_aof(&path).unwrap().commands, 3);
+		let five = replayed.select(5).unwrap();
+		assert_eq!((string(&five, "logged").as_deref(), string(&five, "again").as_deref()), (Some("five"), Some("five")));
+		assert_eq!(string(&replayed, "logged").as_deref(), Some("zero"));
+		fs::remove_file(&path).unwrap();
+	}
+
+	#[test]
+	fn followers_apply_writes_to_the_selected_database() {
+		let leader = Arc::new(CalodStore::new(100));
+		let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+		let port = listener.local_addr().unwrap().port();
+		let serving = leader.clone();
+		thread::spawn(move || {
+			let mut stream = listener.accept().unwrap().0;
+			let mut reader = BufReader::new(stream.try_clone().unwrap());
+			let lines: Vec<String> = (0..7).map(|_| {
+				let mut line = String::new();
+				reader.read_line(&mut line).unwrap();
+				line.trim_end().to_string()
+			}).collect();
+			let _ = serving.serve_replica(&mut stream, &[lines[4].clone(), lines[6].clone()]);
+		});
+		write(&leader.select(2).unwrap(), "seeded", "two");
+
+		let follower = Arc::new(CalodStore::new(100));
+		follower.replicate_from("127.0.0.1", port);
+		wait_for(|| follower.replication().link_up());
+		assert_eq!(string(&follower.select(2).unwrap(), "seeded").as_deref(), Some("two"));
+
+		write(&leader.select(7).unwrap(), "streamed", "seven");
+		write(&leader, "streamed", "zero");
+		wait_for(|| string(&follower, "streamed").is_some());
+		assert_eq!(string(&follower.select(7).unwrap(), "streamed").as_deref(), Some("seven"));
+		assert_eq!(string(&follower, "streamed").as_deref(), Some("zero"));
+		assert_eq!(follower.replication().position(), leader.replication().position());
+	}
+
+	#[tokio::test]
+	async fn client_commands_switch_and_manage_databases() {
+		let store = Arc::new(CalodStore::new(100));
+		let client = Client::new(&serve(store.clone()));
+
+		client.query(commands::set("key", "zero")).await.unwrap();
+		assert!(client.query(commands::move_key("key", 3)).await.unwrap());
+		assert!(!client.query(commands::move_key("key", 3)).await.unwrap());
+		assert_eq!(client.query(commands::dbsize()).await.unwrap(), 0);
+
+		// SELECT stays with the connection it was sent on
+		let mut connection = client.get().await.unwrap();
+		connection.query(&commands::select(3)).await.unwrap();
+		assert_eq!(connection.query(&commands::get("key")).await.unwrap().as_deref(), Some("zero"));
+		connection.query(&commands::flushdb()).await.unwrap();
+		assert_eq!(connection.query(&commands::dbsize()).await.unwrap(), 0);
+		connection.query(&commands::select(0)).await.unwrap();
+		drop(connection);
+
+		client.query(commands::set("key", "zero")).await.unwrap();
+		client.query(commands::swapdb(0, 5)).await.unwrap();
+		assert_eq!(client.query(commands::dbsize()).await.unwrap(), 0);
+		assert_eq!(store.select(5).unwrap().db_size(), 1);
+		client.query(commands::flushall()).await.unwrap();
+		assert_eq!(store.select(5).unwrap().db_size(), 0);
+	}
+}
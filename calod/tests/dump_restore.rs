@@ -0,0 +1,160 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+	use std::collections::LinkedList;
+	use std::net::TcpListener;
+	use std::sync::Arc;
+	use std::thread;
+
+	use chrono::{Duration, Utc};
+	use serde_json::json;
+
+	use calod::persistence::aof::{command_record, AppendOnlyFile, FsyncPolicy};
+	use calod::persistence::dump::{dump_payload, restore_deadline, restore_payload, DumpError, MigrateOptions, MigrateOutcome};
+	use calod::persistence::encoding::{from_hex, to_hex};
+	use calod::store::calod_data::{DataType, Hash};
+	use calod::store::calod_store::{CacheError, CalodStore};
+	use calod::store::sorted_set::SortedSet;
+
+	use crate::common::{serve, string};
+
+	// A second instance on a local port, served by its own store
+	fn start_target() -> (String, Arc<CalodStore>) {
+		let store = Arc::new(CalodStore::new(10));
+		(serve(store.clone()), store)
+	}
+
+	#[test]
+	fn payload_round_trip() {
+		let mut zset = SortedSet::new();
+		zset.insert("a", 1.5);
+		let hash = Hash::new();
+		hash.insert(String::from("field"), String::from("value"));
+		let values = vec![
+			DataType::String(vec![0, 0xff, b'x']),
+			DataType::List(["a", "b"].iter().map(|s| s.to_string()).collect::<LinkedList<String>>()),
+			DataType::Hash(hash),
+			DataType::SortedSet(zset),
+			DataType::Json(json!({"nested": [1, 2]})),
+		];
+
+		for value in values {
+			let payload = dump_payload(&value);
+			assert_eq!(dump_payload(&restore_payload(&payload).unwrap()), payload);
+		}
+
+		let store = CalodStore::new(10);
+		store.replace_value("key", DataType::String(b"hello".to_vec()));
+		let payload = store.dump("key").unwrap();
+		assert!(matches!(restore_payload(&payload), Ok(DataType::String(bytes)) if bytes == b"hello"));
+		assert_eq!(store.dump("missing"), None);
+	}
+
+	#[test]
+	fn damaged_payloads_are_rejected() {
+		let payload = from_hex(&dump_payload(&DataType::String(b"hello".to_vec()))).unwrap();
+
+		let mut flipped = payload.clone();
+		flipped[2] ^= 0x01;
+		assert!(matches!(restore_payload(&to_hex(&flipped)), Err(DumpError::BadPayload)));
+
+		// A payload from a newer version is refused even with a valid checksum
+		let mut newer = payload[..payload.len() - 6].to_vec();
+		newer.extend_from_slice(&2u16.to_le_bytes());
+		let checksum = crc32fast::hash(&newer);
+		newer.extend_from_slice(&checksum.to_le_bytes());
+		assert!(matches!(restore_payload(&to_hex(&newer)), Err(DumpError::BadPayload)));
+
+		assert!(matches!(restore_payload("not hex"), Err(DumpError::BadPayload)));
+		assert!(matches!(restore_payload("é0"), Err(DumpError::BadPayload)));
+		assert!(matches!(restore_payload(""), Err(DumpError::BadPayload)));
+	}
+
+	#[test]
+	fn restore_honours_replace_and_ttl() {
+		let store = CalodStore::new(10);
+		let value = || DataType::String(b"new".to_vec());
+		store.replace_value("key", DataType::String(b"old".to_vec()));
+
+		assert!(matches!(store.restore_value("key", value(), None, false), Err(CacheError::BusyKey)));
+		assert_eq!(string(&store, "key").as_deref(), Some("old"));
+		store.restore_value("key", value(), None, true).unwrap();
+		assert_eq!(string(&store, "key").as_deref(), Some("new"));
+
+		// A deadline in the past deletes the key
+		store.restore_value("key", value(), restore_deadline(1000, true), true).unwrap();
+		assert_eq!(string(&store, "key"), None);
+
+		let deadline = restore_deadline(60_000, false).unwrap();
+		assert!(deadline > Utc::now() + Duration::seconds(59));
+		store.restore_value("ttl", value(), Some(deadline), false).unwrap();
+		assert_eq!(string(&store, "ttl").as_deref(), Some("new"));
+		assert_eq!(restore_deadline(0, true), None);
+	}
+
+	#[test]
+	fn migrate_moves_keys_to_another_instance() {
+		let (target, remote) = start_target();
+		remote.replace_value("taken", DataType::String(b"remote".to_vec()));
+
+		let local = CalodStore::new(10);
+		local.replace_value("moved", DataType::String(b"one".to_vec()));
+		local.replace_value("copied", DataType::String(b"two".to_vec()));
+		local.replace_value("taken", DataType::String(b"local".to_vec()));
+
+		let keys = vec![String::from("moved"), String::from("missing")];
+		let (outcome, result) = local.migrate(target.as_str(), &keys, &MigrateOptions::default());
+		result.unwrap();
+		assert_eq!(outcome, MigrateOutcome { moved: vec![String::from("moved")], missing: 1 });
+		assert_eq!((string(&local, "moved"), string(&remote, "moved").as_deref()), (None, Some("one")));
+
+		let copy = MigrateOptions { copy: true, ..MigrateOptions::default() };
+		local.migrate(target.as_str(), &[String::from("copied")], &copy).1.unwrap();
+		assert_eq!((string(&local, "copied").as_deref(), string(&remote, "copied").as_deref()), (Some("two"), Some("two")));
+
+		// A refused key stays where it was
+		let (outcome, result) = local.migrate(target.as_str(), &[String::from("taken")], &MigrateOptions::default());
+		assert!(matches!(result, Err(DumpError::Target(e)) if e.starts_with("BUSYKEY")));
+		assert!(outcome.moved.is_empty());
+		assert_eq!((string(&local, "taken").as_deref(), string(&remote, "taken").as_deref()), (Some("local"), Some("remote")));
+
+		let replace = MigrateOptions { replace: true, ..MigrateOptions::default() };
+		local.migrate(target.as_str(), &[String::from("taken")], &replace).1.unwrap();
+		assert_eq!((string(&local, "taken"), string(&remote, "taken").as_deref()), (None, Some("local")));
+	}
+
+	#[test]
+	fn failed_transfer_puts_keys_back() {
+		// The target hangs up without replying
+		let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+		let port = listener.local_addr().unwrap().port();
+		thread::spawn(move || drop(listener.accept()));
+
+		let local = CalodStore::new(10);
+		local.replace_value("key", DataType::String(b"value".to_vec()));
+		let (outcome, result) = local.migrate(("127.0.0.1", port), &[String::from("key")], &MigrateOptions::default());
+		assert!(matches!(result, Err(DumpError::Io(_))));
+		assert!(outcome.moved.is_empty());
+		assert_eq!(string(&local, "key").as_deref(), Some("value"));
+	}
+
+	#[test]
+	fn migrated_keys_are_logged_as_a_del() {
+		let (target, _remote) = start_target();
+		let path = std::env::temp_dir().join(format!("calod-migrate-{}.aof", std::process::id())).to_string_lossy().to_string();
+		let local = CalodStore::new(10);
+		local.replace_value("moved", DataType::String(b"one".to_vec()));
+		local.replace_value("copied", DataType::String(b"two".to_vec()));
+		local.enable_aof(AppendOnlyFile::open(local.storage(), &path, FsyncPolicy::Always).unwrap());
+
+		let copy = MigrateOptions { copy: true, ..MigrateOptions::default() };
+		local.migrate(target.as_str(), &[String::from("copied")], &copy).1.unwrap();
+		local.migrate(target.as_str(), &[String::from("moved"), String::from("missing")], &MigrateOptions::default()).1.unwrap();
+
+		let logged = std::fs::read(&path).unwrap();
+		assert!(logged.ends_with(&command_record(vec!["DEL", "moved"])));
+		assert!(!String::from_utf8_lossy(&logged).contains("copied"));
+		std::fs::remove_file(&path).unwrap();
+	}
+}
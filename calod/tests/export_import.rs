@@ -0,0 +1,105 @@
+#[cfg(test)]
+mod tests {
+	use std::collections::LinkedList;
+
+	use serde_json::json;
+
+	use calod::persistence::export::{ExportError, ExportFormat};
+	use calod::store::calod_data::{DataType, Hash, Set};
+	use calod::store::calod_store::CalodStore;
+	use calod::store::glob::glob_match;
+	use calod::store::hyperloglog::HyperLogLog;
+	use calod::store::sorted_set::SortedSet;
+
+	fn sample_store() -> CalodStore {
+		let store = CalodStore::new(100);
+		store.replace_value("text", DataType::String(b"hello, \"world\"\nline two".to_vec()));
+		store.replace_value("binary", DataType::String(vec![0xff, 0x00, 0xfe]));
+		store.replace_value("list", DataType::List(["a", "b,c"].iter().map(|s| s.to_string()).collect::<LinkedList<String>>()));
+		let set = Set::new();
+		set.insert(String::from("x"));
+		set.insert(String::from("y"));
+		store.replace_value("set", DataType::Set(set));
+		let hash = Hash::new();
+		hash.insert(String::from("field"), String::from("value"));
+		store.replace_value("user:1", DataType::Hash(hash));
+		let mut zset = SortedSet::new();
+		zset.insert("low", f64::NEG_INFINITY);
+		zset.insert("mid", 1.5);
+		store.replace_value("zset", DataType::SortedSet(zset));
+		store.replace_value("doc", DataType::Json(json!({"name": "calod", "tags": [1, 2]})));
+		store.replace_value("user:2", DataType::Json(json!("just a string")));
+		let mut hll = HyperLogLog::new();
+		hll.add(b"item");
+		store.replace_value("hll", DataType::HyperLogLog(hll));
+		store
+	}
+
+	fn export(store: &CalodStore, format: ExportFormat, pattern: Option<&str>) -> Vec<String> {
+		let mut out = Vec::new();
+		store.export(&mut out, format, pattern).unwrap();
+		let mut lines: Vec<String> = String::from_utf8(out).unwrap().lines().map(str::to_string).collect();
+		lines.sort();
+		lines
+	}
+
+	#[test]
+	fn round_trip_in_both_formats() {
+		let store = sample_store();
+		for format in [ExportFormat::JsonLines, ExportFormat::Csv] {
+			let mut out = Vec::new();
+			assert_eq!(store.export(&mut out, format, None).unwrap(), 9);
+
+			let imported = CalodStore::new(100);
+			let stats = imported.import(&mut out.as_slice(), format).unwrap();
+			assert_eq!((stats.keys, stats.expired), (9, 0));
+			assert_eq!(export(&imported, format, None), export(&store, format, None));
+		}
+
+		let lines = export(&store, ExportFormat::JsonLines, Some("binary"));
+		assert_eq!(lines, vec![r#"{"key":"binary","type":"string","ttl_ms":null,"value":"ff00fe","encoding":"hex"}"#]);
+	}
+
+	#[test]
+	fn ttl_is_exported_as_remaining_time() {
+		let input = r#"{"key":"session","type":"string","ttl_ms":60000,"value":"v"}
+{"key":"stale","type":"string","ttl_ms":0,"value":"v"}
+"#;
+		let imported = CalodStore::new(100);
+		let stats = imported.import(&mut input.as_bytes(), ExportFormat::JsonLines).unwrap();
+		assert_eq!((stats.keys, stats.expired), (1, 1));
+
+		let line = export(&imported, ExportFormat::JsonLines, None).remove(0);
+		let ttl = serde_json::from_str::<serde_json::Value>(&line).unwrap()["ttl_ms"].as_i64().unwrap();
+		assert!(ttl > 59_000 && ttl <= 60_000, "ttl {}", ttl);
+	}
+
+	#[test]
+	fn glob_filters_keys() {
+		let store = sample_store();
+		let keys: Vec<String> = export(&store, ExportFormat::Csv, Some("user:*")).into_iter().filter(|line| line.starts_with("user:")).collect();
+		assert_eq!(keys.len(), 2);
+
+		assert!(glob_match("h?llo", "hello"));
+		assert!(glob_match("h[ae]llo", "hallo"));
+		assert!(!glob_match("h[^e]llo", "hello"));
+		assert!(glob_match("h[a-f]*o", "hello"));
+		assert!(glob_match("*:1", "user:1"));
+		assert!(glob_match("a\\*b", "a*b"));
+		assert!(!glob_match("a\\*b", "axb"));
+		assert!(glob_match("*", ""));
+	}
+
+	#[test]
+	fn invalid_records_report_their_line() {
+		let input = "key,type,ttl_ms,encoding,value\nok,string,,,v\n\"multi\nline\",list,,,not json\n";
+		let result = CalodStore::new(100).import(&mut input.as_bytes(), ExportFormat::Csv);
+		assert!(matches!(result, Err(ExportError::Record { line: 3, .. })));
+
+		let result = CalodStore::new(100).import(&mut "{\"key\":\"k\",\"type\":\"queue\",\"value\":1}".as_bytes(), ExportFormat::JsonLines);
+		assert!(matches!(result, Err(ExportError::Record { line: 1, .. })));
+
+		let result = CalodStore::new(100).import(&mut "k,string,,,v\n".as_bytes(), ExportFormat::Csv);
+		assert!(matches!(result, Err(ExportError::Record { line: 1, .. })));
+	}
+}
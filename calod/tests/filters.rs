@@ -0,0 +1,79 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+	use calod::request_response::command::Command;
+	use calod::request_response::filter_handler;
+	use calod::store::bloom_filter::BloomFilter;
+	use calod::store::calod_store::CalodStore;
+	use calod::store::cuckoo_filter::CuckooFilter;
+
+	use crate::common::command;
+
+	fn filter(store: &CalodStore, name: Command, args: &[&str]) -> String {
+		command(filter_handler::respond, store, name, args)
+	}
+
+	#[test]
+	fn bloom_filter_scales_past_its_capacity() {
+		let mut filter = BloomFilter::new(0.01, 100, 2, true);
+		for i in 0..1000 {
+			filter.add(format!("item:{}", i).as_bytes()).unwrap();
+		}
+
+		assert!(filter.num_layers() > 1);
+		assert!((0..1000).all(|i| filter.contains(format!("item:{}", i).as_bytes())));
+		assert!(!filter.add(b"item:0").unwrap());
+	}
+
+	#[test]
+	fn non_scaling_bloom_filter_rejects_when_full() {
+		let mut filter = BloomFilter::new(0.01, 10, 2, false);
+		let rejected = (0..50).filter(|i| filter.add(format!("item:{}", i).as_bytes()).is_err()).count();
+		assert!(rejected > 0);
+	}
+
+	#[test]
+	fn cuckoo_filter_deletes_one_copy_at_a_time() {
+		let mut filter = CuckooFilter::new(1024, 2, 20, 1);
+		filter.add(b"item").unwrap();
+		filter.add(b"item").unwrap();
+
+		assert!(filter.remove(b"item"));
+		assert!(filter.contains(b"item"));
+		assert!(filter.remove(b"item"));
+		assert!(!filter.contains(b"item"));
+	}
+
+	#[test]
+	fn madd_reports_items_that_do_not_fit() {
+		let store = CalodStore::new(100);
+		assert_eq!(filter(&store, Command::BFRESERVE, &["bf", "0.01", "2", "NONSCALING"]), "+OK\r\n");
+
+		let reply = filter(&store, Command::BFMADD, &["bf", "a", "b", "c", "d"]);
+		assert!(reply.starts_with("*4\r\n:1\r\n:1\r\n"), "{}", reply);
+		assert!(reply.ends_with("-ERR non scaling filter is full\r\n"), "{}", reply);
+		assert_eq!(filter(&store, Command::BFMEXISTS, &["bf", "a", "b"]), "*2\r\n:1\r\n:1\r\n");
+		assert_eq!(filter(&store, Command::BFADD, &["bf", "e"]), "-ERR non scaling filter is full\r\n");
+	}
+
+	#[test]
+	fn reserve_rejects_out_of_range_parameters() {
+		let store = CalodStore::new(100);
+		assert_eq!(filter(&store, Command::BFRESERVE, &["bf", "0.01", "100", "EXPANSION", "4294967295"]), "-ERR expansion should be greater or equal to 1\r\n");
+		assert_eq!(filter(&store, Command::BFRESERVE, &["bf", "0.01", "18446744073709551615"]), "-ERR (capacity should be larger than 0)\r\n");
+		assert_eq!(filter(&store, Command::CFRESERVE, &["cf", "1024", "BUCKETSIZE", "256"]), "-ERR Bad bucket size\r\n");
+		assert_eq!(filter(&store, Command::CFRESERVE, &["cf", "1024", "EXPANSION", "40000"]), "-ERR Bad expansion\r\n");
+		assert_eq!(filter(&store, Command::CFRESERVE, &["cf", "1024", "BUCKETSIZE", "255"]), "+OK\r\n");
+		assert!(!store.contains_key("bf"));
+	}
+
+	#[test]
+	fn filters_stop_growing_at_the_maximum_size() {
+		// The third layer would hold 16 * 32768^2 fingerprints
+		let mut filter = CuckooFilter::new(16, 1, 1, 32768);
+		let rejected = (0..100_000).filter(|i| filter.add(format!("item:{}", i).as_bytes()).is_err()).count();
+		assert!(rejected > 0);
+		assert_eq!(filter.num_layers(), 2);
+	}
+}
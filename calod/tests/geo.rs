@@ -0,0 +1,70 @@
+#[cfg(test)]
+mod tests {
+	use calod::store::calod_store::CalodStore;
+	use calod::store::geo::*;
+	use calod::store::sorted_set::SortedSet;
+
+	fn sicily() -> SortedSet {
+		let mut set = SortedSet::new();
+		set.insert("Palermo", geohash_encode(13.361389, 38.115556) as f64);
+		set.insert("Catania", geohash_encode(15.087269, 37.502669) as f64);
+		set
+	}
+
+	fn search(shape: GeoShape) -> GeoSearch {
+		GeoSearch {
+			origin: GeoOrigin::LonLat(15.0, 37.0),
+			shape,
+			unit: GeoUnit::Kilometers,
+			order: Some(std::cmp::Ordering::Less),
+			count: None,
+			any: false,
+		}
+	}
+
+	#[test]
+	fn geohash_round_trip_matches_redis() {
+		let (longitude, latitude) = geohash_decode(geohash_encode(13.361389, 38.115556));
+		assert!((longitude - 13.361_389_338_970_184).abs() < 1e-12);
+		assert!((latitude - 38.115_556_395_496_3).abs() < 1e-12);
+	}
+
+	#[test]
+	fn distance_between_members() {
+		let (lon1, lat1) = geohash_decode(geohash_encode(13.361389, 38.115556));
+		let (lon2, lat2) = geohash_decode(geohash_encode(15.087269, 37.502669));
+		assert_eq!(format!("{:.4}", geo_distance(lon1, lat1, lon2, lat2)), "166274.1516");
+	}
+
+	#[test]
+	fn search_by_radius_sorts_by_distance() {
+		let matches = search_sorted_set(&sicily(), (15.0, 37.0), &search(GeoShape::Radius(200000.0)));
+		let found: Vec<(String, String)> = matches.into_iter().map(|m| (m.member, format!("{:.4}", m.distance))).collect();
+
+		assert_eq!(found, vec![
+			(String::from("Catania"), String::from("56.4413")),
+			(String::from("Palermo"), String::from("190.4424")),
+		]);
+	}
+
+	#[test]
+	fn search_by_box() {
+		assert_eq!(search_sorted_set(&sicily(), (15.0, 37.0), &search(GeoShape::Box(400000.0, 400000.0))).len(), 2);
+		assert_eq!(search_sorted_set(&sicily(), (15.0, 37.0), &search(GeoShape::Box(200000.0, 200000.0))).len(), 1);
+	}
+
+	#[test]
+	fn geoadd_xx_leaves_a_missing_key_missing() {
+		let store = CalodStore::new(100);
+		let palermo = vec![(13.361389, 38.115556, String::from("Palermo"))];
+		let xx = GeoAddOptions { xx: true, ..Default::default() };
+
+		assert_eq!(store.geoadd("sicily", &palermo, xx).unwrap(), 0);
+		assert_eq!(store.type_of("sicily"), None);
+
+		assert_eq!(store.geoadd("sicily", &palermo, GeoAddOptions::default()).unwrap(), 1);
+		let catania = vec![(15.087269, 37.502669, String::from("Catania"))];
+		assert_eq!(store.geoadd("sicily", &catania, xx).unwrap(), 0);
+		assert_eq!(store.geopos("sicily", &["Catania"]).unwrap(), vec![None]);
+	}
+}
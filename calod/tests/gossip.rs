@@ -0,0 +1,173 @@
+#[cfg(test)]
+mod tests {
+	use std::net::TcpListener;
+	use std::sync::atomic::{AtomicBool, Ordering};
+	use std::thread;
+	use std::time::{Duration, Instant};
+
+	use calod::cluster::gossip::{Gossip, Message, MessageKind};
+	use calod::cluster::slots::key_slot;
+	use calod::cluster::state::{ClusterNode, ClusterState, Failure, RouteError};
+	use calod::store::calod_store::CalodStore;
+
+	const NODE_TIMEOUT: Duration = Duration::from_millis(300);
+
+	fn node(address: &str, slots: std::ops::Range<u16>) -> ClusterState {
+		let cluster = ClusterState::new();
+		cluster.enable(address);
+		cluster.add_slots(&slots.collect::<Vec<u16>>()).unwrap();
+		cluster
+	}
+
+	// A node on its own bus, unreachable and silent while `alive` is unset
+	struct BusNode {
+		store: &'static CalodStore,
+		alive: AtomicBool,
+	}
+
+	impl BusNode {
+		fn start(address: &str, slots: std::ops::Range<u16>) -> &'static BusNode {
+			let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+			let node: &'static BusNode = Box::leak(Box::new(BusNode { store: Box::leak(Box::new(CalodStore::new(usize::MAX))), alive: AtomicBool::new(true) }));
+			node.store.cluster().enable(address);
+			node.store.cluster().set_bus_port(listener.local_addr().unwrap().port());
+			node.store.cluster().add_slots(&slots.collect::<Vec<u16>>()).unwrap();
+			thread::spawn(move || {
+				for stream in listener.incoming() {
+					let stream = stream.unwrap();
+					if node.alive.load(Ordering::SeqCst) {
+						thread::spawn(move || node.store.serve_cluster_link(stream));
+					}
+				}
+			});
+			thread::spawn(move || loop {
+				if node.alive.load(Ordering::SeqCst) {
+					node.store.cluster_tick(NODE_TIMEOUT);
+				}
+				thread::sleep(NODE_TIMEOUT / 10);
+			});
+			node
+		}
+
+		fn id(&self) -> String {
+			self.store.cluster().myself().id
+		}
+	}
+
+	fn wait_for(mut condition: impl FnMut() -> bool) {
+		let started = Instant::now();
+		while !condition() {
+			assert!(started.elapsed() < Duration::from_secs(10), "condition not met in time");
+			thread::sleep(Duration::from_millis(20));
+		}
+	}
+
+	#[test]
+	fn messages_round_trip() {
+		let message = Message {
+			kind: MessageKind::Ping,
+			sender: ClusterNode { id: String::from("a"), address: String::from("127.0.0.1:7000"), bus_port: 17000, config_epoch: 3 },
+			current_epoch: 5,
+			slots: vec![(0, 99), (200, 200)],
+			gossip: vec![Gossip { id: String::from("b"), address: String::from("127.0.0.1:7001"), bus_port: 17001, failure: Failure::PFail }],
+		};
+		assert_eq!(Message::decode(&message.encode()), Some(message.clone()));
+		assert_eq!(message.encode()[6], "0-99,200-200");
+		assert_eq!(message.encode()[10], "fail?");
+
+		let mut args = message.encode();
+		args.pop();
+		assert_eq!(Message::decode(&args), None);
+		assert_eq!(Message::decode(&[String::from("HELLO")]), None);
+	}
+
+	#[test]
+	fn newer_config_epochs_win_slots() {
+		let (a, b) = (node("127.0.0.1:7000", 0..100), node("127.0.0.1:7001", 100..200));
+		let now = Instant::now();
+
+		// A ping from a stranger is ignored, a MEET introduces it
+		assert!(!b.receive(&a.message(MessageKind::Ping), now));
+		assert!(b.receive(&a.message(MessageKind::Meet), now));
+		assert!(a.receive(&b.message(MessageKind::Pong), now));
+		assert_eq!((a.owner(150).unwrap().id, b.owner(50).unwrap().id), (b.myself().id, a.myself().id));
+
+		// Both started with config epoch 0, the node with the greater ID took a new one
+		let (a_epoch, b_epoch) = (a.myself().config_epoch, b.myself().config_epoch);
+		assert_ne!(a_epoch, b_epoch);
+		assert_eq!(a_epoch.max(b_epoch), 1);
+
+		// Whoever claims a slot with the newer epoch takes it over
+		let (older, newer) = if a_epoch < b_epoch { (&a, &b) } else { (&b, &a) };
+		let slot = older.slot_table()[0].0;
+		let taken = node("127.0.0.1:7002", 0..0);
+		taken.receive(&older.message(MessageKind::Meet), now);
+		let mut claim = newer.message(MessageKind::Meet);
+		claim.slots = vec![(slot, slot)];
+		taken.receive(&claim, now);
+		assert_eq!(taken.owner(slot).unwrap().id, newer.myself().id);
+		older.receive(&claim, now);
+		assert_eq!(older.owner(slot).unwrap().id, newer.myself().id);
+	}
+
+	#[test]
+	fn failures_need_a_majority_of_slot_owners() {
+		let nodes = [node("127.0.0.1:7000", 0..100), node("127.0.0.1:7001", 100..200), node("127.0.0.1:7002", 200..300)];
+		let now = Instant::now();
+		for (i, node) in nodes.iter().enumerate() {
+			for (j, other) in nodes.iter().enumerate() {
+				if i != j {
+					node.receive(&other.message(MessageKind::Meet), now);
+				}
+			}
+		}
+		let (a, b, c) = (&nodes[0], &nodes[1], &nodes[2]);
+		let c_id = c.myself().id;
+
+		// A pong owed for longer than the node timeout makes C PFail, our own opinion is not a majority
+		a.ping_targets(now);
+		a.receive(&b.message(MessageKind::Pong), now);
+		assert!(a.check_failures(NODE_TIMEOUT, now + NODE_TIMEOUT / 2).is_empty());
+		assert!(a.check_failures(NODE_TIMEOUT, now + NODE_TIMEOUT * 2).is_empty());
+		assert_eq!(a.failure(&c_id), Failure::PFail);
+
+		// B reporting C as well makes two of three
+		b.ping_targets(now);
+		b.check_failures(NODE_TIMEOUT, now + NODE_TIMEOUT * 2);
+		a.receive(&b.message(MessageKind::Pong), now + NODE_TIMEOUT * 2);
+		assert_eq!(a.check_failures(NODE_TIMEOUT, now + NODE_TIMEOUT * 2), vec![c_id.clone()]);
+		assert_eq!(a.failure(&c_id), Failure::Fail);
+		let line = a.nodes_text().lines().find(|line| line.starts_with(&c_id)).unwrap().to_string();
+		assert!(line.contains(" master,fail - ") && line.ends_with(" disconnected 200-299"));
+		assert!(a.info_text().contains("cluster_slots_fail:100\r\n"));
+		let key = (0..).map(|i| format!("key{}", i)).find(|key| (200..300).contains(&key_slot(key))).unwrap();
+		assert_eq!(a.route(&[&key], false, |_| false), Err(RouteError::Down));
+
+		// Hearing from C again clears it
+		a.receive(&c.message(MessageKind::Ping), now + NODE_TIMEOUT * 3);
+		assert_eq!(a.failure(&c_id), Failure::Ok);
+	}
+
+	#[test]
+	fn nodes_gossip_membership_and_agree_on_failures() {
+		let nodes = [BusNode::start("127.0.0.1:7100", 0..5462), BusNode::start("127.0.0.1:7101", 5462..10923), BusNode::start("127.0.0.1:7102", 10923..16384)];
+		let bus_port = |i: usize| nodes[i].store.cluster().myself().bus_port;
+
+		// A meets B and B meets C, A learns about C through gossip
+		nodes[0].store.cluster_meet("127.0.0.1", bus_port(1)).unwrap();
+		nodes[1].store.cluster_meet("127.0.0.1", bus_port(2)).unwrap();
+		wait_for(|| nodes.iter().all(|node| node.store.cluster().nodes().len() == 3 && node.store.cluster().info_text().contains("cluster_state:ok")));
+		for slot in [0, 6000, 16383] {
+			let owner = nodes[0].store.cluster().owner(slot).unwrap().id;
+			assert!(nodes.iter().all(|node| node.store.cluster().owner(slot).unwrap().id == owner));
+		}
+
+		nodes[2].alive.store(false, Ordering::SeqCst);
+		let c = nodes[2].id();
+		wait_for(|| nodes[..2].iter().all(|node| node.store.cluster().failure(&c) == Failure::Fail));
+		assert!(nodes[0].store.cluster().info_text().contains("cluster_state:fail"));
+
+		nodes[2].alive.store(true, Ordering::SeqCst);
+		wait_for(|| nodes[..2].iter().all(|node| node.store.cluster().failure(&c) == Failure::Ok));
+	}
+}
@@ -0,0 +1,78 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+	use calod::store::calod_store::{CacheError, CalodStore};
+	use calod::store::hyperloglog::*;
+
+	use crate::common::set;
+
+	#[test]
+	fn add_reports_register_changes() {
+		let mut hll = HyperLogLog::new();
+		assert!(hll.add(b"a"));
+		assert!(!hll.add(b"a"));
+		assert_eq!(hll.count(), 1);
+	}
+
+	#[test]
+	fn promotes_to_dense_and_stays_accurate() {
+		let mut hll = HyperLogLog::new();
+		for i in 0..100000 {
+			hll.add(format!("elem:{}", i).as_bytes());
+		}
+		assert!(!hll.is_sparse());
+
+		let error = (hll.count() as f64 - 100000.0).abs() / 100000.0;
+		assert!(error < 0.02);
+	}
+
+	#[test]
+	fn merge_counts_the_union() {
+		let mut first = HyperLogLog::new();
+		let mut second = HyperLogLog::new();
+		for element in ["a", "b", "c"] {
+			first.add(element.as_bytes());
+		}
+		for element in ["c", "d"] {
+			second.add(element.as_bytes());
+		}
+
+		first.merge(&second);
+		assert_eq!(first.count(), 4);
+	}
+
+	#[test]
+	fn hyperloglogs_are_strings_to_string_commands() {
+		let store = CalodStore::new(100);
+		store.pfadd("hll", &["a", "b", "c"]).unwrap();
+		let bytes = store.get_value("hll").unwrap().unwrap();
+		assert!(bytes.starts_with(HLL_STRING_HEADER));
+		assert_eq!(store.bitcount("hll", None).unwrap(), bytes.iter().map(|b| b.count_ones() as u64).sum::<u64>());
+
+		// A string holding a HyperLogLog counts and merges like one
+		store.set_value("copy", bytes, &None).unwrap();
+		assert_eq!(store.pfcount(&["copy"]).unwrap(), 3);
+		store.pfadd("copy", &["d"]).unwrap();
+		assert_eq!(store.pfcount(&["copy", "hll"]).unwrap(), 4);
+
+		// Writing through a string command leaves a plain string
+		store.setbit("hll", 0, 1).unwrap();
+		assert_eq!(store.type_of("hll"), Some("string"));
+		assert!(matches!(store.pfcount(&["hll"]), Err(CacheError::WrongType)));
+		set(&store, "plain", "value");
+		assert!(matches!(store.pfadd("plain", &["a"]), Err(CacheError::WrongType)));
+	}
+
+	#[test]
+	fn count_of_a_missing_key_leaves_it_missing() {
+		let store = CalodStore::new(100);
+		assert_eq!(store.pfcount(&["hll"]).unwrap(), 0);
+		assert_eq!(store.type_of("hll"), None);
+
+		store.pfadd("hll", &["a", "b"]).unwrap();
+		assert!(store.remove_value("hll"));
+		assert_eq!(store.pfcount(&["hll"]).unwrap(), 0);
+		assert_eq!(store.db_size(), 0);
+	}
+}
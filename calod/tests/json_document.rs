@@ -0,0 +1,76 @@
+#[cfg(test)]
+mod tests {
+	use calod::store::calod_data::DataType;
+	use calod::store::calod_store::{CacheError, CalodStore};
+	use calod::store::json_document::*;
+	use serde_json::{json, Value};
+
+	fn document() -> Value {
+		json!({"a": 2, "b": {"a": 3, "c": [1, 2]}, "arr": [1, 2, 3]})
+	}
+
+	fn matches(root: &Value, path: &str) -> Vec<Value> {
+		JsonPath::parse(path).unwrap().resolve(root).iter()
+			.filter_map(|location| get_at(root, location).cloned())
+			.collect()
+	}
+
+	#[test]
+	fn resolve_recursive_and_indexed_paths() {
+		let root = document();
+		assert_eq!(matches(&root, "$..a"), vec![json!(2), json!(3)]);
+		assert_eq!(matches(&root, ".b.c[-1]"), vec![json!(2)]);
+		assert_eq!(matches(&root, "$['b'].c[*]"), vec![json!(1), json!(2)]);
+		assert!(JsonPath::parse("a.b").unwrap().legacy);
+		assert!(JsonPath::parse("$.a[").is_err());
+	}
+
+	#[test]
+	fn set_path_honours_conditions() {
+		let mut root = document();
+		let new = JsonPath::parse("$.new").unwrap();
+		assert!(set_path(&mut root, &new, json!("x"), None));
+		assert!(!set_path(&mut root, &new, json!("y"), Some(JsonSetCondition::Nx)));
+		assert!(!set_path(&mut root, &JsonPath::parse("$.missing.deeper").unwrap(), json!(1), None));
+		assert_eq!(root["new"], json!("x"));
+	}
+
+	#[test]
+	fn delete_path_removes_every_match() {
+		let mut root = document();
+		assert_eq!(delete_path(&mut root, &JsonPath::parse("$.arr[*]").unwrap()), 3);
+		assert_eq!(delete_path(&mut root, &JsonPath::parse("$..a").unwrap()), 2);
+		assert_eq!(root, json!({"b": {"c": [1, 2]}, "arr": []}));
+		assert_eq!(json_type(&root["b"]["c"]), "array");
+	}
+
+	#[test]
+	fn root_set_conditions_and_delete() {
+		let store = CalodStore::new(100);
+		let root = JsonPath::parse("$").unwrap();
+		assert!(!store.json_set("doc", &root, "1", Some(JsonSetCondition::Xx)).unwrap());
+		assert_eq!(store.type_of("doc"), None);
+		assert!(store.json_set("doc", &root, "1", Some(JsonSetCondition::Nx)).unwrap());
+		assert!(!store.json_set("doc", &root, "2", Some(JsonSetCondition::Nx)).unwrap());
+		assert!(store.json_set("doc", &root, "3", Some(JsonSetCondition::Xx)).unwrap());
+		assert_eq!(store.json_get("doc", &root).unwrap(), Some(vec![json!(3)]));
+
+		store.replace_value("text", DataType::String(b"x".to_vec()));
+		assert!(matches!(store.json_del("text", &root), Err(CacheError::WrongType)));
+		assert_eq!(store.type_of("text"), Some("string"));
+		assert_eq!(store.json_del("doc", &root).unwrap(), 1);
+		assert_eq!(store.json_del("doc", &root).unwrap(), 0);
+	}
+
+	#[test]
+	fn numincrby_changes_nothing_when_a_match_fails() {
+		let store = CalodStore::new(100);
+		let root = JsonPath::parse("$").unwrap();
+		store.json_set("doc", &root, r#"{"a": 1, "b": {"a": 1.5e308}}"#, None).unwrap();
+
+		assert!(store.json_numincrby("doc", &JsonPath::parse("$..a").unwrap(), "1.5e308").is_err());
+		assert_eq!(store.json_get("doc", &root).unwrap(), Some(vec![json!({"a": 1, "b": {"a": 1.5e308}})]));
+		assert_eq!(store.json_numincrby("doc", &JsonPath::parse("$.a").unwrap(), "2").unwrap(), vec![Some(json!(3))]);
+		assert!(store.json_numincrby("missing", &root, "1").is_err());
+	}
+}
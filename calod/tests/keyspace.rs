@@ -0,0 +1,108 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+	use std::collections::HashSet;
+
+	use chrono::{Duration, Utc};
+
+	use calod::request_response::command::Command;
+	use calod::request_response::keyspace_handler;
+	use calod::store::calod_data::{DataType, Hash};
+	use calod::store::calod_store::{CacheError, CalodStore};
+	use calod::store::hyperloglog::HyperLogLog;
+	use calod::store::lazy_free::{self, LAZY_FREE_THRESHOLD};
+
+	use crate::common::{command, set, string};
+
+	fn hash(fields: usize) -> DataType {
+		let hash = Hash::new();
+		for i in 0..fields {
+			hash.insert(format!("field:{}", i), i.to_string());
+		}
+		DataType::Hash(hash)
+	}
+
+	fn keyspace(store: &CalodStore, name: Command, args: &[&str]) -> String {
+		command(keyspace_handler::respond, store, name, args)
+	}
+
+	#[test]
+	fn exists_type_touch_and_del() {
+		let store = CalodStore::new(100);
+		set(&store, "string", "value");
+		store.replace_value("hash", hash(3));
+		store.replace_value("hll", DataType::HyperLogLog(HyperLogLog::new()));
+		store.restore_value("expired", DataType::String(b"gone".to_vec()), Some(Utc::now() + Duration::milliseconds(20)), false).unwrap();
+		std::thread::sleep(std::time::Duration::from_millis(30));
+
+		assert_eq!(keyspace(&store, Command::EXISTS, &["string", "string", "hash", "expired", "missing"]), ":3\r\n");
+		assert_eq!(keyspace(&store, Command::TYPE, &["hash"]), "+hash\r\n");
+		assert_eq!(keyspace(&store, Command::TYPE, &["expired"]), "+none\r\n");
+		assert_eq!(keyspace(&store, Command::TYPE, &["hll"]), "+string\r\n");
+		assert_eq!(keyspace(&store, Command::TOUCH, &["string", "missing"]), ":1\r\n");
+
+		assert_eq!(keyspace(&store, Command::DEL, &["string", "expired", "missing"]), ":1\r\n");
+		assert!(!store.contains_key("string"));
+		assert_eq!(keyspace(&store, Command::DEL, &[]), "-ERR wrong number of arguments for 'del' command\r\n");
+	}
+
+	#[test]
+	fn rename_keeps_the_deadline() {
+		let store = CalodStore::new(100);
+		store.restore_value("old", DataType::String(b"value".to_vec()), Some(Utc::now() + Duration::milliseconds(200)), false).unwrap();
+		set(&store, "taken", "other");
+
+		assert_eq!(keyspace(&store, Command::RENAMENX, &["old", "taken"]), ":0\r\n");
+		assert_eq!((string(&store, "old").as_deref(), string(&store, "taken").as_deref()), (Some("value"), Some("other")));
+		assert_eq!(keyspace(&store, Command::RENAME, &["old", "taken"]), "+OK\r\n");
+		assert_eq!((string(&store, "old"), string(&store, "taken").as_deref()), (None, Some("value")));
+		std::thread::sleep(std::time::Duration::from_millis(250));
+		assert!(!store.contains_key("taken"));
+
+		assert_eq!(keyspace(&store, Command::RENAME, &["old", "new"]), "-ERR no such key\r\n");
+		assert!(matches!(store.rename_key("missing", "missing", true), Err(CacheError::NoSuchKey)));
+		set(&store, "same", "value");
+		assert!(!store.rename_key("same", "same", false).unwrap());
+	}
+
+	#[test]
+	fn copy_within_and_across_databases() {
+		let store = CalodStore::new(100);
+		let other = store.select(4).unwrap();
+		store.replace_value("source", hash(5));
+		set(&store, "taken", "value");
+
+		assert_eq!(keyspace(&store, Command::COPY, &["source", "copy"]), ":1\r\n");
+		assert_eq!(keyspace(&store, Command::COPY, &["source", "taken"]), ":0\r\n");
+		assert_eq!(keyspace(&store, Command::COPY, &["source", "taken", "REPLACE"]), ":1\r\n");
+		assert_eq!(keyspace(&store, Command::COPY, &["source", "source", "DB", "4"]), ":1\r\n");
+		assert_eq!(keyspace(&store, Command::COPY, &["missing", "copy", "REPLACE"]), ":0\r\n");
+		assert_eq!(keyspace(&store, Command::COPY, &["source", "source"]), "-ERR source and destination objects are the same\r\n");
+		assert_eq!(keyspace(&store, Command::COPY, &["source", "copy", "DB", "99"]), "-ERR DB index is out of range\r\n");
+
+		// Copies are independent values
+		store.remove_value("source");
+		assert_eq!((store.type_of("copy"), store.type_of("taken"), other.type_of("source")), (Some("hash"), Some("hash"), Some("hash")));
+	}
+
+	#[test]
+	fn unlink_and_randomkey() {
+		assert!(!lazy_free::free(hash(LAZY_FREE_THRESHOLD)));
+		assert!(lazy_free::free(hash(LAZY_FREE_THRESHOLD + 1)));
+
+		let store = CalodStore::new(100);
+		assert_eq!(keyspace(&store, Command::RANDOMKEY, &[]), "$-1\r\n");
+		store.replace_value("large", hash(100_000));
+		set(&store, "small", "value");
+		assert_eq!(keyspace(&store, Command::UNLINK, &["large", "small", "missing"]), ":2\r\n");
+		assert_eq!(store.db_size(), 0);
+
+		let keys: Vec<String> = (0..20).map(|i| format!("key:{}", i)).collect();
+		for key in &keys {
+			set(&store, key, "value");
+		}
+		let drawn: HashSet<String> = (0..200).filter_map(|_| store.random_key()).collect();
+		assert!(drawn.len() > 1 && drawn.iter().all(|key| keys.contains(key)));
+	}
+}
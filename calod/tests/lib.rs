@@ -1,8 +1,6 @@
 
 #[cfg(test)]
 mod tests {
-	use super::*;
-
 	#[test]
 	fn test_insert_and_get() {
 		// TODO : Write complete tests
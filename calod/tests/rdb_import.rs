@@ -0,0 +1,217 @@
+#[cfg(test)]
+mod tests {
+	use std::sync::Arc;
+
+	use chrono::Utc;
+
+	use calod::persistence::rdb::{crc64, lzf_decompress, RdbError};
+	use calod::persistence::sim_storage::SimStorage;
+	use calod::store::calod_data::DataType;
+	use calod::store::calod_store::CalodStore;
+
+	enum Item {
+		Str(&'static str),
+		Int(i64),
+	}
+
+	fn string(bytes: &[u8]) -> Vec<u8> {
+		let mut out = length(bytes.len());
+		out.extend_from_slice(bytes);
+		out
+	}
+
+	fn length(len: usize) -> Vec<u8> {
+		if len < 64 {
+			vec![len as u8]
+		} else {
+			vec![0x40 | (len >> 8) as u8, len as u8]
+		}
+	}
+
+	// Small strings use the 6 bit encoding, integers the 7 bit or 16 bit one
+	fn listpack(items: &[Item]) -> Vec<u8> {
+		let mut body = Vec::new();
+		for item in items {
+			let entry = match item {
+				Item::Str(s) => [vec![0x80 | s.len() as u8], s.as_bytes().to_vec()].concat(),
+				Item::Int(n) if (0..128).contains(n) => vec![*n as u8],
+				Item::Int(n) => [vec![0xf1], (*n as i16).to_le_bytes().to_vec()].concat(),
+			};
+			let backlen = entry.len() as u8;
+			body.extend(entry);
+			body.push(backlen);
+		}
+		body.push(0xff);
+
+		let mut out = ((body.len() + 6) as u32).to_le_bytes().to_vec();
+		out.extend_from_slice(&(items.len() as u16).to_le_bytes());
+		out.extend(body);
+		out
+	}
+
+	// Strings with a one byte prevlen, integers as int8 or 4 bit immediates
+	fn ziplist(items: &[Item]) -> Vec<u8> {
+		let mut out = vec![0u8; 10];
+		for item in items {
+			out.push(0);
+			match item {
+				Item::Str(s) => {
+					out.push(s.len() as u8);
+					out.extend_from_slice(s.as_bytes());
+				}
+				Item::Int(n) if (0..13).contains(n) => out.push(0xf1 + *n as u8),
+				Item::Int(n) => out.extend_from_slice(&[0xfe, *n as i8 as u8]),
+			}
+		}
+		out.push(0xff);
+		out
+	}
+
+	fn key(out: &mut Vec<u8>, kind: u8, name: &str, value: Vec<u8>) {
+		out.push(kind);
+		out.extend(string(name.as_bytes()));
+		out.extend(value);
+	}
+
+	fn sample_rdb() -> Vec<u8> {
+		let mut rdb = b"REDIS0011".to_vec();
+		rdb.push(0xfa);
+		rdb.extend(string(b"redis-ver"));
+		rdb.extend(string(b"7.2.4"));
+		rdb.extend_from_slice(&[0xfe, 0, 0xfb, 16, 2]);
+
+		key(&mut rdb, 0, "plain", string(b"hello"));
+		key(&mut rdb, 0, "int", [vec![0xc1], 1234i16.to_le_bytes().to_vec()].concat());
+		// "a" as a literal, then a 9 byte match one byte back
+		key(&mut rdb, 0, "lzf", vec![0xc3, 5, 10, 0x00, b'a', 0xe0, 0x00, 0x00]);
+
+		rdb.push(0xfc);
+		rdb.extend_from_slice(&(Utc::now().timestamp_millis() + 3_600_000).to_le_bytes());
+		key(&mut rdb, 0, "ttl", string(b"x"));
+		rdb.push(0xfc);
+		rdb.extend_from_slice(&1000i64.to_le_bytes());
+		key(&mut rdb, 0, "gone", string(b"x"));
+
+		let packed = listpack(&[Item::Str("a"), Item::Str("b"), Item::Int(7)]);
+		key(&mut rdb, 18, "list", [vec![2, 2], string(&packed), vec![1], string(b"plain node")].concat());
+		let intset = [2u32.to_le_bytes().to_vec(), 3u32.to_le_bytes().to_vec(), [-2i16, 1, 300].iter().flat_map(|n| n.to_le_bytes()).collect()].concat();
+		key(&mut rdb, 11, "intset", string(&intset));
+		key(&mut rdb, 20, "setlp", string(&listpack(&[Item::Str("x"), Item::Str("y")])));
+		key(&mut rdb, 16, "hashlp", string(&listpack(&[Item::Str("f"), Item::Str("v"), Item::Str("n"), Item::Int(-300)])));
+		key(&mut rdb, 17, "zsetlp", string(&listpack(&[Item::Str("m1"), Item::Int(1), Item::Str("m2"), Item::Str("2.5")])));
+		key(&mut rdb, 5, "zset2", [vec![1], string(b"z"), 3.5f64.to_le_bytes().to_vec()].concat());
+		key(&mut rdb, 13, "hashzl", string(&ziplist(&[Item::Str("a"), Item::Int(1)])));
+		key(&mut rdb, 10, "listzl", string(&ziplist(&[Item::Str("p"), Item::Int(-5)])));
+
+		// An empty stream, a module value and a key in a database we do not have are skipped
+		key(&mut rdb, 15, "events", vec![0, 0, 0, 0, 0]);
+		key(&mut rdb, 7, "module", [vec![9, 2, 5, 5], string(b"abc"), vec![0]].concat());
+		rdb.extend_from_slice(&[0xfe, 1]);
+		key(&mut rdb, 0, "other", string(b"x"));
+		rdb.extend_from_slice(&[0xfe, 20]);
+		key(&mut rdb, 0, "far", string(b"y"));
+
+		rdb.push(0xff);
+		let checksum = crc64(0, &rdb);
+		rdb.extend_from_slice(&checksum.to_le_bytes());
+		rdb
+	}
+
+	fn import(rdb: &[u8]) -> (CalodStore, Result<calod::persistence::rdb::RdbImportStats, RdbError>) {
+		let sim = SimStorage::new();
+		sim.write("dump.rdb", rdb);
+		let store = CalodStore::with_storage(100, Arc::new(sim));
+		let result = store.import_rdb("dump.rdb");
+		(store, result)
+	}
+
+	fn sorted<T: Ord>(mut items: Vec<T>) -> Vec<T> {
+		items.sort();
+		items
+	}
+
+	#[test]
+	fn imports_every_encoding() {
+		let (store, result) = import(&sample_rdb());
+		let stats = result.unwrap();
+		assert_eq!((stats.version, stats.keys, stats.expired), (11, 13, 1));
+
+		let string = |key: &str| store.read_value(key, |value| match value {
+			Some(DataType::String(bytes)) => Some(String::from_utf8(bytes.clone()).unwrap()),
+			_ => None,
+		});
+		assert_eq!(string("plain").as_deref(), Some("hello"));
+		assert_eq!(string("int").as_deref(), Some("1234"));
+		assert_eq!(string("lzf").as_deref(), Some("aaaaaaaaaa"));
+		assert_eq!(string("gone"), None);
+
+		store.read_value("list", |value| match value {
+			Some(DataType::List(items)) => assert_eq!(items.iter().collect::<Vec<_>>(), vec!["a", "b", "7", "plain node"]),
+			other => panic!("unexpected {:?}", other),
+		});
+		store.read_value("listzl", |value| match value {
+			Some(DataType::List(items)) => assert_eq!(items.iter().collect::<Vec<_>>(), vec!["p", "-5"]),
+			other => panic!("unexpected {:?}", other),
+		});
+		store.read_value("intset", |value| match value {
+			Some(DataType::Set(set)) => assert_eq!(sorted(set.members()), vec!["-2", "1", "300"]),
+			other => panic!("unexpected {:?}", other),
+		});
+		store.read_value("setlp", |value| match value {
+			Some(DataType::Set(set)) => assert_eq!(sorted(set.members()), vec!["x", "y"]),
+			other => panic!("unexpected {:?}", other),
+		});
+		store.read_value("hashlp", |value| match value {
+			Some(DataType::Hash(hash)) => assert_eq!((hash.get("f"), hash.get("n")), (Some(String::from("v")), Some(String::from("-300")))),
+			other => panic!("unexpected {:?}", other),
+		});
+		store.read_value("hashzl", |value| match value {
+			Some(DataType::Hash(hash)) => assert_eq!(hash.get("a"), Some(String::from("1"))),
+			other => panic!("unexpected {:?}", other),
+		});
+		store.read_value("zsetlp", |value| match value {
+			Some(DataType::SortedSet(set)) => assert_eq!((set.score("m1"), set.score("m2")), (Some(1.0), Some(2.5))),
+			other => panic!("unexpected {:?}", other),
+		});
+		store.read_value("zset2", |value| match value {
+			Some(DataType::SortedSet(set)) => assert_eq!(set.score("z"), Some(3.5)),
+			other => panic!("unexpected {:?}", other),
+		});
+	}
+
+	#[test]
+	fn unsupported_values_are_reported() {
+		let (store, result) = import(&sample_rdb());
+		let skipped: Vec<(String, u64)> = result.unwrap().skipped.into_iter().collect();
+		assert_eq!(skipped, vec![(String::from("database 20"), 1), (String::from("module"), 1), (String::from("stream"), 1)]);
+		assert!(store.read_value("events", |value| value.is_none()));
+		assert!(store.read_value("other", |value| value.is_none()));
+		assert!(store.select(1).unwrap().read_value("other", |value| value.is_some()));
+	}
+
+	#[test]
+	fn rejects_bad_files() {
+		let mut rdb = sample_rdb();
+		let last = rdb.len() - 1;
+		rdb[last] ^= 0xff;
+		assert!(matches!(import(&rdb).1, Err(RdbError::ChecksumMismatch { .. })));
+
+		// A zero checksum means the file was written without one
+		let len = rdb.len();
+		rdb[len - 8..].fill(0);
+		assert!(import(&rdb).1.is_ok());
+
+		rdb[5..9].copy_from_slice(b"0012");
+		assert!(matches!(import(&rdb).1, Err(RdbError::UnsupportedVersion(12))));
+		assert!(matches!(import(b"RDB").1, Err(RdbError::BadMagic)));
+		assert!(matches!(import(&sample_rdb()[..200]).1, Err(RdbError::Corrupt(_))));
+	}
+
+	#[test]
+	fn lzf_and_crc64() {
+		assert_eq!(crc64(0, b"123456789"), 0xe9c6d914c4b8d9ca);
+		assert_eq!(lzf_decompress(&[0x02, b'a', b'b', b'c', 0x20, 0x02], 6).unwrap(), b"abcabc");
+		assert!(lzf_decompress(&[0x20, 0x00], 2).is_err());
+		assert!(lzf_decompress(&[0x02, b'a', b'b', b'c'], 4).is_err());
+	}
+}
@@ -0,0 +1,211 @@
+#[cfg(test)]
+mod tests {
+	use std::collections::BTreeMap;
+	use std::io::{BufReader, Write};
+	use std::net::TcpListener;
+	use std::sync::Mutex;
+	use std::thread;
+
+	use calod::cluster::rebalance::{plan, rebalance, RebalanceOptions};
+	use calod::cluster::slots::key_slot;
+	use calod::cluster::state::{ClusterError, ClusterState, RouteError};
+	use calod::parser::parser::RESPOutput;
+	use calod::persistence::dump::MigrateOptions;
+	use calod::request_response::response_helper::format_resp_output;
+	use calod::sentinel::link::read_reply;
+	use calod::store::calod_data::DataType;
+	use calod::store::calod_store::CalodStore;
+
+	const OTHER: &str = "0000000000000000000000000000000000000001";
+
+	static NODES: Mutex<Vec<&'static MockNode>> = Mutex::new(Vec::new());
+
+	// A cluster node with its own slot state and keys, answering what a rebalance sends
+	struct MockNode {
+		address: String,
+		cluster: ClusterState,
+		store: CalodStore,
+	}
+
+	impl MockNode {
+		fn start() -> &'static MockNode {
+			let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+			let node: &'static MockNode = Box::leak(Box::new(MockNode {
+				address: listener.local_addr().unwrap().to_string(),
+				cluster: ClusterState::new(),
+				store: CalodStore::new(usize::MAX),
+			}));
+			node.cluster.enable(&node.address);
+			NODES.lock().unwrap().push(node);
+			thread::spawn(move || {
+				for stream in listener.incoming() {
+					let mut stream = stream.unwrap();
+					thread::spawn(move || {
+						let mut reader = BufReader::new(stream.try_clone().unwrap());
+						while let Ok(request) = read_reply(&mut reader) {
+							let reply = node.respond(request).unwrap_or_else(|e| RESPOutput::Error(format!("ERR {}", e)));
+							stream.write_all(format_resp_output(&reply).as_bytes()).unwrap();
+						}
+					});
+				}
+			});
+			node
+		}
+
+		fn find(address: &str) -> &'static MockNode {
+			NODES.lock().unwrap().iter().find(|node| node.address == address).copied().unwrap()
+		}
+
+		fn respond(&self, request: RESPOutput) -> Result<RESPOutput, ClusterError> {
+			let RESPOutput::Array(args) = request else { panic!("not a command") };
+			let args: Vec<String> = args.into_iter().map(|arg| if let RESPOutput::BulkString(arg) = arg { arg } else { panic!("not a bulk string") }).collect();
+			let ok = RESPOutput::SimpleString(String::from("OK"));
+			match args.iter().map(String::as_str).collect::<Vec<&str>>().as_slice() {
+				["CLUSTER", "NODES"] => Ok(RESPOutput::BulkString(self.cluster.nodes_text())),
+				["CLUSTER", "MEET", host, port] => {
+					let other = MockNode::find(&format!("{}:{}", host, port));
+					self.cluster.add_node(&other.cluster.myself().id, &other.address);
+					Ok(ok)
+				}
+				["CLUSTER", "SETSLOT", slot, action, id] => {
+					let slot = slot.parse().unwrap();
+					match *action {
+						"IMPORTING" => self.cluster.set_importing(slot, id)?,
+						"MIGRATING" => self.cluster.set_migrating(slot, id)?,
+						_ => self.cluster.set_slot_node(slot, id, self.store.count_keys_in_slot(slot) > 0)?,
+					}
+					Ok(ok)
+				}
+				["CLUSTER", "GETKEYSINSLOT", slot, count] => {
+					let keys = self.store.keys_in_slot(slot.parse().unwrap(), count.parse().unwrap());
+					Ok(RESPOutput::Array(keys.into_iter().map(RESPOutput::BulkString).collect()))
+				}
+				["MIGRATE", host, port, "", "0", _, "KEYS", keys @ ..] => {
+					let target = MockNode::find(&format!("{}:{}", host, port));
+					for key in keys {
+						// The source sends the key away only once it is in a migrating slot, the target takes it only after ASKING
+						assert!(matches!(self.cluster.route(&[key], false, |_| false), Err(RouteError::Ask(..))));
+						assert!(matches!(target.cluster.route(&[key], false, |_| false), Err(RouteError::Moved(..))));
+						assert_eq!(target.cluster.route(&[key], true, |_| false), Ok(()));
+						let value = self.store.read_value(key, |value| value.cloned()).unwrap();
+						target.store.replace_value(key, value);
+						self.store.remove_value(key);
+					}
+					Ok(ok)
+				}
+				_ => panic!("unexpected command {:?}", args),
+			}
+		}
+
+		fn slot_count(&self) -> usize {
+			(0..16384).filter(|slot| self.cluster.owner(*slot).map(|owner| owner.id) == Some(self.cluster.myself().id)).count()
+		}
+	}
+
+	fn owned(nodes: &[(&str, std::ops::Range<u16>)]) -> BTreeMap<String, Vec<u16>> {
+		nodes.iter().map(|(id, slots)| (id.to_string(), slots.clone().collect())).collect()
+	}
+
+	#[test]
+	fn plan_moves_the_fewest_slots() {
+		let moves = plan(&owned(&[("a", 0..16384), ("b", 0..0), ("c", 0..0)]));
+		assert_eq!(moves.len(), 16384 - 5462);
+		assert!(moves.iter().all(|slot_move| slot_move.from == "a" && slot_move.slot >= 5462));
+		assert_eq!(moves.iter().filter(|slot_move| slot_move.to == "b").count(), 5461);
+		assert_eq!(moves.iter().filter(|slot_move| slot_move.to == "c").count(), 5461);
+
+		// Balanced nodes keep their slots, the remainder stays where it is
+		assert!(plan(&owned(&[("a", 0..5461), ("b", 5461..10923), ("c", 10923..16384)])).is_empty());
+		let moves = plan(&owned(&[("a", 0..3), ("b", 3..10), ("c", 10..10)]));
+		assert_eq!(moves.iter().map(|slot_move| (slot_move.slot, slot_move.to.as_str())).collect::<Vec<(u16, &str)>>(), vec![(7, "c"), (8, "c"), (9, "c")]);
+	}
+
+	#[test]
+	fn setslot_settles_a_migration() {
+		let cluster = ClusterState::new();
+		cluster.enable("127.0.0.1:7000");
+		cluster.add_node(OTHER, "127.0.0.1:7001");
+		cluster.add_slots(&[1, 2]).unwrap();
+		cluster.assign_slot(3, OTHER).unwrap();
+
+		assert!(matches!(cluster.set_migrating(3, OTHER), Err(ClusterError::NotOwner(3))));
+		assert!(matches!(cluster.set_importing(1, OTHER), Err(ClusterError::AlreadyOwner(1))));
+		assert!(matches!(cluster.set_migrating(1, "unknown"), Err(ClusterError::UnknownNode(_))));
+
+		// The source hands the slot over only once it is empty
+		cluster.set_migrating(1, OTHER).unwrap();
+		assert!(matches!(cluster.set_slot_node(1, OTHER, true), Err(ClusterError::SlotNotEmpty(1))));
+		cluster.set_slot_node(1, OTHER, false).unwrap();
+		assert_eq!(cluster.owner(1).unwrap().id, OTHER);
+		assert!(!cluster.nodes_text().contains("->-"));
+
+		// The target claims it with a new config epoch
+		let myself = cluster.myself().id;
+		cluster.set_importing(3, OTHER).unwrap();
+		cluster.set_slot_node(3, &myself, false).unwrap();
+		assert_eq!(cluster.owner(3).unwrap().id, myself);
+		assert_eq!(cluster.myself().config_epoch, 1);
+
+		cluster.set_migrating(2, OTHER).unwrap();
+		cluster.set_stable(2);
+		assert!(!cluster.nodes_text().contains('['));
+	}
+
+	#[test]
+	fn migrate_asks_before_restoring_in_cluster_mode() {
+		let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+		let port = listener.local_addr().unwrap().port();
+		let target = thread::spawn(move || {
+			let mut stream = listener.accept().unwrap().0;
+			let mut reader = BufReader::new(stream.try_clone().unwrap());
+			let mut commands = Vec::new();
+			for _ in 0..2 {
+				let Ok(RESPOutput::Array(args)) = read_reply(&mut reader) else { panic!("not a command") };
+				commands.push(format_resp_output(&args[0]));
+				stream.write_all(b"+OK\r\n").unwrap();
+			}
+			commands
+		});
+
+		let store = CalodStore::new(usize::MAX);
+		store.cluster().enable("127.0.0.1:7000");
+		store.replace_value("key", DataType::String(b"value".to_vec()));
+		let (outcome, result) = store.migrate(("127.0.0.1", port), &[String::from("key")], &MigrateOptions::default());
+		result.unwrap();
+		assert_eq!(outcome.moved, vec![String::from("key")]);
+		assert_eq!(target.join().unwrap(), vec![String::from("$6\r\nASKING\r\n"), String::from("$7\r\nRESTORE\r\n")]);
+		assert_eq!(store.count_keys_in_slot(key_slot("key")), 0);
+	}
+
+	#[test]
+	fn rebalance_fills_new_nodes() {
+		let nodes = [MockNode::start(), MockNode::start(), MockNode::start()];
+		nodes[0].cluster.add_slots(&(0..16384).collect::<Vec<u16>>()).unwrap();
+		for i in 0..100 {
+			nodes[0].store.replace_value(&format!("key{}", i), DataType::String(i.to_string().into_bytes()));
+		}
+
+		let seeds: Vec<String> = nodes.iter().map(|node| node.address.clone()).collect();
+		let moves = rebalance(&seeds, &RebalanceOptions { pipeline: 4, ..RebalanceOptions::default() }).unwrap();
+		assert_eq!(moves.len(), 16384 - 5462);
+		let mut counts: Vec<usize> = nodes.iter().map(|node| node.slot_count()).collect();
+		counts.sort();
+		assert_eq!(counts, vec![5461, 5461, 5462]);
+
+		// Every node agrees on the owners, and every key lives on the owner of its slot
+		for slot in 0..16384 {
+			let owner = nodes[0].cluster.owner(slot).unwrap().id;
+			assert!(nodes.iter().all(|node| node.cluster.owner(slot).map(|owner| owner.id).as_ref() == Some(&owner)));
+		}
+		for i in 0..100 {
+			let key = format!("key{}", i);
+			let owner = nodes.iter().find(|node| node.cluster.owner(key_slot(&key)).unwrap().id == node.cluster.myself().id).unwrap();
+			assert!(owner.store.contains_key(&key));
+		}
+		assert_eq!(nodes.iter().map(|node| (0..16384).map(|slot| node.store.count_keys_in_slot(slot)).sum::<usize>()).sum::<usize>(), 100);
+		assert!(nodes.iter().all(|node| !node.cluster.nodes_text().contains('[')));
+
+		// A second run finds nothing to do
+		assert!(rebalance(&seeds[..1], &RebalanceOptions::default()).unwrap().is_empty());
+	}
+}
@@ -0,0 +1,230 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+	use std::io::{BufRead, BufReader, Read, Write};
+	use std::net::{TcpListener, TcpStream};
+	use std::sync::Arc;
+	use std::thread;
+	use std::time::Duration;
+
+	use calod::persistence::aof::{command_record, select_record};
+	use calod::persistence::snapshot::SnapshotReader;
+	use calod::replication::backlog::ReplicationBacklog;
+	use calod::replication::state::Role;
+	use calod::store::calod_data::DataType;
+	use calod::store::calod_store::CalodStore;
+
+	use crate::common::{serve, set, string, wait_for};
+
+	fn set_record(key: &str, value: &str) -> Vec<u8> {
+		command_record(vec![String::from("SET"), key.to_string(), value.to_string()])
+	}
+
+	// A client write on the leader, logged and fed to the followers
+	fn write(leader: &CalodStore, key: &str, value: &str) {
+		leader.logged_write(Some(&set_record(key, value)), || set(leader, key, value));
+	}
+
+	// A leader whose every connection starts with PSYNC
+	fn start_leader() -> (Arc<CalodStore>, u16) {
+		let leader = Arc::new(CalodStore::new(100));
+		let port = serve_replicas(leader.clone());
+		(leader, port)
+	}
+
+	fn serve_replicas(serving: Arc<CalodStore>) -> u16 {
+		let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+		let port = listener.local_addr().unwrap().port();
+		thread::spawn(move || {
+			for stream in listener.incoming() {
+				let mut stream = stream.unwrap();
+				let leader = serving.clone();
+				thread::spawn(move || {
+					let mut reader = BufReader::new(stream.try_clone().unwrap());
+					let lines: Vec<String> = (0..7).map(|_| line(&mut reader)).collect();
+					let _ = leader.serve_replica(&mut stream, &[lines[4].clone(), lines[6].clone()]);
+				});
+			}
+		});
+		port
+	}
+
+	fn line<R: BufRead>(reader: &mut R) -> String {
+		let mut line = String::new();
+		reader.read_line(&mut line).unwrap();
+		line.trim_end().to_string()
+	}
+
+	fn psync(port: u16, replid: &str, offset: &str) -> (BufReader<TcpStream>, String) {
+		let mut stream = TcpStream::connect(("127.0.0.1", port)).unwrap();
+		stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+		stream.write_all(&command_record(vec![String::from("PSYNC"), replid.to_string(), offset.to_string()])).unwrap();
+		let mut reader = BufReader::new(stream);
+		let reply = line(&mut reader);
+		(reader, reply)
+	}
+
+	fn read_exact(reader: &mut BufReader<TcpStream>, len: usize) -> Vec<u8> {
+		let mut bytes = vec![0; len];
+		reader.read_exact(&mut bytes).unwrap();
+		bytes
+	}
+
+	#[test]
+	fn backlog_keeps_the_recent_stream() {
+		let mut backlog = ReplicationBacklog::new(8, 100);
+		backlog.append(b"hello");
+		assert_eq!(backlog.since(100).unwrap(), b"hello");
+		assert_eq!(backlog.since(105).unwrap(), b"");
+		assert_eq!(backlog.since(106), None);
+
+		backlog.append(b" world");
+		assert_eq!((backlog.end(), backlog.since(102)), (111, None));
+		assert_eq!(backlog.since(103).unwrap(), b"lo world");
+
+		backlog.reset(500);
+		assert_eq!((backlog.end(), backlog.since(500).unwrap()), (500, Vec::new()));
+	}
+
+	#[test]
+	fn psync_sends_a_snapshot_then_continues_from_the_backlog() {
+		let (leader, port) = start_leader();
+		write(&leader, "before", "1");
+
+		let (mut reader, reply) = psync(port, "?", "-1");
+		let parts: Vec<&str> = reply.split(' ').collect();
+		assert_eq!(parts[0], "+FULLRESYNC");
+		let (replid, offset) = (parts[1].to_string(), parts[2].parse::<u64>().unwrap());
+		assert_eq!((replid.clone(), offset), leader.replication().position());
+
+		// The snapshot comes right after the reply and ends with its own footer
+		let mut snapshot = SnapshotReader::new(&mut reader).unwrap();
+		assert_eq!(snapshot.next_entry().unwrap().unwrap().0, "before");
+		assert!(snapshot.next_entry().unwrap().is_none());
+
+		// Writes after the snapshot arrive as commands, the first one selects its database
+		wait_for(|| leader.replication().replicas().len() == 1);
+		write(&leader, "after", "2");
+		let after = [select_record(0), set_record("after", "2")].concat();
+		assert_eq!(read_exact(&mut reader, after.len()), after);
+		drop(reader);
+
+		// A follower that saw the snapshot only misses the later write
+		let (mut reader, reply) = psync(port, &replid, &offset.to_string());
+		assert_eq!(reply, format!("+CONTINUE {}", replid));
+		assert_eq!(read_exact(&mut reader, after.len()), after);
+
+		// Another history or an offset past the end needs a full resync
+		assert!(psync(port, "0000", &offset.to_string()).1.starts_with("+FULLRESYNC"));
+		assert!(psync(port, &replid, "1000000").1.starts_with("+FULLRESYNC"));
+	}
+
+	#[test]
+	fn wait_and_min_replicas_count_acknowledged_followers() {
+		let (leader, port) = start_leader();
+		leader.replication().set_min_replicas(1, Duration::from_secs(10));
+		assert!(!leader.replication().enough_good_replicas());
+		assert_eq!(leader.replication().wait(1, Some(Duration::from_millis(50))), 0);
+
+		let (mut reader, _) = psync(port, "?", "-1");
+		let mut snapshot = SnapshotReader::new(&mut reader).unwrap();
+		while snapshot.next_entry().unwrap().is_some() {}
+		wait_for(|| leader.replication().replicas().len() == 1);
+
+		// WAIT asks the follower for an acknowledgement after the write
+		write(&leader, "key", "1");
+		let waiting = leader.clone();
+		let waiter = thread::spawn(move || waiting.replication().wait(1, Some(Duration::from_secs(5))));
+		let key = [select_record(0), set_record("key", "1")].concat();
+		assert_eq!(read_exact(&mut reader, key.len()), key);
+		let getack = command_record(vec![String::from("REPLCONF"), String::from("GETACK"), String::from("*")]);
+		assert_eq!(read_exact(&mut reader, getack.len()), getack);
+
+		let (_, offset) = leader.replication().position();
+		reader.get_mut().write_all(&command_record(vec![String::from("REPLCONF"), String::from("ACK"), offset.to_string()])).unwrap();
+		assert_eq!(waiter.join().unwrap(), 1);
+		assert_eq!(leader.replication().replicas()[0].1, offset);
+		assert!(leader.replication().enough_good_replicas());
+	}
+
+	#[test]
+	fn follower_applies_the_stream_and_rejects_writes() {
+		let (leader, port) = start_leader();
+		write(&leader, "seeded", "1");
+
+		let follower = Arc::new(CalodStore::new(100));
+		follower.replace_value("stale", DataType::String(b"x".to_vec()));
+		follower.replicate_from("127.0.0.1", port);
+		wait_for(|| follower.replication().link_up());
+		assert_eq!((string(&follower, "seeded").as_deref(), string(&follower, "stale")), (Some("1"), None));
+
+		write(&leader, "streamed", "2");
+		wait_for(|| string(&follower, "streamed").is_some());
+		assert_eq!(follower.replication().position(), leader.replication().position());
+		assert_eq!(leader.replication().wait(1, Some(Duration::from_secs(5))), 1);
+		assert_eq!(follower.replication().role(), Role::Follower { host: String::from("127.0.0.1"), port });
+
+		// Clients of a follower may read but not write
+		let mut client = TcpStream::connect(serve(follower.clone())).unwrap();
+		client.write_all(&set_record("streamed", "3")).unwrap();
+		assert!(line(&mut BufReader::new(client)).starts_with("-READONLY"));
+		assert_eq!(string(&follower, "streamed").as_deref(), Some("2"));
+
+		// A promoted follower starts its own history and keeps the data
+		let (replid, _) = follower.replication().position();
+		follower.stop_replication();
+		assert_eq!(follower.replication().role(), Role::Leader);
+		assert_ne!(follower.replication().position().0, replid);
+		assert_eq!(string(&follower, "streamed").as_deref(), Some("2"));
+	}
+
+	#[test]
+	fn followers_continue_from_a_promoted_follower() {
+		let (leader, port) = start_leader();
+		write(&leader, "seeded", "1");
+		let (first, second) = (Arc::new(CalodStore::new(100)), Arc::new(CalodStore::new(100)));
+		first.replicate_from("127.0.0.1", port);
+		second.replicate_from("127.0.0.1", port);
+		wait_for(|| [&first, &second].iter().all(|follower| follower.replication().link_up() && follower.replication().position() == leader.replication().position()));
+		let (replid, offset) = leader.replication().position();
+
+		// A full resync would drop it
+		second.replace_value("kept", DataType::String(b"x".to_vec()));
+		first.stop_replication();
+		let promoted = serve_replicas(first.clone());
+		second.replicate_from("127.0.0.1", promoted);
+		wait_for(|| second.replication().link_up());
+		assert_eq!(second.replication().position(), first.replication().position());
+		assert_ne!(second.replication().position().0, replid);
+		assert_eq!(string(&second, "kept").as_deref(), Some("x"));
+
+		write(&first, "after", "2");
+		wait_for(|| string(&second, "after").is_some());
+
+		// Past the end of the old history is a history the promoted follower never saw
+		assert!(psync(promoted, &replid, &(offset + 1).to_string()).1.starts_with("+FULLRESYNC"));
+	}
+
+	#[test]
+	fn evictions_reach_followers_that_do_not_evict_themselves() {
+		let leader = Arc::new(CalodStore::new(2));
+		let port = serve_replicas(leader.clone());
+		let follower = Arc::new(CalodStore::new(1));
+		follower.replicate_from("127.0.0.1", port);
+		wait_for(|| follower.replication().link_up());
+
+		for key in ["a", "b", "c", "d"] {
+			write(&leader, key, "1");
+		}
+		wait_for(|| follower.replication().position() == leader.replication().position());
+		assert_eq!(leader.db_size(), 2);
+
+		// The follower is over its own capacity but holds exactly what the leader kept
+		let mut kept = leader.keys_matching("*");
+		let mut followed = follower.keys_matching("*");
+		kept.sort();
+		followed.sort();
+		assert_eq!(kept, followed);
+	}
+}
@@ -0,0 +1,197 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+	use std::collections::HashSet;
+	use std::sync::atomic::{AtomicBool, Ordering};
+	use std::sync::Arc;
+	use std::thread;
+
+	use calod::client::commands;
+	use calod::client::pool::{Client, ScanCursor};
+	use calod::request_response::command::Command;
+	use calod::request_response::scan_handler;
+	use calod::store::calod_data::{DataType, Hash, Set};
+	use calod::store::calod_store::{CacheError, CalodStore};
+	use calod::store::scan::{scan_position, take_page};
+	use calod::store::sorted_set::SortedSet;
+
+	use crate::common::{command, serve, set};
+
+	// Every key a SCAN from cursor 0 returns, in the order it returns them
+	fn full_scan(store: &CalodStore, count: usize, mut between_pages: impl FnMut(usize)) -> Vec<String> {
+		let mut cursor = 0;
+		let mut keys = Vec::new();
+		for page in 0.. {
+			let (next, found) = store.scan_keys(cursor, count, None, None);
+			keys.extend(found);
+			if next == 0 {
+				break;
+			}
+			between_pages(page);
+			cursor = next;
+		}
+		keys
+	}
+
+	fn scan(store: &CalodStore, name: Command, args: &[&str]) -> String {
+		command(scan_handler::respond, store, name, args)
+	}
+
+	#[test]
+	fn pages_never_split_equal_positions() {
+		let items = vec![(1, "a"), (2, "b"), (2, "c"), (2, "d"), (5, "e")];
+		assert_eq!(take_page(items.clone().into_iter(), 2), (5, vec!["a", "b", "c", "d"]));
+		assert_eq!(take_page(items.clone().into_iter(), 1), (2, vec!["a"]));
+		assert_eq!(take_page(items.into_iter(), 10), (0, vec!["a", "b", "c", "d", "e"]));
+		assert_eq!(scan_position("key"), scan_position("key"));
+	}
+
+	#[test]
+	fn scan_returns_every_key_once_while_the_keyspace_changes() {
+		let store = CalodStore::new(10_000);
+		for i in 0..300 {
+			set(&store, &format!("stable:{}", i), "");
+		}
+		for i in 0..100 {
+			set(&store, &format!("doomed:{}", i), "");
+		}
+
+		// Grow and shrink the keyspace between every page
+		let keys = full_scan(&store, 7, |page| {
+			for i in 0..20 {
+				set(&store, &format!("added:{}:{}", page, i), "");
+			}
+			store.remove_value(&format!("doomed:{}", page));
+		});
+		let unique: HashSet<&String> = keys.iter().collect();
+		assert_eq!(unique.len(), keys.len());
+		assert!((0..300).all(|i| unique.contains(&format!("stable:{}", i))));
+
+		let quiet = full_scan(&store, 1000, |_| {});
+		assert_eq!(quiet.len(), store.db_size());
+	}
+
+	#[test]
+	fn scan_keeps_its_guarantees_under_concurrent_writers() {
+		let store = Arc::new(CalodStore::new(100_000));
+		for i in 0..1000 {
+			set(&store, &format!("stable:{}", i), "");
+		}
+
+		// Writers add and remove keys on every shard while whole scans run
+		let done = Arc::new(AtomicBool::new(false));
+		let writers: Vec<_> = (0..4).map(|writer| {
+			let (store, done) = (store.clone(), done.clone());
+			thread::spawn(move || {
+				let mut i = 0;
+				while !done.load(Ordering::SeqCst) {
+					set(&store, &format!("churn:{}:{}", writer, i), "");
+					if i >= 50 {
+						store.remove_value(&format!("churn:{}:{}", writer, i - 50));
+					}
+					i += 1;
+				}
+			})
+		}).collect();
+		for _ in 0..20 {
+			let keys = full_scan(&store, 10, |_| {});
+			let unique: HashSet<&String> = keys.iter().collect();
+			assert_eq!(unique.len(), keys.len());
+			assert!((0..1000).all(|i| unique.contains(&format!("stable:{}", i))));
+		}
+		done.store(true, Ordering::SeqCst);
+		writers.into_iter().for_each(|writer| writer.join().unwrap());
+	}
+
+	#[test]
+	fn scan_filters_by_pattern_and_type() {
+		let store = CalodStore::new(100);
+		for key in ["user:1", "user:2", "order:1"] {
+			set(&store, key, "");
+		}
+		store.replace_value("user:set", DataType::Set(Set::new()));
+
+		let (next, mut users) = store.scan_keys(0, 100, Some("user:*"), Some("STRING"));
+		users.sort();
+		assert_eq!((next, users), (0, vec![String::from("user:1"), String::from("user:2")]));
+		let mut keys = store.keys_matching("*:1");
+		keys.sort();
+		assert_eq!(keys, vec![String::from("order:1"), String::from("user:1")]);
+
+		assert_eq!(scan(&store, Command::SCAN, &["0", "MATCH", "order:*", "COUNT", "100"]), "*2\r\n$1\r\n0\r\n*1\r\n$7\r\norder:1\r\n");
+		assert_eq!(scan(&store, Command::SCAN, &["0", "TYPE", "set"]), "*2\r\n$1\r\n0\r\n*1\r\n$8\r\nuser:set\r\n");
+		assert_eq!(scan(&store, Command::KEYS, &["order*"]), "*1\r\n$7\r\norder:1\r\n");
+		assert_eq!(scan(&store, Command::SCAN, &["x"]), "-ERR invalid cursor\r\n");
+		assert_eq!(scan(&store, Command::SCAN, &["0", "COUNT", "0"]), "-ERR syntax error\r\n");
+	}
+
+	#[test]
+	fn collection_scans() {
+		let store = CalodStore::new(100);
+		let hash = Hash::new();
+		let members = Set::new();
+		let mut sorted_set = SortedSet::new();
+		for i in 0..50 {
+			hash.insert(format!("field:{}", i), i.to_string());
+			members.insert(format!("member:{}", i));
+			sorted_set.insert(&format!("member:{}", i), i as f64);
+		}
+		store.replace_value("hash", DataType::Hash(hash));
+		store.replace_value("set", DataType::Set(members));
+		store.replace_value("zset", DataType::SortedSet(sorted_set));
+
+		// Walk the hash in small pages
+		let (mut cursor, mut fields) = (0, Vec::new());
+		loop {
+			let (next, page) = store.scan_hash("hash", cursor, 4, None).unwrap();
+			fields.extend(page);
+			if next == 0 {
+				break;
+			}
+			cursor = next;
+		}
+		fields.sort_by_key(|(_, value)| value.parse::<u32>().unwrap());
+		assert_eq!(fields, (0..50).map(|i| (format!("field:{}", i), i.to_string())).collect::<Vec<_>>());
+
+		assert_eq!(store.scan_set("set", 0, 100, Some("member:1?")).unwrap().1.len(), 10);
+		assert_eq!(store.scan_sorted_set("zset", 0, 100, Some("member:7")).unwrap(), (0, vec![(String::from("member:7"), 7.0)]));
+		assert_eq!(store.scan_set("missing", 0, 10, None).unwrap(), (0, vec![]));
+		assert!(matches!(store.scan_set("hash", 0, 10, None), Err(CacheError::WrongType)));
+
+		assert_eq!(scan(&store, Command::ZSCAN, &["zset", "0", "MATCH", "member:7", "COUNT", "100"]), "*2\r\n$1\r\n0\r\n*2\r\n$8\r\nmember:7\r\n$1\r\n7\r\n");
+		assert_eq!(scan(&store, Command::HSCAN, &["hash", "0", "MATCH", "field:7", "COUNT", "100", "NOVALUES"]), "*2\r\n$1\r\n0\r\n*1\r\n$7\r\nfield:7\r\n");
+	}
+
+	#[tokio::test]
+	async fn client_cursors_walk_every_page() {
+		let store = Arc::new(CalodStore::new(1000));
+		let hash = Hash::new();
+		for i in 0..30 {
+			set(&store, &format!("key:{}", i), "");
+			hash.insert(format!("field:{}", i), i.to_string());
+		}
+		store.replace_value("hash", DataType::Hash(hash));
+
+		let client = Client::new(&serve(store.clone()));
+
+		let mut keys = ScanCursor::new(|cursor| commands::scan(cursor).arg("MATCH").arg("key:*").arg("COUNT").arg(4));
+		let mut found = HashSet::new();
+		while let Some(key) = keys.next(&client).await.unwrap() {
+			assert!(found.insert(key));
+		}
+		assert_eq!(found, (0..30).map(|i| format!("key:{}", i)).collect());
+
+		let mut fields = ScanCursor::new(|cursor| commands::hscan("hash", cursor).arg("COUNT").arg(3));
+		let mut values = Vec::new();
+		while let Some((field, value)) = fields.next(&client).await.unwrap() {
+			assert_eq!(field, format!("field:{}", value));
+			values.push(value.parse::<u32>().unwrap());
+		}
+		values.sort();
+		assert_eq!(values, (0..30).collect::<Vec<_>>());
+
+		let matching = client.query(commands::keys("key:1*")).await.unwrap();
+		assert_eq!(matching.len(), 11);
+	}
+}
@@ -0,0 +1,199 @@
+#[cfg(test)]
+mod tests {
+	use std::io::{BufReader, Write};
+	use std::net::TcpListener;
+	use std::sync::atomic::{AtomicBool, Ordering};
+	use std::sync::Mutex;
+	use std::thread;
+	use std::time::{Duration, Instant};
+
+	use calod::parser::parser::RESPOutput;
+	use calod::request_response::response_helper::format_resp_output;
+	use calod::sentinel::link::{join_address, query, read_reply, split_address};
+	use calod::sentinel::state::{Sentinel, SentinelConfig};
+
+	// Stands in for a calod server, answers ROLE and REPLICAOF and drops every connection while down
+	struct MockServer {
+		address: String,
+		offset: u64,
+		following: Mutex<Option<String>>,
+		replicas: Mutex<Vec<String>>,
+		alive: AtomicBool,
+	}
+
+	impl MockServer {
+		fn start(following: Option<&str>, offset: u64) -> &'static MockServer {
+			let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+			let server: &'static MockServer = Box::leak(Box::new(MockServer {
+				address: listener.local_addr().unwrap().to_string(),
+				offset,
+				following: Mutex::new(following.map(String::from)),
+				replicas: Mutex::new(Vec::new()),
+				alive: AtomicBool::new(true),
+			}));
+			thread::spawn(move || {
+				for stream in listener.incoming() {
+					let mut stream = stream.unwrap();
+					if !server.alive.load(Ordering::SeqCst) {
+						continue;
+					}
+					if let Ok(request) = read_reply(&mut BufReader::new(stream.try_clone().unwrap())) {
+						let _ = stream.write_all(format_resp_output(&server.respond(request)).as_bytes());
+					}
+				}
+			});
+			server
+		}
+
+		fn respond(&self, request: RESPOutput) -> RESPOutput {
+			let RESPOutput::Array(args) = request else { panic!("not a command") };
+			let args: Vec<String> = args.into_iter().map(|arg| if let RESPOutput::BulkString(arg) = arg { arg } else { panic!("not a bulk string") }).collect();
+			match args.iter().map(String::as_str).collect::<Vec<&str>>().as_slice() {
+				["ROLE"] => match self.following() {
+					None => RESPOutput::Array(vec![
+						RESPOutput::BulkString(String::from("master")),
+						RESPOutput::Integer(self.offset as i64),
+						RESPOutput::Array(
+							self.replicas.lock().unwrap().iter().map(|address| RESPOutput::Array(vec![RESPOutput::BulkString(address.clone()), RESPOutput::Integer(0)])).collect(),
+						),
+					]),
+					Some(leader) => {
+						let (host, port) = split_address(&leader).unwrap();
+						RESPOutput::Array(vec![
+							RESPOutput::BulkString(String::from("slave")),
+							RESPOutput::BulkString(host),
+							RESPOutput::Integer(port as i64),
+							RESPOutput::BulkString(String::from("connected")),
+							RESPOutput::Integer(self.offset as i64),
+						])
+					}
+				},
+				["REPLICAOF", "NO", "ONE"] => {
+					*self.following.lock().unwrap() = None;
+					RESPOutput::SimpleString(String::from("OK"))
+				}
+				["REPLICAOF", host, port] => {
+					*self.following.lock().unwrap() = Some(join_address(host, port.parse().unwrap()));
+					RESPOutput::SimpleString(String::from("OK"))
+				}
+				_ => RESPOutput::Error(String::from("ERR unknown command")),
+			}
+		}
+
+		fn following(&self) -> Option<String> {
+			self.following.lock().unwrap().clone()
+		}
+
+		fn set_alive(&self, alive: bool) {
+			self.alive.store(alive, Ordering::SeqCst);
+		}
+	}
+
+	// `count` sentinels on local ports that know each other
+	fn start_sentinels(count: usize, quorum: usize, leader: &str) -> Vec<&'static Sentinel> {
+		let listeners: Vec<TcpListener> = (0..count).map(|_| TcpListener::bind("127.0.0.1:0").unwrap()).collect();
+		let addresses: Vec<String> = listeners.iter().map(|listener| listener.local_addr().unwrap().to_string()).collect();
+		listeners
+			.into_iter()
+			.enumerate()
+			.map(|(i, listener)| {
+				let mut config = fast_config(leader, quorum);
+				config.peers = addresses.iter().enumerate().filter(|(j, _)| *j != i).map(|(_, address)| address.clone()).collect();
+				let sentinel: &'static Sentinel = Box::leak(Box::new(Sentinel::new(config)));
+				sentinel.start(listener);
+				sentinel
+			})
+			.collect()
+	}
+
+	fn fast_config(leader: &str, quorum: usize) -> SentinelConfig {
+		let mut config = SentinelConfig::new("cache", leader, quorum);
+		config.down_after = Duration::from_millis(300);
+		config.failover_timeout = Duration::from_millis(500);
+		config.ping_interval = Duration::from_millis(50);
+		config
+	}
+
+	fn wait_for(mut condition: impl FnMut() -> bool) {
+		let deadline = Instant::now() + Duration::from_secs(10);
+		while !condition() {
+			assert!(Instant::now() < deadline, "timed out");
+			thread::sleep(Duration::from_millis(10));
+		}
+	}
+
+	fn command(sentinel: &Sentinel, args: &[&str]) -> RESPOutput {
+		sentinel.respond(&args.iter().map(|arg| arg.to_string()).collect::<Vec<String>>()).unwrap()
+	}
+
+	#[test]
+	fn failover_promotes_the_most_up_to_date_follower() {
+		let leader = MockServer::start(None, 300);
+		let behind = MockServer::start(Some(&leader.address), 100);
+		let ahead = MockServer::start(Some(&leader.address), 200);
+		*leader.replicas.lock().unwrap() = vec![behind.address.clone(), ahead.address.clone()];
+
+		let sentinels = start_sentinels(3, 2, &leader.address);
+		wait_for(|| sentinels.iter().all(|sentinel| sentinel.replicas().len() == 2));
+
+		leader.set_alive(false);
+		wait_for(|| sentinels.iter().all(|sentinel| sentinel.leader() == ahead.address));
+		assert_eq!(ahead.following(), None);
+		wait_for(|| behind.following().as_deref() == Some(ahead.address.as_str()));
+		let epoch = sentinels[0].config_epoch();
+		assert!(epoch > 0 && sentinels.iter().all(|sentinel| sentinel.config_epoch() == epoch));
+
+		// The old leader follows the new one once it is back
+		leader.set_alive(true);
+		wait_for(|| leader.following().as_deref() == Some(ahead.address.as_str()));
+		assert_eq!(sentinels[0].leader(), ahead.address);
+	}
+
+	#[test]
+	fn no_failover_without_a_quorum() {
+		let leader = MockServer::start(None, 0);
+		let follower = MockServer::start(Some(&leader.address), 0);
+		*leader.replicas.lock().unwrap() = vec![follower.address.clone()];
+
+		// The only other sentinel never answers
+		let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+		let mut config = fast_config(&leader.address, 2);
+		config.peers = vec![String::from("127.0.0.1:1")];
+		let sentinel: &'static Sentinel = Box::leak(Box::new(Sentinel::new(config)));
+		let address = listener.local_addr().unwrap().to_string();
+		sentinel.start(listener);
+		wait_for(|| sentinel.replicas().len() == 1);
+
+		leader.set_alive(false);
+		wait_for(|| sentinel.subjectively_down());
+		thread::sleep(Duration::from_millis(600));
+		assert_eq!(follower.following().as_deref(), Some(leader.address.as_str()));
+
+		// Clients still get the old leader
+		let (host, port) = split_address(&leader.address).unwrap();
+		let reply = query(&address, &["SENTINEL", "GET-MASTER-ADDR-BY-NAME", "cache"], Duration::from_secs(1)).unwrap();
+		assert_eq!(reply, RESPOutput::Array(vec![RESPOutput::BulkString(host), RESPOutput::BulkString(port.to_string())]));
+		assert_eq!(query(&address, &["SENTINEL", "GET-MASTER-ADDR-BY-NAME", "other"], Duration::from_secs(1)).unwrap(), RESPOutput::Null);
+	}
+
+	#[test]
+	fn one_vote_per_epoch_and_newer_configurations_win() {
+		let sentinel = Sentinel::new(SentinelConfig::new("cache", "127.0.0.1:7000", 2));
+		let vote = |port: &str, epoch: &str, runid: &str| command(&sentinel, &["SENTINEL", "is-master-down-by-addr", "127.0.0.1", port, epoch, runid]);
+		let reply = |runid: &str, epoch: i64| RESPOutput::Array(vec![RESPOutput::Integer(0), RESPOutput::BulkString(runid.to_string()), RESPOutput::Integer(epoch)]);
+
+		assert_eq!(vote("7000", "1", "a"), reply("a", 1));
+		assert_eq!(vote("7000", "1", "b"), reply("a", 1));
+		assert_eq!(vote("7000", "2", "b"), reply("b", 2));
+		assert_eq!(vote("7000", "1", "c"), reply("b", 2));
+		assert_eq!(vote("7001", "3", "c"), reply("*", 0));
+
+		// A hello only replaces the configuration when its epoch is newer
+		command(&sentinel, &["SENTINEL", "HELLO", "cache", "1", "127.0.0.1:7001"]);
+		command(&sentinel, &["SENTINEL", "HELLO", "cache", "1", "127.0.0.1:7002"]);
+		assert_eq!((sentinel.leader(), sentinel.config_epoch()), (String::from("127.0.0.1:7001"), 1));
+		assert_eq!(sentinel.replicas(), vec![String::from("127.0.0.1:7000")]);
+		assert_eq!(split_address("[::1]:7000"), Some((String::from("::1"), 7000)));
+		assert_eq!(join_address("::1", 7000), "[::1]:7000");
+	}
+}
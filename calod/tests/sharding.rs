@@ -0,0 +1,123 @@
+#[cfg(test)]
+mod tests {
+	use std::collections::HashMap;
+	use std::io::{BufReader, Write};
+	use std::net::TcpListener;
+	use std::sync::{Arc, Mutex};
+	use std::thread;
+
+	use calod::client::ring::{Crc32, HashRing, KeyHasher, DEFAULT_VNODES};
+	use calod::client::sharded::{ShardOptions, ShardedClient};
+	use calod::parser::parser::RESPOutput;
+	use calod::request_response::response_helper::format_resp_output;
+	use calod::sentinel::link::read_reply;
+
+	fn ring(servers: &[&str]) -> HashRing {
+		let mut ring = HashRing::new(DEFAULT_VNODES);
+		for server in servers {
+			ring.add(server);
+		}
+		ring
+	}
+
+	// A server answering GET and SET from its own map, returns its address and its keys
+	fn start_server() -> (String, Arc<Mutex<HashMap<String, String>>>) {
+		let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+		let address = listener.local_addr().unwrap().to_string();
+		let keys: Arc<Mutex<HashMap<String, String>>> = Arc::default();
+		let served = keys.clone();
+		thread::spawn(move || {
+			for stream in listener.incoming() {
+				let (mut stream, keys) = (stream.unwrap(), served.clone());
+				thread::spawn(move || {
+					let mut reader = BufReader::new(stream.try_clone().unwrap());
+					while let Ok(RESPOutput::Array(args)) = read_reply(&mut reader) {
+						let args: Vec<String> = args.into_iter().map(|arg| if let RESPOutput::BulkString(arg) = arg { arg } else { panic!("not a bulk string") }).collect();
+						let reply = match args.iter().map(String::as_str).collect::<Vec<&str>>().as_slice() {
+							["GET", key] => keys.lock().unwrap().get(*key).cloned().map_or(RESPOutput::Null, RESPOutput::BulkString),
+							["SET", key, value] => {
+								keys.lock().unwrap().insert(key.to_string(), value.to_string());
+								RESPOutput::SimpleString(String::from("OK"))
+							}
+							_ => RESPOutput::Error(String::from("ERR unknown command")),
+						};
+						stream.write_all(format_resp_output(&reply).as_bytes()).unwrap();
+					}
+				});
+			}
+		});
+		(address, keys)
+	}
+
+	#[test]
+	fn virtual_nodes_spread_keys_evenly() {
+		let ring = ring(&["a:6379", "b:6379", "c:6379", "d:6379"]);
+		let mut counts: HashMap<&str, usize> = HashMap::new();
+		for i in 0..40000 {
+			*counts.entry(ring.server_for(format!("key{}", i).as_bytes()).unwrap()).or_default() += 1;
+		}
+		assert_eq!(counts.len(), 4);
+		assert!(counts.values().all(|count| (7000..13000).contains(count)), "{:?}", counts);
+		assert_eq!(HashRing::new(1).server_for(b"key"), None);
+	}
+
+	#[test]
+	fn adding_a_server_only_moves_keys_onto_it() {
+		let keys: Vec<String> = (0..10000).map(|i| format!("key{}", i)).collect();
+		let mut ring = ring(&["a:6379", "b:6379", "c:6379"]);
+		let before: Vec<String> = keys.iter().map(|key| ring.server_for(key.as_bytes()).unwrap().to_string()).collect();
+
+		ring.add("d:6379");
+		let mut moved = 0;
+		for (key, old) in keys.iter().zip(&before) {
+			let new = ring.server_for(key.as_bytes()).unwrap();
+			if new != old {
+				assert_eq!(new, "d:6379");
+				moved += 1;
+			}
+		}
+		assert!((1500..3500).contains(&moved), "{} keys moved", moved);
+
+		// Removing it again puts every key back
+		assert!(ring.remove("d:6379"));
+		assert!(!ring.remove("d:6379"));
+		assert!(keys.iter().zip(&before).all(|(key, old)| ring.server_for(key.as_bytes()) == Some(old.as_str())));
+	}
+
+	#[test]
+	fn hashers_are_pluggable_and_hash_tags_colocate_keys() {
+		let first_byte = |bytes: &[u8]| bytes.first().map_or(0, |byte| (*byte as u64) << 56);
+		assert_eq!(first_byte.hash(b"\x01"), 1 << 56);
+		let mut ring = HashRing::with_hasher(Crc32, 4);
+		ring.add("a:6379");
+		assert_eq!(ring.hash(b"key"), crc32fast::hash(b"key") as u64);
+
+		let client = ShardedClient::with_hasher(&["a:6379", "b:6379", "c:6379"], Crc32, ShardOptions::default());
+		let server = client.server_for("{user:1}:name");
+		assert!(server.is_some());
+		assert!(["{user:1}:email", "{user:1}:age", "user:1"].iter().all(|key| client.server_for(key) == server));
+	}
+
+	#[test]
+	fn sharded_client_spreads_keys_over_servers() {
+		let servers = [start_server(), start_server(), start_server()];
+		let addresses: Vec<&str> = servers.iter().map(|(address, _)| address.as_str()).collect();
+		let client = ShardedClient::new(&addresses);
+		for i in 0..300 {
+			client.set(&format!("key{}", i), &i.to_string()).unwrap();
+		}
+		for i in 0..300 {
+			let key = format!("key{}", i);
+			assert_eq!(client.get(&key).unwrap(), Some(i.to_string()));
+			let (_, keys) = servers.iter().find(|(address, _)| Some(address.as_str()) == client.server_for(&key)).unwrap();
+			assert!(keys.lock().unwrap().contains_key(&key));
+		}
+		assert!(servers.iter().all(|(_, keys)| !keys.lock().unwrap().is_empty()));
+		assert_eq!(client.get("missing").unwrap(), None);
+		assert!(client.command("key1", &["PING"]).is_err());
+
+		let mut client = client;
+		assert!(addresses.iter().all(|address| client.remove_server(address)));
+		assert_eq!(client.get("key1").unwrap_err().kind(), std::io::ErrorKind::NotFound);
+	}
+}
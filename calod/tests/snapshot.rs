@@ -0,0 +1,90 @@
+#[cfg(test)]
+mod tests {
+	use std::collections::LinkedList;
+
+	use calod::persistence::snapshot::{SnapshotError, SnapshotReader, SnapshotWriter};
+	use calod::store::bloom_filter::BloomFilter;
+	use calod::store::calod_data::{CacheEntry, DataType, Hash};
+	use calod::store::hyperloglog::HyperLogLog;
+	use calod::store::sorted_set::SortedSet;
+	use chrono::{Duration, Utc};
+
+	fn write(entries: &[(&str, CacheEntry)]) -> Vec<u8> {
+		let mut writer = SnapshotWriter::new(Vec::new()).unwrap();
+		for (key, entry) in entries {
+			writer.write_entry(key, entry).unwrap();
+		}
+		writer.finish().unwrap()
+	}
+
+	fn read(bytes: &[u8]) -> Result<Vec<(String, CacheEntry)>, SnapshotError> {
+		let mut reader = SnapshotReader::new(bytes)?;
+		let mut entries = Vec::new();
+		while let Some(entry) = reader.next_entry()? {
+			entries.push(entry);
+		}
+		Ok(entries)
+	}
+
+	#[test]
+	fn round_trip_preserves_values_and_ttl() {
+		let mut hll = HyperLogLog::new();
+		for i in 0..5000 {
+			hll.add(format!("item:{}", i).as_bytes());
+		}
+		let mut bloom = BloomFilter::new(0.01, 100, 2, true);
+		bloom.add(b"present").unwrap();
+		let mut zset = SortedSet::new();
+		zset.insert("a", 1.5);
+		zset.insert("b", -2.0);
+		let hash = Hash::new();
+		hash.insert(String::from("field"), String::from("value"));
+
+		let mut expiring = CacheEntry::new(DataType::String(vec![0, 255, 10]));
+		expiring.ttl = Some(Utc::now() + Duration::seconds(60));
+
+		let bytes = write(&[
+			("bin", expiring.clone()),
+			("list", CacheEntry::new(DataType::List(LinkedList::from([String::from("x"), String::from("y")])))),
+			("hash", CacheEntry::new(DataType::Hash(hash))),
+			("zset", CacheEntry::new(DataType::SortedSet(zset.clone()))),
+			("hll", CacheEntry::new(DataType::HyperLogLog(hll.clone()))),
+			("bloom", CacheEntry::new(DataType::BloomFilter(bloom))),
+			("doc", CacheEntry::new(DataType::Json(serde_json::json!({"a": [1, 2]})))),
+		]);
+		let entries = read(&bytes).unwrap();
+		assert_eq!(entries.len(), 7);
+
+		assert_eq!(entries[0].0, "bin");
+		assert!(matches!(&entries[0].1.value, DataType::String(b) if b == &vec![0, 255, 10]));
+		assert_eq!(entries[0].1.ttl.unwrap().timestamp_millis(), expiring.ttl.unwrap().timestamp_millis());
+		assert!(matches!(&entries[1].1.value, DataType::List(l) if l.iter().eq(["x", "y"].iter())));
+		assert!(matches!(&entries[2].1.value, DataType::Hash(h) if h.get("field").as_deref() == Some("value")));
+		assert!(matches!(&entries[3].1.value, DataType::SortedSet(z) if z == &zset));
+		match &entries[4].1.value {
+			DataType::HyperLogLog(loaded) => assert_eq!(loaded.clone().count(), hll.count()),
+			other => panic!("unexpected {:?}", other),
+		}
+		assert!(matches!(&entries[5].1.value, DataType::BloomFilter(b) if b.contains(b"present")));
+		assert!(matches!(&entries[6].1.value, DataType::Json(d) if d["a"][1] == 2));
+	}
+
+	#[test]
+	fn corrupted_payload_fails_checksum() {
+		let mut bytes = write(&[("key", CacheEntry::new(DataType::String(b"value".to_vec())))]);
+		let last_value_byte = bytes.len() - 6;
+		bytes[last_value_byte] ^= 0x01;
+		assert!(matches!(read(&bytes), Err(SnapshotError::ChecksumMismatch { .. })));
+	}
+
+	#[test]
+	fn rejects_newer_versions_and_truncation() {
+		let bytes = write(&[("key", CacheEntry::new(DataType::String(b"value".to_vec())))]);
+
+		let mut newer = bytes.clone();
+		newer[8] = 0xff;
+		assert!(matches!(SnapshotReader::new(&newer[..]), Err(SnapshotError::UnsupportedVersion(_))));
+		assert!(matches!(read(&bytes[..bytes.len() - 3]), Err(SnapshotError::Corrupt(_))));
+		assert!(matches!(SnapshotReader::new(&b"not a snapshot"[..]), Err(SnapshotError::BadMagic)));
+	}
+}